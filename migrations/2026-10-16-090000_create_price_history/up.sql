@@ -0,0 +1,12 @@
+CREATE TABLE price_history (
+  id int(11) NOT NULL AUTO_INCREMENT,
+  product_id int(11) NOT NULL COMMENT 'id товара в базе',
+  old_price float NOT NULL COMMENT 'предыдущая цена',
+  new_price float NOT NULL COMMENT 'новая цена',
+  currencyId enum('RUB','UAH','BYR','KZT','EUR','USD') DEFAULT NULL COMMENT 'валюта',
+  run_id bigint(20) NOT NULL COMMENT 'идентификатор импорта',
+  created_at timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (id) USING BTREE,
+  KEY product_id (product_id) USING BTREE,
+  KEY run_id (run_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,4 @@
+ALTER TABLE products
+  DROP KEY on_sale,
+  DROP COLUMN discount_percent,
+  DROP COLUMN on_sale;
@@ -0,0 +1,4 @@
+ALTER TABLE products
+  ADD COLUMN on_sale tinyint(4) DEFAULT 0 COMMENT 'акция (есть скидка)',
+  ADD COLUMN discount_percent int(11) DEFAULT NULL COMMENT 'размер скидки, %',
+  ADD KEY on_sale (on_sale) USING BTREE;
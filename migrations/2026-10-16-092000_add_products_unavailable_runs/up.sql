@@ -0,0 +1,2 @@
+ALTER TABLE products
+  ADD COLUMN unavailable_runs int(11) NOT NULL DEFAULT 0 COMMENT 'кол-во импортов подряд без наличия';
@@ -0,0 +1,2 @@
+ALTER TABLE products
+  ADD COLUMN tags varchar(256) DEFAULT NULL COMMENT 'теги из правил импорта';
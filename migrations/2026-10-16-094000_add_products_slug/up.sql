@@ -0,0 +1,3 @@
+ALTER TABLE products
+  ADD COLUMN slug varchar(128) DEFAULT NULL COMMENT 'url товара',
+  ADD UNIQUE KEY slug (slug) USING BTREE;
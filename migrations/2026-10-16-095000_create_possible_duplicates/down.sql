@@ -0,0 +1 @@
+DROP TABLE possible_duplicates;
@@ -0,0 +1,10 @@
+CREATE TABLE possible_duplicates (
+  id int(11) NOT NULL AUTO_INCREMENT,
+  product_id int(11) NOT NULL COMMENT 'новый товар',
+  duplicate_product_id int(11) NOT NULL COMMENT 'похожий товар другого поставщика',
+  similarity float NOT NULL COMMENT 'похожесть названий (0..1)',
+  reason enum('name','barcode') NOT NULL COMMENT 'причина',
+  created_at timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (id) USING BTREE,
+  UNIQUE KEY product_duplicate (product_id, duplicate_product_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,9 @@
+CREATE TABLE pricing_rules (
+  id int(11) NOT NULL AUTO_INCREMENT,
+  category_id int(11) DEFAULT NULL COMMENT 'id категории, NULL - правило по умолчанию',
+  percent float NOT NULL DEFAULT 0 COMMENT 'наценка, %',
+  fixed_add float NOT NULL DEFAULT 0 COMMENT 'фиксированная наценка',
+  rounding float DEFAULT NULL COMMENT 'шаг округления цены',
+  PRIMARY KEY (id) USING BTREE,
+  UNIQUE KEY category_id (category_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
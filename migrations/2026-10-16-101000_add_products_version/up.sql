@@ -0,0 +1,2 @@
+ALTER TABLE products
+  ADD COLUMN version int(11) NOT NULL DEFAULT 0 COMMENT 'версия, увеличивается при каждом изменении';
@@ -0,0 +1,2 @@
+ALTER TABLE products
+  ADD COLUMN locked_fields varchar(256) DEFAULT NULL COMMENT 'поля, которые нельзя обновлять импортом (CSV или JSON)';
@@ -0,0 +1,2 @@
+ALTER TABLE products
+  ADD COLUMN price_from tinyint(4) NOT NULL DEFAULT 0 COMMENT 'цена "от" (минимальная цена)';
@@ -0,0 +1,2 @@
+ALTER TABLE products
+  ADD COLUMN vat varchar(16) DEFAULT NULL COMMENT 'код ставки НДС (VAT_20, NO_VAT, ...)';
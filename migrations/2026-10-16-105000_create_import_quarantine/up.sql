@@ -0,0 +1,12 @@
+CREATE TABLE import_quarantine (
+  id int(11) NOT NULL AUTO_INCREMENT,
+  offer_id varchar(255) NOT NULL COMMENT 'id предложения в файле',
+  hub_stock_id varchar(255) NOT NULL COMMENT 'id предложения в хабе',
+  name text NOT NULL COMMENT 'название товара',
+  error text NOT NULL COMMENT 'ошибка базы данных',
+  run_id bigint(20) NOT NULL COMMENT 'идентификатор импорта',
+  created_at timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (id) USING BTREE,
+  KEY offer_id (offer_id) USING BTREE,
+  KEY run_id (run_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,2 @@
+ALTER TABLE products
+  ADD COLUMN status enum('active','draft') NOT NULL DEFAULT 'active' COMMENT 'статус модерации (draft - ожидает проверки)';
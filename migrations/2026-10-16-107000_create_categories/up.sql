@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS categories (
+  id int(11) NOT NULL COMMENT 'id категории',
+  parent_id int(11) DEFAULT NULL COMMENT 'id родительской категории',
+  name varchar(256) NOT NULL COMMENT 'название',
+  auto_created tinyint(4) NOT NULL DEFAULT 0 COMMENT 'создана автоматически при импорте',
+  PRIMARY KEY (id) USING BTREE,
+  KEY parent_id (parent_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,14 @@
+CREATE TABLE import_runs (
+  id int(11) NOT NULL AUTO_INCREMENT,
+  file_path varchar(1024) NOT NULL COMMENT 'путь к файлу импорта',
+  total_offers int(11) unsigned NOT NULL COMMENT 'всего предложений',
+  ignored_offers int(11) unsigned NOT NULL COMMENT 'пропущено предложений',
+  parsed_offers int(11) unsigned NOT NULL COMMENT 'разобрано предложений',
+  updated_price int(11) unsigned NOT NULL COMMENT 'изменилась цена',
+  updated_available int(11) unsigned NOT NULL COMMENT 'изменилось наличие',
+  inserted_products int(11) unsigned NOT NULL COMMENT 'новых товаров',
+  marked_as_unavailable int(11) unsigned NOT NULL COMMENT 'отмечено отсутствующими',
+  created_at timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (id) USING BTREE,
+  KEY file_path (file_path(255)) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,2 @@
+ALTER TABLE products
+  ADD COLUMN source_supplier varchar(128) DEFAULT NULL COMMENT 'поставщик, чье предложение выбрано при слиянии фидов';
@@ -0,0 +1,9 @@
+CREATE TABLE product_stocks (
+  hub_stock_id varchar(32) NOT NULL COMMENT 'id оригинала товара',
+  supplier varchar(128) NOT NULL DEFAULT '' COMMENT 'поставщик (название магазина в фиде)',
+  quantity int(11) NOT NULL COMMENT 'остаток у поставщика',
+  run_id bigint(20) NOT NULL COMMENT 'идентификатор импорта, в котором обновлен остаток',
+  updated_at timestamp NOT NULL DEFAULT current_timestamp() ON UPDATE current_timestamp(),
+  PRIMARY KEY (hub_stock_id, supplier) USING BTREE,
+  KEY run_id (run_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
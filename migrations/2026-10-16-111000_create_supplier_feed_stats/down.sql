@@ -0,0 +1 @@
+DROP TABLE supplier_feed_stats;
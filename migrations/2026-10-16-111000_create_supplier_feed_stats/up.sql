@@ -0,0 +1,10 @@
+CREATE TABLE supplier_feed_stats (
+  id int(11) NOT NULL AUTO_INCREMENT,
+  supplier varchar(128) NOT NULL COMMENT 'поставщик (название магазина в фиде или имя файла)',
+  feed_date datetime DEFAULT NULL COMMENT 'дата формирования фида (атрибут date у yml_catalog)',
+  run_at timestamp NOT NULL DEFAULT current_timestamp() COMMENT 'время импорта',
+  total_offers int(11) unsigned NOT NULL COMMENT 'всего предложений',
+  error_offers int(11) unsigned NOT NULL COMMENT 'пропущено или отклонено предложений',
+  PRIMARY KEY (id) USING BTREE,
+  KEY supplier (supplier, run_at) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
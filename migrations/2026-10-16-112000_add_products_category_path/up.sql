@@ -0,0 +1,2 @@
+ALTER TABLE products
+  ADD COLUMN category_path varchar(1024) DEFAULT NULL COMMENT 'полный путь категории из фида, например "Электроника > Телефоны"';
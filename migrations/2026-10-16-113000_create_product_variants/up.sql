@@ -0,0 +1,14 @@
+CREATE TABLE product_variants (
+  hub_stock_id varchar(32) NOT NULL COMMENT 'id предложения-варианта',
+  group_id varchar(64) NOT NULL COMMENT 'group_id предложений одного товара',
+  product_hub_stock_id varchar(32) NOT NULL COMMENT 'id оригинала основного товара группы',
+  price float NOT NULL COMMENT 'цена варианта',
+  available tinyint(1) NOT NULL COMMENT 'наличие варианта',
+  size varchar(64) DEFAULT NULL COMMENT 'размер из param',
+  color varchar(64) DEFAULT NULL COMMENT 'цвет из param',
+  params text DEFAULT NULL COMMENT 'все param варианта в JSON',
+  run_id bigint(20) NOT NULL COMMENT 'идентификатор импорта, в котором обновлен вариант',
+  updated_at timestamp NOT NULL DEFAULT current_timestamp() ON UPDATE current_timestamp(),
+  PRIMARY KEY (hub_stock_id) USING BTREE,
+  KEY product_hub_stock_id (product_hub_stock_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,6 @@
+CREATE TABLE product_keywords (
+  hub_stock_id varchar(32) NOT NULL COMMENT 'id оригинала товара',
+  keyword varchar(128) NOT NULL COMMENT 'ключевое слово поставщика из keywords',
+  PRIMARY KEY (hub_stock_id, keyword) USING BTREE,
+  KEY keyword (keyword) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
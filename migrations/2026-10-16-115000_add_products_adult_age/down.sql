@@ -0,0 +1,3 @@
+ALTER TABLE products
+  DROP COLUMN adult,
+  DROP COLUMN age;
@@ -0,0 +1,3 @@
+ALTER TABLE products
+  ADD COLUMN adult tinyint(1) NOT NULL DEFAULT 0 COMMENT 'товар для взрослых (adult в фиде)',
+  ADD COLUMN age int(11) DEFAULT NULL COMMENT 'возрастное ограничение в годах (age в фиде)';
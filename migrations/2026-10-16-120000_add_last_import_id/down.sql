@@ -0,0 +1,5 @@
+ALTER TABLE import_runs
+  DROP COLUMN import_id;
+
+ALTER TABLE products
+  DROP COLUMN last_import_id;
@@ -0,0 +1,7 @@
+ALTER TABLE products
+  ADD COLUMN last_import_id char(36) DEFAULT NULL COMMENT 'id последнего импорта, изменившего товар',
+  ADD KEY last_import_id (last_import_id) USING BTREE;
+
+ALTER TABLE import_runs
+  ADD COLUMN import_id char(36) DEFAULT NULL COMMENT 'уникальный id импорта' AFTER id,
+  ADD UNIQUE KEY import_id (import_id) USING BTREE;
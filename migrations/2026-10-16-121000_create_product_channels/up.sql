@@ -0,0 +1,9 @@
+CREATE TABLE product_channels (
+  hub_stock_id varchar(32) NOT NULL COMMENT 'id оригинала товара',
+  channel varchar(32) NOT NULL COMMENT 'канал продаж: rozetka, prom, site',
+  visible tinyint(1) NOT NULL COMMENT 'товар показывается в канале',
+  run_id bigint(20) NOT NULL COMMENT 'запуск импорта, изменивший видимость',
+  updated_at timestamp NOT NULL DEFAULT current_timestamp() ON UPDATE current_timestamp(),
+  PRIMARY KEY (hub_stock_id, channel) USING BTREE,
+  KEY channel_visible (channel, visible) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,2 @@
+ALTER TABLE import_runs
+  ADD COLUMN rules_version varchar(255) DEFAULT NULL COMMENT 'версия правил, использованных импортом' AFTER marked_as_unavailable;
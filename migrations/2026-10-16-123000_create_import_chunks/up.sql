@@ -0,0 +1,10 @@
+CREATE TABLE import_chunks (
+  chunk_hash char(40) NOT NULL COMMENT 'sha1 ключа идемпотентности, номера и содержимого чанка',
+  idempotency_key varchar(255) NOT NULL COMMENT 'значение --idempotency-key',
+  chunk_index int(10) unsigned NOT NULL COMMENT 'номер чанка в файле',
+  products int(10) unsigned NOT NULL COMMENT 'количество товаров в чанке',
+  import_id char(36) NOT NULL COMMENT 'id импорта, применившего чанк',
+  applied_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'время применения',
+  PRIMARY KEY (chunk_hash) USING BTREE,
+  KEY idempotency_key (idempotency_key) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,9 @@
+CREATE TABLE reservations (
+  id int(11) NOT NULL AUTO_INCREMENT,
+  product_id int(11) NOT NULL COMMENT 'id товара',
+  order_id int(11) NOT NULL COMMENT 'id ожидающего заказа',
+  quantity int(11) NOT NULL COMMENT 'зарезервированное количество',
+  created_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'время резервирования',
+  PRIMARY KEY (id) USING BTREE,
+  KEY product_id (product_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
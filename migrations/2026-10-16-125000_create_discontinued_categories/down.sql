@@ -0,0 +1 @@
+DROP TABLE discontinued_categories;
@@ -0,0 +1,6 @@
+CREATE TABLE discontinued_categories (
+  category_id int(11) NOT NULL COMMENT 'id категории, выведенной из ассортимента',
+  reason varchar(255) DEFAULT NULL COMMENT 'причина вывода из ассортимента',
+  created_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'время вывода из ассортимента',
+  PRIMARY KEY (category_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,3 @@
+ALTER TABLE products
+  ADD COLUMN sku varchar(64) DEFAULT NULL COMMENT 'внутренний артикул, сгенерированный по --sku-template',
+  ADD UNIQUE KEY sku (sku);
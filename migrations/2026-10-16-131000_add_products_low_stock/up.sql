@@ -0,0 +1,2 @@
+ALTER TABLE products
+  ADD COLUMN low_stock tinyint(1) NOT NULL DEFAULT 0 COMMENT 'остаток не больше --low-stock-threshold';
@@ -0,0 +1,11 @@
+CREATE TABLE product_original_prices (
+  hub_stock_id varchar(32) NOT NULL COMMENT 'id оригинала товара',
+  price_original float NOT NULL COMMENT 'цена из фида в валюте поставщика',
+  oldprice_original float DEFAULT NULL COMMENT 'старая цена из фида в валюте поставщика',
+  currency_original varchar(3) NOT NULL COMMENT 'валюта поставщика',
+  rate float NOT NULL COMMENT 'курс, по которому цена пересчитана в гривны',
+  run_id bigint(20) NOT NULL COMMENT 'запуск импорта, пересчитавший цену',
+  updated_at timestamp NOT NULL DEFAULT current_timestamp() ON UPDATE current_timestamp(),
+  PRIMARY KEY (hub_stock_id) USING BTREE,
+  KEY currency_original (currency_original) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
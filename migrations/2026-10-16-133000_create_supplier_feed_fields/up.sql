@@ -0,0 +1,8 @@
+CREATE TABLE supplier_feed_fields (
+  supplier varchar(128) NOT NULL COMMENT 'поставщик (название магазина в фиде или имя файла)',
+  field varchar(128) NOT NULL COMMENT 'элемент предложения или атрибут (@имя)',
+  offers int(11) unsigned NOT NULL COMMENT 'предложений с полем в последнем импорте',
+  total_offers int(11) unsigned NOT NULL COMMENT 'всего предложений в последнем импорте',
+  run_at timestamp NOT NULL DEFAULT current_timestamp() COMMENT 'время последнего импорта',
+  PRIMARY KEY (supplier, field) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,2 @@
+ALTER TABLE import_runs
+  ADD COLUMN archive_path varchar(1024) DEFAULT NULL COMMENT 'копия фида, импортированного запуском' AFTER rules_version;
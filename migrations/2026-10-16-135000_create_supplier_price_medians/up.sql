@@ -0,0 +1,9 @@
+CREATE TABLE supplier_price_medians (
+  id int(11) NOT NULL AUTO_INCREMENT,
+  supplier varchar(128) NOT NULL COMMENT 'поставщик (название магазина в фиде или имя файла)',
+  median_price double NOT NULL COMMENT 'медианная цена предложений',
+  offers int(11) unsigned NOT NULL COMMENT 'предложений с ценой',
+  run_at timestamp NOT NULL DEFAULT current_timestamp() COMMENT 'время импорта',
+  PRIMARY KEY (id) USING BTREE,
+  KEY supplier (supplier, run_at) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
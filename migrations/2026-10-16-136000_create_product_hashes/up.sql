@@ -0,0 +1,6 @@
+CREATE TABLE product_hashes (
+  hub_stock_id varchar(32) NOT NULL COMMENT 'id оригинала товара',
+  offer_hash char(40) NOT NULL COMMENT 'хеш полей предложения на момент последней синхронизации',
+  updated_at timestamp NOT NULL DEFAULT current_timestamp() ON UPDATE current_timestamp(),
+  PRIMARY KEY (hub_stock_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
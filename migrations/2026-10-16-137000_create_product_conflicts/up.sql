@@ -0,0 +1,12 @@
+CREATE TABLE product_conflicts (
+  product_id int(11) NOT NULL COMMENT 'товар',
+  field varchar(32) NOT NULL COMMENT 'поле товара',
+  offer_id varchar(32) NOT NULL COMMENT 'id товара в системе',
+  change_class varchar(16) NOT NULL COMMENT 'что изменилось с прошлого импорта: feed, manual или both',
+  db_value text NOT NULL COMMENT 'значение в базе',
+  feed_value text NOT NULL COMMENT 'значение из фида',
+  import_id varchar(36) NOT NULL COMMENT 'импорт, обнаруживший конфликт',
+  created_at timestamp NOT NULL DEFAULT current_timestamp() ON UPDATE current_timestamp(),
+  PRIMARY KEY (product_id, field) USING BTREE,
+  KEY import_id (import_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,7 @@
+CREATE TABLE category_stats (
+  category_id int(11) NOT NULL COMMENT 'категория',
+  available_products int(11) unsigned NOT NULL COMMENT 'активных товаров в наличии',
+  total_products int(11) unsigned NOT NULL COMMENT 'всего товаров',
+  updated_at timestamp NOT NULL DEFAULT current_timestamp() ON UPDATE current_timestamp() COMMENT 'время пересчёта',
+  PRIMARY KEY (category_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,2 @@
+ALTER TABLE price_history
+  ADD COLUMN feed_price float DEFAULT NULL COMMENT 'цена в фиде до сглаживания' AFTER new_price;
@@ -0,0 +1,7 @@
+CREATE TABLE product_relations (
+  product_id int(11) NOT NULL COMMENT 'id товара в базе',
+  related_product_id int(11) NOT NULL COMMENT 'id связанного товара',
+  type enum('accessory','similar') NOT NULL COMMENT 'тип связи: аксессуар или похожий товар',
+  PRIMARY KEY (product_id, related_product_id, type) USING BTREE,
+  KEY related_product_id (related_product_id) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
@@ -0,0 +1,19 @@
+-- UUIDs have no numeric form, rows of the runs after the migration get 0
+UPDATE price_history SET run_id = '0' WHERE run_id NOT REGEXP '^[0-9]+$';
+ALTER TABLE price_history
+  MODIFY COLUMN run_id bigint(20) NOT NULL COMMENT 'идентификатор импорта';
+UPDATE import_quarantine SET run_id = '0' WHERE run_id NOT REGEXP '^[0-9]+$';
+ALTER TABLE import_quarantine
+  MODIFY COLUMN run_id bigint(20) NOT NULL COMMENT 'идентификатор импорта';
+UPDATE product_stocks SET run_id = '0' WHERE run_id NOT REGEXP '^[0-9]+$';
+ALTER TABLE product_stocks
+  MODIFY COLUMN run_id bigint(20) NOT NULL COMMENT 'идентификатор импорта, в котором обновлен остаток';
+UPDATE product_variants SET run_id = '0' WHERE run_id NOT REGEXP '^[0-9]+$';
+ALTER TABLE product_variants
+  MODIFY COLUMN run_id bigint(20) NOT NULL COMMENT 'идентификатор импорта, в котором обновлен вариант';
+UPDATE product_channels SET run_id = '0' WHERE run_id NOT REGEXP '^[0-9]+$';
+ALTER TABLE product_channels
+  MODIFY COLUMN run_id bigint(20) NOT NULL COMMENT 'запуск импорта, изменивший видимость';
+UPDATE product_original_prices SET run_id = '0' WHERE run_id NOT REGEXP '^[0-9]+$';
+ALTER TABLE product_original_prices
+  MODIFY COLUMN run_id bigint(20) NOT NULL COMMENT 'запуск импорта, пересчитавший цену';
@@ -0,0 +1,13 @@
+-- run-tagged rows are joined with import_runs.import_id, ids of the earlier runs stay as numbers
+ALTER TABLE price_history
+  MODIFY COLUMN run_id char(36) NOT NULL COMMENT 'id импорта (import_runs.import_id)';
+ALTER TABLE import_quarantine
+  MODIFY COLUMN run_id char(36) NOT NULL COMMENT 'id импорта (import_runs.import_id)';
+ALTER TABLE product_stocks
+  MODIFY COLUMN run_id char(36) NOT NULL COMMENT 'id импорта, в котором обновлен остаток (import_runs.import_id)';
+ALTER TABLE product_variants
+  MODIFY COLUMN run_id char(36) NOT NULL COMMENT 'id импорта, в котором обновлен вариант (import_runs.import_id)';
+ALTER TABLE product_channels
+  MODIFY COLUMN run_id char(36) NOT NULL COMMENT 'id импорта, изменившего видимость (import_runs.import_id)';
+ALTER TABLE product_original_prices
+  MODIFY COLUMN run_id char(36) NOT NULL COMMENT 'id импорта, пересчитавшего цену (import_runs.import_id)';
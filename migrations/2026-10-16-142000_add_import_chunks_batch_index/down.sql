@@ -0,0 +1 @@
+ALTER TABLE import_chunks DROP COLUMN batch_index;
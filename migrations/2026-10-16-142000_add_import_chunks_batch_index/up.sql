@@ -0,0 +1,2 @@
+ALTER TABLE import_chunks
+  ADD COLUMN batch_index int(10) unsigned NOT NULL DEFAULT 0 COMMENT 'номер части чанка, разбитого из-за больших описаний' AFTER chunk_index;
@@ -0,0 +1,19 @@
+CREATE TABLE product_original_prices (
+  hub_stock_id varchar(32) NOT NULL COMMENT 'id оригинала товара',
+  price_original float NOT NULL COMMENT 'цена из фида в валюте поставщика',
+  oldprice_original float DEFAULT NULL COMMENT 'старая цена из фида в валюте поставщика',
+  currency_original varchar(3) NOT NULL COMMENT 'валюта поставщика',
+  rate float NOT NULL COMMENT 'курс, по которому цена пересчитана в гривны',
+  run_id char(36) NOT NULL COMMENT 'id импорта, пересчитавшего цену (import_runs.import_id)',
+  updated_at timestamp NOT NULL DEFAULT current_timestamp() ON UPDATE current_timestamp(),
+  PRIMARY KEY (hub_stock_id) USING BTREE,
+  KEY currency_original (currency_original) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8;
+INSERT INTO product_original_prices (hub_stock_id, price_original, oldprice_original, currency_original, rate, run_id)
+  SELECT hub_stock_id, price_original, oldprice_original, currency_original, currency_rate, COALESCE(last_import_id, '0')
+  FROM products WHERE currency_original IS NOT NULL AND hub_stock_id IS NOT NULL;
+ALTER TABLE products
+  DROP COLUMN price_original,
+  DROP COLUMN oldprice_original,
+  DROP COLUMN currency_original,
+  DROP COLUMN currency_rate;
@@ -0,0 +1,10 @@
+-- original prices are kept next to the converted ones, the finance audit reads them without a join
+ALTER TABLE products
+  ADD COLUMN price_original float DEFAULT NULL COMMENT 'цена из фида в валюте поставщика',
+  ADD COLUMN oldprice_original float DEFAULT NULL COMMENT 'старая цена из фида в валюте поставщика',
+  ADD COLUMN currency_original varchar(3) DEFAULT NULL COMMENT 'валюта поставщика, NULL если цена не пересчитана',
+  ADD COLUMN currency_rate float DEFAULT NULL COMMENT 'курс, по которому цена пересчитана в гривны';
+UPDATE products p JOIN product_original_prices o ON o.hub_stock_id = p.hub_stock_id
+  SET p.price_original = o.price_original, p.oldprice_original = o.oldprice_original,
+    p.currency_original = o.currency_original, p.currency_rate = o.rate;
+DROP TABLE product_original_prices;
@@ -0,0 +1,121 @@
+//! RabbitMQ publisher of `--publish amqp://...` (`amqps://` for TLS) built on lapin,
+//! every published message must be confirmed by the broker.
+
+use std::future::Future;
+use std::time::Duration;
+
+use async_io::{block_on, Timer};
+use futures_lite::future;
+
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties};
+use lapin::options::{BasicPublishOptions, ConfirmSelectOptions};
+use lapin::publisher_confirm::Confirmation;
+
+use url::Url;
+
+use crate::error::Error;
+
+/// Time to connect to the broker
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time the broker has to confirm a published batch
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+/// Messages survive a restart of the broker if the queue is durable
+const PERSISTENT: u8 = 2;
+
+fn amqp_error<M: Into<String>>(message: M) -> Error {
+    Error::external("amqp", message)
+}
+
+/// Runs the future to completion on the current thread failing when it takes longer than the timeout
+fn with_timeout<T>(
+    timeout: Duration, action: &str, f: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    block_on(future::or(f, async {
+        Timer::after(timeout).await;
+        Err(amqp_error(format!("Timed out after {:?} waiting to {}", timeout, action)))
+    }))
+}
+
+pub(crate) struct AmqpPublisher {
+    connection: Connection,
+    channel: Channel,
+}
+
+impl AmqpPublisher {
+    /// Connects to the broker and opens a channel in the confirm mode
+    pub fn connect(url: &Url) -> Result<AmqpPublisher, Error> {
+        with_timeout(CONNECT_TIMEOUT, "connect", async {
+            let connection = Connection::connect(url.as_str(), ConnectionProperties::default()).await?;
+            let channel = connection.create_channel().await?;
+            channel.confirm_select(ConfirmSelectOptions::default()).await?;
+            Ok(AmqpPublisher { connection, channel })
+        })
+    }
+
+    /// Publishes persistent messages and waits for the broker to confirm them. Messages that are nacked
+    /// or returned as unroutable, e.g. when the queue does not exist, fail the batch.
+    pub fn publish<'a>(
+        &mut self, exchange: &str, routing_key: &str, messages: impl Iterator<Item = &'a [u8]>,
+    ) -> Result<(), Error> {
+        let channel = &self.channel;
+        with_timeout(CONFIRM_TIMEOUT, "publish confirms", async {
+            let options = BasicPublishOptions { mandatory: true, ..Default::default() };
+            let mut confirms = vec!();
+            for message in messages {
+                confirms.push(
+                    channel.basic_publish(
+                        exchange, routing_key, options, message,
+                        BasicProperties::default().with_delivery_mode(PERSISTENT),
+                    ).await?
+                );
+            }
+            for confirm in confirms {
+                check_confirmation(confirm.await?)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Drop for AmqpPublisher {
+    fn drop(&mut self) {
+        // unconfirmed messages are already reported by publish, a failed close loses nothing
+        let _ = with_timeout(CONNECT_TIMEOUT, "close connection", async {
+            Ok(self.connection.close(200, "OK").await?)
+        });
+    }
+}
+
+fn check_confirmation(confirmation: Confirmation) -> Result<(), Error> {
+    match confirmation {
+        Confirmation::Ack(None) => Ok(()),
+        Confirmation::Ack(Some(returned)) | Confirmation::Nack(Some(returned)) => Err(amqp_error(format!(
+            "Message was returned by the broker: {} {}", returned.reply_code, returned.reply_text
+        ))),
+        Confirmation::Nack(None) => Err(amqp_error("Message was rejected by the broker")),
+        Confirmation::NotRequested => Err(amqp_error("Channel is not in the confirm mode")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_confirmation() {
+        assert!(check_confirmation(Confirmation::Ack(None)).is_ok());
+        assert_eq!(
+            check_confirmation(Confirmation::Nack(None)).unwrap_err().to_string(),
+            "amqp error: Message was rejected by the broker"
+        );
+        assert!(check_confirmation(Confirmation::NotRequested).is_err());
+    }
+
+    #[test]
+    fn test_with_timeout() {
+        let err = with_timeout(Duration::from_millis(10), "test", future::pending::<Result<(), Error>>())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "amqp error: Timed out after 10ms waiting to test");
+        assert_eq!(with_timeout(Duration::from_secs(1), "test", async { Ok(1) }).unwrap(), 1);
+    }
+}
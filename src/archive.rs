@@ -0,0 +1,22 @@
+use log::info;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::input;
+
+/// Copies the imported feed into the archive directory as `<import id>-<file name>`
+/// so `verify` can re-read it later, returns the path of the copy
+pub(crate) fn save(dir: &Path, file_path: &Path, import_id: &str) -> Result<PathBuf, Error> {
+    if input::is_index(file_path)? {
+        return Err(Error::config("--archive-feeds does not support index files of feed parts"));
+    }
+    fs::create_dir_all(dir)
+        .map_err(|e| Error::config_caused_by(format!("Cannot create archive directory {}", dir.display()), e))?;
+    let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    let archive_path = dir.join(format!("{}-{}", import_id, file_name));
+    fs::copy(file_path, &archive_path)?;
+    info!("Archived the feed into {}", archive_path.display());
+    Ok(archive_path)
+}
@@ -0,0 +1,144 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{Timestamp, Varchar};
+
+use log::info;
+
+use std::time::Instant;
+
+use crate::{Opts, ProcessedStat};
+use crate::context::ImportContext;
+use crate::error::Error;
+use crate::models;
+use crate::offer_hashes;
+use crate::parser::parse_products;
+use crate::store::ProductStore;
+use crate::schema::available_offers;
+use crate::staging::{hold_unavailable, locked, missing_offers, reset_unavailable_runs, UnavailableOffers};
+
+/// Options that need products to be compared one by one and cannot be used with `--available-only`
+pub(crate) fn check_opts(opts: &Opts) -> Result<(), Error> {
+    let incompatible = [
+        ("--update-price", opts.update_price),
+        ("--clear-missing-oldprice", opts.clear_missing_oldprice),
+        ("--insert-new", opts.insert_new),
+        ("--staging", opts.staging),
+        ("--price-history", opts.price_history),
+        ("--optimistic-locking", opts.optimistic_locking),
+        ("--publish", opts.publishes()),
+        ("--search-url", opts.search_url.is_some()),
+        ("--idempotency-key", opts.idempotency_key.is_some()),
+        ("--respect-reservations", opts.respect_reservations),
+        ("--reactivated-out", opts.reactivated_out.is_some()),
+        ("--changes-csv", opts.changes_csv.is_some()),
+        ("--low-stock-threshold", opts.low_stock_threshold.is_some()),
+        ("--convert-prices", opts.convert_prices),
+        ("--discontinued-categories", opts.discontinued_categories),
+        ("--discontinued-category", !opts.discontinued_category.is_empty()),
+        ("--apply-deletes", opts.apply_deletes.is_some()),
+        ("--update-relations", opts.update_relations),
+        ("--resolve", !opts.resolve.is_empty()),
+    ];
+    for (option, is_set) in incompatible.iter() {
+        if *is_set {
+            return Err(Error::config(format!("{} cannot be used with --available-only", option)));
+        }
+    }
+    Ok(())
+}
+
+/// Refreshes only the availability: offer ids with their availability are loaded into
+/// a temporary table and products are updated with a single joined `UPDATE`
+/// without selecting and comparing them.
+pub(crate) fn parse_offers(ctx: &ImportContext, conn: &mut MysqlConnection) -> Result<ProcessedStat, Error> {
+    let opts = ctx.opts;
+    let date_processed = ctx.date_processed;
+    let start_processing_at = Instant::now();
+    let mut stat = ProcessedStat {
+        import_id: ctx.import_id.clone(),
+        ..Default::default()
+    };
+
+    diesel::sql_query(
+        "CREATE TEMPORARY TABLE IF NOT EXISTS available_offers \
+         (hub_stock_id VARCHAR(255) NOT NULL PRIMARY KEY, available TINYINT NOT NULL)"
+    )
+        .execute(conn)?;
+    diesel::sql_query("TRUNCATE TABLE available_offers")
+        .execute(conn)?;
+
+    parse_products(
+        ctx, &mut stat,
+        |products_bucket, stat| {
+            load_bucket(conn, &products_bucket)?;
+            stat.synced_products += products_bucket.len() as u32;
+            Ok(())
+        }
+    )?;
+    stat.parse_duration = start_processing_at.elapsed();
+
+    let start_updating_at = Instant::now();
+    let unavailable = match opts.available_hysteresis {
+        Some(hysteresis) => hold_unavailable(conn, "available_offers", hysteresis, &mut stat.postponed_unavailable)?,
+        None => UnavailableOffers::default(),
+    };
+    let updated = diesel::sql_query(format!(
+        "UPDATE products p JOIN available_offers a ON a.hub_stock_id = p.hub_stock_id \
+         SET p.available = a.available, p.renew_date = ?, p.to_renew = 1, p.version = p.version + 1, \
+         p.last_import_id = ? \
+         WHERE NOT (p.available <=> a.available) AND NOT {}",
+        locked("available")
+    ))
+        .bind::<Timestamp, _>(&date_processed)
+        .bind::<Varchar, _>(&stat.import_id)
+        .execute(conn)?;
+    stat.updated_available = updated as u32;
+    unavailable.mark(conn, opts, &stat.import_id)?;
+    reset_unavailable_runs(conn, "available_offers")?;
+    stat.update_duration = start_updating_at.elapsed();
+    info!("Updated availability of {} products in {:?}", updated, stat.update_duration);
+
+    let file_ids = ctx.supplier_file_ids();
+    if opts.mark_missing_unavailable && !file_ids.is_empty() {
+        let start_mark_missing_at = Instant::now();
+        let file_ids = file_ids.iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        stat.marked_as_unavailable = missing_offers(conn, "available_offers", &file_ids)?
+            .mark(conn, opts, &stat.import_id)?;
+        stat.mark_missing_duration = start_mark_missing_at.elapsed();
+    }
+
+    // availability changed outside of the full sync, the offers must be compared again,
+    // `--skip-unchanged` is accepted here only to keep the hashes of the full syncs right
+    if offer_hashes::is_tracked(opts) {
+        diesel::sql_query(
+            "DELETE h FROM product_hashes h JOIN products p ON p.hub_stock_id = h.hub_stock_id \
+             WHERE p.last_import_id = ?"
+        )
+            .bind::<Varchar, _>(&stat.import_id)
+            .execute(conn)?;
+    }
+
+    diesel::sql_query("DROP TEMPORARY TABLE IF EXISTS available_offers")
+        .execute(conn)?;
+    conn.set_processed_date(&date_processed)?;
+
+    stat.total_duration = start_processing_at.elapsed();
+    Ok(stat)
+}
+
+fn load_bucket(conn: &mut MysqlConnection, products: &[models::NewProduct]) -> Result<(), Error> {
+    let rows = products.iter()
+        .map(|p| (
+            available_offers::hub_stock_id.eq(&p.hub_stock_id),
+            available_offers::available.eq(p.available),
+        ))
+        .collect::<Vec<_>>();
+    // the last offer wins for duplicated ids
+    diesel::replace_into(available_offers::table)
+        .values(&rows)
+        .execute(conn)?;
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use log::info;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::Opts;
+use crate::error::Error;
+use crate::models::{NewCategory, NewProduct};
+use crate::schema::{categories, discontinued_categories};
+use crate::store::{mysql_read_connection, ProductStore};
+
+/// Separator of the category names in `products.category_path`
+const PATH_SEPARATOR: &str = " > ";
+/// Protects from cycles in `parentId` references
+const MAX_PATH_DEPTH: usize = 32;
+
+/// Category tree from the feed's `<categories>` section
+#[derive(Default)]
+pub(crate) struct FeedCategories {
+    pub names: HashMap<i32, String>,
+    pub parents: HashMap<i32, i32>,
+}
+
+impl FeedCategories {
+    /// Full path of the category from the root, e.g. `Electronics > Phones > Smartphones`
+    pub fn path(&self, id: i32) -> Option<String> {
+        let mut names = vec!(self.names.get(&id)?.as_str());
+        let mut current_id = id;
+        while let Some(parent_id) = self.parents.get(&current_id) {
+            match self.names.get(parent_id) {
+                Some(name) if names.len() < MAX_PATH_DEPTH => names.push(name),
+                _ => break,
+            }
+            current_id = *parent_id;
+        }
+        names.reverse();
+        Some(names.join(PATH_SEPARATOR))
+    }
+}
+
+/// Creates placeholder categories for offers that reference unknown `categoryId` values.
+pub(crate) struct CategoryCreator {
+    known_ids: HashSet<i32>,
+    /// Category names from the feed's `<categories>` section
+    feed_names: HashMap<i32, String>,
+    parent_id: Option<i32>,
+}
+
+impl CategoryCreator {
+    pub fn load(
+        conn: &mut MysqlConnection, feed_categories: FeedCategories, parent_id: Option<i32>,
+    ) -> Result<CategoryCreator, Error> {
+        let known_ids = categories::table
+            .select(categories::id)
+            .load::<i32>(conn)?
+            .into_iter()
+            .collect();
+        Ok(CategoryCreator { known_ids, feed_names: feed_categories.names, parent_id })
+    }
+
+    /// Inserts missing categories of the products. Returns a number of created categories.
+    pub fn create_missing(
+        &mut self, conn: &mut MysqlConnection, products: &[NewProduct],
+    ) -> Result<u32, Error> {
+        let mut new_categories = vec!();
+        for p in products {
+            if self.known_ids.insert(p.categoryId) {
+                let name = self.feed_names.get(&p.categoryId)
+                    .cloned()
+                    .unwrap_or_else(|| format!("Category {}", p.categoryId));
+                info!("Creating category {}: {}", p.categoryId, name);
+                new_categories.push(NewCategory {
+                    id: p.categoryId,
+                    parent_id: self.parent_id,
+                    name,
+                    auto_created: 1,
+                });
+            }
+        }
+        if !new_categories.is_empty() {
+            diesel::insert_or_ignore_into(categories::table)
+                .values(&new_categories)
+                .execute(conn)?;
+        }
+        Ok(new_categories.len() as u32)
+    }
+}
+
+/// Categories of the products withdrawn from the assortment: `--discontinued-category` ids
+/// and, with `--discontinued-categories`, the ones from discontinued_categories table
+pub(crate) fn load_discontinued(
+    store: &mut dyn ProductStore, opts: &Opts, products: &[NewProduct],
+) -> Result<HashSet<i32>, Error> {
+    let mut discontinued = opts.discontinued_category.iter()
+        .copied()
+        .collect::<HashSet<_>>();
+    if opts.discontinued_categories {
+        let category_ids = products.iter()
+            .map(|p| p.categoryId)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        discontinued.extend(
+            discontinued_categories::table
+                .select(discontinued_categories::category_id)
+                .filter(discontinued_categories::category_id.eq_any(category_ids))
+                .load::<i32>(mysql_read_connection(store, "--discontinued-categories")?)?
+        );
+    }
+    Ok(discontinued)
+}
@@ -0,0 +1,36 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+use diesel::sql_types::Varchar;
+
+use crate::error::Error;
+use crate::models::STATUS_ACTIVE;
+use crate::schema::category_stats;
+
+/// Categories of category_stats table after the refresh
+#[derive(Debug, Default)]
+pub(crate) struct CategoryStatsSummary {
+    pub categories: u32,
+    /// Categories without available active products, storefronts hide them
+    pub empty: u32,
+}
+
+/// Recomputes available and total products of every category in one aggregated pass.
+/// The table is replaced in a transaction, so categories left without products disappear from it
+/// and readers never see it half-filled.
+pub(crate) fn refresh(conn: &mut MysqlConnection) -> Result<CategoryStatsSummary, Error> {
+    conn.transaction::<_, Error, _>(|conn| {
+        diesel::delete(category_stats::table)
+            .execute(conn)?;
+        let categories = diesel::sql_query(
+            "INSERT INTO category_stats (category_id, available_products, total_products) \
+             SELECT categoryId, SUM(available = 1 AND status = ?), COUNT(*) FROM products GROUP BY categoryId"
+        )
+            .bind::<Varchar, _>(STATUS_ACTIVE)
+            .execute(conn)?;
+        let empty = category_stats::table
+            .filter(category_stats::available_products.eq(0))
+            .count()
+            .get_result::<i64>(conn)?;
+        Ok(CategoryStatsSummary { categories: categories as u32, empty: empty as u32 })
+    })
+}
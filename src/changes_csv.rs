@@ -0,0 +1,60 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::error::Error;
+use crate::locale::ReportLocale;
+use crate::process::ProductChange;
+use crate::reactivation::csv_field;
+
+/// Fractional fields written with the decimal separator of `--report-locale`
+const PRICE_FIELDS: &[&str] = &["price", "oldprice"];
+
+/// Audit file of the changes applied by the run (`--changes-csv`): a row per changed field
+/// of the updated products and per field of the inserted ones
+pub(crate) struct ChangesCsv {
+    out: BufWriter<File>,
+    locale: ReportLocale,
+}
+
+impl ChangesCsv {
+    pub fn create(path: &Path, locale: ReportLocale) -> Result<ChangesCsv, Error> {
+        let file = File::create(path)
+            .map_err(|e| Error::config_caused_by(format!("Cannot create changes file: {}", path.display()), e))?;
+        let mut out = BufWriter::new(file);
+        writeln!(out, "offer_id,product_id,change,field,old,new")?;
+        Ok(ChangesCsv { out, locale })
+    }
+
+    /// Returns a number of written rows
+    pub fn write(&mut self, changes: &[ProductChange]) -> Result<u32, Error> {
+        let mut rows = 0;
+        for change in changes {
+            let product_id = change.product_id.map(|id| id.to_string()).unwrap_or_default();
+            for (i, (field, value)) in change.values.iter().enumerate() {
+                let old = change.old_values.get(i).map_or("", String::as_str);
+                let (old, value) = if PRICE_FIELDS.contains(field) {
+                    (self.locale.csv_number(old), self.locale.csv_number(value))
+                } else {
+                    (old.to_string(), value.clone())
+                };
+                writeln!(
+                    self.out, "{},{},{},{},{},{}",
+                    csv_field(&change.offer_id),
+                    product_id,
+                    change.kind.as_str(),
+                    field,
+                    csv_field(&old),
+                    csv_field(&value),
+                )?;
+                rows += 1;
+            }
+        }
+        Ok(rows)
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
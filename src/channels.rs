@@ -0,0 +1,26 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use crate::error::Error;
+use crate::models::{NewProduct, NewProductChannel};
+use crate::schema::product_channels;
+
+/// Saves visibility of the products in the sales channel, returns a number of hidden products
+pub(crate) fn save_visibility(
+    conn: &mut MysqlConnection, channel: &str, products: &[NewProduct], import_id: &str,
+) -> Result<u32, Error> {
+    let rows = products.iter()
+        .map(|p| NewProductChannel {
+            hub_stock_id: &p.hub_stock_id,
+            channel,
+            visible: p.channel_visible as i8,
+            run_id: import_id,
+        })
+        .collect::<Vec<_>>();
+    if !rows.is_empty() {
+        diesel::replace_into(product_channels::table)
+            .values(&rows)
+            .execute(conn)?;
+    }
+    Ok(products.iter().filter(|p| !p.channel_visible).count() as u32)
+}
@@ -0,0 +1,367 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use std::env;
+use std::error::Error as _;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{establish_mysql_connection, Opts};
+use crate::availability;
+use crate::database;
+use crate::error::Error;
+use crate::fetch;
+use crate::ledger;
+use crate::normalize::NameNormalizer;
+use crate::offer_hashes;
+use crate::pim::{self, PimStore};
+use crate::pricing::PricingRules;
+use crate::rules::Rules;
+use crate::schema::{
+    categories, category_stats, discontinued_categories, import_chunks, import_runs, possible_duplicates, price_history,
+    product_channels, product_conflicts, product_hashes, product_keywords, product_relations, product_stocks,
+    product_variants, products, reservations, supplier_feed_fields, supplier_feed_stats, supplier_price_medians,
+};
+use crate::shops::ShopSuppliers;
+use crate::store::Target;
+use crate::{sql_out, staging};
+
+/// Checks of `config check` subcommand, every failed check is printed
+/// and the run continues with the next one
+#[derive(Default)]
+struct Checks {
+    total: u32,
+    failed: u32,
+}
+
+impl Checks {
+    fn check<F>(&mut self, name: &str, f: F)
+    where
+        F: FnOnce() -> Result<(), Error>,
+    {
+        self.total += 1;
+        match f() {
+            Ok(()) => println!("ok      {}", name),
+            Err(e) => {
+                self.failed += 1;
+                println!("FAILED  {}: {}", name, e);
+                let mut source = e.source();
+                while let Some(cause) = source {
+                    println!("        caused by: {}", cause);
+                    source = cause.source();
+                }
+            }
+        }
+    }
+
+    fn result(self, message: &str) -> Result<(), Error> {
+        println!("{} of {} checks failed", self.failed, self.total);
+        if self.failed > 0 {
+            return Err(Error::config(message));
+        }
+        Ok(())
+    }
+}
+
+/// Validates options, referenced files, database connectivity and tables used by the options
+/// (or the PIM token with `--target pim`) without importing anything
+pub(crate) fn check_config(opts: &Opts) -> Result<(), Error> {
+    let mut checks = Checks::default();
+    let mut conn = check_common(&mut checks, opts);
+    if let Some(ref mut conn) = conn {
+        check_tables(&mut checks, conn, opts);
+    }
+    checks.result("Configuration is invalid")
+}
+
+/// Readiness probe of the daemon: the checks of `config check`, permission to write products
+/// and free disk space for downloaded feeds and output files
+pub(crate) fn healthcheck(opts: &Opts, min_free_space: u64) -> Result<(), Error> {
+    let mut checks = Checks::default();
+    let mut conn = check_common(&mut checks, opts);
+    if let Some(ref mut conn) = conn {
+        check_tables(&mut checks, conn, opts);
+        checks.check("write permission", || check_write_permission(conn));
+    }
+    for dir in output_dirs(opts) {
+        checks.check(&format!("free space in {}", dir.display()), || check_free_space(&dir, min_free_space));
+    }
+    checks.result("Health check failed")
+}
+
+/// Checks the options, referenced files and connection to the store,
+/// returns the connection when MySQL is used
+fn check_common(checks: &mut Checks, opts: &Opts) -> Option<MysqlConnection> {
+    checks.check("options", || {
+        if opts.staging {
+            staging::check_opts(opts)?;
+        }
+        if opts.sql_out.is_some() {
+            sql_out::check_opts(opts)?;
+        }
+        if opts.idempotency_key.is_some() {
+            ledger::check_opts(opts)?;
+        }
+        if opts.available_only {
+            availability::check_opts(opts)?;
+        }
+        if opts.target == Target::Pim {
+            pim::check_opts(opts)?;
+        }
+        Ok(())
+    });
+    if let Some(ref file_path) = opts.file_path {
+        if !file_path.to_str().is_some_and(fetch::is_remote) {
+            checks.check("feed file", || check_readable(file_path));
+        }
+    }
+    if let Some(ref path) = opts.rules {
+        checks.check("rules", || Rules::load(path).map(|_| ()));
+    }
+    if let Some(ref path) = opts.channel_filter {
+        checks.check("channel filter", || Rules::load(path).map(|_| ()));
+    }
+    if let Some(ref path) = opts.shop_suppliers {
+        checks.check("shop suppliers", || ShopSuppliers::load(path).map(|_| ()));
+    }
+    if opts.normalize_names {
+        checks.check("banned words", || {
+            NameNormalizer::new(opts.banned_words.as_deref(), opts.max_name_length).map(|_| ())
+        });
+    }
+    if let Some(ref cmd) = opts.transform_cmd {
+        checks.check("transform command", || check_command(cmd));
+    }
+
+    if let (Target::Pim, Some(url)) = (opts.target, &opts.pim_url) {
+        checks.check("PIM token", || PimStore::new(url).map(|_| ()));
+        None
+    } else {
+        let mut conn = None;
+        checks.check("database connection", || {
+            conn = Some(establish_mysql_connection(&database::database_url(opts)?)?);
+            Ok(())
+        });
+        conn
+    }
+}
+
+/// Selects all the columns the import uses, so missing tables and migrations are reported
+fn check_tables(checks: &mut Checks, conn: &mut MysqlConnection, opts: &Opts) {
+    checks.check("products table", || {
+        products::table.select(products::all_columns).limit(0).execute(conn)?;
+        Ok(())
+    });
+    if opts.price_history {
+        checks.check("price_history table", || {
+            price_history::table.select(price_history::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.find_duplicates {
+        checks.check("possible_duplicates table", || {
+            possible_duplicates::table.select(possible_duplicates::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.aggregate_stocks {
+        checks.check("product_stocks table", || {
+            product_stocks::table.select(product_stocks::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.respect_reservations {
+        checks.check("reservations table", || {
+            reservations::table.select(reservations::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.discontinued_categories {
+        checks.check("discontinued_categories table", || {
+            discontinued_categories::table.select(discontinued_categories::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.convert_prices {
+        checks.check("products original price columns", || {
+            products::table
+                .select((products::price_original, products::currency_original, products::currency_rate))
+                .limit(0)
+                .execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.channel.is_some() {
+        checks.check("product_channels table", || {
+            product_channels::table.select(product_channels::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.update_keywords {
+        checks.check("product_keywords table", || {
+            product_keywords::table.select(product_keywords::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.group_variants {
+        checks.check("product_variants table", || {
+            product_variants::table.select(product_variants::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.update_relations {
+        checks.check("product_relations table", || {
+            product_relations::table.select(product_relations::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.idempotency_key.is_some() {
+        checks.check("import_chunks table", || {
+            import_chunks::table.select(import_chunks::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.track_runs {
+        checks.check("import_runs table", || {
+            import_runs::table.select(import_runs::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.track_suppliers {
+        checks.check("supplier_feed_stats table", || {
+            supplier_feed_stats::table.select(supplier_feed_stats::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.track_feed_fields {
+        checks.check("supplier_feed_fields table", || {
+            supplier_feed_fields::table.select(supplier_feed_fields::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if offer_hashes::is_tracked(opts) {
+        checks.check("product_hashes table", || {
+            product_hashes::table.select(product_hashes::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if !opts.resolve.is_empty() {
+        checks.check("product_conflicts table", || {
+            product_conflicts::table.select(product_conflicts::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.category_stats {
+        checks.check("category_stats table", || {
+            category_stats::table.select(category_stats::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.check_price_scale {
+        checks.check("supplier_price_medians table", || {
+            supplier_price_medians::table.select(supplier_price_medians::all_columns).limit(0).execute(conn)?;
+            Ok(())
+        });
+    }
+    if opts.create_categories {
+        checks.check("categories table", || {
+            categories::table.select(categories::all_columns).limit(0).execute(conn)?;
+            if let Some(parent_id) = opts.categories_parent {
+                let found = categories::table
+                    .filter(categories::id.eq(parent_id))
+                    .count()
+                    .get_result::<i64>(conn)?;
+                if found == 0 {
+                    return Err(Error::config(format!("Parent category {} does not exist", parent_id)));
+                }
+            }
+            Ok(())
+        });
+    }
+    if opts.pricing_rules {
+        checks.check("pricing rules", || PricingRules::load(conn).map(|_| ()));
+    }
+}
+
+fn check_file(path: &Path) -> Result<(), Error> {
+    if !path.is_file() {
+        return Err(Error::config(format!("File does not exist: {}", path.display())));
+    }
+    Ok(())
+}
+
+fn check_readable(path: &Path) -> Result<(), Error> {
+    check_file(path)?;
+    File::open(path)
+        .map_err(|e| Error::config_caused_by(format!("Cannot read file: {}", path.display()), e))?;
+    Ok(())
+}
+
+/// Updates no rows in a transaction, fails without the permission to update products
+fn check_write_permission(conn: &mut MysqlConnection) -> Result<(), Error> {
+    conn.transaction(|conn| {
+        diesel::update(products::table.filter(products::id.eq(-1)))
+            .set(products::id.eq(products::id))
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+/// Temporary directory of the downloaded feeds and directories of the output files
+fn output_dirs(opts: &Opts) -> Vec<PathBuf> {
+    let mut dirs = vec!(env::temp_dir());
+    let outputs = [&opts.sql_out, &opts.changes_csv, &opts.reactivated_out];
+    for path in outputs.iter().filter_map(|path| path.as_deref()) {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    // the archive receives a copy of every feed
+    if let Some(ref dir) = opts.archive_feeds {
+        if !dirs.contains(dir) {
+            dirs.push(dir.clone());
+        }
+    }
+    dirs
+}
+
+/// Available space is taken from `df -Pk` output:
+/// `Filesystem 1024-blocks Used Available Capacity Mounted on`
+fn check_free_space(dir: &Path, min_free_space: u64) -> Result<(), Error> {
+    let output = Command::new("df").arg("-Pk").arg(dir).output()
+        .map_err(|e| Error::external("df", format!("Cannot run df: {}", e)))?;
+    if !output.status.success() {
+        return Err(Error::external("df", String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let available = stdout.lines()
+        .nth(1)
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb << 10)
+        .ok_or_else(|| Error::external("df", format!("Cannot parse output: {}", stdout.trim())))?;
+    if available < min_free_space {
+        return Err(Error::external(
+            "df", format!("{} MiB available, at least {} MiB are required", available >> 20, min_free_space >> 20)
+        ));
+    }
+    Ok(())
+}
+
+/// Commands without a directory are searched in `PATH` like when they are run
+fn check_command(cmd: &Path) -> Result<(), Error> {
+    if cmd.components().count() > 1 {
+        return check_file(cmd);
+    }
+    let found = env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(cmd).is_file()));
+    if !found {
+        return Err(Error::config(format!("Command is not found in PATH: {}", cmd.display())));
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use chrono::NaiveDateTime;
+
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use std::str::FromStr;
+
+use crate::Opts;
+use crate::error::Error;
+use crate::models::NewProductConflict;
+use crate::offer_hashes::StoredHash;
+use crate::schema::product_conflicts;
+
+/// What changed since the product was last synced: the offer (its hash differs)
+/// or the product (its renew_date is later than the sync). Imports keeping the hashes move the sync time
+/// of the products they write, so their own writes do not make the product manually changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ChangeClass {
+    Feed,
+    Manual,
+    Both,
+}
+
+impl ChangeClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeClass::Feed => "feed",
+            ChangeClass::Manual => "manual",
+            ChangeClass::Both => "both",
+        }
+    }
+}
+
+impl FromStr for ChangeClass {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ChangeClass, Error> {
+        match s {
+            "feed" => Ok(ChangeClass::Feed),
+            "manual" => Ok(ChangeClass::Manual),
+            "both" => Ok(ChangeClass::Both),
+            _ => Err(Error::config(format!("Unknown change class: {}", s))),
+        }
+    }
+}
+
+/// How differences of the class are resolved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ConflictPolicy {
+    /// The product is updated from the feed
+    Feed,
+    /// The product is kept as is
+    Manual,
+    /// The product is kept as is and the differences are written into product_conflicts table
+    Review,
+}
+
+impl ConflictPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Feed => "feed",
+            ConflictPolicy::Manual => "manual",
+            ConflictPolicy::Review => "review",
+        }
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ConflictPolicy, Error> {
+        match s {
+            "feed" => Ok(ConflictPolicy::Feed),
+            "manual" => Ok(ConflictPolicy::Manual),
+            "review" => Ok(ConflictPolicy::Review),
+            _ => Err(Error::config(format!("Unknown conflict policy: {}", s))),
+        }
+    }
+}
+
+/// Parses `--resolve` value like `both=review`
+pub(crate) fn parse_resolution(s: &str) -> Result<(ChangeClass, ConflictPolicy), Error> {
+    let (class, policy) = s.split_once('=')
+        .ok_or_else(|| Error::config(format!("Invalid resolution, expected CLASS=POLICY: {}", s)))?;
+    Ok((class.trim().parse()?, policy.trim().parse()?))
+}
+
+/// Policy of the class, the feed wins unless `--resolve` says otherwise
+pub(crate) fn policy(opts: &Opts, class: ChangeClass) -> ConflictPolicy {
+    opts.resolve.iter().rev()
+        .find(|(c, _)| *c == class)
+        .map(|(_, policy)| *policy)
+        .unwrap_or(ConflictPolicy::Feed)
+}
+
+/// Products that were never synced with a stored hash cannot be classified
+pub(crate) fn classify(
+    stored: Option<&StoredHash>, offer_hash: &str, renew_date: Option<&NaiveDateTime>,
+) -> Option<ChangeClass> {
+    let stored = stored?;
+    let feed_changed = stored.offer_hash != offer_hash;
+    let manually_changed = renew_date.is_some_and(|renew_date| *renew_date > stored.synced_at);
+    match (feed_changed, manually_changed) {
+        (true, true) => Some(ChangeClass::Both),
+        (true, false) => Some(ChangeClass::Feed),
+        (false, true) => Some(ChangeClass::Manual),
+        (false, false) => None,
+    }
+}
+
+/// Products of unchanged offers are not even loaded with `--skip-unchanged`, so their manual changes always win
+pub(crate) fn check_opts(opts: &Opts) -> Result<(), Error> {
+    if opts.skip_unchanged && policy(opts, ChangeClass::Manual) != ConflictPolicy::Manual {
+        return Err(Error::config("--resolve with --skip-unchanged requires manual=manual"));
+    }
+    Ok(())
+}
+
+/// Queues the differences for review, a newer difference of the same field replaces the queued one
+pub(crate) fn queue(conn: &mut MysqlConnection, rows: &[NewProductConflict]) -> Result<(), Error> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    diesel::replace_into(product_conflicts::table)
+        .values(rows)
+        .execute(conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    fn date(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_classify() {
+        let stored = StoredHash { offer_hash: "a".to_string(), synced_at: date("2026-10-16 12:00:00") };
+        let synced = date("2026-10-16 12:00:00");
+        let edited = date("2026-10-16 15:00:00");
+        assert_eq!(classify(None, "a", Some(&edited)), None);
+        assert_eq!(classify(Some(&stored), "a", Some(&synced)), None);
+        assert_eq!(classify(Some(&stored), "a", None), None);
+        assert_eq!(classify(Some(&stored), "b", Some(&synced)), Some(ChangeClass::Feed));
+        assert_eq!(classify(Some(&stored), "a", Some(&edited)), Some(ChangeClass::Manual));
+        assert_eq!(classify(Some(&stored), "b", Some(&edited)), Some(ChangeClass::Both));
+    }
+
+    #[test]
+    fn test_policy() {
+        let opts = Opts::from_iter(&[
+            "hubber_xml", "--resolve", "manual=review", "--resolve", "manual=manual", "feed.xml",
+        ]);
+        assert_eq!(policy(&opts, ChangeClass::Manual), ConflictPolicy::Manual);
+        assert_eq!(policy(&opts, ChangeClass::Both), ConflictPolicy::Feed);
+        assert!(parse_resolution("both").is_err());
+        assert_eq!(parse_resolution(" both = review ").unwrap(), (ChangeClass::Both, ConflictPolicy::Review));
+    }
+}
@@ -0,0 +1,90 @@
+use chrono::{NaiveDateTime, Timelike, Utc};
+
+use std::sync::Mutex;
+
+use uuid::Uuid;
+
+use crate::Opts;
+use crate::error::Error;
+use crate::models::HUBBER_FILE_ID;
+use crate::metrics::MetricsFile;
+use crate::pricing::PricingRules;
+use crate::progress::{Progress, StatSink};
+use crate::rules::Rules;
+use crate::shops::ShopSuppliers;
+use crate::store::{mysql_read_connection, ProductStore};
+
+/// Everything an import needs besides the store: the options, loaded rules and mappings,
+/// the identity of the run and the sinks of its progress and counters. Parsing and syncing functions
+/// receive the context instead of loading their rules from the options, so the rules can also be given
+/// programmatically.
+pub(crate) struct ImportContext<'a> {
+    pub opts: &'a Opts,
+    /// Id of the run written into last_import_id of the changed products
+    pub import_id: String,
+    /// Time of the run written into renew_date of the changed products
+    pub date_processed: NaiveDateTime,
+    /// `--rules` script applied to every offer
+    pub rules: Option<Rules>,
+    /// `--channel-filter` script deciding the visibility in the `--channel`
+    pub channel_filter: Option<Rules>,
+    /// `--pricing-rules` loaded from the database
+    pub pricing_rules: Option<PricingRules>,
+    /// `--shop-suppliers` mapping of the shops to the suppliers
+    pub shop_suppliers: Option<ShopSuppliers>,
+    /// Sinks of the stage progress, the stages of a run report it one after another
+    pub progress: Mutex<Progress>,
+    /// Sinks of the counters of the finished run
+    pub stat_sinks: Vec<Box<dyn StatSink>>,
+}
+
+impl<'a> ImportContext<'a> {
+    /// Context with the rules and mappings read from the files of the options,
+    /// pricing rules need the database and are not loaded
+    pub fn new(opts: &'a Opts) -> Result<ImportContext<'a>, Error> {
+        let rules = match opts.rules {
+            Some(ref path) => Some(Rules::load(path)?),
+            None => None,
+        };
+        let channel_filter = match opts.channel_filter {
+            Some(ref path) => Some(Rules::load(path)?),
+            None => None,
+        };
+        let shop_suppliers = match opts.shop_suppliers {
+            Some(ref path) => Some(ShopSuppliers::load(path)?),
+            None => None,
+        };
+        let mut stat_sinks = Vec::<Box<dyn StatSink>>::new();
+        if let Some(ref path) = opts.metrics_file {
+            stat_sinks.push(Box::new(MetricsFile::new(path, opts.file_path())));
+        }
+        Ok(ImportContext {
+            opts,
+            import_id: Uuid::new_v4().to_string(),
+            date_processed: Utc::now().naive_utc().with_nanosecond(0).unwrap(),
+            rules,
+            channel_filter,
+            pricing_rules: None,
+            shop_suppliers,
+            progress: Mutex::new(Progress::new(opts)),
+            stat_sinks,
+        })
+    }
+
+    /// Context of an import into the store, also loads `--pricing-rules`
+    pub fn load(opts: &'a Opts, store: &mut dyn ProductStore) -> Result<ImportContext<'a>, Error> {
+        let mut ctx = ImportContext::new(opts)?;
+        if opts.pricing_rules {
+            ctx.pricing_rules = Some(PricingRules::load(mysql_read_connection(store, "--pricing-rules")?)?);
+        }
+        Ok(ctx)
+    }
+
+    /// File ids of the suppliers whose products are imported from the feed
+    pub fn supplier_file_ids(&self) -> Vec<i8> {
+        match self.shop_suppliers {
+            Some(ref shop_suppliers) => shop_suppliers.file_ids(),
+            None => vec!(HUBBER_FILE_ID),
+        }
+    }
+}
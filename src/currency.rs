@@ -0,0 +1,136 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{Float, Nullable, Varchar};
+
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::models::NewProduct;
+use crate::schema::products;
+
+/// Currency of prices without `<currencyId>`
+pub(crate) const DEFAULT_CURRENCY: &str = "UAH";
+/// Suppliers round converted prices, so converted prices differing less than this
+/// are the same price
+const TOLERANCE_PERCENT: f32 = 0.5;
+
+/// Parses `USD=41.5`: the rate of the currency to the default one
+pub(crate) fn parse_rate(s: &str) -> Result<(String, f32), Error> {
+    let (currency, rate) = s.split_once('=')
+        .ok_or_else(|| Error::config(format!("Invalid currency rate, expected CUR=RATE: {}", s)))?;
+    let rate = rate.trim().parse::<f32>()
+        .ok()
+        .filter(|rate| *rate > 0.0)
+        .ok_or_else(|| Error::config(format!("Invalid currency rate: {}", s)))?;
+    Ok((currency.trim().to_uppercase(), rate))
+}
+
+fn rate(rates: &[(String, f32)], currency: Option<&str>) -> Option<f32> {
+    let currency = currency.unwrap_or(DEFAULT_CURRENCY);
+    if currency == DEFAULT_CURRENCY {
+        return Some(1.0);
+    }
+    rates.iter()
+        .find(|(c, _)| c == currency)
+        .map(|(_, rate)| *rate)
+}
+
+/// Price converted into the default currency, `None` when the rate is unknown
+pub(crate) fn to_default(rates: &[(String, f32)], price: f32, currency: Option<&str>) -> Option<f32> {
+    rate(rates, currency).map(|rate| price * rate)
+}
+
+/// Whether the prices in different currencies are the same after converting them
+/// into the default currency. Prices are different when a rate is unknown.
+pub(crate) fn same_converted(
+    rates: &[(String, f32)],
+    price: f32, currency: Option<&str>,
+    other_price: f32, other_currency: Option<&str>,
+) -> bool {
+    let (rate, other_rate) = match (rate(rates, currency), rate(rates, other_currency)) {
+        (Some(rate), Some(other_rate)) => (rate, other_rate),
+        _ => return false,
+    };
+    let converted = price * rate;
+    let other_converted = other_price * other_rate;
+    (converted - other_converted).abs() <= converted.abs().max(other_converted.abs()) * TOLERANCE_PERCENT / 100.0
+}
+
+/// Price of the offer in the supplier's currency before the conversion
+#[derive(Clone, Debug, Serialize)]
+pub struct OriginalPrice {
+    pub price: f32,
+    pub oldprice: Option<f32>,
+    pub currency: String,
+    pub rate: f32,
+}
+
+/// Converts the offer's prices into the default currency, returns `None` for prices
+/// in the default currency and when the rate is unknown
+pub(crate) fn convert(
+    rates: &[(String, f32)], price: f32, oldprice: Option<f32>, currency: Option<&str>,
+) -> Option<(f32, Option<f32>, OriginalPrice)> {
+    let currency = currency.filter(|c| *c != DEFAULT_CURRENCY)?;
+    let rate = rate(rates, Some(currency))?;
+    let original = OriginalPrice {
+        price,
+        oldprice,
+        currency: currency.to_string(),
+        rate,
+    };
+    Some((round_price(price * rate), oldprice.map(|p| round_price(p * rate)), original))
+}
+
+fn round_price(price: f32) -> f32 {
+    (price * 100.0).round() / 100.0
+}
+
+/// Saves the original prices of the converted products next to the converted ones and clears them
+/// for the products that are priced in the default currency now. The converted products are updated
+/// with a single statement joining the rows of the bucket sent as a `UNION ALL` query.
+pub(crate) fn save_original_prices(conn: &mut MysqlConnection, products: &[NewProduct]) -> Result<(), Error> {
+    let converted = products.iter()
+        .filter_map(|p| p.original_price.as_ref().map(|original| (&p.hub_stock_id, original)))
+        .collect::<Vec<_>>();
+    if !converted.is_empty() {
+        let row = "SELECT ? AS hub_stock_id, ? AS price_original, ? AS oldprice_original, \
+            ? AS currency_original, ? AS currency_rate";
+        let mut query = diesel::sql_query(format!(
+            "UPDATE products p JOIN ({}) o ON o.hub_stock_id = p.hub_stock_id \
+             SET p.price_original = o.price_original, p.oldprice_original = o.oldprice_original, \
+             p.currency_original = o.currency_original, p.currency_rate = o.currency_rate",
+            vec![row; converted.len()].join(" UNION ALL ")
+        ))
+            .into_boxed();
+        for (hub_stock_id, original) in converted {
+            query = query
+                .bind::<Varchar, _>(hub_stock_id)
+                .bind::<Float, _>(original.price)
+                .bind::<Nullable<Float>, _>(original.oldprice)
+                .bind::<Varchar, _>(&original.currency)
+                .bind::<Float, _>(original.rate);
+        }
+        query.execute(conn)?;
+    }
+    let unconverted_ids = products.iter()
+        .filter(|p| {
+            p.original_price.is_none() && p.currencyId.as_deref().unwrap_or(DEFAULT_CURRENCY) == DEFAULT_CURRENCY
+        })
+        .map(|p| p.hub_stock_id.as_str())
+        .collect::<Vec<_>>();
+    if !unconverted_ids.is_empty() {
+        diesel::update(
+            products::table
+                .filter(products::hub_stock_id.eq_any(unconverted_ids))
+                .filter(products::currency_original.is_not_null())
+        )
+            .set((
+                products::price_original.eq(None::<f32>),
+                products::oldprice_original.eq(None::<f32>),
+                products::currency_original.eq(None::<&str>),
+                products::currency_rate.eq(None::<f32>),
+            ))
+            .execute(conn)?;
+    }
+    Ok(())
+}
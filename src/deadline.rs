@@ -0,0 +1,56 @@
+use log::warn;
+
+use std::time::Duration;
+
+use crate::{CHUNK_SIZE, Opts, ProcessedStat};
+use crate::models::NewProduct;
+use crate::process::ProcessedProducts;
+
+/// Chunks are never reduced below this size
+const MIN_CHUNK_SIZE: usize = 50;
+
+/// Size of the chunks reduced by `--adaptive-chunk-size`, `None` while chunks have the full size
+pub(crate) fn reduced_chunk_size(opts: &Opts, stat: &ProcessedStat) -> Option<usize> {
+    if opts.adaptive_chunk_size {
+        stat.reduced_chunk_size
+    } else {
+        None
+    }
+}
+
+/// Logs the chunk that exceeded `--chunk-deadline` with its timings and offer ids.
+/// With `--adaptive-chunk-size` slow chunks halve the size of the next chunks
+/// and fast ones grow it back.
+pub(crate) fn check_chunk(
+    opts: &Opts,
+    chunk_index: u32,
+    products: &[NewProduct],
+    processed: &ProcessedProducts,
+    stat: &mut ProcessedStat,
+) {
+    let deadline = match opts.chunk_deadline {
+        Some(secs) => Duration::from_secs(secs),
+        None => return,
+    };
+    let chunk_size = stat.reduced_chunk_size.unwrap_or(CHUNK_SIZE);
+    if processed.duration > deadline {
+        stat.slow_chunks += 1;
+        warn!(
+            "Chunk {} of {} products ({} .. {}) took {:?}, longer than {:?}: \
+             select {:?}, update {:?}, insert {:?}",
+            chunk_index, products.len(),
+            products.first().map_or("", |p| p.offer_id.as_str()),
+            products.last().map_or("", |p| p.offer_id.as_str()),
+            processed.duration, deadline,
+            processed.select_duration, processed.update_duration, processed.insert_duration,
+        );
+        if opts.adaptive_chunk_size && chunk_size > MIN_CHUNK_SIZE {
+            let reduced = (chunk_size / 2).max(MIN_CHUNK_SIZE);
+            warn!("Reducing chunk size to {} products", reduced);
+            stat.reduced_chunk_size = Some(reduced);
+        }
+    } else if opts.adaptive_chunk_size && chunk_size < CHUNK_SIZE && processed.duration < deadline / 4 {
+        let grown = chunk_size * 2;
+        stat.reduced_chunk_size = if grown < CHUNK_SIZE { Some(grown) } else { None };
+    }
+}
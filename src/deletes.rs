@@ -0,0 +1,56 @@
+use diesel::prelude::*;
+
+use std::str::FromStr;
+
+use crate::CHUNK_SIZE;
+use crate::error::Error;
+use crate::store::{mysql_connection, ProductStore};
+
+/// What to do with products of the offers the feed marks as deleted
+/// (`<offer deleted="true">` or offers of the `<removed>` section)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum DeleteMode {
+    /// Products are marked as unavailable
+    Soft,
+    /// Products are deleted from the table
+    Hard,
+}
+
+impl FromStr for DeleteMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<DeleteMode, Error> {
+        match s {
+            "soft" => Ok(DeleteMode::Soft),
+            "hard" => Ok(DeleteMode::Hard),
+            _ => Err(Error::config(format!("Unknown delete mode: {}", s))),
+        }
+    }
+}
+
+/// Deactivates or deletes products of the deleted offers among products of the given files,
+/// returns a number of the affected products
+pub(crate) fn apply(
+    store: &mut dyn ProductStore, mode: DeleteMode, offer_ids: &[String], file_ids: &[i8], import_id: &str,
+    forget_hashes: bool,
+) -> Result<u32, Error> {
+    let mut affected = 0;
+    for offer_ids in offer_ids.chunks(CHUNK_SIZE) {
+        affected += match mode {
+            // deleted offers are never coming back so the hysteresis is not applied
+            DeleteMode::Soft => store.mark_missing(offer_ids, file_ids, None, import_id, forget_hashes)?,
+            DeleteMode::Hard => {
+                use crate::schema::products::dsl;
+
+                diesel::delete(
+                    dsl::products.filter(
+                        dsl::hub_stock_id.eq_any(offer_ids)
+                            .and(dsl::file_id.eq_any(file_ids))
+                    )
+                )
+                    .execute(mysql_connection(store, "--apply-deletes hard")?)? as u32
+            }
+        };
+    }
+    Ok(affected)
+}
@@ -0,0 +1,55 @@
+use crate::{Opts, ProcessedStat};
+use crate::models::NewProduct;
+
+/// Number of the largest descriptions reported in the summary
+pub(crate) const LARGEST_DESCRIPTIONS: usize = 5;
+/// Products with oversize descriptions are synced by this number of products
+const LARGE_DESCRIPTION_BATCH: usize = 10;
+
+pub(crate) fn description_bytes(p: &NewProduct) -> usize {
+    p.description.as_ref().map_or(0, |d| d.len())
+}
+
+pub(crate) fn is_large(opts: &Opts, p: &NewProduct) -> bool {
+    description_bytes(p) as u64 >= opts.large_description
+}
+
+/// Buffered descriptions of the chunk reached `--max-chunk-description-bytes`,
+/// so the chunk is synced before it has `CHUNK_SIZE` products
+pub(crate) fn chunk_is_full(opts: &Opts, description_bytes: u64) -> bool {
+    description_bytes >= opts.max_chunk_description_bytes
+}
+
+/// Remembers the largest oversize descriptions of the run
+pub(crate) fn track_largest(opts: &Opts, p: &NewProduct, stat: &mut ProcessedStat) {
+    if !is_large(opts, p) {
+        return;
+    }
+    stat.large_descriptions += 1;
+    let bytes = description_bytes(p);
+    let largest = &mut stat.largest_descriptions;
+    if largest.len() == LARGEST_DESCRIPTIONS && largest.last().is_some_and(|(_, smallest)| *smallest >= bytes) {
+        return;
+    }
+    let position = largest.iter().position(|(_, b)| *b < bytes).unwrap_or(largest.len());
+    largest.insert(position, (p.offer_id.clone(), bytes));
+    largest.truncate(LARGEST_DESCRIPTIONS);
+}
+
+/// Splits the chunk with oversize descriptions so they are written by `LARGE_DESCRIPTION_BATCH` products
+/// and do not make huge statements together with the rest of the chunk. `None` when the chunk can be
+/// synced as is.
+pub(crate) fn split_large(opts: &Opts, products: &[NewProduct]) -> Option<Vec<Vec<NewProduct>>> {
+    let (large, regular): (Vec<_>, Vec<_>) = products.iter()
+        .cloned()
+        .partition(|p| is_large(opts, p));
+    if large.is_empty() || (regular.is_empty() && large.len() <= LARGE_DESCRIPTION_BATCH) {
+        return None;
+    }
+    let mut batches = vec!();
+    if !regular.is_empty() {
+        batches.push(regular);
+    }
+    batches.extend(large.chunks(LARGE_DESCRIPTION_BATCH).map(|batch| batch.to_vec()));
+    Some(batches)
+}
@@ -0,0 +1,106 @@
+use log::info;
+
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use std::fmt;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::input;
+
+/// How much of the feed is read to detect its dialect
+const SNIFF_SIZE: u64 = 64 * 1024;
+
+/// Format of the feed, every dialect has its own parser profile
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Dialect {
+    /// Yandex Market Language: `<yml_catalog>` with `<offer>` elements
+    Yml,
+    /// Google Merchant Center RSS 2.0 or Atom feed with `g:` namespace
+    GoogleMerchant,
+    /// `<offer>` elements under another root, e.g. `<price>` of some suppliers,
+    /// they are parsed like YML
+    Custom,
+}
+
+impl FromStr for Dialect {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Dialect, Error> {
+        match s {
+            "yml" => Ok(Dialect::Yml),
+            "google-merchant" => Ok(Dialect::GoogleMerchant),
+            "custom" => Ok(Dialect::Custom),
+            _ => Err(Error::config(format!("Unknown feed dialect: {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Dialect::Yml => "yml",
+            Dialect::GoogleMerchant => "google-merchant",
+            Dialect::Custom => "custom",
+        })
+    }
+}
+
+impl Dialect {
+    /// Whether the element contains a single offer
+    pub fn is_offer_element(&self, name: &[u8]) -> bool {
+        match self {
+            Dialect::Yml | Dialect::Custom => name == b"offer",
+            Dialect::GoogleMerchant => name == b"item" || name == b"entry",
+        }
+    }
+}
+
+/// Detects the dialect by the root element and namespaces of the beginning of the feed
+pub(crate) fn detect(file_path: &Path) -> Result<Dialect, Error> {
+    let (reader, _) = input::open(file_path)?;
+    let mut xml_reader = Reader::from_reader(BufReader::new(reader.take(SNIFF_SIZE)));
+    let mut buf = vec!();
+    let mut root = None;
+    let dialect = loop {
+        // the sniffed part usually ends in the middle of an element, syntax errors
+        // are reported by the parser
+        let event = match xml_reader.read_event(&mut buf) {
+            Ok(event) => event,
+            Err(_) => break None,
+        };
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let name = String::from_utf8_lossy(e.name()).into_owned();
+                let has_google_namespace = e.attributes()
+                    .filter_map(Result::ok)
+                    .any(|attr| attr.key == b"xmlns:g");
+                // parts of a split feed follow the index root
+                if name == "yml_catalog" {
+                    break Some(Dialect::Yml);
+                } else if ((name == "rss" || name == "feed") && has_google_namespace) || name.starts_with("g:") {
+                    break Some(Dialect::GoogleMerchant);
+                } else if name == "offer" {
+                    break Some(Dialect::Custom);
+                }
+                root.get_or_insert(name);
+            }
+            Event::Eof => break None,
+            _ => {}
+        }
+        buf.clear();
+    };
+    match dialect {
+        Some(dialect) => {
+            info!("Detected {} feed dialect", dialect);
+            Ok(dialect)
+        }
+        None => Err(Error::config(format!(
+            "Cannot detect dialect of the feed with <{}> root element, specify it with --dialect",
+            root.unwrap_or_default()
+        ))),
+    }
+}
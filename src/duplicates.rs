@@ -0,0 +1,149 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{Bigint, Integer, Varchar};
+
+
+use std::collections::{HashMap, HashSet};
+
+use crate::error::Error;
+use crate::models::{self, HUBBER_FILE_ID};
+use crate::schema::possible_duplicates;
+
+const MAX_NAME_CANDIDATES: i64 = 10;
+/// Products looked up by a single query
+const LOOKUP_BATCH_SIZE: usize = 100;
+
+#[derive(QueryableByName)]
+struct Candidate {
+    /// Inserted product the candidate was found for
+    #[diesel(sql_type = Integer)]
+    product_id: i32,
+    #[diesel(sql_type = Integer)]
+    id: i32,
+    #[diesel(sql_type = Varchar)]
+    name: String,
+}
+
+/// Searches products of other suppliers that look like the newly inserted products
+/// and stores them into `possible_duplicates` table for manual review.
+/// Returns number of stored candidates, the ones found by previous runs are not counted.
+pub(crate) fn find_possible_duplicates(
+    conn: &mut MysqlConnection,
+    inserted_products: &[models::NewProduct],
+    min_similarity: f32,
+) -> Result<u32, Error> {
+    use crate::schema::products::dsl;
+
+    let offer_ids = inserted_products.iter()
+        .map(|p| p.hub_stock_id.as_str())
+        .collect::<Vec<_>>();
+    let file_ids = inserted_products.iter()
+        .map(|p| p.file_id.unwrap_or(HUBBER_FILE_ID))
+        .collect::<HashSet<_>>();
+    let inserted = dsl::products.select((dsl::id, dsl::name, dsl::file_id))
+        .filter(dsl::hub_stock_id.eq_any(offer_ids))
+        .filter(dsl::file_id.eq_any(file_ids))
+        .load::<(i32, String, Option<i8>)>(conn)?;
+
+    let mut duplicates = vec!();
+    for batch in inserted.chunks(LOOKUP_BATCH_SIZE) {
+        let mut seen = HashSet::new();
+        for candidate in same_barcode(conn, batch)? {
+            seen.insert((candidate.product_id, candidate.id));
+            duplicates.push(models::NewPossibleDuplicate {
+                product_id: candidate.product_id,
+                duplicate_product_id: candidate.id,
+                similarity: 1.0,
+                reason: "barcode".to_string(),
+            });
+        }
+
+        let tokens = batch.iter()
+            .map(|(product_id, name, _)| (*product_id, name_tokens(name)))
+            .filter(|(_, tokens)| !tokens.is_empty())
+            .collect::<HashMap<_, _>>();
+        let batch = batch.iter()
+            .filter(|(product_id, _, _)| tokens.contains_key(product_id))
+            .collect::<Vec<_>>();
+        for candidate in same_name(conn, &batch)? {
+            if seen.contains(&(candidate.product_id, candidate.id)) {
+                continue;
+            }
+            let similarity = jaccard(&tokens[&candidate.product_id], &name_tokens(&candidate.name));
+            if similarity >= min_similarity {
+                duplicates.push(models::NewPossibleDuplicate {
+                    product_id: candidate.product_id,
+                    duplicate_product_id: candidate.id,
+                    similarity,
+                    reason: "name".to_string(),
+                });
+            }
+        }
+    }
+
+    if duplicates.is_empty() {
+        return Ok(0);
+    }
+    // pairs found by previous runs are ignored
+    let stored = diesel::insert_or_ignore_into(possible_duplicates::table)
+        .values(&duplicates)
+        .execute(conn)?;
+    Ok(stored as u32)
+}
+
+/// Products of other suppliers with the same barcode
+fn same_barcode(conn: &mut MysqlConnection, batch: &[(i32, String, Option<i8>)]) -> Result<Vec<Candidate>, Error> {
+    let ids = batch.iter()
+        .map(|(id, _, _)| id.to_string())
+        .collect::<Vec<_>>();
+    Ok(
+        diesel::sql_query(format!(
+            "SELECT n.id AS product_id, p.id, p.name FROM products n \
+             JOIN products p ON p.GTIN = n.GTIN AND p.id <> n.id \
+             AND (p.file_id IS NULL OR p.file_id <> IFNULL(n.file_id, {})) \
+             WHERE n.id IN ({}) AND n.GTIN IS NOT NULL",
+            HUBBER_FILE_ID, ids.join(", ")
+        ))
+            .load::<Candidate>(conn)?
+    )
+}
+
+/// Products of other suppliers with similar names found by the fulltext index,
+/// lookups of the batch are sent as a single `UNION ALL` query
+fn same_name(conn: &mut MysqlConnection, batch: &[&(i32, String, Option<i8>)]) -> Result<Vec<Candidate>, Error> {
+    if batch.is_empty() {
+        return Ok(vec!());
+    }
+    let lookup = "(SELECT ? AS product_id, id, name FROM products \
+        WHERE MATCH(name) AGAINST(? IN NATURAL LANGUAGE MODE) \
+        AND id <> ? AND (file_id IS NULL OR file_id <> ?) \
+        LIMIT ?)";
+    let mut query = diesel::sql_query(vec![lookup; batch.len()].join(" UNION ALL "))
+        .into_boxed();
+    for (product_id, name, file_id) in batch {
+        query = query
+            .bind::<Integer, _>(*product_id)
+            .bind::<Varchar, _>(name)
+            .bind::<Integer, _>(*product_id)
+            .bind::<Integer, _>(file_id.unwrap_or(HUBBER_FILE_ID) as i32)
+            .bind::<Bigint, _>(MAX_NAME_CANDIDATES);
+    }
+    Ok(query.load::<Candidate>(conn)?)
+}
+
+/// Normalized name: lowercased alphanumeric words
+fn name_tokens(name: &str) -> HashSet<String> {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f32 / union as f32
+}
@@ -0,0 +1,20 @@
+use log::info;
+
+use crate::Opts;
+use crate::error::Error;
+
+/// Loads `--env-file` files in the given order, without them `.env` of the working directory
+/// or its parents is loaded if it exists. Variables are never overridden: the process environment
+/// wins over the files and earlier files win over later ones.
+pub(crate) fn load(opts: &Opts) -> Result<(), Error> {
+    if opts.env_file.is_empty() {
+        dotenv::dotenv().ok();
+        return Ok(());
+    }
+    for path in &opts.env_file {
+        dotenv::from_path(path)
+            .map_err(|e| Error::config_caused_by(format!("Cannot load env file {}", path.display()), e))?;
+        info!("Loaded environment from {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use thiserror::Error;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Where in the feed an error happened
+#[derive(Debug, Default, Clone)]
+pub struct ErrorContext {
+    pub offer_id: Option<String>,
+    pub position: Option<usize>,
+}
+
+impl ErrorContext {
+    pub fn offer(offer_id: &str) -> ErrorContext {
+        ErrorContext {
+            offer_id: Some(offer_id.to_string()),
+            position: None,
+        }
+    }
+
+    pub fn position(position: usize) -> ErrorContext {
+        ErrorContext {
+            offer_id: None,
+            position: Some(position),
+        }
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.offer_id, self.position) {
+            (Some(offer_id), Some(position)) => write!(f, "offer {} at position {}: ", offer_id, position),
+            (Some(offer_id), None) => write!(f, "offer {}: ", offer_id),
+            (None, Some(position)) => write!(f, "position {}: ", position),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The feed is malformed
+    #[error("Parse error: {context}{message}")]
+    Parse {
+        message: String,
+        context: ErrorContext,
+        source: Option<BoxError>,
+    },
+    /// Offer data is invalid
+    #[error("Validation error: {context}{message}")]
+    Validation {
+        message: String,
+        context: ErrorContext,
+    },
+    #[error("Database error: {context}{message}")]
+    Db {
+        message: String,
+        context: ErrorContext,
+        source: BoxError,
+    },
+    /// Invalid options, environment or files referenced by them
+    #[error("Config error: {message}")]
+    Config {
+        message: String,
+        source: Option<BoxError>,
+    },
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// Failure of an external service or command: message queue, search backend, etc.
+    #[error("{service} error: {message}")]
+    External {
+        service: &'static str,
+        message: String,
+    },
+}
+
+impl Error {
+    pub fn parse<M: Into<String>>(message: M, context: ErrorContext) -> Error {
+        Error::Parse { message: message.into(), context, source: None }
+    }
+
+    /// The file ended before the element was closed
+    pub fn unexpected_eof(file_path: &Path, element: &[u8], position: usize) -> Error {
+        Error::parse(
+            format!(
+                "{} ended inside of <{}> element", file_path.display(), String::from_utf8_lossy(element)
+            ),
+            ErrorContext::position(position),
+        )
+    }
+
+    pub fn validation<M: Into<String>>(message: M, context: ErrorContext) -> Error {
+        Error::Validation { message: message.into(), context }
+    }
+
+    pub fn config<M: Into<String>>(message: M) -> Error {
+        Error::Config { message: message.into(), source: None }
+    }
+
+    pub fn config_caused_by<M, E>(message: M, source: E) -> Error
+    where
+        M: Into<String>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Error::Config { message: message.into(), source: Some(Box::new(source)) }
+    }
+
+    pub fn external<M: Into<String>>(service: &'static str, message: M) -> Error {
+        Error::External { service, message: message.into() }
+    }
+
+    /// Adds position in the feed to the parse, validation and database errors.
+    pub fn at_position(mut self, position: usize) -> Error {
+        match self {
+            Error::Parse { ref mut context, .. } |
+            Error::Validation { ref mut context, .. } |
+            Error::Db { ref mut context, .. } => {
+                context.position = Some(position);
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Database errors that can disappear when the query is repeated:
+    /// deadlocks, lock wait timeouts and lost connections
+    pub fn is_transient(&self) -> bool {
+        use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+        let source = match self {
+            Error::Db { source, .. } => source,
+            _ => return false,
+        };
+        match source.downcast_ref::<DieselError>() {
+            Some(DieselError::DatabaseError(DatabaseErrorKind::SerializationFailure, _)) |
+            Some(DieselError::DatabaseError(DatabaseErrorKind::ClosedConnection, _)) |
+            Some(DieselError::DatabaseError(DatabaseErrorKind::UnableToSendCommand, _)) => true,
+            Some(DieselError::DatabaseError(_, info)) => {
+                let message = info.message();
+                message.contains("Lock wait timeout") ||
+                    message.contains("Deadlock found") ||
+                    message.contains("server has gone away") ||
+                    message.contains("Lost connection")
+            }
+            _ => false,
+        }
+    }
+
+    /// The database rejected the data itself: constraint violations, too long
+    /// or invalid values. Repeating the query with the same data won't help.
+    pub fn is_data_error(&self) -> bool {
+        use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+        let source = match self {
+            Error::Db { source, .. } => source,
+            _ => return false,
+        };
+        match source.downcast_ref::<DieselError>() {
+            Some(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) |
+            Some(DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) |
+            Some(DieselError::DatabaseError(DatabaseErrorKind::NotNullViolation, _)) |
+            Some(DieselError::DatabaseError(DatabaseErrorKind::CheckViolation, _)) => true,
+            // MySQL errors 1264, 1265, 1292, 1366 and 1406 have no kind of their own
+            Some(DieselError::DatabaseError(DatabaseErrorKind::Unknown, info)) => {
+                let message = info.message();
+                message.starts_with("Out of range value") ||
+                    message.starts_with("Data truncated") ||
+                    message.starts_with("Data too long") ||
+                    message.starts_with("Truncated incorrect") ||
+                    (message.starts_with("Incorrect ") && message.contains(" value"))
+            }
+            _ => false,
+        }
+    }
+
+    /// Process exit code for the error class
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config { .. } => 2,
+            Error::Parse { .. } => 3,
+            Error::Validation { .. } => 4,
+            Error::Db { .. } => 5,
+            Error::Io(_) => 6,
+            Error::External { .. } => 7,
+        }
+    }
+}
+
+impl From<quick_xml::Error> for Error {
+    fn from(e: quick_xml::Error) -> Error {
+        Error::Parse {
+            message: e.to_string(),
+            context: ErrorContext::default(),
+            source: Some(Box::new(e)),
+        }
+    }
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Error {
+        Error::Db {
+            message: e.to_string(),
+            context: ErrorContext::default(),
+            source: Box::new(e),
+        }
+    }
+}
+
+impl From<diesel::ConnectionError> for Error {
+    fn from(e: diesel::ConnectionError) -> Error {
+        Error::Db {
+            message: e.to_string(),
+            context: ErrorContext::default(),
+            source: Box::new(e),
+        }
+    }
+}
+
+#[cfg(feature = "amqp")]
+impl From<lapin::Error> for Error {
+    fn from(e: lapin::Error) -> Error {
+        Error::external("amqp", e.to_string())
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl From<rdkafka::error::KafkaError> for Error {
+    fn from(e: rdkafka::error::KafkaError) -> Error {
+        Error::external("kafka", e.to_string())
+    }
+}
+
+impl From<redis::RedisError> for Error {
+    fn from(e: redis::RedisError) -> Error {
+        Error::external("redis", e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+    use super::*;
+
+    fn db_error(kind: DatabaseErrorKind, message: &str) -> Error {
+        DieselError::DatabaseError(kind, Box::new(message.to_string())).into()
+    }
+
+    #[test]
+    fn test_is_data_error() {
+        assert!(db_error(DatabaseErrorKind::UniqueViolation, "Duplicate entry 'a' for key 'slug'").is_data_error());
+        assert!(db_error(DatabaseErrorKind::Unknown, "Data too long for column 'name' at row 1").is_data_error());
+        assert!(db_error(DatabaseErrorKind::Unknown, "Out of range value for column 'price' at row 3").is_data_error());
+        assert!(db_error(DatabaseErrorKind::Unknown, "Incorrect integer value: 'x' for column 'age'").is_data_error());
+        assert!(!db_error(DatabaseErrorKind::Unknown, "Table 'hub.products' doesn't exist").is_data_error());
+        assert!(!db_error(DatabaseErrorKind::Unknown, "Lock wait timeout exceeded").is_data_error());
+        assert!(!db_error(DatabaseErrorKind::ClosedConnection, "MySQL server has gone away").is_data_error());
+    }
+}
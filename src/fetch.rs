@@ -0,0 +1,650 @@
+use log::{info, warn};
+
+use percent_encoding::percent_decode_str;
+
+use sha2::{Digest, Sha256};
+
+use ssh2::{CheckResult, KnownHostFileKind, Session, Sftp};
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempPath;
+
+use url::Url;
+
+use crate::Opts;
+use crate::error::Error;
+
+/// How many times the feed is downloaded when it is being written on the server
+const FETCH_ATTEMPTS: u32 = 3;
+/// Pause before the next attempt so the supplier can finish writing the file
+const FETCH_RETRY_DELAY: Duration = Duration::from_secs(30);
+const FTP_TIMEOUT: Duration = Duration::from_secs(60);
+const SFTP_TIMEOUT: Duration = Duration::from_secs(60);
+/// How many times an interrupted HTTP download is resumed
+const RESUME_ATTEMPTS: u32 = 5;
+const RESUME_DELAY: Duration = Duration::from_secs(5);
+const HTTP_CONNECT_TIMEOUT_MS: u64 = 30_000;
+const HTTP_READ_TIMEOUT_MS: u64 = 60_000;
+
+/// Checks of the downloaded feed before it is processed, a truncated feed
+/// would mark most of the products as unavailable
+#[derive(Default)]
+pub(crate) struct FeedChecks {
+    /// `--expect-sha256`
+    pub sha256: Option<String>,
+    /// `--sha256-sidecar`: the checksum is downloaded from `<URL>.sha256`
+    pub sha256_sidecar: bool,
+    /// `--min-feed-size`
+    pub min_size: Option<u64>,
+}
+
+impl FeedChecks {
+    pub fn from_opts(opts: &Opts) -> FeedChecks {
+        FeedChecks {
+            sha256: opts.expect_sha256.clone(),
+            sha256_sidecar: opts.sha256_sidecar,
+            min_size: opts.min_feed_size,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.sha256.is_none() && !self.sha256_sidecar && self.min_size.is_none()
+    }
+
+    fn verify(&self, url: &Url, local_path: &Path) -> Result<(), Error> {
+        let size = fs::metadata(local_path)?.len();
+        if let Some(min_size) = self.min_size {
+            if size < min_size {
+                return Err(Error::external(
+                    "fetch", format!("{} is too small: {} bytes, at least {} are expected", url, size, min_size)
+                ));
+            }
+        }
+        let expected = match self.sha256 {
+            Some(ref sha256) => sha256.to_lowercase(),
+            None if self.sha256_sidecar => download_sidecar(url)?,
+            None => return Ok(()),
+        };
+        let actual = sha256_file(local_path)?;
+        if actual != expected {
+            return Err(Error::external(
+                "fetch", format!("SHA-256 of {} does not match: expected {}, got {}", url, expected, actual)
+            ));
+        }
+        info!("SHA-256 of {} is verified", url);
+        Ok(())
+    }
+}
+
+/// Downloaded feed, the file is removed when it is dropped
+pub(crate) struct DownloadedFile(TempPath);
+
+/// Creates an empty file with a unique name in the temporary directory,
+/// the name ends with the `suffix` so the extension of the remote file is kept
+fn temp_file(suffix: &str) -> Result<DownloadedFile, Error> {
+    let file = tempfile::Builder::new()
+        .prefix("hubber_xml-")
+        .suffix(suffix)
+        .tempfile()?;
+    Ok(DownloadedFile(file.into_temp_path()))
+}
+
+/// Downloads `http(s)://`, `ftp://` and `sftp://` feeds into a temporary file and replaces the file path
+/// with it, the downloaded file is verified with the `checks`.
+/// Credentials of ftp and sftp servers are taken from the URL, the host section of `--feed-credentials`
+/// file or `FEED_USER`, `FEED_PASSWORD`, `FEED_SFTP_IDENTITY` and `FEED_SFTP_KNOWN_HOSTS`
+/// environment variables, see [`Credentials`]. http(s) downloads go through the proxy
+/// of `--proxy` or `HTTP_PROXY` and `HTTPS_PROXY` environment variables.
+pub(crate) fn fetch_remote_file(
+    file_path: &mut Option<PathBuf>, checks: &FeedChecks,
+) -> Result<Option<DownloadedFile>, Error> {
+    let url = match file_path.as_deref().and_then(Path::to_str) {
+        Some(path) if is_remote(path) => {
+            Url::parse(path).map_err(|e| Error::config_caused_by(format!("Invalid feed URL: {}", path), e))?
+        }
+        Some(_) if !checks.is_empty() => {
+            return Err(Error::config("--expect-sha256, --sha256-sidecar and --min-feed-size require a feed URL"));
+        }
+        _ => return Ok(None),
+    };
+    let host = url.host_str()
+        .ok_or_else(|| Error::config(format!("Feed URL without host: {}", url)))?;
+    let file_name = url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| Error::config(format!("Feed URL without file name: {}", url)))?;
+    let downloaded = temp_file(&format!("-{}-{}", host, file_name))?;
+    let local_path = downloaded.0.to_path_buf();
+
+    for attempt in 1..=FETCH_ATTEMPTS {
+        info!("Downloading {} (attempt {})", url, attempt);
+        let complete = download(&url, &local_path)?;
+        if complete {
+            checks.verify(&url, &local_path)?;
+            *file_path = Some(local_path);
+            return Ok(Some(downloaded));
+        }
+        if attempt < FETCH_ATTEMPTS {
+            warn!("{} is being written on the server, retrying in {:?}", url, FETCH_RETRY_DELAY);
+            thread::sleep(FETCH_RETRY_DELAY);
+        }
+    }
+    Err(Error::external("fetch", format!("{} keeps changing while downloading", url)))
+}
+
+/// Applies `--feed-credentials` to the ftp and sftp downloads of the process,
+/// the file is read by every download so it can be changed without restarting the daemon
+pub(crate) fn use_credentials(path: &Path) -> Result<(), Error> {
+    fs::metadata(path)
+        .map_err(|e| Error::config_caused_by(format!("Cannot read feed credentials: {}", path.display()), e))?;
+    env::set_var("FEED_CREDENTIALS", path);
+    Ok(())
+}
+
+/// Applies `--proxy` to the http(s) downloads of the process, it takes precedence
+/// over `HTTP_PROXY` and `HTTPS_PROXY` environment variables
+pub(crate) fn use_proxy(proxy: &str) -> Result<(), Error> {
+    parse_proxy(proxy)?;
+    env::set_var("HTTP_PROXY", proxy);
+    env::set_var("HTTPS_PROXY", proxy);
+    Ok(())
+}
+
+/// Proxy of the request: `HTTPS_PROXY` for https and `HTTP_PROXY` for http URLs
+/// (or their lower case variants), hosts listed in `NO_PROXY` are reached directly
+pub(crate) fn proxy_for(url: &Url) -> Result<Option<ureq::Proxy>, Error> {
+    let vars = match url.scheme() {
+        "https" => ["HTTPS_PROXY", "https_proxy"],
+        _ => ["HTTP_PROXY", "http_proxy"],
+    };
+    let proxy = match vars.iter().filter_map(|var| env::var(var).ok()).find(|proxy| !proxy.trim().is_empty()) {
+        Some(proxy) => proxy,
+        None => return Ok(None),
+    };
+    if is_no_proxy(url.host_str().unwrap_or_default()) {
+        return Ok(None);
+    }
+    parse_proxy(proxy.trim()).map(Some)
+}
+
+/// `NO_PROXY` lists hosts and domains (`example.com` also matches its subdomains) or `*`
+fn is_no_proxy(host: &str) -> bool {
+    let no_proxy = env::var("NO_PROXY").or_else(|_| env::var("no_proxy")).unwrap_or_default();
+    no_proxy.split(',')
+        .map(|entry| entry.trim().trim_start_matches('.'))
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| entry == "*" || host == entry || host.ends_with(&format!(".{}", entry)))
+}
+
+/// `[http://][user:password@]host[:port]`, credentials are also taken
+/// from `PROXY_USER` and `PROXY_PASSWORD` environment variables
+fn parse_proxy(proxy: &str) -> Result<ureq::Proxy, Error> {
+    let invalid = || format!("Invalid proxy: {}", proxy);
+    let url = if proxy.contains("://") {
+        Url::parse(proxy)
+    } else {
+        Url::parse(&format!("http://{}", proxy))
+    }
+        .map_err(|e| Error::config_caused_by(invalid(), e))?;
+    if url.scheme() != "http" {
+        return Err(Error::config(format!("Only http:// proxies are supported: {}", proxy)));
+    }
+    let host = url.host_str().ok_or_else(|| Error::config(invalid()))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let user = match url.username() {
+        "" => env::var("PROXY_USER").ok(),
+        user => Some(user.to_string()),
+    };
+    let address = match user {
+        Some(user) => {
+            let password = url.password()
+                .map(str::to_string)
+                .or_else(|| env::var("PROXY_PASSWORD").ok())
+                .unwrap_or_default();
+            format!("http://{}:{}@{}:{}", user, password, host, port)
+        }
+        None => format!("http://{}:{}", host, port),
+    };
+    ureq::Proxy::new(address).map_err(|e| Error::config_caused_by(invalid(), e))
+}
+
+pub(crate) fn is_remote(path: &str) -> bool {
+    ["http://", "https://", "ftp://", "sftp://"].iter().any(|scheme| path.starts_with(scheme))
+}
+
+/// Returns `false` when the remote file was changed during the download
+fn download(url: &Url, local_path: &Path) -> Result<bool, Error> {
+    match url.scheme() {
+        "http" | "https" => http_download(url, local_path),
+        "ftp" => ftp_download(url, local_path),
+        _ => sftp_download(url, local_path),
+    }
+}
+
+/// Downloads `<URL>.sha256` next to the feed, the file contains the checksum
+/// optionally followed by the file name like `sha256sum` writes it
+fn download_sidecar(url: &Url) -> Result<String, Error> {
+    let mut sidecar_url = url.clone();
+    sidecar_url.set_path(&format!("{}.sha256", url.path()));
+    let sidecar_path = temp_file(".sha256")?;
+    if !download(&sidecar_url, &sidecar_path.0)? {
+        return Err(Error::external("fetch", format!("{} was changed while downloading", sidecar_url)));
+    }
+    let content = fs::read_to_string(&sidecar_path.0)?;
+    content.split_whitespace()
+        .next()
+        .filter(|sha256| sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_lowercase)
+        .ok_or_else(|| Error::external("fetch", format!("Invalid checksum in {}: {}", sidecar_url, content.trim())))
+}
+
+fn sha256_file(path: &Path) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Downloads the file resuming the interrupted transfer with `Range` requests.
+/// `If-Range` makes the server send the whole file again when it was changed meanwhile.
+/// The size is checked against `Content-Length`, the file is always complete when it succeeds.
+fn http_download(url: &Url, local_path: &Path) -> Result<bool, Error> {
+    File::create(local_path)?;
+    let mut validator: Option<String> = None;
+    let mut last_error = None;
+    for attempt in 0..=RESUME_ATTEMPTS {
+        if let Some(ref e) = last_error {
+            warn!("Download of {} was interrupted: {}, resuming in {:?}", url, e, RESUME_DELAY);
+            thread::sleep(RESUME_DELAY);
+        }
+        let received = fs::metadata(local_path)?.len();
+        let mut req = ureq::get(url.as_str());
+        req.timeout_connect(HTTP_CONNECT_TIMEOUT_MS)
+            .timeout_read(HTTP_READ_TIMEOUT_MS);
+        if let Some(proxy) = proxy_for(url)? {
+            req.set_proxy(proxy);
+        }
+        if let (true, Some(validator)) = (received > 0, validator.as_deref()) {
+            req.set("Range", &format!("bytes={}-", received))
+                .set("If-Range", validator);
+        }
+        let resp = req.call();
+        if let Some(e) = resp.synthetic_error() {
+            last_error = Some(e.to_string());
+            continue;
+        }
+        let append = match resp.status() {
+            200 => false,
+            206 => true,
+            status if status >= 500 && attempt < RESUME_ATTEMPTS => {
+                last_error = Some(format!("HTTP {}", status));
+                continue;
+            }
+            status => return Err(Error::external("fetch", format!("Cannot download {}: HTTP {}", url, status))),
+        };
+        if !append && received > 0 {
+            info!("{} was changed on the server, downloading it again", url);
+        }
+        validator = resp.header("ETag")
+            .or_else(|| resp.header("Last-Modified"))
+            .map(str::to_string);
+        let expected_size = match resp.header("Content-Range") {
+            // bytes 1000-1999/2000
+            Some(range) => range.rsplit('/').next().and_then(|total| total.parse::<u64>().ok()),
+            None => resp.header("Content-Length").and_then(|len| len.parse::<u64>().ok()),
+        };
+        let mut out = OpenOptions::new()
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(local_path)?;
+        let mut body = resp.into_reader();
+        if let Err(e) = io::copy(&mut body, &mut out) {
+            last_error = Some(e.to_string());
+            continue;
+        }
+        let size = fs::metadata(local_path)?.len();
+        match expected_size {
+            Some(expected_size) if size < expected_size => {
+                last_error = Some(format!("received {} of {} bytes", size, expected_size));
+            }
+            Some(expected_size) if size > expected_size => {
+                return Err(Error::external(
+                    "fetch", format!("{} is larger than expected: {} of {} bytes", url, size, expected_size)
+                ));
+            }
+            _ => return Ok(true),
+        }
+    }
+    Err(Error::external(
+        "fetch",
+        format!("Cannot download {}: {}", url, last_error.unwrap_or_default()),
+    ))
+}
+
+/// Credentials of a feed server. The user and the password of the URL take precedence,
+/// then the `[host]` section of `--feed-credentials` file:
+///
+/// ```text
+/// [ftp.supplier.com]
+/// user = hubber
+/// password = secret
+///
+/// [sftp.other-supplier.com]
+/// user = hubber
+/// identity = /etc/hubber_xml/id_ed25519
+/// known_hosts = /etc/hubber_xml/known_hosts
+/// ```
+///
+/// and at last `FEED_USER`, `FEED_PASSWORD`, `FEED_SFTP_IDENTITY` and `FEED_SFTP_KNOWN_HOSTS`
+/// environment variables.
+#[derive(Debug, Default, PartialEq)]
+struct Credentials {
+    user: Option<String>,
+    password: Option<String>,
+    /// Private key for sftp
+    identity: Option<PathBuf>,
+    /// OpenSSH known hosts file to check the host key of sftp servers
+    known_hosts: Option<PathBuf>,
+}
+
+impl Credentials {
+    fn user(&self) -> &str {
+        self.user.as_deref().unwrap_or("anonymous")
+    }
+}
+
+fn credentials(url: &Url) -> Result<Credentials, Error> {
+    let mut section = match env::var_os("FEED_CREDENTIALS") {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            let content = fs::read_to_string(&path)
+                .map_err(|e| Error::config_caused_by(format!("Cannot read feed credentials: {}", path.display()), e))?;
+            parse_credentials(&content, url.host_str().unwrap_or_default())?
+        }
+        None => HashMap::new(),
+    };
+    let mut setting = |key: &str, var: &str| section.remove(key).or_else(|| env::var(var).ok());
+    let user = match url.username() {
+        "" => setting("user", "FEED_USER"),
+        user => Some(decode(user)?),
+    };
+    let password = match url.password() {
+        Some(password) => Some(decode(password)?),
+        None => setting("password", "FEED_PASSWORD"),
+    };
+    Ok(Credentials {
+        user,
+        password,
+        identity: setting("identity", "FEED_SFTP_IDENTITY").map(PathBuf::from),
+        known_hosts: setting("known_hosts", "FEED_SFTP_KNOWN_HOSTS").map(PathBuf::from),
+    })
+}
+
+/// Reads `key = value` lines of the host section, lines starting with `#` are ignored
+fn parse_credentials(content: &str, host: &str) -> Result<HashMap<String, String>, Error> {
+    let mut settings = HashMap::new();
+    let mut in_host = false;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            in_host = line[1..line.len() - 1].trim() == host;
+            continue;
+        }
+        if !in_host {
+            continue;
+        }
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| Error::config(format!("Invalid feed credentials line: {}", line)))?;
+        settings.insert(key.trim().to_string(), value.trim().to_string());
+    }
+    Ok(settings)
+}
+
+fn decode(value: &str) -> Result<String, Error> {
+    percent_decode_str(value)
+        .decode_utf8()
+        .map(|value| value.into_owned())
+        .map_err(|e| Error::config_caused_by(format!("Invalid URL encoding: {}", value), e))
+}
+
+/// Path of the file on the server: the decoded path of the URL
+fn remote_path(url: &Url) -> Result<String, Error> {
+    let path = decode(url.path())?;
+    if path.contains(['\r', '\n']) {
+        return Err(Error::config(format!("Line breaks are not allowed in the feed path: {}", url)));
+    }
+    Ok(path)
+}
+
+/// Returns `false` when the remote file was changed during the download. The size of the file
+/// is compared before and after the download, servers without `SIZE` command are checked
+/// with the size announced for the transfer when they report it.
+fn ftp_download(url: &Url, local_path: &Path) -> Result<bool, Error> {
+    let credentials = credentials(url)?;
+    let remote_path = remote_path(url)?;
+    let mut ftp = FtpClient::connect(url.host_str().unwrap_or_default(), url.port().unwrap_or(21))?;
+    ftp.login(credentials.user(), credentials.password.as_deref().unwrap_or_default())?;
+    ftp.command("TYPE I", &[200])?;
+    let size_before = ftp.size(&remote_path)?;
+    let (received, announced_size) = ftp.retrieve(&remote_path, local_path)?;
+    let size_after = ftp.size(&remote_path)?;
+    ftp.command("QUIT", &[221]).ok();
+    match (size_before, size_after, announced_size) {
+        (Some(size_before), Some(size_after), _) => Ok(size_before == size_after && size_after == received),
+        (_, _, Some(announced_size)) => Ok(announced_size == received),
+        _ => {
+            warn!("{} does not report the file size, the download cannot be verified", url);
+            Ok(true)
+        }
+    }
+}
+
+/// Minimal passive mode FTP client. Data connections go to the host of the control connection,
+/// the address of `PASV` response is ignored as it is often private behind NAT.
+struct FtpClient {
+    control: BufReader<TcpStream>,
+}
+
+impl FtpClient {
+    fn connect(host: &str, port: u16) -> Result<FtpClient, Error> {
+        let stream = TcpStream::connect((host, port))?;
+        stream.set_read_timeout(Some(FTP_TIMEOUT))?;
+        let mut client = FtpClient { control: BufReader::new(stream) };
+        client.expect(&[220])?;
+        Ok(client)
+    }
+
+    fn login(&mut self, user: &str, password: &str) -> Result<(), Error> {
+        let (code, _) = self.command(&format!("USER {}", user), &[230, 331])?;
+        if code == 331 {
+            self.command(&format!("PASS {}", password), &[230])?;
+        }
+        Ok(())
+    }
+
+    /// Returns `None` when the server does not implement `SIZE`
+    fn size(&mut self, path: &str) -> Result<Option<u64>, Error> {
+        let (code, message) = self.command(&format!("SIZE {}", path), &[213, 500, 502, 504])?;
+        if code != 213 {
+            return Ok(None);
+        }
+        message.trim().parse()
+            .map(Some)
+            .map_err(|_| Error::external("ftp", format!("Invalid SIZE response: {}", message)))
+    }
+
+    /// Returns a number of received bytes and the size announced by the server
+    fn retrieve(&mut self, path: &str, local_path: &Path) -> Result<(u64, Option<u64>), Error> {
+        let (_, message) = self.command("PASV", &[227])?;
+        let port = parse_pasv_port(&message)?;
+        let host = self.control.get_ref().peer_addr()?.ip();
+        let mut data = TcpStream::connect((host, port))?;
+        data.set_read_timeout(Some(FTP_TIMEOUT))?;
+        let (_, message) = self.command(&format!("RETR {}", path), &[125, 150])?;
+        let received = io::copy(&mut data, &mut File::create(local_path)?)?;
+        drop(data);
+        self.expect(&[226, 250])?;
+        Ok((received, parse_transfer_size(&message)))
+    }
+
+    fn command(&mut self, command: &str, expected: &[u32]) -> Result<(u32, String), Error> {
+        let stream = self.control.get_mut();
+        stream.write_all(command.as_bytes())?;
+        stream.write_all(b"\r\n")?;
+        self.expect(expected)
+    }
+
+    /// Reads a response, multiline responses end with a line starting with the code and a space
+    fn expect(&mut self, expected: &[u32]) -> Result<(u32, String), Error> {
+        let mut line = String::new();
+        self.control.read_line(&mut line)?;
+        let code = line.get(..3)
+            .and_then(|code| code.parse::<u32>().ok())
+            .ok_or_else(|| Error::external("ftp", format!("Invalid response: {}", line.trim())))?;
+        if line.as_bytes().get(3) == Some(&b'-') {
+            let last_line_prefix = format!("{} ", code);
+            loop {
+                let mut next_line = String::new();
+                if self.control.read_line(&mut next_line)? == 0 || next_line.starts_with(&last_line_prefix) {
+                    break;
+                }
+            }
+        }
+        if !expected.contains(&code) {
+            return Err(Error::external("ftp", line.trim().to_string()));
+        }
+        Ok((code, line.get(4..).unwrap_or_default().trim_end().to_string()))
+    }
+}
+
+/// Port of `227 Entering Passive Mode (h1,h2,h3,h4,p1,p2)`
+fn parse_pasv_port(message: &str) -> Result<u16, Error> {
+    let invalid = || Error::external("ftp", format!("Invalid PASV response: {}", message));
+    let numbers = message
+        .split(['(', ')'])
+        .nth(1)
+        .ok_or_else(invalid)?
+        .split(',')
+        .map(|n| n.trim().parse::<u8>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid())?;
+    match numbers[..] {
+        [_, _, _, _, p1, p2] => Ok((p1 as u16) << 8 | p2 as u16),
+        _ => Err(invalid()),
+    }
+}
+
+/// Size of `150 Opening BINARY mode data connection for feed.xml (12345 bytes)`
+fn parse_transfer_size(message: &str) -> Option<u64> {
+    let (_, size) = message.rsplit_once('(')?;
+    size.strip_suffix(" bytes)")?.trim().parse().ok()
+}
+
+fn sftp_error(e: ssh2::Error) -> Error {
+    Error::external("sftp", e.to_string())
+}
+
+/// Returns `false` when the remote file was changed during the download
+fn sftp_download(url: &Url, local_path: &Path) -> Result<bool, Error> {
+    let remote_path = PathBuf::from(remote_path(url)?);
+    let sftp = sftp_session(url)?;
+    let size_before = sftp.stat(&remote_path).map_err(sftp_error)?.size;
+    let mut remote_file = sftp.open(&remote_path).map_err(sftp_error)?;
+    let received = io::copy(&mut remote_file, &mut File::create(local_path)?)?;
+    let size_after = sftp.stat(&remote_path).map_err(sftp_error)?.size;
+    Ok(size_before.is_some() && size_before == size_after && size_after == Some(received))
+}
+
+/// Connects to the server rejecting host keys missing from the known hosts file
+/// (`~/.ssh/known_hosts` by default). The identity file is used when it is set,
+/// then the password and at last the keys of the SSH agent.
+fn sftp_session(url: &Url) -> Result<Sftp, Error> {
+    let credentials = credentials(url)?;
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port().unwrap_or(22);
+    let mut session = Session::new().map_err(sftp_error)?;
+    session.set_tcp_stream(TcpStream::connect((host, port))?);
+    session.set_timeout(SFTP_TIMEOUT.as_millis() as u32);
+    session.handshake().map_err(sftp_error)?;
+
+    let known_hosts_path = match credentials.known_hosts {
+        Some(ref path) => path.clone(),
+        None => env::var_os("HOME")
+            .map(|home| Path::new(&home).join(".ssh/known_hosts"))
+            .ok_or_else(|| Error::config("Known hosts file is not set for sftp"))?,
+    };
+    let mut known_hosts = session.known_hosts().map_err(sftp_error)?;
+    known_hosts.read_file(&known_hosts_path, KnownHostFileKind::OpenSSH)
+        .map_err(|e| Error::config(format!("Cannot read known hosts {}: {}", known_hosts_path.display(), e)))?;
+    let (key, _) = session.host_key()
+        .ok_or_else(|| Error::external("sftp", format!("{} has not sent its host key", host)))?;
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => {}
+        CheckResult::NotFound => return Err(Error::external(
+            "sftp", format!("Host key of {} is not found in {}", host, known_hosts_path.display())
+        )),
+        CheckResult::Mismatch => return Err(Error::external(
+            "sftp", format!("Host key of {} does not match the one in {}", host, known_hosts_path.display())
+        )),
+        CheckResult::Failure => return Err(Error::external("sftp", format!("Cannot check host key of {}", host))),
+    }
+
+    let user = credentials.user();
+    match (&credentials.identity, &credentials.password) {
+        (Some(identity), _) => session.userauth_pubkey_file(user, None, identity, None),
+        (None, Some(password)) => session.userauth_password(user, password),
+        (None, None) => session.userauth_agent(user),
+    }
+        .map_err(|e| Error::external("sftp", format!("Authentication of {} at {} failed: {}", user, host, e)))?;
+    session.sftp().map_err(sftp_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pasv_port() {
+        assert_eq!(parse_pasv_port("Entering Passive Mode (10,0,0,5,195,80).").unwrap(), 50000);
+        assert!(parse_pasv_port("Entering Passive Mode (10,0,0,5,195)").is_err());
+        assert!(parse_pasv_port("Entering Passive Mode (10,0,0,5,195,800)").is_err());
+        assert!(parse_pasv_port("Entering Passive Mode").is_err());
+    }
+
+    #[test]
+    fn test_parse_transfer_size() {
+        assert_eq!(
+            parse_transfer_size("Opening BINARY mode data connection for feed (1).xml (12345 bytes)"),
+            Some(12345)
+        );
+        assert_eq!(parse_transfer_size("Here comes the file"), None);
+    }
+
+    #[test]
+    fn test_remote_path() {
+        let url = Url::parse("ftp://example.com/price%20lists/feed%231.xml").unwrap();
+        assert_eq!(remote_path(&url).unwrap(), "/price lists/feed#1.xml");
+        let url = Url::parse("ftp://example.com/feed.xml%0D%0ADELE%20feed.xml").unwrap();
+        assert!(remote_path(&url).is_err());
+    }
+
+    #[test]
+    fn test_parse_credentials() {
+        let content = "# feed servers\n[ftp.example.com]\nuser = hubber\npassword = a=b\n\n[other.com]\nuser = other\n";
+        let settings = parse_credentials(content, "ftp.example.com").unwrap();
+        assert_eq!(settings.get("user").map(String::as_str), Some("hubber"));
+        assert_eq!(settings.get("password").map(String::as_str), Some("a=b"));
+        assert!(parse_credentials(content, "unknown.com").unwrap().is_empty());
+        assert!(parse_credentials("[ftp.example.com]\nuser\n", "ftp.example.com").is_err());
+    }
+}
@@ -0,0 +1,282 @@
+use flate2::read::GzDecoder;
+
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use url::Url;
+
+use crate::error::Error;
+use crate::fetch::{self, DownloadedFile, FeedChecks};
+
+/// Tracks how much of the input source was consumed. Bytes are counted before decompression
+/// so the progress is meaningful for compressed inputs too.
+pub(crate) struct InputProgress {
+    /// Size of the source: file size or content length, compressed size for compressed inputs
+    pub size: Option<u64>,
+    /// BOM and junk skipped before the XML
+    pub leading_junk: LeadingJunk,
+    consumed: Rc<Cell<u64>>,
+}
+
+impl InputProgress {
+    pub fn consumed(&self) -> u64 {
+        self.consumed.get()
+    }
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+/// How much of the junk is kept for the report
+const JUNK_SAMPLE_SIZE: usize = 80;
+
+/// UTF-8 BOM and stray lines (e.g. log output of the feed generator) before the first `<`
+#[derive(Default)]
+pub(crate) struct LeadingJunk {
+    pub bom: bool,
+    /// Skipped bytes after the BOM
+    pub bytes: u64,
+    pub sample: String,
+}
+
+/// Skips the BOM and everything before the first `<` that the XML reader fails on
+fn skip_leading_junk(reader: &mut dyn BufRead) -> io::Result<LeadingJunk> {
+    let mut junk = LeadingJunk::default();
+    let mut sample = vec!();
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        if !junk.bom && junk.bytes == 0 && buf.starts_with(UTF8_BOM) {
+            junk.bom = true;
+            reader.consume(UTF8_BOM.len());
+            continue;
+        }
+        let (skip, found) = match buf.iter().position(|b| *b == b'<') {
+            Some(pos) => (pos, true),
+            None => (buf.len(), false),
+        };
+        if sample.len() < JUNK_SAMPLE_SIZE {
+            sample.extend_from_slice(&buf[..skip.min(JUNK_SAMPLE_SIZE - sample.len())]);
+        }
+        junk.bytes += skip as u64;
+        reader.consume(skip);
+        if found {
+            break;
+        }
+    }
+    junk.sample = String::from_utf8_lossy(&sample).split_whitespace().collect::<Vec<_>>().join(" ");
+    Ok(junk)
+}
+
+struct CountingReader<R> {
+    inner: R,
+    consumed: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.consumed.set(self.consumed.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// Part of a feed split into several files
+enum FeedPart {
+    File(PathBuf),
+    Url(Url),
+}
+
+impl FeedPart {
+    fn is_gzipped(&self) -> bool {
+        match self {
+            FeedPart::File(path) => path.extension().is_some_and(|ext| ext == "gz"),
+            FeedPart::Url(url) => url.path().ends_with(".gz"),
+        }
+    }
+}
+
+/// Reads the parts one after another, every part is opened when the previous one is exhausted
+struct PartsReader {
+    parts: VecDeque<FeedPart>,
+    current: Option<Box<dyn Read>>,
+    downloaded: Option<DownloadedFile>,
+    consumed: Rc<Cell<u64>>,
+}
+
+impl PartsReader {
+    fn open_part(&mut self, part: FeedPart) -> Result<Box<dyn Read>, Error> {
+        let is_gzipped = part.is_gzipped();
+        let source: Box<dyn Read> = match part {
+            FeedPart::File(path) => Box::new(File::open(path)?),
+            FeedPart::Url(url) => {
+                let mut file_path = Some(PathBuf::from(url.as_str()));
+                self.downloaded = fetch::fetch_remote_file(&mut file_path, &FeedChecks::default())?;
+                match file_path {
+                    Some(ref path) if self.downloaded.is_some() => Box::new(File::open(path)?),
+                    _ => return Err(Error::config(format!("Unsupported feed part url: {}", url))),
+                }
+            }
+        };
+        let source = CountingReader {
+            inner: source,
+            consumed: self.consumed.clone(),
+        };
+        Ok(if is_gzipped {
+            Box::new(GzDecoder::new(source))
+        } else {
+            Box::new(source)
+        })
+    }
+}
+
+impl Read for PartsReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(ref mut current) = self.current {
+                let n = current.read(buf)?;
+                if n > 0 {
+                    return Ok(n);
+                }
+                self.current = None;
+                self.downloaded = None;
+            }
+            let part = match self.parts.pop_front() {
+                Some(part) => part,
+                None => return Ok(0),
+            };
+            let reader = self.open_part(part)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            self.current = Some(reader);
+        }
+    }
+}
+
+/// Opens plain or gzipped file. An index file that references parts of the feed
+/// with `<xi:include href="..."/>` or `<file>...</file>` elements is read together with its parts
+/// as a single feed. Parts are local paths (relative to the index file) or http(s), ftp and sftp urls.
+pub(crate) fn open(file_path: &Path) -> Result<(Box<dyn BufRead>, InputProgress), Error> {
+    let mut parts = VecDeque::from(vec!(FeedPart::File(file_path.to_path_buf())));
+    parts.extend(find_parts(file_path)?);
+    let mut size = Some(0);
+    for part in &parts {
+        size = match (size, part) {
+            (Some(size), FeedPart::File(path)) => {
+                let part_size = fs::metadata(path)
+                    .map_err(|e| Error::config_caused_by(format!("Cannot read feed file: {}", path.display()), e))?
+                    .len();
+                Some(size + part_size)
+            }
+            _ => None,
+        };
+    }
+    let consumed = Rc::new(Cell::new(0));
+    let mut reader = BufReader::new(PartsReader {
+        parts,
+        current: None,
+        downloaded: None,
+        consumed: consumed.clone(),
+    });
+    let leading_junk = skip_leading_junk(&mut reader)?;
+    let progress = InputProgress {
+        size,
+        leading_junk,
+        consumed,
+    };
+    Ok((Box::new(reader), progress))
+}
+
+/// Reads events of the whole feed, parts of a split feed included, until the callback returns `false`.
+/// Passes that read the feed before the import scan it with this, so they see the same offers as the parser.
+pub(crate) fn scan<F>(file_path: &Path, mut on_event: F) -> Result<(), Error>
+where
+    F: FnMut(&Reader<Box<dyn BufRead>>, Event) -> Result<bool, Error>,
+{
+    let (reader, _) = open(file_path)?;
+    let mut xml_reader = Reader::from_reader(reader);
+    let mut buf = vec!();
+    loop {
+        let event = xml_reader.read_event(&mut buf)?;
+        if matches!(event, Event::Eof) || !on_event(&xml_reader, event)? {
+            return Ok(());
+        }
+        buf.clear();
+    }
+}
+
+/// Whether the file is an index referencing parts of the feed
+pub(crate) fn is_index(file_path: &Path) -> Result<bool, Error> {
+    Ok(!find_parts(file_path)?.is_empty())
+}
+
+/// Collects parts referenced by the index file. Only the beginning of the file
+/// before offers is scanned so regular feeds are not read twice.
+fn find_parts(file_path: &Path) -> Result<Vec<FeedPart>, Error> {
+    let file = File::open(file_path)?;
+    let mut reader: Box<dyn BufRead> = if file_path.extension().is_some_and(|ext| ext == "gz") {
+        Box::new(BufReader::new(GzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+    skip_leading_junk(&mut reader)?;
+    let base_dir = file_path.parent().unwrap_or_else(|| Path::new(""));
+    let mut xml_reader = Reader::from_reader(reader);
+    let mut buf = vec!();
+    let mut in_file = false;
+    let mut parts = vec!();
+    loop {
+        let event = xml_reader.read_event(&mut buf)?;
+        let is_empty_element = matches!(event, Event::Empty(_));
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) if e.local_name() == b"include" => {
+                for attr_res in e.attributes() {
+                    let attr = attr_res?;
+                    if attr.key == b"href" {
+                        parts.push(feed_part(base_dir, &String::from_utf8_lossy(&attr.value))?);
+                    }
+                }
+            }
+            Event::Start(ref e) | Event::Empty(ref e) if e.name() == b"file" => {
+                // the location is either in the attribute or in the element text
+                in_file = !is_empty_element;
+                for attr_res in e.attributes() {
+                    let attr = attr_res?;
+                    if attr.key == b"href" || attr.key == b"url" {
+                        parts.push(feed_part(base_dir, &String::from_utf8_lossy(&attr.value))?);
+                        in_file = false;
+                    }
+                }
+            }
+            Event::Text(ref v) if in_file => {
+                let location = v.unescape_and_decode(&xml_reader)?;
+                if !location.trim().is_empty() {
+                    parts.push(feed_part(base_dir, location.trim())?);
+                }
+            }
+            Event::End(ref e) if e.name() == b"file" => {
+                in_file = false;
+            }
+            Event::Start(ref e) if e.name() == b"offers" || e.name() == b"offer" => break,
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(parts)
+}
+
+fn feed_part(base_dir: &Path, location: &str) -> Result<FeedPart, Error> {
+    if location.contains("://") {
+        let url = Url::parse(location)
+            .map_err(|e| Error::config_caused_by(format!("Invalid feed part url: {}", location), e))?;
+        return Ok(FeedPart::Url(url));
+    }
+    Ok(FeedPart::File(base_dir.join(location)))
+}
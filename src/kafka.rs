@@ -0,0 +1,124 @@
+//! Kafka producer of `--publish-kafka` built on librdkafka, TLS and SASL are configured
+//! with `--kafka-property` the same way as for other librdkafka clients.
+
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rdkafka::ClientContext;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext};
+
+use crate::error::Error;
+
+/// Time the messages have to be acknowledged by all in-sync replicas
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn kafka_error<M: Into<String>>(message: M) -> Error {
+    Error::external("kafka", message)
+}
+
+/// Producer config: idempotent producer waiting for all replicas, the password for SASL
+/// can be passed with `KAFKA_SASL_PASSWORD` to keep it out of the command line
+fn producer_config(brokers: &str, properties: &[(String, String)]) -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config
+        .set("bootstrap.servers", brokers)
+        .set("client.id", "hubber_xml")
+        .set("enable.idempotence", "true")
+        .set("acks", "all")
+        .set("message.timeout.ms", DELIVERY_TIMEOUT.as_millis().to_string());
+    if let Ok(password) = env::var("KAFKA_SASL_PASSWORD") {
+        config.set("sasl.password", password);
+    }
+    for (key, value) in properties {
+        config.set(key, value);
+    }
+    config
+}
+
+/// Collects delivery failures reported by the background thread of librdkafka
+#[derive(Default)]
+struct DeliveryContext {
+    errors: Mutex<Vec<String>>,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: ()) {
+        if let Err((e, _)) = result {
+            self.errors.lock().unwrap().push(e.to_string());
+        }
+    }
+}
+
+pub(crate) struct KafkaMessage<'a> {
+    pub key: &'a [u8],
+    pub value: &'a [u8],
+}
+
+pub(crate) struct KafkaProducer {
+    producer: BaseProducer<DeliveryContext>,
+}
+
+impl KafkaProducer {
+    pub fn connect(brokers: &str, properties: &[(String, String)]) -> Result<KafkaProducer, Error> {
+        let producer = producer_config(brokers, properties)
+            .create_with_context(DeliveryContext::default())
+            .map_err(|e| Error::config_caused_by(format!("Invalid Kafka producer config: {}", e), e))?;
+        Ok(KafkaProducer { producer })
+    }
+
+    /// Sends the messages and waits until all in-sync replicas acknowledge them,
+    /// messages with the same key go into the same partition
+    pub fn send(&mut self, topic: &str, messages: &[KafkaMessage]) -> Result<(), Error> {
+        let started_at = Instant::now();
+        for m in messages {
+            let mut record = BaseRecord::to(topic).key(m.key).payload(m.value);
+            loop {
+                match self.producer.send(record) {
+                    Ok(()) => break,
+                    Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), rejected)) => {
+                        if started_at.elapsed() > DELIVERY_TIMEOUT {
+                            return Err(kafka_error("Timed out waiting for space in the producer queue"));
+                        }
+                        // delivery reports free the queue
+                        self.producer.poll(Duration::from_millis(100));
+                        record = rejected;
+                    }
+                    Err((e, _)) => return Err(e.into()),
+                }
+            }
+        }
+        self.producer.flush(DELIVERY_TIMEOUT)?;
+        let errors = std::mem::take(&mut *self.producer.context().errors.lock().unwrap());
+        match errors.first() {
+            Some(first) => Err(kafka_error(format!(
+                "{} of {} messages were not delivered: {}", errors.len(), messages.len(), first
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_producer_config() {
+        let config = producer_config(
+            "kafka-1:9092,kafka-2:9092",
+            &[("security.protocol".to_string(), "SASL_SSL".to_string()), ("acks".to_string(), "1".to_string())],
+        );
+        assert_eq!(config.get("bootstrap.servers"), Some("kafka-1:9092,kafka-2:9092"));
+        assert_eq!(config.get("security.protocol"), Some("SASL_SSL"));
+        // explicit properties override the defaults
+        assert_eq!(config.get("acks"), Some("1"));
+        assert_eq!(config.get("enable.idempotence"), Some("true"));
+    }
+}
@@ -0,0 +1,39 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use crate::error::Error;
+use crate::models::{NewProduct, NewProductKeyword};
+use crate::schema::product_keywords;
+
+/// Longer keywords do not fit into the column, they are usually whole sentences anyway
+const MAX_KEYWORD_LEN: usize = 128;
+
+/// Replaces keywords of the bucket products with the ones from the feed,
+/// returns a number of products that have keywords
+pub(crate) fn save_keywords(conn: &mut MysqlConnection, products: &[NewProduct]) -> Result<u32, Error> {
+    let hub_stock_ids = products.iter()
+        .map(|p| p.hub_stock_id.as_str())
+        .collect::<Vec<_>>();
+    let rows = products.iter()
+        .flat_map(|p| {
+            p.keywords.iter()
+                .filter(|k| k.chars().count() <= MAX_KEYWORD_LEN)
+                .map(move |k| NewProductKeyword {
+                    hub_stock_id: &p.hub_stock_id,
+                    keyword: k,
+                })
+        })
+        .collect::<Vec<_>>();
+    conn.transaction::<_, Error, _>(|conn| {
+        diesel::delete(product_keywords::table)
+            .filter(product_keywords::hub_stock_id.eq_any(&hub_stock_ids))
+            .execute(conn)?;
+        if !rows.is_empty() {
+            diesel::insert_or_ignore_into(product_keywords::table)
+                .values(&rows)
+                .execute(conn)?;
+        }
+        Ok(())
+    })?;
+    Ok(products.iter().filter(|p| !p.keywords.is_empty()).count() as u32)
+}
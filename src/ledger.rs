@@ -0,0 +1,102 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use sha1_smol::Sha1;
+
+use crate::Opts;
+use crate::error::Error;
+use crate::models::{self, NewImportChunk};
+use crate::schema::import_chunks;
+
+/// Options that apply chunks outside of the ledger transaction
+pub(crate) fn check_opts(opts: &Opts) -> Result<(), Error> {
+    let incompatible = [
+        ("--staging", opts.staging),
+        ("--commit-every", opts.commit_every.is_some()),
+        ("--adaptive-chunk-size", opts.adaptive_chunk_size),
+    ];
+    for (option, is_set) in incompatible.iter() {
+        if *is_set {
+            return Err(Error::config(format!("{} cannot be used with --idempotency-key", option)));
+        }
+    }
+    Ok(())
+}
+
+/// Deterministic hash of the chunk: the same offers at the same position of the feed
+/// imported with the same idempotency key always give the same hash. Offers are hashed
+/// as JSON, so the hash does not depend on the `Debug` output of the models.
+pub(crate) fn chunk_hash(
+    idempotency_key: &str, chunk_index: u32, batch_index: u32, products: &[models::NewProduct],
+) -> String {
+    let mut content = Sha1::new();
+    for product in products {
+        content.update(&serde_json::to_vec(product).expect("Product is serialized into JSON"));
+        content.update(b"\n");
+    }
+    let mut hash = Sha1::new();
+    hash.update(idempotency_key.as_bytes());
+    hash.update(b"\0");
+    hash.update(chunk_index.to_string().as_bytes());
+    hash.update(b"\0");
+    hash.update(batch_index.to_string().as_bytes());
+    hash.update(b"\0");
+    hash.update(content.digest().to_string().as_bytes());
+    hash.digest().to_string()
+}
+
+pub(crate) fn is_applied(conn: &mut MysqlConnection, chunk_hash: &str) -> Result<bool, Error> {
+    let found = import_chunks::table
+        .filter(import_chunks::chunk_hash.eq(chunk_hash))
+        .count()
+        .get_result::<i64>(conn)?;
+    Ok(found > 0)
+}
+
+/// Records the applied chunk, must be called in the transaction applying the chunk
+/// after the changes of the chunk were consumed
+pub(crate) fn record(
+    conn: &mut MysqlConnection,
+    chunk_hash: &str,
+    idempotency_key: &str,
+    chunk_index: u32,
+    batch_index: u32,
+    products: u32,
+    import_id: &str,
+) -> Result<(), Error> {
+    diesel::insert_into(import_chunks::table)
+        .values(&NewImportChunk {
+            chunk_hash,
+            idempotency_key,
+            chunk_index,
+            batch_index,
+            products,
+            import_id,
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::tests::test_product;
+
+    #[test]
+    fn test_chunk_hash() {
+        let products = vec!(test_product());
+        let hash = chunk_hash("feed-1", 3, 0, &products);
+        assert_eq!(hash, chunk_hash("feed-1", 3, 0, &products));
+        // batches of a split chunk are separate chunks
+        assert_ne!(hash, chunk_hash("feed-1", 3, 1, &products));
+        assert_ne!(hash, chunk_hash("feed-2", 3, 0, &products));
+
+        let mut changed = test_product();
+        changed.price = 10.0;
+        assert_ne!(hash, chunk_hash("feed-1", 3, 0, &[changed]));
+        // the import stamp is not the content of the chunk
+        let mut stamped = test_product();
+        stamped.last_import_id = Some("import-2".to_string());
+        assert_eq!(hash, chunk_hash("feed-1", 3, 0, &[stamped]));
+    }
+}
@@ -0,0 +1,104 @@
+use std::str::FromStr;
+
+use crate::error::{Error, ErrorContext};
+use crate::parser::Offer;
+
+/// `products.name` is `varchar(256)`
+const NAME_MAX_CHARS: usize = 256;
+/// `products.currencyId` is an enum of 3 letter codes
+const CURRENCY_ID_MAX_CHARS: usize = 3;
+/// `products.description` is `mediumtext`, its limit is in bytes
+const DESCRIPTION_MAX_BYTES: usize = 16_777_215;
+
+/// What to do with offers whose fields don't fit into the database columns
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LengthPolicy {
+    /// Cut the values to the column limits
+    Truncate,
+    /// Skip the offer
+    Reject,
+    /// Stop the import
+    Error,
+}
+
+impl FromStr for LengthPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<LengthPolicy, Error> {
+        match s {
+            "truncate" => Ok(LengthPolicy::Truncate),
+            "reject" => Ok(LengthPolicy::Reject),
+            "error" => Ok(LengthPolicy::Error),
+            _ => Err(Error::config(format!("Unknown length policy: {}", s))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum LengthCheck {
+    Fits,
+    Truncated,
+    Rejected,
+}
+
+/// Checks the offer fields against the column limits
+pub(crate) fn check_lengths(offer: &mut Offer, policy: LengthPolicy) -> Result<LengthCheck, Error> {
+    let mut oversized = vec!();
+    if let Some(ref mut name) = offer.name {
+        if fit_chars(name, NAME_MAX_CHARS, policy) {
+            oversized.push("name");
+        }
+    }
+    if let Some(ref mut currency_id) = offer.currency_id {
+        if fit_chars(currency_id, CURRENCY_ID_MAX_CHARS, policy) {
+            oversized.push("currencyId");
+        }
+    }
+    if let Some(ref mut description) = offer.description {
+        if fit_bytes(description, DESCRIPTION_MAX_BYTES, policy) {
+            oversized.push("description");
+        }
+    }
+    if oversized.is_empty() {
+        return Ok(LengthCheck::Fits);
+    }
+
+    match policy {
+        LengthPolicy::Truncate => Ok(LengthCheck::Truncated),
+        LengthPolicy::Reject => Ok(LengthCheck::Rejected),
+        LengthPolicy::Error => Err(Error::validation(
+            format!("Too long fields: {}", oversized.join(", ")),
+            ErrorContext::offer(&offer.offer_id),
+        )),
+    }
+}
+
+/// Returns `true` when the value is longer than `max_chars` characters,
+/// the value is truncated if the policy says so
+fn fit_chars(value: &mut String, max_chars: usize, policy: LengthPolicy) -> bool {
+    match value.char_indices().nth(max_chars) {
+        Some((end, _)) => {
+            if policy == LengthPolicy::Truncate {
+                value.truncate(end);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns `true` when the value is longer than `max_bytes` bytes,
+/// the value is truncated by a character boundary if the policy says so
+fn fit_bytes(value: &mut String, max_bytes: usize, policy: LengthPolicy) -> bool {
+    if value.len() <= max_bytes {
+        return false;
+    }
+    if policy == LengthPolicy::Truncate {
+        let mut end = max_bytes;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.truncate(end);
+    }
+    true
+}
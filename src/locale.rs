@@ -0,0 +1,106 @@
+use chrono::NaiveDateTime;
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Formatting of numbers, dates and durations in the summary, reports and CSV files
+/// (`--report-locale`). `en` keeps plain numbers that scripts parse.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ReportLocale {
+    En,
+    /// Decimal comma, spaces between thousands, `дд.мм.рррр` dates and durations in Ukrainian
+    Uk,
+}
+
+impl FromStr for ReportLocale {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ReportLocale, Error> {
+        match s {
+            "en" => Ok(ReportLocale::En),
+            "uk" => Ok(ReportLocale::Uk),
+            _ => Err(Error::config(format!("Unknown report locale: {}", s))),
+        }
+    }
+}
+
+impl ReportLocale {
+    /// Integer with thousands separators, signs are kept
+    pub fn int<N: ToString>(self, n: N) -> String {
+        match self {
+            ReportLocale::En => n.to_string(),
+            ReportLocale::Uk => group_thousands(&n.to_string(), '\u{a0}'),
+        }
+    }
+
+    pub fn decimal(self, v: f64, precision: usize) -> String {
+        let formatted = format!("{:.*}", precision, v);
+        match self {
+            ReportLocale::En => formatted,
+            ReportLocale::Uk => {
+                let (int_part, fraction) = match formatted.split_once('.') {
+                    Some((int_part, fraction)) => (int_part, Some(fraction)),
+                    None => (formatted.as_str(), None),
+                };
+                let int_part = group_thousands(int_part, '\u{a0}');
+                match fraction {
+                    Some(fraction) => format!("{},{}", int_part, fraction),
+                    None => int_part,
+                }
+            }
+        }
+    }
+
+    /// Number written into CSV files, thousands are not separated so spreadsheets parse it
+    pub fn csv_number(self, value: &str) -> String {
+        match self {
+            ReportLocale::En => value.to_string(),
+            ReportLocale::Uk => value.replacen('.', ",", 1),
+        }
+    }
+
+    pub fn duration(self, d: Duration) -> String {
+        match self {
+            ReportLocale::En => format!("{:?}", d),
+            ReportLocale::Uk => {
+                let secs = d.as_secs();
+                if secs >= 3600 {
+                    format!("{} год {} хв {} с", secs / 3600, secs % 3600 / 60, secs % 60)
+                } else if secs >= 60 {
+                    format!("{} хв {} с", secs / 60, secs % 60)
+                } else if secs >= 1 {
+                    format!("{} с", self.decimal(d.as_secs_f64(), 1))
+                } else {
+                    format!("{} мс", self.decimal(d.as_secs_f64() * 1000.0, 1))
+                }
+            }
+        }
+    }
+
+    pub fn date(self, date: &NaiveDateTime) -> String {
+        match self {
+            ReportLocale::En => date.to_string(),
+            ReportLocale::Uk => date.format("%d.%m.%Y %H:%M:%S").to_string(),
+        }
+    }
+}
+
+/// Inserts the separator between every three digits of the leading number of the string
+fn group_thousands(s: &str, separator: char) -> String {
+    let digits_start = s.find(|c: char| c.is_ascii_digit()).unwrap_or(s.len());
+    let digits_end = s[digits_start..].find(|c: char| !c.is_ascii_digit())
+        .map_or(s.len(), |end| digits_start + end);
+    let digits = &s[digits_start..digits_end];
+    let mut grouped = String::with_capacity(s.len() + digits.len() / 3 * 2);
+    grouped.push_str(&s[..digits_start]);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(c);
+    }
+    grouped.push_str(&s[digits_end..]);
+    grouped
+}
@@ -0,0 +1,168 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use log::info;
+
+use std::collections::HashSet;
+use std::fs;
+use std::mem;
+
+use crate::CHUNK_SIZE;
+use crate::error::Error;
+use crate::schema::seen_offer_ids;
+use crate::store::{mysql_read_connection, ProductStore};
+
+/// Parses sizes like `512M`, `1G` or plain number of bytes
+pub(crate) fn parse_size(s: &str) -> Result<u64, Error> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&s[..s.len() - 1], 1 << 10),
+        Some('M') => (&s[..s.len() - 1], 1 << 20),
+        Some('G') => (&s[..s.len() - 1], 1 << 30),
+        _ => (s, 1),
+    };
+    digits.trim().parse::<u64>()
+        .map(|n| n * multiplier)
+        .map_err(|_| Error::config(format!("Invalid size: {}", s)))
+}
+
+/// Resident set size of the process in bytes: current and peak. Available only on Linux.
+pub(crate) fn rss() -> Option<(u64, u64)> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let mut current = None;
+    let mut peak = None;
+    for line in status.lines() {
+        if let Some(v) = line.strip_prefix("VmRSS:") {
+            current = parse_kb(v);
+        } else if let Some(v) = line.strip_prefix("VmHWM:") {
+            peak = parse_kb(v);
+        }
+    }
+    Some((current?, peak?))
+}
+
+fn parse_kb(v: &str) -> Option<u64> {
+    v.trim().trim_end_matches("kB").trim().parse::<u64>().ok().map(|kb| kb * 1024)
+}
+
+/// Offer ids found in the file. They are kept in memory until the process exceeds
+/// the memory limit, then they are moved into a temporary table.
+pub(crate) enum SeenOfferIds {
+    Memory {
+        ids: HashSet<String>,
+        size: usize,
+    },
+    TempTable {
+        count: usize,
+    },
+}
+
+impl Default for SeenOfferIds {
+    fn default() -> SeenOfferIds {
+        SeenOfferIds::Memory {
+            ids: HashSet::new(),
+            size: 0,
+        }
+    }
+}
+
+impl SeenOfferIds {
+    pub fn add(
+        &mut self,
+        store: &mut dyn ProductStore,
+        offer_ids: Vec<String>,
+        max_memory: Option<u64>,
+    ) -> Result<(), Error> {
+        match self {
+            SeenOfferIds::Memory { ids, size } => {
+                for offer_id in offer_ids {
+                    *size += offer_id.capacity() + mem::size_of::<String>() + mem::size_of::<u64>();
+                    ids.insert(offer_id);
+                }
+                let exceeded = match (max_memory, rss()) {
+                    (Some(max_memory), Some((current, _))) => current > max_memory,
+                    _ => false,
+                };
+                if exceeded {
+                    self.spill(mysql_read_connection(store, "--max-memory")?)?;
+                }
+            }
+            SeenOfferIds::TempTable { count } => {
+                *count += insert_seen(mysql_read_connection(store, "--max-memory")?, &offer_ids)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self, conn: &mut MysqlConnection) -> Result<(), Error> {
+        let ids = match mem::replace(self, SeenOfferIds::TempTable { count: 0 }) {
+            SeenOfferIds::Memory { ids, .. } => ids.into_iter().collect::<Vec<_>>(),
+            SeenOfferIds::TempTable { count } => {
+                *self = SeenOfferIds::TempTable { count };
+                return Ok(());
+            }
+        };
+        info!("Memory limit exceeded, moving {} offer ids into a temporary table", ids.len());
+        diesel::sql_query(
+            "CREATE TEMPORARY TABLE IF NOT EXISTS seen_offer_ids \
+             (hub_stock_id VARCHAR(255) NOT NULL PRIMARY KEY)"
+        )
+            .execute(conn)?;
+        let mut count = 0;
+        for chunk in ids.chunks(CHUNK_SIZE) {
+            count += insert_seen(conn, chunk)?;
+        }
+        *self = SeenOfferIds::TempTable { count };
+        Ok(())
+    }
+
+    /// Returns offer ids that were not found in the file
+    pub fn missing(
+        &self,
+        store: &mut dyn ProductStore,
+        offer_ids: Vec<String>,
+    ) -> Result<Vec<String>, Error> {
+        match self {
+            SeenOfferIds::Memory { ids, .. } => {
+                Ok(offer_ids.into_iter().filter(|id| !ids.contains(id)).collect())
+            }
+            SeenOfferIds::TempTable { .. } => {
+                let seen = seen_offer_ids::table
+                    .select(seen_offer_ids::hub_stock_id)
+                    .filter(seen_offer_ids::hub_stock_id.eq_any(&offer_ids))
+                    .load::<String>(mysql_read_connection(store, "--max-memory")?)?
+                    .into_iter()
+                    .collect::<HashSet<_>>();
+                Ok(offer_ids.into_iter().filter(|id| !seen.contains(id)).collect())
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            SeenOfferIds::Memory { ids, .. } => ids.len(),
+            SeenOfferIds::TempTable { count } => *count,
+        }
+    }
+
+    /// Approximate size of the in-memory set in bytes
+    pub fn memory_size(&self) -> usize {
+        match self {
+            SeenOfferIds::Memory { size, .. } => *size,
+            SeenOfferIds::TempTable { .. } => 0,
+        }
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        matches!(self, SeenOfferIds::TempTable { .. })
+    }
+}
+
+fn insert_seen(conn: &mut MysqlConnection, offer_ids: &[String]) -> Result<usize, Error> {
+    let rows = offer_ids.iter()
+        .map(|id| seen_offer_ids::hub_stock_id.eq(id))
+        .collect::<Vec<_>>();
+    Ok(diesel::insert_or_ignore_into(seen_offer_ids::table)
+        .values(&rows)
+        .execute(conn)?)
+}
@@ -0,0 +1,129 @@
+use log::warn;
+
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::models::{AVAILABLE, NOT_AVAILABLE};
+use crate::parser::Offer;
+
+/// Parses Google Merchant `<item>` (RSS 2.0) or `<entry>` (Atom) of the file into the offer.
+/// Returns `None` for items without `g:id`.
+pub(crate) fn parse_merchant_item<B: BufRead>(
+    xml_reader: &mut Reader<B>, buf: &mut Vec<u8>, file_path: &Path, item_name: &[u8], is_empty_element: bool,
+) -> Result<Option<Offer>, Error> {
+    let item_name = item_name.to_vec();
+    let mut fields = vec!();
+    let mut field = None;
+    if !is_empty_element {
+        loop {
+            match xml_reader.read_event(buf) {
+                Ok(Event::Start(ref e)) => {
+                    field = Some(String::from_utf8_lossy(e.name()).into_owned());
+                }
+                Ok(Event::Text(ref v)) => {
+                    if let Some(name) = field.take() {
+                        let value = v.unescape_and_decode(xml_reader)?;
+                        fields.push((name, value.trim().to_string()));
+                    }
+                }
+                // CDATA content is not escaped
+                Ok(Event::CData(ref v)) => {
+                    if let Some(name) = field.take() {
+                        fields.push((name, String::from_utf8_lossy(v).trim().to_string()));
+                    }
+                }
+                Ok(Event::End(ref e)) => {
+                    if e.name() == item_name.as_slice() {
+                        break;
+                    }
+                    field = None;
+                }
+                Ok(Event::Eof) => {
+                    return Err(Error::unexpected_eof(file_path, &item_name, xml_reader.buffer_position()));
+                }
+                Err(e) => {
+                    return Err(Error::from(e).at_position(xml_reader.buffer_position()));
+                }
+                _ => {}
+            }
+            buf.clear();
+        }
+    }
+
+    let offer_id = match fields.iter().find(|(name, _)| name == "g:id") {
+        Some((_, id)) if !id.is_empty() => id.clone(),
+        _ => return Ok(None),
+    };
+    let mut offer = Offer::new(offer_id, NOT_AVAILABLE);
+    let mut price = None;
+    let mut sale_price = None;
+    for (name, value) in fields {
+        if value.is_empty() {
+            continue;
+        }
+        match name.as_str() {
+            "g:title" | "title" => offer.name = Some(value),
+            "g:description" | "description" | "summary" => offer.description = Some(value),
+            "g:price" => price = Some(value),
+            "g:sale_price" => sale_price = Some(value),
+            "g:availability" => {
+                offer.available = match value.to_lowercase().replace('_', " ").as_str() {
+                    "in stock" => AVAILABLE,
+                    "out of stock" | "preorder" | "backorder" => NOT_AVAILABLE,
+                    v => {
+                        warn!("{}: Unknown availability: {}", offer.offer_id, v);
+                        NOT_AVAILABLE
+                    }
+                };
+            }
+            "g:brand" => offer.vendor = Some(value),
+            "g:mpn" => offer.vendor_code = Some(value),
+            "g:gtin" => offer.barcode = Some(value),
+            "g:item_group_id" => offer.group_id = Some(value),
+            "g:google_product_category" => match value.parse() {
+                Ok(category_id) => offer.category_id = Some(category_id),
+                Err(_) => warn!("{}: Only numeric google_product_category is supported: {}", offer.offer_id, value),
+            },
+            "g:size" => offer.params.push(("size".to_string(), value)),
+            "g:color" => offer.params.push(("color".to_string(), value)),
+            "g:image_link" => offer.pictures.insert(0, value),
+            "g:additional_image_link" => offer.pictures.push(value),
+            _ => {}
+        }
+    }
+    // the sale price is the actual one, the regular price becomes the old one
+    let (price, old_price) = match sale_price {
+        Some(sale_price) => (Some(sale_price), price),
+        None => (price, None),
+    };
+    if let Some(price) = price {
+        match parse_price(&price) {
+            Some((price, currency_id)) => {
+                offer.price = Some(price);
+                offer.currency_id = currency_id;
+            }
+            None => warn!("{}: Cannot parse price: {}", offer.offer_id, price),
+        }
+    }
+    offer.old_price = old_price.as_deref()
+        .and_then(parse_price)
+        .map(|(price, _)| price);
+    Ok(Some(offer))
+}
+
+/// Parses `15.00 USD` into the price and currency code
+pub(crate) fn parse_price(value: &str) -> Option<(f32, Option<String>)> {
+    let mut price = None;
+    let mut currency_id = None;
+    for part in value.split_whitespace() {
+        match part.parse() {
+            Ok(p) => price = Some(p),
+            Err(_) => currency_id = Some(part.to_string()),
+        }
+    }
+    Some((price?, currency_id))
+}
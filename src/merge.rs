@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::str::FromStr;
+
+use crate::currency;
+use crate::error::Error;
+use crate::models::NewProduct;
+use crate::parser::Offer;
+
+/// How to choose between offers of different suppliers for the same SKU
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum MergeStrategy {
+    /// Offer of the supplier listed first in `--supplier-priority` wins
+    Priority,
+    /// The cheapest offer wins, supplier priority breaks ties
+    LowestPrice,
+}
+
+impl FromStr for MergeStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<MergeStrategy, Error> {
+        match s {
+            "priority" => Ok(MergeStrategy::Priority),
+            "lowest-price" => Ok(MergeStrategy::LowestPrice),
+            _ => Err(Error::config(format!("Unknown merge strategy: {}", s))),
+        }
+    }
+}
+
+/// SKU that identifies the same goods in feeds of different suppliers
+pub(crate) fn merge_key(offer: &Offer) -> Option<String> {
+    if let Some(barcode) = offer.barcode.as_deref().filter(|b| !b.is_empty()) {
+        return Some(format!("barcode:{}", barcode));
+    }
+    offer.vendor_code.as_deref()
+        .filter(|c| !c.is_empty())
+        .map(|c| format!("vendorCode:{}", c))
+}
+
+/// Result of adding an offer to the merge
+#[derive(Debug, PartialEq)]
+pub(crate) enum Merged {
+    /// The first offer of the SKU
+    First,
+    /// The offer beats the offers of the SKU seen before
+    Wins,
+    /// An offer of another supplier seen before is better
+    Loses,
+}
+
+/// Chooses the best offer per SKU while the file is parsed. Only the rank and the price
+/// of the current winner are kept, so products are synced as they come: a later better offer
+/// updates the merged product again.
+pub(crate) struct OfferMerger<'a> {
+    strategy: MergeStrategy,
+    /// Suppliers (`<shop>` names) from the highest priority to the lowest one
+    priorities: &'a [String],
+    /// Rates of `--currency-rate` to compare prices in different currencies
+    rates: &'a [(String, f32)],
+    best: HashMap<String, (usize, Option<f32>)>,
+}
+
+impl<'a> OfferMerger<'a> {
+    pub fn new(strategy: MergeStrategy, priorities: &'a [String], rates: &'a [(String, f32)]) -> OfferMerger<'a> {
+        OfferMerger { strategy, priorities, rates, best: HashMap::new() }
+    }
+
+    /// Compares the product with the best offer of the SKU seen before. The product gets
+    /// the supplier-independent id of the SKU so all the suppliers update the same product.
+    pub fn add(&mut self, key: &str, product: &mut NewProduct) -> Merged {
+        product.offer_id = key.to_string();
+        product.hub_stock_id = key.to_string();
+        let rank = product.source_supplier.as_ref()
+            .and_then(|s| self.priorities.iter().position(|p| p == s))
+            .unwrap_or(self.priorities.len());
+        let price = currency::to_default(self.rates, product.price, product.currencyId.as_deref());
+        match self.best.entry(key.to_string()) {
+            Entry::Vacant(e) => {
+                e.insert((rank, price));
+                Merged::First
+            }
+            Entry::Occupied(mut e) => {
+                let (best_rank, best_price) = *e.get();
+                if wins(self.strategy, (rank, price), (best_rank, best_price)) {
+                    e.insert((rank, price));
+                    Merged::Wins
+                } else {
+                    Merged::Loses
+                }
+            }
+        }
+    }
+}
+
+/// Prices are compared in the default currency, offers whose price cannot be converted
+/// are compared by the supplier priority
+fn wins(
+    strategy: MergeStrategy, (rank, price): (usize, Option<f32>), (best_rank, best_price): (usize, Option<f32>),
+) -> bool {
+    match (strategy, price, best_price) {
+        (MergeStrategy::LowestPrice, Some(price), Some(best_price)) if price != best_price => price < best_price,
+        _ => rank < best_rank,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wins() {
+        use MergeStrategy::*;
+
+        assert!(wins(Priority, (0, Some(200.0)), (1, Some(100.0))));
+        assert!(!wins(Priority, (2, Some(50.0)), (1, Some(100.0))));
+        assert!(wins(LowestPrice, (2, Some(50.0)), (1, Some(100.0))));
+        assert!(!wins(LowestPrice, (0, Some(150.0)), (1, Some(100.0))));
+        // ties and unknown rates fall back to the priority
+        assert!(wins(LowestPrice, (0, Some(100.0)), (1, Some(100.0))));
+        assert!(wins(LowestPrice, (0, None), (1, Some(100.0))));
+        assert!(!wins(LowestPrice, (2, Some(10.0)), (1, None)));
+    }
+
+    #[test]
+    fn test_lowest_price_in_different_currencies() {
+        let priorities = vec!("first".to_string(), "second".to_string());
+        let rates = vec!(("USD".to_string(), 40.0));
+        let mut merger = OfferMerger::new(MergeStrategy::LowestPrice, &priorities, &rates);
+        let product = |supplier: &str, price: f32, currency: &str| NewProduct {
+            source_supplier: Some(supplier.to_string()),
+            price,
+            currencyId: Some(currency.to_string()),
+            ..test_product()
+        };
+
+        let mut uah = product("first", 1000.0, "UAH");
+        assert_eq!(merger.add("barcode:1", &mut uah), Merged::First);
+        assert_eq!(uah.hub_stock_id, "barcode:1");
+        // 30 USD is 1200 UAH
+        assert_eq!(merger.add("barcode:1", &mut product("second", 30.0, "USD")), Merged::Loses);
+        assert_eq!(merger.add("barcode:1", &mut product("second", 20.0, "USD")), Merged::Wins);
+    }
+
+    pub(crate) fn test_product() -> NewProduct {
+        NewProduct {
+            offer_id: "1".to_string(),
+            hub_stock_id: "1".to_string(),
+            categoryId: 1,
+            name: "Product".to_string(),
+            price: 0.0,
+            oldprice: None,
+            currencyId: None,
+            available: 1,
+            description: None,
+            file_id: None,
+            on_sale: None,
+            discount_percent: None,
+            tags: None,
+            slug: None,
+            GTIN: None,
+            price_from: 0,
+            vat: None,
+            status: "active".to_string(),
+            source_supplier: None,
+            category_path: None,
+            adult: 0,
+            age: None,
+            last_import_id: None,
+            sku: None,
+            low_stock: 0,
+            stocks: vec!(),
+            stock_only: false,
+            keywords: vec!(),
+            channel_visible: true,
+            group_id: None,
+            params: vec!(),
+            pictures: vec!(),
+            original_price: None,
+            variant_of: None,
+            relations: vec!(),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use chrono::Utc;
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::ProcessedStat;
+use crate::error::Error;
+use crate::progress::StatSink;
+
+/// Counters of the run in Prometheus text format (`--metrics-file`). The file is replaced
+/// atomically so the textfile collector never reads a partially written one.
+pub(crate) struct MetricsFile {
+    path: PathBuf,
+    /// Value of the `feed` label, the metrics of different feeds can be collected from one directory
+    feed: String,
+}
+
+impl MetricsFile {
+    pub fn new(path: &Path, feed_path: &Path) -> MetricsFile {
+        MetricsFile {
+            path: path.to_path_buf(),
+            feed: feed_path.to_string_lossy().into_owned(),
+        }
+    }
+
+    fn write(&self, out: &mut dyn Write, stat: &ProcessedStat) -> Result<(), Error> {
+        let metrics: [(&str, &str, f64); 14] = [
+            ("total_offers", "Offers in the feed", stat.total_offers as f64),
+            ("parsed_offers", "Offers converted into products", stat.parsed_offers as f64),
+            ("ignored_offers", "Offers with errors or missing required fields", stat.ignored_offers as f64),
+            ("rejected_offers", "Offers rejected by the transform or the rules", stat.rejected_offers as f64),
+            ("synced_products", "Products synced into the database", stat.synced_products as f64),
+            ("updated_price", "Products with updated price", stat.updated_price as f64),
+            ("updated_available", "Products with updated availability", stat.updated_available as f64),
+            ("inserted_products", "Inserted products", stat.inserted_products as f64),
+            ("marked_as_unavailable", "Products missing in the feed", stat.marked_as_unavailable as f64),
+            ("failed_offers", "Offers failed with transient database errors", stat.failed_offers as f64),
+            ("chunks", "Synced chunks", stat.chunk_durations.len() as f64),
+            ("duration_seconds", "Duration of the run", stat.total_duration.as_secs_f64()),
+            ("parse_duration_seconds", "Duration of parsing the feed", stat.parse_duration.as_secs_f64()),
+            ("last_success_timestamp_seconds", "Time the run finished", Utc::now().timestamp() as f64),
+        ];
+        let feed = self.feed.replace('\\', "\\\\").replace('"', "\\\"");
+        for (name, help, value) in metrics.iter() {
+            writeln!(out, "# HELP hubber_xml_{} {}", name, help)?;
+            writeln!(out, "# TYPE hubber_xml_{} gauge", name)?;
+            writeln!(out, "hubber_xml_{}{{feed=\"{}\"}} {}", name, feed, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl StatSink for MetricsFile {
+    fn record(&self, stat: &ProcessedStat) -> Result<(), Error> {
+        let tmp_path = self.path.with_extension("tmp");
+        let file = File::create(&tmp_path)
+            .map_err(|e| Error::config_caused_by(format!("Cannot create metrics file: {}", tmp_path.display()), e))?;
+        let mut out = BufWriter::new(file);
+        self.write(&mut out, stat)?;
+        out.flush()?;
+        drop(out);
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
@@ -1,13 +1,25 @@
 #![allow(non_snake_case)]
-use super::schema::products;
+use super::schema::{
+    categories, import_chunks, import_quarantine, import_runs, possible_duplicates, price_history, product_channels,
+    product_conflicts, product_hashes, product_keywords, product_relations, product_stocks, product_variants,
+    products, products_staging, supplier_feed_fields, supplier_feed_stats, supplier_price_medians,
+};
+
+use serde::Serialize;
+
+use crate::currency::OriginalPrice;
+use crate::relations::RelationType;
 
 pub const AVAILABLE: i8 = 1;
 pub const NOT_AVAILABLE: i8 = 0;
 
 pub const HUBBER_FILE_ID: i8 = 1;
 
-#[derive(Insertable)]
-#[table_name="products"]
+pub const STATUS_ACTIVE: &str = "active";
+pub const STATUS_DRAFT: &str = "draft";
+
+#[derive(Insertable, Clone, Debug, Serialize)]
+#[diesel(table_name = products)]
 pub struct NewProduct {
     pub offer_id: String,
     pub hub_stock_id: String,
@@ -19,8 +31,114 @@ pub struct NewProduct {
     pub available: i8,
     pub description: Option<String>,
     pub file_id: Option<i8>,
+    pub on_sale: Option<i8>,
+    pub discount_percent: Option<i32>,
+    pub tags: Option<String>,
+    pub slug: Option<String>,
+    pub GTIN: Option<i64>,
+    pub price_from: i8,
+    pub vat: Option<String>,
+    pub status: String,
+    pub source_supplier: Option<String>,
+    pub category_path: Option<String>,
+    pub adult: i8,
+    pub age: Option<i32>,
+    #[serde(skip)]
+    pub last_import_id: Option<String>,
+    /// Internal SKU built by `--sku-template`
+    pub sku: Option<String>,
+    /// Quantity is at or below `--low-stock-threshold`
+    pub low_stock: i8,
+    /// Quantity in stock per supplier, they are saved into `product_stocks`
+    #[diesel(skip_insertion)]
+    pub stocks: Vec<(String, i32)>,
+    /// Offer lost to an offer of another supplier with `--merge-strategy`, only its stock is saved
+    #[diesel(skip_insertion)]
+    pub stock_only: bool,
+    /// Supplier keywords, they are saved into `product_keywords`
+    #[diesel(skip_insertion)]
+    pub keywords: Vec<String>,
+    /// Visibility in the `--channel` sales channel, it is saved into `product_channels`
+    #[diesel(skip_insertion)]
+    pub channel_visible: bool,
+    #[diesel(skip_insertion)]
+    pub group_id: Option<String>,
+    /// `<param name="...">` values of the offer
+    #[diesel(skip_insertion)]
+    pub params: Vec<(String, String)>,
+    /// Picture URLs checked by `--validate-picture-urls`
+    #[diesel(skip_insertion)]
+    pub pictures: Vec<String>,
+    /// Price of the offer before `--convert-prices`, it is saved into the `*_original` columns
+    #[diesel(skip_insertion)]
+    pub original_price: Option<OriginalPrice>,
+    /// Offer id of the canonical product when the product is its variant,
+    /// variants are saved into `product_variants`
+    #[diesel(skip_insertion)]
+    pub variant_of: Option<String>,
+    /// Offer ids of the related products, they are saved into `product_relations`
+    #[diesel(skip_insertion)]
+    pub relations: Vec<(String, RelationType)>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = products_staging)]
+pub struct NewStagedProduct<'a> {
+    pub offer_id: &'a str,
+    pub hub_stock_id: &'a str,
+    pub categoryId: i32,
+    pub name: &'a str,
+    pub price: f32,
+    pub oldprice: Option<f32>,
+    pub currencyId: Option<&'a str>,
+    pub available: i8,
+    pub description: Option<&'a str>,
+    pub file_id: Option<i8>,
+    pub on_sale: Option<i8>,
+    pub discount_percent: Option<i32>,
+    pub tags: Option<&'a str>,
+    pub slug: Option<&'a str>,
+    pub GTIN: Option<i64>,
+    pub price_from: i8,
+    pub vat: Option<&'a str>,
+    pub status: &'a str,
+    pub source_supplier: Option<&'a str>,
+    pub category_path: Option<&'a str>,
+    pub adult: i8,
+    pub age: Option<i32>,
+}
+
+impl<'a> From<&'a NewProduct> for NewStagedProduct<'a> {
+    fn from(p: &'a NewProduct) -> NewStagedProduct<'a> {
+        NewStagedProduct {
+            offer_id: &p.offer_id,
+            hub_stock_id: &p.hub_stock_id,
+            categoryId: p.categoryId,
+            name: &p.name,
+            price: p.price,
+            oldprice: p.oldprice,
+            currencyId: p.currencyId.as_deref(),
+            available: p.available,
+            description: p.description.as_deref(),
+            file_id: p.file_id,
+            on_sale: p.on_sale,
+            discount_percent: p.discount_percent,
+            tags: p.tags.as_deref(),
+            slug: p.slug.as_deref(),
+            GTIN: p.GTIN,
+            price_from: p.price_from,
+            vat: p.vat.as_deref(),
+            status: &p.status,
+            source_supplier: p.source_supplier.as_deref(),
+            category_path: p.category_path.as_deref(),
+            adult: p.adult,
+            age: p.age,
+        }
+    }
 }
 
+/// Full row of the products table, not every column is used by the sync
+#[allow(dead_code)]
 #[derive(Queryable, Debug)]
 pub struct Product {
     pub id: i32,
@@ -35,6 +153,30 @@ pub struct Product {
     pub description: Option<String>,
     pub renew_data: Option<chrono::NaiveDateTime>,
     pub file_id: Option<i8>,
+    pub on_sale: Option<i8>,
+    pub discount_percent: Option<i32>,
+    pub unavailable_runs: i32,
+    pub tags: Option<String>,
+    pub slug: Option<String>,
+    pub GTIN: Option<i64>,
+    pub version: i32,
+    pub locked_fields: Option<String>,
+    pub to_renew: Option<i8>,
+    pub price_from: i8,
+    pub vat: Option<String>,
+    pub status: String,
+    pub source_supplier: Option<String>,
+    pub quantity_in_stock: Option<i32>,
+    pub category_path: Option<String>,
+    pub adult: i8,
+    pub age: Option<i32>,
+    pub last_import_id: Option<String>,
+    pub sku: Option<String>,
+    pub low_stock: i8,
+    pub price_original: Option<f32>,
+    pub oldprice_original: Option<f32>,
+    pub currency_original: Option<String>,
+    pub currency_rate: Option<f32>,
 }
 
 //#[derive(QueryableByName)]
@@ -43,15 +185,228 @@ pub struct Product {
 //}
 
 #[derive(AsChangeset, Default, Debug)]
-#[table_name="products"]
+#[diesel(table_name = products)]
 pub struct ModProduct<'a> {
     pub available: Option<&'a i8>,
     pub price: Option<&'a f32>,
     pub oldprice: Option<Option<&'a f32>>,
     pub currencyId: Option<Option<&'a str>>,
     pub renew_date: Option<&'a chrono::NaiveDateTime>,
+    pub on_sale: Option<Option<&'a i8>>,
+    pub discount_percent: Option<Option<&'a i32>>,
+    pub unavailable_runs: Option<i32>,
+    pub to_renew: Option<i8>,
+    pub price_from: Option<&'a i8>,
+    pub vat: Option<Option<&'a str>>,
+    pub category_path: Option<Option<&'a str>>,
+    pub adult: Option<&'a i8>,
+    pub age: Option<Option<&'a i32>>,
+    pub last_import_id: Option<&'a str>,
+    pub low_stock: Option<&'a i8>,
+    pub source_supplier: Option<Option<&'a str>>,
 //    pub categoryId: Option<&'a i32>,
 //    pub name: Option<&'a str>,
 //    pub oldprice: Option<&'a Option<f32>>,
 //    pub description: Option<&'a Option<String>>,
 }
+
+#[derive(Insertable)]
+#[diesel(table_name = price_history)]
+pub struct NewPriceHistory {
+    pub product_id: i32,
+    pub old_price: f32,
+    pub new_price: f32,
+    /// Feed price before `--price-smoothing`
+    pub feed_price: Option<f32>,
+    pub currencyId: Option<String>,
+    /// Id of the import run, see `ImportContext::import_id`
+    pub run_id: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = possible_duplicates)]
+pub struct NewPossibleDuplicate {
+    pub product_id: i32,
+    pub duplicate_product_id: i32,
+    pub similarity: f32,
+    pub reason: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = import_quarantine)]
+pub struct NewQuarantinedOffer {
+    pub offer_id: String,
+    pub hub_stock_id: String,
+    pub name: String,
+    pub error: String,
+    pub run_id: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_stocks)]
+pub struct NewProductStock {
+    pub hub_stock_id: String,
+    pub supplier: String,
+    pub quantity: i32,
+    pub run_id: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_channels)]
+pub struct NewProductChannel<'a> {
+    pub hub_stock_id: &'a str,
+    pub channel: &'a str,
+    pub visible: i8,
+    pub run_id: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_hashes)]
+pub struct NewProductHash<'a> {
+    pub hub_stock_id: &'a str,
+    pub offer_hash: &'a str,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_conflicts)]
+pub struct NewProductConflict {
+    pub product_id: i32,
+    pub field: String,
+    pub offer_id: String,
+    pub change_class: String,
+    pub db_value: String,
+    pub feed_value: String,
+    pub import_id: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_keywords)]
+pub struct NewProductKeyword<'a> {
+    pub hub_stock_id: &'a str,
+    pub keyword: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_relations)]
+pub struct NewProductRelation<'a> {
+    pub product_id: i32,
+    pub related_product_id: i32,
+    pub relation_type: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_variants)]
+pub struct NewProductVariant {
+    pub hub_stock_id: String,
+    pub group_id: String,
+    pub product_hub_stock_id: String,
+    pub price: f32,
+    pub available: i8,
+    pub size: Option<String>,
+    pub color: Option<String>,
+    pub params: Option<String>,
+    pub run_id: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = categories)]
+pub struct NewCategory {
+    pub id: i32,
+    pub parent_id: Option<i32>,
+    pub name: String,
+    pub auto_created: i8,
+}
+
+#[derive(Queryable, Debug)]
+pub struct ImportRun {
+    pub total_offers: u32,
+    pub ignored_offers: u32,
+    pub parsed_offers: u32,
+    pub updated_price: u32,
+    pub updated_available: u32,
+    pub inserted_products: u32,
+    pub marked_as_unavailable: u32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = import_chunks)]
+pub struct NewImportChunk<'a> {
+    pub chunk_hash: &'a str,
+    pub idempotency_key: &'a str,
+    pub chunk_index: u32,
+    pub batch_index: u32,
+    pub products: u32,
+    pub import_id: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = import_runs)]
+pub struct NewImportRun {
+    pub import_id: String,
+    pub file_path: String,
+    pub total_offers: u32,
+    pub ignored_offers: u32,
+    pub parsed_offers: u32,
+    pub updated_price: u32,
+    pub updated_available: u32,
+    pub inserted_products: u32,
+    pub marked_as_unavailable: u32,
+    pub rules_version: Option<String>,
+    pub archive_path: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = supplier_feed_stats)]
+pub struct NewSupplierFeedStat {
+    pub supplier: String,
+    pub feed_date: Option<chrono::NaiveDateTime>,
+    pub run_at: chrono::NaiveDateTime,
+    pub total_offers: u32,
+    pub error_offers: u32,
+}
+
+#[derive(Queryable, Debug)]
+pub struct SupplierFeedStat {
+    pub supplier: String,
+    pub feed_date: Option<chrono::NaiveDateTime>,
+    pub run_at: chrono::NaiveDateTime,
+    pub total_offers: u32,
+    pub error_offers: u32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = supplier_feed_fields)]
+pub struct NewSupplierFeedField {
+    pub supplier: String,
+    pub field: String,
+    pub offers: u32,
+    pub total_offers: u32,
+    pub run_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Debug)]
+pub struct SupplierFeedField {
+    pub field: String,
+    pub offers: u32,
+    pub total_offers: u32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = supplier_price_medians)]
+pub struct NewSupplierPriceMedian {
+    pub supplier: String,
+    pub median_price: f64,
+    pub offers: u32,
+    pub run_at: chrono::NaiveDateTime,
+}
+
+#[derive(Queryable, Debug)]
+pub struct PricingRule {
+    pub category_id: Option<i32>,
+    pub percent: f32,
+    pub fixed_add: f32,
+    pub rounding: Option<f32>,
+}
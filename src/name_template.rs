@@ -0,0 +1,98 @@
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::parser::Offer;
+
+const FIELDS: &[&str] = &["name", "vendor", "vendorCode", "typePrefix", "model"];
+
+#[derive(Debug)]
+pub(crate) enum Part {
+    Literal(String),
+    /// Offer fields separated by `|`, the first non-empty one is used
+    Field(Vec<String>),
+}
+
+/// Template of product names, e.g. `{vendor} {typePrefix} {model|name}`
+#[derive(Debug)]
+pub(crate) struct NameTemplate {
+    parts: Vec<Part>,
+}
+
+impl FromStr for NameTemplate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<NameTemplate, Error> {
+        Ok(NameTemplate { parts: parse_parts(s, FIELDS, "name template")? })
+    }
+}
+
+/// Parses `{field|fallback}` placeholders and literals of a template of the given kind
+pub(crate) fn parse_parts(s: &str, known_fields: &[&str], kind: &str) -> Result<Vec<Part>, Error> {
+    let mut parts = vec!();
+    let mut rest = s;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(Part::Literal(rest[..start].to_string()));
+        }
+        let end = rest[start..].find('}')
+            .ok_or_else(|| Error::config(format!("Unclosed placeholder in {}: {}", kind, s)))?;
+        let fields = rest[start + 1..start + end]
+            .split('|')
+            .map(|f| f.trim().to_string())
+            .collect::<Vec<_>>();
+        if let Some(unknown) = fields.iter().find(|f| !known_fields.contains(&f.as_str())) {
+            return Err(Error::config(format!(
+                "Unknown field in {}: {}, expected one of: {}", kind, unknown, known_fields.join(", ")
+            )));
+        }
+        parts.push(Part::Field(fields));
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest.to_string()));
+    }
+    if !parts.iter().any(|p| matches!(p, Part::Field(_))) {
+        return Err(Error::config(format!("The {} has no placeholders: {}", kind, s)));
+    }
+    Ok(parts)
+}
+
+impl NameTemplate {
+    /// Builds the name from the offer fields, missing fields are skipped with their surrounding
+    /// whitespace. Returns `None` when all the fields are missing.
+    pub fn render(&self, offer: &Offer) -> Option<String> {
+        let mut name = String::new();
+        let mut has_values = false;
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => name.push_str(literal),
+                Part::Field(fields) => {
+                    let value = fields.iter().find_map(|f| field_value(offer, f));
+                    if let Some(value) = value {
+                        name.push_str(value);
+                        has_values = true;
+                    }
+                }
+            }
+        }
+        if !has_values {
+            return None;
+        }
+        Some(name.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+pub(crate) fn field_value<'a>(offer: &'a Offer, field: &str) -> Option<&'a str> {
+    let value = match field {
+        "offer_id" => Some(offer.offer_id.as_str()),
+        "supplier" => offer.shop.as_deref(),
+        "barcode" => offer.barcode.as_deref(),
+        "name" => offer.name.as_deref(),
+        "vendor" => offer.vendor.as_deref(),
+        "vendorCode" => offer.vendor_code.as_deref(),
+        "typePrefix" => offer.type_prefix.as_deref(),
+        "model" => offer.model.as_deref(),
+        _ => None,
+    };
+    value.filter(|v| !v.is_empty())
+}
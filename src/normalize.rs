@@ -0,0 +1,179 @@
+use regex::{Regex, RegexBuilder};
+
+use std::fs;
+use std::path::Path;
+
+use crate::error::Error;
+
+#[derive(Default, Debug)]
+pub(crate) struct NameNormalizationStat {
+    pub changed: u32,
+    pub whitespace: u32,
+    pub skus: u32,
+    pub emoji: u32,
+    pub all_caps: u32,
+    pub banned: u32,
+    pub truncated: u32,
+}
+
+/// Cleans up product names provided by the supplier.
+pub(crate) struct NameNormalizer {
+    sku_re: Regex,
+    banned_re: Option<Regex>,
+    max_length: Option<usize>,
+}
+
+impl NameNormalizer {
+    pub fn new(banned_words_path: Option<&Path>, max_length: Option<usize>) -> Result<NameNormalizer, Error> {
+        let banned_re = match banned_words_path {
+            Some(path) => {
+                let content = fs::read_to_string(path)
+                    .map_err(|e| Error::config_caused_by(
+                        format!("Cannot read banned words: {}", path.display()), e
+                    ))?;
+                let phrases = content.lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .map(regex::escape)
+                    .collect::<Vec<_>>();
+                if phrases.is_empty() {
+                    None
+                } else {
+                    Some(
+                        RegexBuilder::new(&format!(r"\b(?:{})\b", phrases.join("|")))
+                            .case_insensitive(true)
+                            .build()
+                            .map_err(|e| Error::config_caused_by("Invalid banned words", e))?
+                    )
+                }
+            }
+            None => None,
+        };
+        Ok(NameNormalizer {
+            sku_re: RegexBuilder::new(r"[(\[]?\s*\b(?:арт|артикул|art|код|sku)\b\.?\s*:?\s*[\w\-/.]*\d[\w\-/.]*\s*[)\]]?")
+                .case_insensitive(true)
+                .build()
+                .expect("valid SKU regex"),
+            banned_re,
+            max_length,
+        })
+    }
+
+    pub fn normalize(
+        &self, name: &str, vendor_code: Option<&str>, stat: &mut NameNormalizationStat
+    ) -> String {
+        let mut name = name.to_string();
+
+        let stripped = self.sku_re.replace_all(&name, " ");
+        let stripped = match vendor_code {
+            Some(code) if code.chars().count() >= 3 => {
+                strip_vendor_code(&stripped, code).unwrap_or_else(|| stripped.into_owned())
+            }
+            _ => stripped.into_owned(),
+        };
+        if stripped != name {
+            stat.skus += 1;
+            name = stripped;
+        }
+
+        if name.chars().any(is_emoji) {
+            stat.emoji += 1;
+            name = name.chars().filter(|&c| !is_emoji(c)).collect();
+        }
+
+        if let Some(ref banned_re) = self.banned_re {
+            let cleaned = banned_re.replace_all(&name, " ");
+            if cleaned != name {
+                stat.banned += 1;
+                name = cleaned.into_owned();
+            }
+        }
+
+        if is_all_caps(&name) {
+            stat.all_caps += 1;
+            name = to_sentence_case(&name);
+        }
+
+        let collapsed = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        if collapsed != name {
+            stat.whitespace += 1;
+            name = collapsed;
+        }
+
+        if let Some(max_length) = self.max_length {
+            if name.chars().count() > max_length {
+                stat.truncated += 1;
+                name = truncate_at_word(&name, max_length);
+            }
+        }
+
+        name
+    }
+}
+
+/// Removes the vendor code when it is the first or the last word of the name, e.g. `AB-123 Drill`
+/// or `Drill (AB-123)`. The code inside another word or in the middle of the name is kept.
+fn strip_vendor_code(name: &str, code: &str) -> Option<String> {
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+    let is_separator = |c: char| c.is_whitespace() || "([-–:,/".contains(c);
+    let name = name.trim();
+    if let Some(rest) = name.strip_prefix(code) {
+        if is_boundary(rest.chars().next()) {
+            return Some(rest.trim_start_matches(|c| is_separator(c) && c != '(' && c != '[').to_string());
+        }
+    }
+    let rest = name.trim_end_matches([')', ']']).strip_suffix(code)?;
+    if is_boundary(rest.chars().last()) {
+        return Some(rest.trim_end_matches(is_separator).to_string());
+    }
+    None
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0xFE0F | 0x200D)
+}
+
+fn is_all_caps(s: &str) -> bool {
+    let letters = s.chars().filter(|c| c.is_alphabetic()).collect::<Vec<_>>();
+    letters.len() >= 4 && letters.iter().all(|c| c.is_uppercase())
+}
+
+fn to_sentence_case(s: &str) -> String {
+    let lower = s.to_lowercase();
+    let mut chars = lower.chars();
+    let mut res = String::with_capacity(lower.len());
+    for c in &mut chars {
+        if c.is_alphabetic() {
+            res.extend(c.to_uppercase());
+            break;
+        }
+        res.push(c);
+    }
+    res.extend(chars);
+    res
+}
+
+fn truncate_at_word(s: &str, max_length: usize) -> String {
+    let truncated = s.chars().take(max_length).collect::<String>();
+    match truncated.rfind(' ') {
+        Some(pos) if pos > 0 && s.chars().nth(max_length) != Some(' ') => {
+            truncated[..pos].trim_end().to_string()
+        }
+        _ => truncated.trim_end().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_vendor_code() {
+        assert_eq!(strip_vendor_code("AB-123 Drill", "AB-123").as_deref(), Some("Drill"));
+        assert_eq!(strip_vendor_code("Drill - AB-123", "AB-123").as_deref(), Some("Drill"));
+        assert_eq!(strip_vendor_code("Drill (AB-123)", "AB-123").as_deref(), Some("Drill"));
+        assert_eq!(strip_vendor_code("Drill AB-1234", "AB-123"), None);
+        assert_eq!(strip_vendor_code("Drill AB-123 Pro", "AB-123"), None);
+        assert_eq!(strip_vendor_code("Samsung Galaxy", "sung"), None);
+    }
+}
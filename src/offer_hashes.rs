@@ -0,0 +1,150 @@
+use chrono::NaiveDateTime;
+
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use sha1_smol::Sha1;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::Opts;
+use crate::error::Error;
+use crate::models::{self, NewProductHash};
+use crate::schema::{product_hashes, products};
+
+/// Hashes are kept in product_hashes table with `--skip-unchanged` and `--resolve` only
+pub(crate) fn is_tracked(opts: &Opts) -> bool {
+    opts.skip_unchanged || !opts.resolve.is_empty()
+}
+
+/// Hash of the feed values the sync compares. Options that enable comparisons are hashed too,
+/// so the products are compared again after they change.
+pub(crate) fn offer_hash(opts: &Opts, p: &models::NewProduct) -> String {
+    let mut hash = Sha1::new();
+    let options = (
+        opts.update_price,
+        opts.update_available,
+        opts.clear_missing_oldprice,
+        opts.min_discount,
+        opts.low_stock_threshold,
+        opts.category_paths,
+        &opts.currency_rates,
+    );
+    hash.update(format!("{:?}", options).as_bytes());
+    let prices = (p.price, p.price_from, p.oldprice, &p.currencyId, &p.vat, p.on_sale, p.discount_percent);
+    let values = (p.available, &p.name, &p.description, &p.category_path, p.adult, p.age, p.low_stock);
+    hash.update(b"\0");
+    hash.update(format!("{:?}", prices).as_bytes());
+    hash.update(b"\0");
+    hash.update(format!("{:?}", values).as_bytes());
+    hash.digest().to_string()
+}
+
+/// Hash of the offer values the product was last synced with
+pub(crate) struct StoredHash {
+    pub offer_hash: String,
+    /// Time of the run that synced the product, it is also written into renew_date of the updated products
+    pub synced_at: NaiveDateTime,
+}
+
+pub(crate) fn load(conn: &mut MysqlConnection, offer_ids: &[&str]) -> Result<HashMap<String, StoredHash>, Error> {
+    Ok(
+        product_hashes::table
+            .select((product_hashes::hub_stock_id, product_hashes::offer_hash, product_hashes::updated_at))
+            .filter(product_hashes::hub_stock_id.eq_any(offer_ids))
+            .load::<(String, String, NaiveDateTime)>(conn)?
+            .into_iter()
+            .map(|(offer_id, offer_hash, synced_at)| (offer_id, StoredHash { offer_hash, synced_at }))
+            .collect()
+    )
+}
+
+/// Offer ids whose stored hash is the same as the current one. Hashes of the products
+/// deleted from the table are ignored so such offers can be inserted again.
+pub(crate) fn load_unchanged(
+    conn: &mut MysqlConnection, hashes: &HashMap<&str, String>, stored: &HashMap<String, StoredHash>,
+) -> Result<HashSet<String>, Error> {
+    let matching_ids = matching_ids(hashes, stored);
+    if matching_ids.is_empty() {
+        return Ok(HashSet::new());
+    }
+    Ok(
+        products::table
+            .select(products::hub_stock_id)
+            .filter(products::hub_stock_id.eq_any(&matching_ids))
+            .load::<Option<String>>(conn)?
+            .into_iter()
+            .flatten()
+            .collect()
+    )
+}
+
+/// Offer ids whose hash did not change since the sync, offers without a stored hash are never matching
+fn matching_ids<'a>(hashes: &HashMap<&str, String>, stored: &'a HashMap<String, StoredHash>) -> Vec<&'a str> {
+    stored.iter()
+        .filter(|(offer_id, s)| hashes.get(offer_id.as_str()) == Some(&s.offer_hash))
+        .map(|(offer_id, _)| offer_id.as_str())
+        .collect()
+}
+
+/// Saves hashes of the products that match the feed after the sync
+pub(crate) fn save(
+    conn: &mut MysqlConnection, hashes: &[(&str, &str)], synced_at: &NaiveDateTime,
+) -> Result<(), Error> {
+    if hashes.is_empty() {
+        return Ok(());
+    }
+    let rows = hashes.iter()
+        .map(|&(hub_stock_id, offer_hash)| NewProductHash { hub_stock_id, offer_hash, updated_at: *synced_at })
+        .collect::<Vec<_>>();
+    diesel::replace_into(product_hashes::table)
+        .values(&rows)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Forgets hashes of the products changed outside of the sync, e.g. marked as unavailable,
+/// so their offers are compared again even when they come back unchanged
+pub(crate) fn invalidate(conn: &mut MysqlConnection, offer_ids: &[String]) -> Result<(), Error> {
+    diesel::delete(product_hashes::table.filter(product_hashes::hub_stock_id.eq_any(offer_ids)))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Moves the sync time of the hashes to renew_date of the products written by the import, so writes
+/// of the importer that save no hashes are not taken for manual changes. `import_id` is a bound
+/// parameter or an inlined value.
+pub(crate) fn mark_written_sql(import_id: &str) -> String {
+    format!(
+        "UPDATE product_hashes h JOIN products p ON p.hub_stock_id = h.hub_stock_id \
+         SET h.updated_at = p.renew_date WHERE p.last_import_id = {} AND p.renew_date > h.updated_at",
+        import_id
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::tests::test_product;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_matching_ids() {
+        let opts = Opts::from_iter(&["hubber_xml", "feed.xml"]);
+        let product = test_product();
+        let hash = offer_hash(&opts, &product);
+        let hashes = vec!((product.hub_stock_id.as_str(), hash.clone())).into_iter().collect::<HashMap<_, _>>();
+        let synced_at = NaiveDateTime::parse_from_str("2026-10-16 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let stored = vec!(("1".to_string(), StoredHash { offer_hash: hash, synced_at }))
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        assert_eq!(matching_ids(&hashes, &stored), vec!("1"));
+
+        let mut changed = test_product();
+        changed.price = 10.0;
+        let changed_hashes = vec!(("1", offer_hash(&opts, &changed))).into_iter().collect::<HashMap<_, _>>();
+        assert!(matching_ids(&changed_hashes, &stored).is_empty());
+        // the hash of a product marked as missing is forgotten, its identical offer is synced again
+        assert!(matching_ids(&hashes, &HashMap::new()).is_empty());
+    }
+}
@@ -1,40 +1,72 @@
-use byteorder::{LittleEndian, ReadBytesExt};
+use chrono::NaiveDateTime;
 
-use chrono::{Utc, Timelike};
 
+use diesel::Connection;
 use diesel::mysql::MysqlConnection;
 
-use failure::Error;
 
-use flate2::bufread::GzDecoder;
-
-use indicatif::{ProgressBar, ProgressStyle};
-
-use log::{error, warn};
+use log::{debug, error, warn};
 
 use quick_xml::Reader;
-use quick_xml::events::Event;
+use quick_xml::events::{BytesStart, Event};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use std::collections::HashSet;
-use std::ffi::OsStr;
-use std::fs::{self, File};
-use std::io::{BufReader, SeekFrom};
-use std::io::prelude::*;
+use std::collections::BTreeSet;
 use std::time::{Duration, Instant};
 
-use crate::{Opts, ProcessedStat};
-use crate::models::{AVAILABLE, NOT_AVAILABLE};
+use crate::{CHUNK_SIZE, LISTED_DRAFTS, Opts, ProcessedStat};
+use crate::categories::{CategoryCreator, FeedCategories};
+use crate::changes_csv::ChangesCsv;
+use crate::channels::save_visibility;
+use crate::context::ImportContext;
+use crate::currency::{self, save_original_prices, OriginalPrice};
+use crate::deadline;
+use crate::deletes;
+use crate::descriptions;
+use crate::dialect::{self, Dialect};
+use crate::error::{Error, ErrorContext};
+use crate::input;
+use crate::keywords::save_keywords;
+use crate::ledger;
+use crate::memory::{self, SeenOfferIds};
+use crate::merchant::parse_merchant_item;
+use crate::merge::{merge_key, Merged, OfferMerger};
+use crate::models::{self, AVAILABLE, NOT_AVAILABLE};
+use crate::offer_hashes;
 use crate::process::{
     convert_offer_to_product,
-    finilize_processing,
     mark_missing_as_unavailable,
-    sync_products_chunk
+    sync_products_chunk,
+    ChangeKind,
+    ProcessedProducts,
 };
+use crate::normalize::NameNormalizer;
+use crate::progress::{ProgressSink, Stage};
+use crate::publish::Publisher;
+use crate::rejects::{self, RejectsXml};
+use crate::relations::{RelationCollector, RelationType};
+use crate::search::SearchIndexer;
+use crate::limits::{check_lengths, LengthCheck};
+use crate::sanitize::sanitize_offer;
+use crate::staging::{apply_staging, create_staging_table, load_bucket};
+use crate::suppliers::parse_feed_date;
+use crate::stocks::{aggregate_stocks, save_stocks};
+use crate::store::{mysql_connection, ProductStore};
+use crate::trace::{is_traced, trace, trace_offer, Provenance};
+use crate::variants::{save_variants, VariantGrouper};
+use crate::transform::transform_offer;
+use crate::vat::{to_gross, vat_rate, PricesAre};
 
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct Offer {
     pub offer_id: String,
     pub available: i8,
     pub price: Option<f32>,
+    /// The price is the minimal one ("price from")
+    #[serde(default)]
+    pub price_from: bool,
     pub old_price: Option<f32>,
     pub currency_id: Option<String>,
     pub category_id: Option<i32>,
@@ -42,6 +74,48 @@ pub(crate) struct Offer {
     pub description: Option<String>,
     pub vendor: Option<String>,
     pub vendor_code: Option<String>,
+    #[serde(default)]
+    pub type_prefix: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub barcode: Option<String>,
+    /// VAT code, e.g. `VAT_20`
+    #[serde(default)]
+    pub vat: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Name of the enclosing `<shop>`
+    #[serde(default)]
+    pub shop: Option<String>,
+    #[serde(default)]
+    pub quantity: Option<i32>,
+    /// `<adult>true</adult>`
+    #[serde(default)]
+    pub adult: bool,
+    /// `<age>` restriction in years
+    #[serde(default)]
+    pub age: Option<i32>,
+    /// Lead time of the fastest `<delivery-options>` option or `<delivery_days>`
+    #[serde(default)]
+    pub delivery_days: Option<u32>,
+    /// Comma separated `<keywords>`
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// `group_id` attribute joining variants (sizes, colors) of the same goods
+    #[serde(default)]
+    pub group_id: Option<String>,
+    /// `<param name="...">` elements
+    #[serde(default)]
+    pub params: Vec<(String, String)>,
+    /// `<picture>` URLs, the first one is the main picture
+    #[serde(default)]
+    pub pictures: Vec<String>,
+    /// Offer ids of `<rec>` and `<related_offer type="...">` elements with the relation type
+    #[serde(default)]
+    pub relations: Vec<(String, RelationType)>,
+    /// Price in the supplier's currency converted by `--convert-prices`
+    #[serde(skip)]
+    pub original_price: Option<OriginalPrice>,
 }
 
 impl Offer {
@@ -50,6 +124,7 @@ impl Offer {
             offer_id,
             available,
             price: None,
+            price_from: false,
             old_price: None,
             currency_id: None,
             category_id: None,
@@ -57,6 +132,22 @@ impl Offer {
             description: None,
             vendor: None,
             vendor_code: None,
+            type_prefix: None,
+            model: None,
+            barcode: None,
+            vat: None,
+            tags: vec!(),
+            shop: None,
+            quantity: None,
+            adult: false,
+            age: None,
+            delivery_days: None,
+            keywords: vec!(),
+            group_id: None,
+            params: vec!(),
+            pictures: vec!(),
+            relations: vec!(),
+            original_price: None,
         }
     }
 }
@@ -71,220 +162,1403 @@ enum OfferFields {
     Description,
     Vendor,
     VendorCode,
+    TypePrefix,
+    Model,
+    Barcode,
+    Vat,
+    Available,
+    Quantity,
+    InStock,
+    Adult,
+    Age,
+    DeliveryDays,
+    Keywords,
+    Param,
+    Picture,
+    Related,
+}
+
+/// Availability signals of the offer in the order of precedence: `available` attribute,
+/// `<available>` element, `<instock>` element and `count` attribute. Feeds using `count`
+/// and `<instock>` have no other signals, so their quantities also mark the offer available.
+#[derive(Default)]
+struct AvailabilitySignals {
+    attribute: bool,
+    element: bool,
+    in_stock: Option<i8>,
+    count: Option<i32>,
+}
+
+impl AvailabilitySignals {
+    fn resolve(&self, offer: &mut Offer) {
+        if self.attribute || self.element {
+            return;
+        }
+        if let Some(in_stock) = self.in_stock {
+            offer.available = in_stock;
+        } else if let Some(count) = self.count {
+            offer.available = if count > 0 { AVAILABLE } else { NOT_AVAILABLE };
+        }
+    }
+}
+
+/// `<instock>` is either a flag (`true`, `1`, `yes`, `+`) or a quantity
+fn parse_instock(value: &str) -> Option<(i8, Option<i32>)> {
+    match value.to_lowercase().as_str() {
+        "true" | "1" | "yes" | "+" => Some((AVAILABLE, None)),
+        "false" | "0" | "no" | "-" => Some((NOT_AVAILABLE, None)),
+        v => v.parse::<i32>()
+            .ok()
+            .map(|quantity| (if quantity > 0 { AVAILABLE } else { NOT_AVAILABLE }, Some(quantity))),
+    }
+}
+
+/// `<price from="true">` marks offers with a minimal price. The price itself can be
+/// in the `from`, `base` or `value` attribute instead of the element text.
+fn parse_price_attributes(e: &BytesStart, offer: &mut Offer) -> Result<(), Error> {
+    for attr_res in e.attributes() {
+        let attr = attr_res?;
+        let value = String::from_utf8_lossy(&attr.value);
+        let (price, price_from) = match (attr.key, value.trim()) {
+            (b"from", "true") | (b"from", "1") => (None, true),
+            (b"from", "false") | (b"from", "0") | (b"from", "") => (None, false),
+            (b"from", v) | (b"base", v) => (Some(v), true),
+            (b"value", v) => (Some(v), false),
+            _ => continue,
+        };
+        offer.price_from |= price_from;
+        if let Some(price) = price {
+            if let Ok(price) = price.parse() {
+                offer.price = Some(price);
+            } else {
+                warn!("{}: Cannot parse price attribute: {}", offer.offer_id, price);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Keeps the fastest of `<delivery-options>` options: `<option cost="0" days="2-4"/>`
+fn parse_delivery_option(e: &BytesStart, offer: &mut Offer) -> Result<(), Error> {
+    for attr_res in e.attributes() {
+        let attr = attr_res?;
+        if attr.key != b"days" {
+            continue;
+        }
+        let value = String::from_utf8_lossy(&attr.value);
+        match parse_delivery_days(&value) {
+            Some(days) => {
+                offer.delivery_days = Some(offer.delivery_days.map_or(days, |d| d.min(days)));
+            }
+            None => warn!("{}: Cannot parse delivery days: {}", offer.offer_id, value),
+        }
+    }
+    Ok(())
+}
+
+/// Days are either a number or a range like `2-4` whose upper bound is taken
+fn parse_delivery_days(value: &str) -> Option<u32> {
+    let days = value.rsplit('-').next()?;
+    days.trim().parse().ok()
+}
+
+/// Everything that follows synced buckets: creates missing categories before syncing,
+/// publishes and indexes the applied changes after it
+pub(crate) struct ChangeConsumers {
+    categories: Option<CategoryCreator>,
+    publisher: Option<Publisher>,
+    search_indexer: Option<SearchIndexer>,
+    changes_csv: Option<ChangesCsv>,
+    relations: Option<RelationCollector>,
+}
+
+impl ChangeConsumers {
+    pub fn new(opts: &Opts, store: &mut dyn ProductStore) -> Result<ChangeConsumers, Error> {
+        Ok(ChangeConsumers {
+            categories: if opts.create_categories {
+                Some(CategoryCreator::load(
+                    mysql_connection(store, "--create-categories")?, parse_categories(opts)?, opts.categories_parent
+                )?)
+            } else {
+                None
+            },
+            publisher: Publisher::from_opts(opts)?,
+            search_indexer: opts.search_url.as_ref().map(|url| {
+                SearchIndexer::new(opts.search_backend, url, &opts.search_index, opts.search_batch_size)
+            }),
+            changes_csv: match opts.changes_csv {
+                Some(ref path) => Some(ChangesCsv::create(path, opts.report_locale)?),
+                None => None,
+            },
+            relations: if opts.update_relations {
+                Some(RelationCollector::default())
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Resolves the collected offer relations when every offer is synced and saves them
+    pub fn save_relations(&mut self, conn: &mut MysqlConnection, stat: &mut ProcessedStat) -> Result<(), Error> {
+        if let Some(relations) = self.relations.take() {
+            let relations_stat = relations.save(conn)?;
+            stat.product_relations = relations_stat.relations;
+            stat.unresolved_relations = relations_stat.unresolved;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self, stat: &mut ProcessedStat) -> Result<(), Error> {
+        if let Some(ref mut search_indexer) = self.search_indexer {
+            stat.indexed_documents += search_indexer.flush()?;
+        }
+        if let Some(ref mut changes_csv) = self.changes_csv {
+            changes_csv.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Suppliers whose stocks are provided by the file: names of its `<shop>` sections
+pub(crate) fn stock_suppliers(stat: &ProcessedStat) -> Vec<String> {
+    stat.shop_offers.keys()
+        .filter(|shop| !shop.is_empty())
+        .cloned()
+        .collect()
+}
+
+/// Buckets of products that failed with transient database errors,
+/// they are retried after the main pass.
+#[derive(Default)]
+pub(crate) struct RetryQueue {
+    /// Buckets with their indexes in the file
+    buckets: Vec<(u32, Vec<models::NewProduct>)>,
+}
+
+impl RetryQueue {
+    /// Keeps the bucket when the error is transient and retries are enabled
+    pub fn defer(
+        &mut self, products_bucket: Vec<models::NewProduct>, chunk_index: u32, error: Error, ctx: &ImportContext,
+    ) -> Result<(), Error> {
+        if ctx.opts.retry_attempts == 0 || !error.is_transient() {
+            return Err(error);
+        }
+        warn!("Syncing a chunk of {} products failed, will retry it later: {}", products_bucket.len(), error);
+        self.buckets.push((chunk_index, products_bucket));
+        Ok(())
+    }
+
+    pub fn retry(
+        self,
+        store: &mut dyn ProductStore,
+        ctx: &ImportContext,
+        stat: &mut ProcessedStat,
+        consumers: &mut ChangeConsumers,
+        seen_offer_ids: &mut SeenOfferIds,
+    ) -> Result<Duration, Error> {
+        let mut total_sync_duration = Duration::default();
+        for (chunk_index, products_bucket) in self.buckets {
+            let mut attempt = 1;
+            loop {
+                match sync_products_bucket(
+                    store, &products_bucket, chunk_index, 0, ctx, stat, consumers, seen_offer_ids
+                ) {
+                    Ok(sync_duration) => {
+                        total_sync_duration += sync_duration;
+                        stat.retried_chunks += 1;
+                        break;
+                    }
+                    Err(e) if e.is_transient() && attempt < ctx.opts.retry_attempts => {
+                        warn!("Retrying a chunk of {} products failed: {}", products_bucket.len(), e);
+                        attempt += 1;
+                    }
+                    Err(e) if e.is_transient() => {
+                        let offer_ids = products_bucket.iter()
+                            .map(|p| p.offer_id.as_str())
+                            .collect::<Vec<_>>();
+                        error!(
+                            "Giving up syncing a chunk after {} attempts: {}; failed offers: {}",
+                            attempt, e, offer_ids.join(", ")
+                        );
+                        stat.failed_offers += products_bucket.len() as u32;
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(total_sync_duration)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sync_products_bucket(
+    store: &mut dyn ProductStore,
+    products_bucket: &Vec<models::NewProduct>,
+    chunk_index: u32,
+    batch_index: u32,
+    ctx: &ImportContext,
+    stat: &mut ProcessedStat,
+    consumers: &mut ChangeConsumers,
+    seen_offer_ids: &mut SeenOfferIds,
+) -> Result<Duration, Error> {
+    let opts = ctx.opts;
+    let import_id = ctx.import_id.as_str();
+    if let Some(batches) = descriptions::split_large(opts, products_bucket) {
+        let mut sync_duration = Duration::default();
+        // every batch is a separate chunk of the ledger
+        for (batch_index, batch) in batches.iter().enumerate() {
+            if batch.iter().any(|p| descriptions::is_large(opts, p)) {
+                stat.large_description_batches += 1;
+            }
+            sync_duration += sync_products_bucket(
+                store, batch, chunk_index, batch_index as u32, ctx, stat, consumers, seen_offer_ids
+            )?;
+        }
+        return Ok(sync_duration);
+    }
+    if let Some(mut size) = deadline::reduced_chunk_size(opts, stat).filter(|size| *size < products_bucket.len()) {
+        // the size can change after every smaller chunk
+        let mut sync_duration = Duration::default();
+        let mut offset = 0;
+        while offset < products_bucket.len() {
+            let end = (offset + size).min(products_bucket.len());
+            sync_duration += sync_products_bucket(
+                store, &products_bucket[offset..end].to_vec(), chunk_index, batch_index, ctx, stat, consumers,
+                seen_offer_ids
+            )?;
+            offset = end;
+            size = deadline::reduced_chunk_size(opts, stat).unwrap_or(CHUNK_SIZE);
+        }
+        return Ok(sync_duration);
+    }
+    let start_syncing_at = Instant::now();
+    let merged_products;
+    let products_bucket = if products_bucket.iter().any(|p| p.stock_only) {
+        let (stock_offers, products) = products_bucket.iter()
+            .cloned()
+            .partition::<Vec<_>, _>(|p| p.stock_only);
+        if opts.aggregate_stocks {
+            save_stocks(mysql_connection(store, "--aggregate-stocks")?, &stock_offers, import_id)?;
+        }
+        merged_products = products;
+        &merged_products
+    } else {
+        products_bucket
+    };
+    let canonical_products;
+    let products_bucket = if opts.group_variants {
+        let (variants, canonical) = products_bucket.iter()
+            .cloned()
+            .partition::<Vec<_>, _>(|p| p.variant_of.is_some());
+        save_variants(mysql_connection(store, "--group-variants")?, &variants, import_id)?;
+        canonical_products = canonical;
+        &canonical_products
+    } else {
+        products_bucket
+    };
+    if let Some(ref mut relations) = consumers.relations {
+        relations.add(products_bucket);
+    }
+    if let Some(ref mut categories) = consumers.categories {
+        stat.created_categories += categories.create_missing(
+            mysql_connection(store, "--create-categories")?, products_bucket
+        )?;
+    }
+    if opts.staging {
+        let conn = mysql_connection(store, "--staging")?;
+        load_bucket(conn, products_bucket)?;
+        if opts.aggregate_stocks {
+            save_stocks(conn, products_bucket, import_id)?;
+        }
+        if opts.update_keywords {
+            stat.keyword_products += save_keywords(conn, products_bucket)?;
+        }
+        if let Some(ref channel) = opts.channel {
+            stat.channel_hidden_products += save_visibility(conn, channel, products_bucket, import_id)?;
+        }
+        stat.staged_products += products_bucket.len() as u32;
+        stat.synced_products += products_bucket.len() as u32;
+        return Ok(start_syncing_at.elapsed());
+    }
+    if opts.mark_missing_unavailable {
+        let offer_ids = products_bucket.iter()
+            .map(|p| p.offer_id.clone())
+            .collect();
+        seen_offer_ids.add(store, offer_ids, opts.max_memory)?;
+    }
+    match opts.idempotency_key {
+        Some(ref idempotency_key) => {
+            let chunk_hash = ledger::chunk_hash(idempotency_key, chunk_index, batch_index, products_bucket);
+            let conn = mysql_connection(store, "--idempotency-key")?;
+            if ledger::is_applied(conn, &chunk_hash)? {
+                debug!("Chunk {}.{} was already applied, skipping it", chunk_index, batch_index);
+                stat.skipped_chunks += 1;
+                return Ok(start_syncing_at.elapsed());
+            }
+            // the chunk and its ledger record are committed together, the record is written
+            // only when the changes were published so a failed publish applies the chunk again
+            conn.transaction(|conn| {
+                let processed_products_stat = apply_bucket(conn, products_bucket, ctx, stat)?;
+                consume_bucket(products_bucket, chunk_index, processed_products_stat, ctx, stat, consumers)?;
+                ledger::record(
+                    conn, &chunk_hash, idempotency_key, chunk_index, batch_index,
+                    products_bucket.len() as u32, import_id
+                )
+            })?;
+        }
+        None => {
+            let processed_products_stat = apply_bucket(store, products_bucket, ctx, stat)?;
+            consume_bucket(products_bucket, chunk_index, processed_products_stat, ctx, stat, consumers)?;
+        }
+    }
+    Ok(start_syncing_at.elapsed())
+}
+
+/// Adds the result of the applied chunk to the stat and passes its changes to the consumers
+fn consume_bucket(
+    products_bucket: &[models::NewProduct],
+    chunk_index: u32,
+    processed_products_stat: ProcessedProducts,
+    ctx: &ImportContext,
+    stat: &mut ProcessedStat,
+    consumers: &mut ChangeConsumers,
+) -> Result<(), Error> {
+    let opts = ctx.opts;
+    stat.updated_price += processed_products_stat.updated_price;
+    stat.updated_discount += processed_products_stat.updated_discount;
+    stat.updated_vat += processed_products_stat.updated_vat;
+    stat.cleared_oldprices += processed_products_stat.cleared_oldprices;
+    stat.currency_only_changes += processed_products_stat.currency_only_changes;
+    stat.converted_same_prices += processed_products_stat.converted_same_prices;
+    stat.smoothed_prices += processed_products_stat.smoothed_prices;
+    stat.updated_available += processed_products_stat.updated_available;
+    stat.reactivated_products += processed_products_stat.reactivated;
+    stat.deactivated_products += processed_products_stat.deactivated;
+    stat.reactivated_list.extend_from_slice(&processed_products_stat.reactivated_products);
+    stat.postponed_unavailable += processed_products_stat.postponed_unavailable;
+    stat.inserted_products += processed_products_stat.inserted;
+    stat.price_history_records += processed_products_stat.price_history_records;
+    stat.possible_duplicates += processed_products_stat.possible_duplicates;
+    stat.conflicts += processed_products_stat.conflicts;
+    stat.suppressed_by_locks += processed_products_stat.suppressed_by_locks;
+    stat.reserved_products += processed_products_stat.reserved;
+    stat.discontinued_offers += processed_products_stat.discontinued;
+    stat.skipped_newer_products += processed_products_stat.skipped_newer;
+    stat.unchanged_products += processed_products_stat.unchanged;
+    stat.kept_manual_products += processed_products_stat.kept_manual;
+    stat.queued_conflicts += processed_products_stat.queued_conflicts;
+    stat.quarantined_offers += processed_products_stat.quarantined;
+    stat.sku_collisions += processed_products_stat.sku_collisions;
+    stat.unverified_pictures += processed_products_stat.unverified_pictures;
+    for offer_id in &processed_products_stat.dead_pictures {
+        stat.dead_picture_products += 1;
+        if stat.dead_picture_offer_ids.len() < LISTED_DRAFTS {
+            stat.dead_picture_offer_ids.push(offer_id.clone());
+        }
+    }
+    if opts.new_as_draft {
+        for change in &processed_products_stat.changes {
+            if change.kind == ChangeKind::Inserted {
+                stat.drafted_products += 1;
+                if stat.drafted_offer_ids.len() < LISTED_DRAFTS {
+                    stat.drafted_offer_ids.push(change.offer_id.clone());
+                }
+            }
+        }
+    }
+    stat.select_duration += processed_products_stat.select_duration;
+    stat.update_duration += processed_products_stat.update_duration;
+    stat.insert_duration += processed_products_stat.insert_duration;
+    stat.synced_products += products_bucket.len() as u32;
+    stat.chunk_durations.push(processed_products_stat.duration);
+    deadline::check_chunk(opts, chunk_index, products_bucket, &processed_products_stat, stat);
+    debug!(
+        "Synced chunk of {} products in {:?} ({:.0} rows/s)",
+        products_bucket.len(), processed_products_stat.duration,
+        rows_per_second(products_bucket.len() as u32, processed_products_stat.duration)
+    );
+    if let Some(ref mut publisher) = consumers.publisher {
+        stat.published_changes += publisher.publish(&processed_products_stat.changes)?;
+    }
+    if let Some(ref mut search_indexer) = consumers.search_indexer {
+        stat.indexed_documents += search_indexer.add(
+            &processed_products_stat.changes, products_bucket
+        )?;
+    }
+    if let Some(ref mut changes_csv) = consumers.changes_csv {
+        stat.changes_csv_rows += changes_csv.write(&processed_products_stat.changes)?;
+    }
+    Ok(())
 }
 
-fn get_gzip_file_uncompressed_size(file: &mut File) -> Result<u32, Error> {
-    let orig_position = file.seek(SeekFrom::Current(0))?;
-    file.seek(SeekFrom::End(-4))?;
-    let size = file.read_u32::<LittleEndian>()?;
-    file.seek(SeekFrom::Start(orig_position))?;
-    return Ok(size);
+/// Updates and inserts the products and saves their related data
+fn apply_bucket(
+    store: &mut dyn ProductStore,
+    products_bucket: &Vec<models::NewProduct>,
+    ctx: &ImportContext,
+    stat: &mut ProcessedStat,
+) -> Result<ProcessedProducts, Error> {
+    let opts = ctx.opts;
+    let import_id = ctx.import_id.as_str();
+    let processed_products_stat = sync_products_chunk(store, products_bucket, ctx, stat.feed_date.as_ref())?;
+    if opts.aggregate_stocks {
+        save_stocks(mysql_connection(store, "--aggregate-stocks")?, products_bucket, import_id)?;
+    }
+    if opts.update_keywords {
+        stat.keyword_products += save_keywords(mysql_connection(store, "--update-keywords")?, products_bucket)?;
+    }
+    if let Some(ref channel) = opts.channel {
+        stat.channel_hidden_products += save_visibility(
+            mysql_connection(store, "--channel")?, channel, products_bucket, import_id
+        )?;
+    }
+    if opts.convert_prices {
+        save_original_prices(mysql_connection(store, "--convert-prices")?, products_bucket)?;
+    }
+    Ok(processed_products_stat)
+}
+
+pub(crate) fn add_memory_stat(stat: &mut ProcessedStat, seen_offer_ids: &SeenOfferIds) {
+    stat.peak_memory = memory::rss().map(|(_, peak)| peak);
+    stat.seen_offer_ids = seen_offer_ids.len();
+    stat.seen_offer_ids_memory = seen_offer_ids.memory_size();
+    stat.seen_offer_ids_spilled = seen_offer_ids.is_spilled();
+}
+
+pub(crate) fn rows_per_second(rows: u32, duration: Duration) -> f64 {
+    let secs = duration.as_secs_f64();
+    if secs > 0.0 {
+        rows as f64 / secs
+    } else {
+        0.0
+    }
 }
 
+/// Parses the file syncing every full bucket of products before the next one is parsed
 pub(crate) fn parse_offers(
-    opts: &Opts, conn: &MysqlConnection,
+    ctx: &ImportContext, store: &mut dyn ProductStore,
 ) -> Result<ProcessedStat, Error> {
     let start_processing_at = Instant::now();
-    let mut total_sync_duration = Duration::default();
-    let file_path = opts.file_path.as_path();
-    let mut _reader: BufReader<File>;
-    let mut _gz_decoder: BufReader<GzDecoder<BufReader<File>>>;
-    let file_size: u64;
-    let reader: &mut dyn BufRead = match file_path.extension() {
-        Some(ext) if ext == OsStr::new("gz") => {
-            let mut file = File::open(file_path)?;
-            file_size = get_gzip_file_uncompressed_size(&mut file)? as u64;
-            _gz_decoder = BufReader::new(GzDecoder::new(BufReader::new(file)));
-            &mut _gz_decoder
+    let mut sync = SyncStage::start(ctx, store)?;
+    let mut stat = ProcessedStat::default();
+    parse_products(
+        ctx, &mut stat,
+        |products_bucket, stat| sync.sync_bucket(products_bucket, stat.feed_date)
+    )?;
+    stat.parse_duration = start_processing_at.elapsed() - sync.sync_duration;
+    sync.finish(stat, start_processing_at)
+}
+
+/// Sync side of an import shared by the sequential and the `--pipeline` imports: syncs parsed
+/// buckets of products and applies everything that follows the last one.
+pub(crate) struct SyncStage<'a, 'c> {
+    ctx: &'a ImportContext<'c>,
+    store: &'a mut dyn ProductStore,
+    consumers: ChangeConsumers,
+    retry_queue: RetryQueue,
+    seen_offer_ids: SeenOfferIds,
+    stat: ProcessedStat,
+    chunk_index: u32,
+    sync_duration: Duration,
+}
+
+impl<'a, 'c> SyncStage<'a, 'c> {
+    pub fn start(ctx: &'a ImportContext<'c>, store: &'a mut dyn ProductStore) -> Result<Self, Error> {
+        let consumers = ChangeConsumers::new(ctx.opts, store)?;
+        if ctx.opts.staging {
+            create_staging_table(mysql_connection(store, "--staging")?)?;
         }
-        _ => {
-            file_size = fs::metadata(file_path)?.len();
-            _reader = BufReader::new(File::open(file_path)?);
-            &mut _reader
+        Ok(SyncStage {
+            ctx,
+            store,
+            consumers,
+            retry_queue: RetryQueue::default(),
+            seen_offer_ids: SeenOfferIds::default(),
+            stat: ProcessedStat {
+                import_id: ctx.import_id.clone(),
+                ..Default::default()
+            },
+            chunk_index: 0,
+            sync_duration: Duration::default(),
+        })
+    }
+
+    /// Syncs the next bucket of the file, buckets failed with transient errors are retried by `finish`
+    pub fn sync_bucket(
+        &mut self, products_bucket: Vec<models::NewProduct>, feed_date: Option<NaiveDateTime>,
+    ) -> Result<(), Error> {
+        // the feed date is parsed from the root element before the first bucket
+        self.stat.feed_date = feed_date;
+        match sync_products_bucket(
+            self.store, &products_bucket, self.chunk_index, 0, self.ctx,
+            &mut self.stat, &mut self.consumers, &mut self.seen_offer_ids
+        ) {
+            Ok(sync_duration) => self.sync_duration += sync_duration,
+            Err(e) => self.retry_queue.defer(products_bucket, self.chunk_index, e, self.ctx)?,
         }
-    };
+        self.chunk_index += 1;
+        Ok(())
+    }
 
-    let update_progress_after_chunk = file_size / 100;
-    let progress_bar = if !opts.no_progress {
-        let pb = ProgressBar::new(file_size);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta}) parsing file and updating products")
-                .progress_chars("#>-")
-        );
-        Some(pb)
-    } else {
-        None
+    /// Retries failed buckets, marks missing products and saves the data collected
+    /// from the whole file. Statistics of the parser are merged with the sync ones.
+    pub fn finish(self, parse_stat: ProcessedStat, start_processing_at: Instant) -> Result<ProcessedStat, Error> {
+        let SyncStage { ctx, store, mut consumers, retry_queue, mut seen_offer_ids, stat: sync_stat, .. } = self;
+        let opts = ctx.opts;
+        let date_processed = ctx.date_processed;
+        let mut stat = parse_stat;
+        stat.merge(sync_stat);
+
+        retry_queue.retry(store, ctx, &mut stat, &mut consumers, &mut seen_offer_ids)?;
+
+        consumers.flush(&mut stat)?;
+
+        if opts.staging {
+            apply_staging(
+                mysql_connection(store, "--staging")?, opts, &ctx.supplier_file_ids(), &date_processed, &mut stat
+            )?;
+        } else if opts.mark_missing_unavailable {
+            let start_mark_missing_at = Instant::now();
+            stat.marked_as_unavailable = mark_missing_as_unavailable(store, &seen_offer_ids, ctx)?;
+            stat.mark_missing_duration = start_mark_missing_at.elapsed();
+        }
+        if opts.update_relations {
+            consumers.save_relations(mysql_connection(store, "--update-relations")?, &mut stat)?;
+        }
+        if let Some(mode) = opts.apply_deletes {
+            stat.applied_deletes = deletes::apply(
+                store, mode, &stat.deleted_offer_ids, &ctx.supplier_file_ids(), &ctx.import_id,
+                offer_hashes::is_tracked(opts)
+            )?;
+        }
+        add_memory_stat(&mut stat, &seen_offer_ids);
+
+        if opts.aggregate_stocks {
+            stat.aggregated_stocks = aggregate_stocks(
+                mysql_connection(store, "--aggregate-stocks")?, &stock_suppliers(&stat), &ctx.import_id
+            )?;
+        }
+
+        if offer_hashes::is_tracked(opts) {
+            store.mark_written(&ctx.import_id)?;
+        }
+        store.set_processed_date(&date_processed)?;
+        stat.sql_statements = store.finish()?;
+
+        stat.total_duration = start_processing_at.elapsed();
+        Ok(stat)
+    }
+}
+
+/// Reads category names and parents from the `<categories>` section that precedes offers
+fn parse_categories(opts: &Opts) -> Result<FeedCategories, Error> {
+    let mut categories = FeedCategories::default();
+    let mut category_id = None;
+    input::scan(opts.file_path(), |xml_reader, event| {
+        match event {
+            Event::Start(ref e) if e.name() == b"category" => {
+                category_id = None;
+                let mut parent_id = None;
+                for attr_res in e.attributes() {
+                    let attr = attr_res?;
+                    match attr.key {
+                        b"id" => category_id = String::from_utf8_lossy(&attr.value).trim().parse().ok(),
+                        b"parentId" => parent_id = String::from_utf8_lossy(&attr.value).trim().parse().ok(),
+                        _ => {}
+                    }
+                }
+                if let (Some(id), Some(parent_id)) = (category_id, parent_id) {
+                    categories.parents.insert(id, parent_id);
+                }
+            }
+            Event::Text(ref v) => {
+                if let Some(id) = category_id.take() {
+                    let name = v.unescape_and_decode(xml_reader)?;
+                    categories.names.insert(id, name.trim().to_string());
+                }
+            }
+            Event::End(ref e) if e.name() == b"category" => {
+                category_id = None;
+            }
+            Event::Start(ref e) if e.name() == b"offers" => return Ok(false),
+            _ => {}
+        }
+        Ok(true)
+    })?;
+    Ok(categories)
+}
+
+/// Parses offers from the file and passes full buckets of products to the `sync_bucket` callback.
+pub(crate) fn parse_products<F>(
+    ctx: &ImportContext,
+    stat: &mut ProcessedStat,
+    mut sync_bucket: F,
+) -> Result<(), Error>
+where
+    F: FnMut(Vec<models::NewProduct>, &mut ProcessedStat) -> Result<(), Error>,
+{
+    let opts = ctx.opts;
+    let pricing_rules = ctx.pricing_rules.as_ref();
+    let shop_suppliers = ctx.shop_suppliers.as_ref();
+    let rules = ctx.rules.as_ref();
+    let channel_filter = ctx.channel_filter.as_ref();
+    let dialect = match opts.dialect {
+        Some(dialect) => dialect,
+        None => dialect::detect(opts.file_path())?,
     };
 
+    let (reader, input_progress) = input::open(opts.file_path())?;
+    let junk = &input_progress.leading_junk;
+    if junk.bom {
+        debug!("Skipped UTF-8 BOM");
+    }
+    if junk.bytes > 0 {
+        warn!("Skipped {} bytes before the XML: {}", junk.bytes, junk.sample);
+        stat.leading_junk_bytes = junk.bytes;
+    }
+
+    let mut progress = ctx.progress.lock().unwrap();
+    progress.start(Stage::Parsing, input_progress.size);
+
     let mut xml_reader = Reader::from_reader(reader);
     let mut buf = vec!();
     let mut offer_buf = vec!();
-    let mut stat = ProcessedStat::default();
 
-    let mut products_bucket = vec!();
-    let mut all_offer_ids = HashSet::new();
+    let mut products_bucket: Vec<models::NewProduct> = Vec::with_capacity(CHUNK_SIZE);
+    // descriptions dominate memory of the bucket, a chunk of huge descriptions is synced earlier
+    let mut bucket_description_bytes = 0u64;
+
+    let mut rejects_xml = match opts.rejects_as_xml {
+        Some(ref path) => Some(RejectsXml::create(path)?),
+        None => None,
+    };
+
+    let mut variant_grouper = if opts.group_variants {
+        Some(VariantGrouper::default())
+    } else {
+        None
+    };
+
+    let feed_categories = if opts.category_paths {
+        Some(parse_categories(opts)?)
+    } else {
+        None
+    };
+
+    let name_normalizer = if opts.normalize_names {
+        Some(NameNormalizer::new(opts.banned_words.as_deref(), opts.max_name_length)?)
+    } else {
+        None
+    };
+
+    let mut merger = opts.merge_strategy
+        .map(|strategy| OfferMerger::new(strategy, &opts.supplier_priority, &opts.currency_rates));
 
-    let date_processed = Utc::now().naive_utc().with_nanosecond(0).unwrap();
+    let mut shop = None;
+    let mut in_shop_name = false;
+    let mut in_removed = false;
 
     loop {
-        match xml_reader.read_event(&mut buf) {
+        let event = xml_reader.read_event(&mut buf);
+        let is_empty_element = matches!(event, Ok(Event::Empty(_)));
+        match event {
+            Ok(Event::Start(ref e)) if e.name() == b"name" => {
+                in_shop_name = true;
+            }
+            Ok(Event::Text(ref v)) if in_shop_name => {
+                let name = v.unescape_and_decode(&xml_reader)
+                    .map_err(|e| Error::from(e).at_position(xml_reader.buffer_position()))?;
+                shop = Some(name.trim().to_string());
+            }
+            Ok(Event::End(ref e)) if e.name() == b"name" => {
+                in_shop_name = false;
+            }
+            Ok(Event::End(ref e)) if e.name() == b"removed" => {
+                in_removed = false;
+            }
             Ok(Event::Start(ref e)) |
             Ok(Event::Empty(ref e)) => {
                 match e.name() {
-                    b"offer" => {
-                        let mut offer_id = None;
-                        let mut available = NOT_AVAILABLE;
+                    b"yml_catalog" => {
                         for attr_res in e.attributes() {
                             let attr = attr_res?;
-                            match attr.key {
-                                b"id" => {
-                                    offer_id = Some(String::from_utf8_lossy(&attr.value).to_string());
-                                }
-                                b"available" => {
-                                    available = match attr.value.as_ref() {
-                                        b"" => NOT_AVAILABLE,
-                                        b"true" | b"1" => AVAILABLE,
-                                        b"false" | b"0" => NOT_AVAILABLE,
-                                        v => {
-                                            return Err(format_err!(
-                                                "Unknown \"available\" attribute: {}", String::from_utf8_lossy(v)
-                                            ))
-                                        }
-                                    };
-                                }
-                                _ => {}
+                            if attr.key == b"date" {
+                                stat.feed_date = parse_feed_date(&String::from_utf8_lossy(&attr.value));
                             }
                         }
-                        let mut offer = if let Some(offer_id) = offer_id {
-                            Offer::new(offer_id, available)
+                    }
+                    b"shop" => {
+                        shop = None;
+                    }
+                    // offers of the `<removed>` section are deletion markers
+                    b"removed" => {
+                        in_removed = !is_empty_element;
+                    }
+                    b"offer" | b"item" | b"entry" if dialect.is_offer_element(e.name()) => {
+                        let mut offer_fields = BTreeSet::new();
+                        let mut deleted = in_removed;
+                        let mut offer = if dialect == Dialect::GoogleMerchant {
+                            match parse_merchant_item(
+                                &mut xml_reader, &mut offer_buf, opts.file_path(), e.name(), is_empty_element
+                            )? {
+                                Some(offer) => offer,
+                                None => {
+                                    warn!("An offer without id was found");
+                                    continue;
+                                }
+                            }
                         } else {
-                            warn!("An offer without id was found");
-                            continue;
-                        };
-                        let mut offer_field = OfferFields::None;
-
-                        loop {
-                            match xml_reader.read_event(&mut offer_buf) {
-                                Ok(Event::Start(ref offer_event)) => {
-                                    match offer_event.name() {
-                                        b"price" => {
-                                            offer_field = OfferFields::Price;
-                                        }
-                                        b"oldprice" => {
-                                            offer_field = OfferFields::OldPrice;
-                                        }
-                                        b"currencyId" => {
-                                            offer_field = OfferFields::CurrencyId;
-                                        }
-                                        b"categoryId" => {
-                                            offer_field = OfferFields::CategoryId;
-                                        }
-                                        b"name" => {
-                                            offer_field = OfferFields::Name;
-                                        }
-                                        b"description" => {
-                                            offer_field = OfferFields::Description;
-                                        }
-                                        b"vendor" => {
-                                            offer_field = OfferFields::Vendor;
-                                        }
-                                        b"vendorCode" => {
-                                            offer_field = OfferFields::VendorCode;
+                            let mut offer_id = None;
+                            let mut group_id = None;
+                            let mut available = NOT_AVAILABLE;
+                            let mut signals = AvailabilitySignals::default();
+                            for attr_res in e.attributes() {
+                                let attr = attr_res?;
+                                if opts.track_feed_fields {
+                                    offer_fields.insert(format!("@{}", String::from_utf8_lossy(attr.key)));
+                                }
+                                match attr.key {
+                                    b"id" => {
+                                        offer_id = Some(String::from_utf8_lossy(&attr.value).to_string());
+                                    }
+                                    b"deleted" => {
+                                        deleted = matches!(attr.value.as_ref(), b"true" | b"1");
+                                    }
+                                    b"group_id" => {
+                                        group_id = Some(String::from_utf8_lossy(&attr.value).trim().to_string())
+                                            .filter(|g| !g.is_empty());
+                                    }
+                                    b"count" => {
+                                        let value = String::from_utf8_lossy(&attr.value);
+                                        match value.trim().parse() {
+                                            Ok(count) => signals.count = Some(count),
+                                            Err(_) => warn!("Cannot parse \"count\" attribute: {}", value),
                                         }
-                                        _ => {}
                                     }
-                                }
-                                Ok(Event::Text(ref v)) => {
-                                    let value = String::from_utf8_lossy(v.escaped());
-                                    match offer_field {
-                                        OfferFields::Price => {
-                                            if let Ok(price) = value.parse() {
-                                                offer.price = Some(price);
-                                            } else {
-                                                warn!("{}: Cannot parse price: {}", offer.offer_id, value);
+                                    b"available" => {
+                                        signals.attribute = true;
+                                        available = match attr.value.as_ref() {
+                                            b"" => NOT_AVAILABLE,
+                                            b"true" | b"1" => AVAILABLE,
+                                            b"false" | b"0" => NOT_AVAILABLE,
+                                            v => {
+                                                return Err(Error::validation(
+                                                    format!("Unknown \"available\" attribute: {}", String::from_utf8_lossy(v)),
+                                                    ErrorContext {
+                                                        offer_id: offer_id.clone(),
+                                                        position: Some(xml_reader.buffer_position()),
+                                                    }
+                                                ))
                                             }
+                                        };
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            let mut offer = if let Some(offer_id) = offer_id {
+                                Offer::new(offer_id, available)
+                            } else {
+                                warn!("An offer without id was found");
+                                continue;
+                            };
+                            offer.group_id = group_id;
+                            offer.quantity = signals.count;
+                            let mut offer_field = OfferFields::None;
+                            let mut param_name = None;
+                            let mut relation_type = None;
+                            let mut age_in_months = false;
+                            let mut in_delivery_options = false;
+
+                            // self-closing `<offer ... />` has attributes only
+                            if !is_empty_element {
+                                loop {
+                                    let offer_event = xml_reader.read_event(&mut offer_buf);
+                                    if opts.track_feed_fields {
+                                        if let Ok(Event::Start(ref e)) | Ok(Event::Empty(ref e)) = offer_event {
+                                            offer_fields.insert(String::from_utf8_lossy(e.name()).to_string());
+                                        }
+                                    }
+                                    match offer_event {
+                                        Ok(Event::Empty(ref offer_event)) if offer_event.name() == b"price" => {
+                                            parse_price_attributes(offer_event, &mut offer)?;
                                         }
-                                        OfferFields::OldPrice => {
-                                            offer.old_price = value.parse().ok();
+                                        Ok(Event::Empty(ref offer_event))
+                                            if in_delivery_options && offer_event.name() == b"option" =>
+                                        {
+                                            parse_delivery_option(offer_event, &mut offer)?;
                                         }
-                                        OfferFields::CurrencyId => {
-                                            match value.as_ref() {
-                                                "UAH" | "USD" | "EUR" | "RUB" | "BYR" | "KZT" => {
-                                                    offer.currency_id = Some(value.to_string());
+                                        Ok(Event::Start(ref offer_event)) => {
+                                            match offer_event.name() {
+                                                b"price" => {
+                                                    parse_price_attributes(offer_event, &mut offer)?;
+                                                    offer_field = OfferFields::Price;
                                                 }
-                                                "" => {
-                                                    offer.currency_id = Some("UAH".to_string());
+                                                b"oldprice" => {
+                                                    offer_field = OfferFields::OldPrice;
                                                 }
-                                                _ => {
-                                                    warn!("{}: Unknown currencyId: {}", offer.offer_id, value);
+                                                b"currencyId" => {
+                                                    offer_field = OfferFields::CurrencyId;
+                                                }
+                                                b"categoryId" => {
+                                                    offer_field = OfferFields::CategoryId;
+                                                }
+                                                b"name" => {
+                                                    offer_field = OfferFields::Name;
+                                                }
+                                                b"description" => {
+                                                    offer_field = OfferFields::Description;
+                                                }
+                                                b"vendor" => {
+                                                    offer_field = OfferFields::Vendor;
+                                                }
+                                                b"vendorCode" => {
+                                                    offer_field = OfferFields::VendorCode;
+                                                }
+                                                b"typePrefix" => {
+                                                    offer_field = OfferFields::TypePrefix;
+                                                }
+                                                b"model" => {
+                                                    offer_field = OfferFields::Model;
+                                                }
+                                                b"barcode" => {
+                                                    offer_field = OfferFields::Barcode;
+                                                }
+                                                b"vat" => {
+                                                    offer_field = OfferFields::Vat;
+                                                }
+                                                b"available" if !signals.attribute => {
+                                                    offer_field = OfferFields::Available;
+                                                }
+                                                b"quantity" | b"stock_quantity" | b"quantity_in_stock" => {
+                                                    offer_field = OfferFields::Quantity;
                                                 }
+                                                b"instock" => {
+                                                    offer_field = OfferFields::InStock;
+                                                }
+                                                b"adult" => {
+                                                    offer_field = OfferFields::Adult;
+                                                }
+                                                b"age" => {
+                                                    offer_field = OfferFields::Age;
+                                                    age_in_months = false;
+                                                    for attr_res in offer_event.attributes() {
+                                                        let attr = attr_res?;
+                                                        if attr.key == b"unit" {
+                                                            age_in_months = &*attr.value == b"month";
+                                                        }
+                                                    }
+                                                }
+                                                b"delivery-options" => {
+                                                    in_delivery_options = true;
+                                                }
+                                                b"delivery_days" => {
+                                                    offer_field = OfferFields::DeliveryDays;
+                                                }
+                                                b"keywords" => {
+                                                    offer_field = OfferFields::Keywords;
+                                                }
+                                                b"picture" => {
+                                                    offer_field = OfferFields::Picture;
+                                                }
+                                                b"rec" => {
+                                                    offer_field = OfferFields::Related;
+                                                    relation_type = Some(RelationType::Accessory);
+                                                }
+                                                b"related_offer" | b"related-offer" => {
+                                                    offer_field = OfferFields::Related;
+                                                    relation_type = Some(RelationType::Similar);
+                                                    for attr_res in offer_event.attributes() {
+                                                        let attr = attr_res?;
+                                                        if attr.key == b"type" {
+                                                            let value = String::from_utf8_lossy(&attr.value);
+                                                            relation_type = value.trim().parse().map_err(|e| {
+                                                                warn!("{}: {}", offer.offer_id, e);
+                                                            }).ok();
+                                                        }
+                                                    }
+                                                }
+                                                b"param" => {
+                                                    offer_field = OfferFields::Param;
+                                                    param_name = None;
+                                                    for attr_res in offer_event.attributes() {
+                                                        let attr = attr_res?;
+                                                        if attr.key == b"name" {
+                                                            param_name = Some(
+                                                                String::from_utf8_lossy(&attr.value).trim().to_string()
+                                                            );
+                                                        }
+                                                    }
+                                                }
+                                                _ => {}
                                             }
                                         }
-                                        OfferFields::CategoryId => {
-                                            if let Ok(cat_id) = value.parse() {
-                                                offer.category_id = Some(cat_id);
-                                            } else {
-                                                warn!("{}: Cannot parse categoryId: {}", offer.offer_id, value);
+                                        Ok(Event::Text(ref v)) => {
+                                            let raw_value = String::from_utf8_lossy(v.escaped());
+                                            let value = raw_value.trim();
+                                            let is_field = !matches!(offer_field, OfferFields::None);
+                                            if is_field && value.len() != raw_value.len() {
+                                                stat.normalized_values += 1;
+                                            }
+                                            // whitespace-only values are treated as absent,
+                                            // empty currency still means the default one
+                                            if !value.is_empty() || matches!(offer_field, OfferFields::CurrencyId) {
+                                                match offer_field {
+                                                    OfferFields::Price => {
+                                                        if let Ok(price) = value.parse() {
+                                                            offer.price = Some(price);
+                                                        } else {
+                                                            warn!("{}: Cannot parse price: {}", offer.offer_id, value);
+                                                        }
+                                                    }
+                                                    OfferFields::OldPrice => {
+                                                        offer.old_price = value.parse().ok();
+                                                    }
+                                                    OfferFields::CurrencyId => {
+                                                        match value {
+                                                            "UAH" | "USD" | "EUR" | "RUB" | "BYR" | "KZT" => {
+                                                                offer.currency_id = Some(value.to_string());
+                                                            }
+                                                            "" => {
+                                                                offer.currency_id = Some("UAH".to_string());
+                                                            }
+                                                            _ => {
+                                                                warn!("{}: Unknown currencyId: {}", offer.offer_id, value);
+                                                            }
+                                                        }
+                                                    }
+                                                    OfferFields::CategoryId => {
+                                                        if let Ok(cat_id) = value.parse() {
+                                                            offer.category_id = Some(cat_id);
+                                                        } else {
+                                                            warn!("{}: Cannot parse categoryId: {}", offer.offer_id, value);
+                                                        }
+                                                    }
+                                                    OfferFields::Name => {
+                                                        offer.name = Some(value.to_string());
+                                                    }
+                                                    OfferFields::Description => {
+                                                        offer.description = Some(value.to_string());
+                                                    }
+                                                    OfferFields::Vendor => {
+                                                        offer.vendor = Some(value.to_string());
+                                                    }
+                                                    OfferFields::VendorCode => {
+                                                        offer.vendor_code = Some(value.to_string());
+                                                    }
+                                                    OfferFields::TypePrefix => {
+                                                        offer.type_prefix = Some(value.to_string());
+                                                    }
+                                                    OfferFields::Model => {
+                                                        offer.model = Some(value.to_string());
+                                                    }
+                                                    OfferFields::Barcode => {
+                                                        offer.barcode = Some(value.to_string());
+                                                    }
+                                                    OfferFields::Vat => {
+                                                        offer.vat = Some(value.to_string());
+                                                    }
+                                                    OfferFields::Available => {
+                                                        match value {
+                                                            "true" | "1" => offer.available = AVAILABLE,
+                                                            "false" | "0" => offer.available = NOT_AVAILABLE,
+                                                            v => warn!("{}: Unknown available: {}", offer.offer_id, v),
+                                                        }
+                                                        signals.element = true;
+                                                    }
+                                                    OfferFields::InStock => {
+                                                        match parse_instock(value) {
+                                                            Some((in_stock, quantity)) => {
+                                                                signals.in_stock = Some(in_stock);
+                                                                if quantity.is_some() {
+                                                                    offer.quantity = quantity;
+                                                                }
+                                                            }
+                                                            None => warn!("{}: Unknown instock: {}", offer.offer_id, value),
+                                                        }
+                                                    }
+                                                    OfferFields::Quantity => {
+                                                        if let Ok(quantity) = value.parse() {
+                                                            offer.quantity = Some(quantity);
+                                                        } else {
+                                                            warn!("{}: Cannot parse quantity: {}", offer.offer_id, value);
+                                                        }
+                                                    }
+                                                    OfferFields::Adult => {
+                                                        match value {
+                                                            "true" | "1" => offer.adult = true,
+                                                            "false" | "0" => offer.adult = false,
+                                                            v => warn!("{}: Unknown adult: {}", offer.offer_id, v),
+                                                        }
+                                                    }
+                                                    // ages in months are used for baby goods, they do not restrict anything
+                                                    OfferFields::Age if !age_in_months => {
+                                                        if let Ok(age) = value.parse() {
+                                                            offer.age = Some(age);
+                                                        } else {
+                                                            warn!("{}: Cannot parse age: {}", offer.offer_id, value);
+                                                        }
+                                                    }
+                                                    OfferFields::DeliveryDays => {
+                                                        if let Some(days) = parse_delivery_days(value) {
+                                                            offer.delivery_days = Some(days);
+                                                        } else {
+                                                            warn!("{}: Cannot parse delivery days: {}", offer.offer_id, value);
+                                                        }
+                                                    }
+                                                    OfferFields::Keywords => {
+                                                        for keyword in value.split(',').map(str::trim) {
+                                                            if !keyword.is_empty() &&
+                                                                !offer.keywords.iter().any(|k| k == keyword)
+                                                            {
+                                                                offer.keywords.push(keyword.to_string());
+                                                            }
+                                                        }
+                                                    }
+                                                    OfferFields::Param => {
+                                                        if let Some(name) = param_name.take() {
+                                                            offer.params.push((name, value.to_string()));
+                                                        }
+                                                    }
+                                                    OfferFields::Related => {
+                                                        if let Some(relation_type) = relation_type {
+                                                            for related_id in value.split(',').map(str::trim) {
+                                                                if !related_id.is_empty() &&
+                                                                    !offer.relations.iter().any(|(id, t)| {
+                                                                        id == related_id && *t == relation_type
+                                                                    })
+                                                                {
+                                                                    offer.relations.push(
+                                                                        (related_id.to_string(), relation_type)
+                                                                    );
+                                                                }
+                                                            }
+                                                        }
+                                                    }
+                                                    // query strings of the URLs contain escaped `&`
+                                                    OfferFields::Picture => {
+                                                        offer.pictures.push(v.unescape_and_decode(&xml_reader)?.trim().to_string());
+                                                    }
+                                                    _ => {}
+                                                }
                                             }
                                         }
-                                        OfferFields::Name => {
-                                            offer.name = Some(value.to_string());
-                                        }
-                                        OfferFields::Description => {
-                                            offer.description = Some(value.to_string());
+                                        Ok(Event::End(ref e)) => {
+                                            match e.name() {
+                                                b"offer" => {
+                                                    break;
+                                                }
+                                                b"delivery-options" => {
+                                                    in_delivery_options = false;
+                                                }
+                                                _ => {
+                                                    offer_field = OfferFields::None;
+                                                }
+                                            }
                                         }
-                                        OfferFields::Vendor => {
-                                            offer.vendor = Some(value.to_string());
+                                        Ok(Event::Eof) => {
+                                            return Err(Error::unexpected_eof(
+                                                opts.file_path(), b"offer", xml_reader.buffer_position()
+                                            ));
                                         }
-                                        OfferFields::VendorCode => {
-                                            offer.vendor_code = Some(value.to_string());
+                                        Err(e) => {
+                                            return Err(Error::from(e).at_position(xml_reader.buffer_position()));
                                         }
                                         _ => {}
                                     }
+
+                                    offer_buf.clear();
                                 }
-                                Ok(Event::End(ref e)) => {
-                                    match e.name() {
-                                        b"offer" => {
-                                            break;
-                                        }
-                                        _ => {
-                                            offer_field = OfferFields::None;
-                                        }
+                            }
+                            signals.resolve(&mut offer);
+                            offer
+                        };
+
+                        if deleted {
+                            if is_traced(opts, &offer.offer_id) {
+                                trace(opts, &offer.offer_id, "parsed", || json!("deleted"));
+                            }
+                            stat.deleted_offer_ids.push(offer.offer_id);
+                            continue;
+                        }
+                        stat.total_offers += 1;
+                        if let Some(ref shop) = shop {
+                            offer.shop = Some(shop.clone());
+                            *stat.shop_offers.entry(shop.clone()).or_default() += 1;
+                        }
+                        // fields of Google Merchant items are not collected
+                        if opts.track_feed_fields && dialect != Dialect::GoogleMerchant {
+                            let shop_fields = stat.feed_fields.entry(shop.clone().unwrap_or_default()).or_default();
+                            for field in offer_fields {
+                                *shop_fields.entry(field).or_default() += 1;
+                            }
+                        }
+                        let traced_id = is_traced(opts, &offer.offer_id).then(|| offer.offer_id.clone());
+                        let traced_id = traced_id.as_deref();
+                        let mut provenance = (traced_id.is_some() && opts.trace_provenance)
+                            .then(|| Provenance::new(&offer));
+                        if sanitize_offer(&mut offer) {
+                            stat.sanitized_offers += 1;
+                        }
+                        // the offer is written into --rejects-as-xml as the supplier sent it
+                        let original_offer = rejects_xml.is_some().then(|| offer.clone());
+                        let mut reject_reason = None;
+                        if let Some(ref mut provenance) = provenance {
+                            provenance.record("sanitize", &offer);
+                        }
+                        if let Some(ref name_template) = opts.name_template {
+                            if let Some(name) = name_template.render(&offer) {
+                                if offer.name.as_ref() != Some(&name) {
+                                    stat.templated_names += 1;
+                                }
+                                offer.name = Some(name);
+                            }
+                            if let Some(ref mut provenance) = provenance {
+                                provenance.record("name_template", &offer);
+                            }
+                        }
+                        if let (Some(max_days), Some(days)) = (opts.max_delivery_days, offer.delivery_days) {
+                            if days > max_days && offer.available == AVAILABLE {
+                                offer.available = NOT_AVAILABLE;
+                                stat.long_delivery_offers += 1;
+                            }
+                            if let Some(ref mut provenance) = provenance {
+                                provenance.record("max_delivery_days", &offer);
+                            }
+                        }
+                        if let Some(id) = traced_id {
+                            trace_offer(opts, id, "parsed", Some(&offer));
+                        }
+                        let offer = match opts.transform_cmd {
+                            Some(ref cmd) => {
+                                let offer = transform_offer(cmd, offer)?;
+                                if offer.is_none() {
+                                    reject_reason = Some("rejected by the transform command");
+                                }
+                                if let Some(id) = traced_id {
+                                    trace_offer(opts, id, "transformed", offer.as_ref());
+                                }
+                                if let (Some(ref mut provenance), Some(ref o)) = (&mut provenance, &offer) {
+                                    provenance.record("transform", o);
+                                }
+                                offer
+                            }
+                            None => Some(offer),
+                        };
+                        let mut offer = match (offer, &rules) {
+                            (Some(offer), Some(rules)) => {
+                                let offer = rules.apply(offer)?;
+                                if offer.is_none() {
+                                    reject_reason = Some("rejected by the rules");
+                                }
+                                if let Some(id) = traced_id {
+                                    trace_offer(opts, id, "rules", offer.as_ref());
+                                }
+                                if let (Some(ref mut provenance), Some(ref o)) = (&mut provenance, &offer) {
+                                    provenance.record("rules", o);
+                                }
+                                offer
+                            }
+                            (offer, _) => offer,
+                        };
+                        if let (Some(ref mut offer), true) = (&mut offer, opts.convert_prices) {
+                            if let Some(price) = offer.price {
+                                let currency = offer.currency_id.as_deref();
+                                match currency::convert(&opts.currency_rates, price, offer.old_price, currency) {
+                                    Some((price, old_price, original)) => {
+                                        offer.price = Some(price);
+                                        offer.old_price = old_price;
+                                        offer.currency_id = Some(currency::DEFAULT_CURRENCY.to_string());
+                                        offer.original_price = Some(original);
+                                        stat.converted_prices += 1;
+                                    }
+                                    None if currency.is_some_and(|c| c != currency::DEFAULT_CURRENCY) => {
+                                        warn!(
+                                            "{}: Unknown rate of {}, the price is not converted",
+                                            offer.offer_id, currency.unwrap_or_default()
+                                        );
+                                        stat.unconverted_prices += 1;
                                     }
+                                    None => {}
                                 }
-                                Ok(Event::Eof) => {
-                                    unreachable!();
+                            }
+                        }
+                        if let (Some(ref mut provenance), Some(ref o)) = (&mut provenance, &offer) {
+                            provenance.record("currency", o);
+                        }
+                        if let (Some(ref mut offer), PricesAre::Net) = (&mut offer, opts.prices_are) {
+                            let rate = offer.vat.as_deref().and_then(vat_rate).or(opts.vat_rate);
+                            if let Some(rate) = rate {
+                                offer.price = offer.price.map(|p| to_gross(p, rate));
+                                offer.old_price = offer.old_price.map(|p| to_gross(p, rate));
+                                stat.grossed_up_offers += 1;
+                            } else {
+                                warn!("{}: Unknown VAT rate, net price is kept", offer.offer_id);
+                            }
+                        }
+                        if let (Some(ref mut provenance), Some(ref o)) = (&mut provenance, &offer) {
+                            provenance.record("vat", o);
+                        }
+                        if let (Some(ref mut offer), Some(pricing_rules)) = (&mut offer, &pricing_rules) {
+                            if let Some(price) = offer.price {
+                                if let Some(new_price) = pricing_rules.apply(offer.category_id, price) {
+                                    offer.price = Some(new_price);
+                                    offer.old_price = offer.old_price
+                                        .and_then(|p| pricing_rules.apply(offer.category_id, p));
+                                    stat.repriced_offers += 1;
+                                }
+                            }
+                        }
+                        if let (Some(ref mut provenance), Some(ref o)) = (&mut provenance, &offer) {
+                            provenance.record("pricing_rules", o);
+                        }
+                        if let (Some(ref mut offer), Some(normalizer)) = (&mut offer, &name_normalizer) {
+                            if let Some(name) = offer.name.take() {
+                                let normalized = normalizer.normalize(
+                                    &name, offer.vendor_code.as_deref(), &mut stat.name_normalization
+                                );
+                                if normalized != name {
+                                    stat.name_normalization.changed += 1;
                                 }
-                                Err(e) => {
-                                    error!("Error at position: {}", xml_reader.buffer_position());
-                                    Err(e)?;
+                                if !normalized.is_empty() {
+                                    offer.name = Some(normalized);
                                 }
-                                _ => {}
                             }
-
-                            offer_buf.clear();
                         }
-
-                        stat.total_offers += 1;
-                        if let Some(product) = convert_offer_to_product(offer) {
-                            if opts.mark_missing_unavailable {
-                                all_offer_ids.insert(product.offer_id.clone());
+                        if let (Some(ref mut provenance), Some(ref o)) = (&mut provenance, &offer) {
+                            provenance.record("normalize_names", o);
+                        }
+                        if let (Some(ref o), Some(id)) = (&offer, traced_id) {
+                            trace_offer(opts, id, "prepared", Some(o));
+                        }
+                        if let Some(ref mut o) = offer {
+                            let length_check = check_lengths(o, opts.length_policy)?;
+                            if let Some(id) = traced_id {
+                                trace(opts, id, "length_check", || json!(format!("{:?}", length_check)));
+                            }
+                            match length_check {
+                                LengthCheck::Fits => {}
+                                LengthCheck::Truncated => stat.oversized_offers += 1,
+                                LengthCheck::Rejected => {
+                                    stat.oversized_offers += 1;
+                                    reject_reason = Some("fields exceed column limits");
+                                    offer = None;
+                                }
+                            }
+                        }
+                        if let (Some(ref mut provenance), Some(ref o), Some(id)) = (&mut provenance, &offer, traced_id) {
+                            provenance.record("length_check", o);
+                            trace(opts, id, "provenance", || provenance.to_json());
+                        }
+                        if let Some(ref offer) = offer {
+                            for tag in &offer.tags {
+                                *stat.tagged_offers.entry(tag.clone()).or_default() += 1;
                             }
-                            products_bucket.push(product);
-                            stat.parsed_offers += 1;
+                        }
+                        let channel_visible = match (&offer, &channel_filter) {
+                            (Some(offer), Some(channel_filter)) => {
+                                let visible = channel_filter.matches(offer)?;
+                                if let Some(id) = traced_id {
+                                    trace(opts, id, "channel_filter", || json!({"visible": visible}));
+                                }
+                                visible
+                            }
+                            _ => true,
+                        };
+                        let sku = if merger.is_some() {
+                            offer.as_ref().and_then(merge_key)
                         } else {
-                            stat.ignored_offers += 1;
+                            None
+                        };
+                        let ignore_reason = rejects_xml.as_ref().and(offer.as_ref()).map(rejects::missing_fields);
+                        match offer.map(|offer| convert_offer_to_product(offer, opts)) {
+                            Some(Some(product)) if opts.skip_adult && product.adult != 0 => {
+                                if let Some(id) = traced_id {
+                                    trace(opts, id, "converted", || json!("skipped: adult"));
+                                }
+                                stat.skipped_adult_offers += 1;
+                            }
+                            Some(Some(mut product)) => {
+                                product.channel_visible = channel_visible;
+                                let shop_file_id = match (shop_suppliers, &shop) {
+                                    (Some(shop_suppliers), Some(shop)) => shop_suppliers.file_id(shop),
+                                    _ => None,
+                                };
+                                if let Some(file_id) = shop_file_id {
+                                    product.file_id = Some(file_id);
+                                }
+                                if product.low_stock == 1 {
+                                    stat.low_stock_offers += 1;
+                                }
+                                if let Some(ref feed_categories) = feed_categories {
+                                    product.category_path = feed_categories.path(product.categoryId);
+                                }
+                                if let Some(ref mut variant_grouper) = variant_grouper {
+                                    if variant_grouper.assign(&mut product) {
+                                        stat.variant_offers += 1;
+                                    }
+                                }
+                                if let Some(id) = traced_id {
+                                    trace(opts, id, "converted", || json!({"file_id": product.file_id}));
+                                }
+                                if let (Some(merger), Some(sku)) = (&mut merger, sku) {
+                                    product.source_supplier = shop.clone();
+                                    match merger.add(&sku, &mut product) {
+                                        Merged::First => {}
+                                        Merged::Wins => {
+                                            stat.merged_offers += 1;
+                                            // the previous winner of the bucket keeps only its stock
+                                            for p in products_bucket.iter_mut().filter(|p| p.hub_stock_id == sku) {
+                                                p.stock_only = true;
+                                            }
+                                        }
+                                        Merged::Loses => {
+                                            stat.merged_offers += 1;
+                                            product.stock_only = true;
+                                        }
+                                    }
+                                }
+                                if !product.stock_only {
+                                    descriptions::track_largest(opts, &product, stat);
+                                    bucket_description_bytes += descriptions::description_bytes(&product) as u64;
+                                }
+                                products_bucket.push(product);
+                                stat.parsed_offers += 1;
+                            }
+                            Some(None) => {
+                                if let Some(id) = traced_id {
+                                    trace(opts, id, "converted", || json!("ignored: missing name, categoryId or price"));
+                                }
+                                stat.ignored_offers += 1;
+                                if let Some(ref shop) = shop {
+                                    *stat.shop_error_offers.entry(shop.clone()).or_default() += 1;
+                                }
+                                if let (Some(rejects_xml), Some(o), Some(reason)) =
+                                    (&mut rejects_xml, &original_offer, &ignore_reason)
+                                {
+                                    rejects_xml.add(o, reason)?;
+                                }
+                            }
+                            None => {
+                                stat.rejected_offers += 1;
+                                if let Some(ref shop) = shop {
+                                    *stat.shop_error_offers.entry(shop.clone()).or_default() += 1;
+                                }
+                                if let (Some(rejects_xml), Some(o)) = (&mut rejects_xml, &original_offer) {
+                                    rejects_xml.add(o, reject_reason.unwrap_or("rejected"))?;
+                                }
+                            }
                         }
-                        if products_bucket.len() == 1000 {
-                            let processed_products_stat = sync_products_chunk(
-                                conn, &products_bucket, opts, &date_processed
-                            )?;
-                            stat.updated_price += processed_products_stat.updated_price;
-                            stat.updated_available += processed_products_stat.updated_available;
-                            stat.inserted_products += processed_products_stat.inserted;
-                            total_sync_duration += processed_products_stat.duration;
-                            products_bucket.clear();
+                        let descriptions_full = descriptions::chunk_is_full(opts, bucket_description_bytes);
+                        if products_bucket.len() == CHUNK_SIZE || descriptions_full {
+                            if products_bucket.len() < CHUNK_SIZE {
+                                stat.description_flushes += 1;
+                            }
+                            let full_bucket = std::mem::replace(
+                                &mut products_bucket, Vec::with_capacity(CHUNK_SIZE)
+                            );
+                            bucket_description_bytes = 0;
+                            sync_bucket(full_bucket, stat)?;
                         }
                     }
                     _ => {}
@@ -294,46 +1568,140 @@ pub(crate) fn parse_offers(
                 break;
             }
             Err(e) => {
-                error!("Error at position: {}", xml_reader.buffer_position());
-                Err(e)?;
+                return Err(Error::from(e).at_position(xml_reader.buffer_position()));
             }
             _ => {}
         }
 
         buf.clear();
 
-        if let Some(ref pb) = progress_bar {
-            let cur_file_position = xml_reader.buffer_position() as u64;
-            if cur_file_position > pb.position() + update_progress_after_chunk {
-                pb.set_position(cur_file_position);
-            }
-        };
+        progress.update(input_progress.consumed(), Some(stat));
     }
 
+
     if !products_bucket.is_empty() {
-        let processed_products_stat = sync_products_chunk(
-            conn, &products_bucket, opts, &date_processed
-        )?;
-        stat.updated_price += processed_products_stat.updated_price;
-        stat.updated_available += processed_products_stat.updated_available;
-        stat.inserted_products += processed_products_stat.inserted;
-        total_sync_duration += processed_products_stat.duration;
+        sync_bucket(products_bucket, stat)?;
     }
 
-    if let Some(ref pb) = progress_bar {
-        pb.finish();
-    };
+    if let Some(rejects_xml) = rejects_xml {
+        stat.rejects_xml_offers = rejects_xml.finish()?;
+    }
 
-    if opts.mark_missing_unavailable {
-        let start_mark_missing_at = Instant::now();
-        stat.marked_as_unavailable = mark_missing_as_unavailable(conn, &all_offer_ids, opts)?;
-        stat.mark_missing_duration = start_mark_missing_at.elapsed();
+    progress.finish(input_progress.consumed(), Some(stat));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use structopt::StructOpt;
+
+    use super::*;
+
+    fn try_parse_feed(name: &str, feed: &str) -> Result<(Vec<models::NewProduct>, ProcessedStat), Error> {
+        let file_path = std::env::temp_dir().join(format!("hubber_xml_test_{}.xml", name));
+        fs::write(&file_path, feed).unwrap();
+        let opts = Opts::from_iter(&["hubber_xml", "--no-progress", file_path.to_str().unwrap()]);
+        let mut stat = ProcessedStat::default();
+        let mut products = vec!();
+        let result = parse_products(&ImportContext::new(&opts).unwrap(), &mut stat, |bucket, _| {
+            products.extend(bucket);
+            Ok(())
+        });
+        fs::remove_file(&file_path).unwrap();
+        result.map(|_| (products, stat))
     }
 
-    finilize_processing(conn, &date_processed)?;
+    fn parse_feed(name: &str, feed: &str) -> (Vec<models::NewProduct>, ProcessedStat) {
+        try_parse_feed(name, feed).unwrap()
+    }
+
+    #[test]
+    fn test_empty_offers_mixed_with_full_ones() {
+        let (products, stat) = parse_feed("mixed_offers", r#"<?xml version="1.0" encoding="UTF-8"?>
+<yml_catalog>
+  <shop>
+    <offers>
+      <offer id="1" available="true">
+        <price>10</price>
+        <categoryId>5</categoryId>
+        <name>First</name>
+      </offer>
+      <offer id="2" available="true"/>
+      <offer id="3" available="false">
+        <price>30</price>
+        <categoryId>5</categoryId>
+        <name>Third</name>
+      </offer>
+      <offer id="4"/>
+    </offers>
+  </shop>
+</yml_catalog>
+"#);
+        assert_eq!(stat.total_offers, 4);
+        assert_eq!(stat.ignored_offers, 2);
+        let parsed = products.iter()
+            .map(|p| (p.offer_id.as_str(), p.name.as_str(), p.available))
+            .collect::<Vec<_>>();
+        assert_eq!(parsed, vec!(("1", "First", AVAILABLE), ("3", "Third", NOT_AVAILABLE)));
+    }
+
+    #[test]
+    fn test_empty_offer_is_last() {
+        let (products, stat) = parse_feed("empty_offer_last", r#"<yml_catalog><shop><offers>
+<offer id="1"><price>10</price><categoryId>5</categoryId><name>First</name></offer>
+<offer id="2"/>
+</offers></shop></yml_catalog>
+"#);
+        assert_eq!(stat.total_offers, 2);
+        assert_eq!(products.len(), 1);
+        assert_eq!(products[0].offer_id, "1");
+    }
 
-    stat.total_duration = start_processing_at.elapsed();
-    stat.parse_duration = stat.total_duration - total_sync_duration - stat.mark_missing_duration;
+    #[test]
+    fn test_related_offers() {
+        let (products, _) = parse_feed("related_offers", r#"<yml_catalog><shop><offers>
+<offer id="1"><price>10</price><categoryId>5</categoryId><name>First</name>
+<rec>2, 3,2</rec>
+<related_offer type="similar">4</related_offer>
+<related_offer type="unknown">5</related_offer>
+</offer>
+</offers></shop></yml_catalog>
+"#);
+        let relations = products[0].relations.iter()
+            .map(|(id, relation_type)| (id.as_str(), *relation_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            relations,
+            vec!(("2", RelationType::Accessory), ("3", RelationType::Accessory), ("4", RelationType::Similar))
+        );
+    }
 
-    Ok(stat)
+    #[test]
+    fn test_stock_suppliers() {
+        let (products, stat) = parse_feed("stock_suppliers", r#"<yml_catalog>
+<shop><name>Supplier &amp; Co</name><offers>
+<offer id="1"><price>10</price><categoryId>5</categoryId><name>First</name><quantity>3</quantity></offer>
+</offers></shop>
+<shop><name> </name><offers>
+<offer id="2"><price>20</price><categoryId>5</categoryId><name>Second</name><quantity>4</quantity></offer>
+</offers></shop>
+</yml_catalog>
+"#);
+        assert_eq!(stock_suppliers(&stat), vec!("Supplier & Co".to_string()));
+        assert_eq!(products[0].stocks, vec!(("Supplier & Co".to_string(), 3)));
+        // the stock of an unnamed shop is not saved
+        assert_eq!(products[1].stocks, vec!((String::new(), 4)));
+    }
+
+    #[test]
+    fn test_truncated_offer() {
+        let err = try_parse_feed("truncated_offer", r#"<yml_catalog><shop><offers>
+<offer id="1"><price>10</price>"#).unwrap_err();
+        let message = err.to_string();
+        assert!(message.starts_with("Parse error: position "), "{}", message);
+        assert!(message.ends_with("hubber_xml_test_truncated_offer.xml ended inside of <offer> element"), "{}", message);
+    }
 }
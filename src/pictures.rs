@@ -0,0 +1,145 @@
+use log::warn;
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use url::Url;
+
+use crate::Opts;
+use crate::fetch::proxy_for;
+use crate::models::{self, STATUS_DRAFT};
+
+/// Attempts of a picture request failing with a network error, 429 or 5xx status
+const PICTURE_ATTEMPTS: u32 = 3;
+/// Pause before the next attempt, it grows with every attempt
+const PICTURE_RETRY_DELAY: Duration = Duration::from_secs(1);
+const PICTURE_TIMEOUT_MS: u64 = 10_000;
+
+enum PictureCheck {
+    Alive,
+    Dead(String),
+    /// The server did not give a definite answer, the picture is not considered dead
+    Unverified(String),
+}
+
+#[derive(Default)]
+pub(crate) struct PicturesStat {
+    /// Offer ids of the products with dead pictures
+    pub dead: Vec<String>,
+    pub unverified: u32,
+}
+
+/// Checks pictures of the new products with HEAD requests, products with a dead picture
+/// are inserted as drafts so they are not published with broken photos.
+/// `--picture-sample` pictures of every product are checked by `--picture-check-concurrency` threads.
+pub(crate) fn check_new_products(products: &mut [models::NewProduct], opts: &Opts) -> PicturesStat {
+    let sample = match opts.picture_sample {
+        0 => usize::MAX,
+        sample => sample,
+    };
+    let urls = products.iter()
+        .flat_map(|p| p.pictures.iter().take(sample))
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    let checks = check_urls(urls, opts.picture_check_concurrency.max(1));
+
+    let mut stat = PicturesStat::default();
+    for check in checks.values() {
+        if let PictureCheck::Unverified(_) = check {
+            stat.unverified += 1;
+        }
+    }
+    for p in products.iter_mut() {
+        let dead = p.pictures.iter().take(sample)
+            .filter_map(|url| match checks.get(url) {
+                Some(PictureCheck::Dead(reason)) => Some(format!("{} ({})", url, reason)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if !dead.is_empty() {
+            warn!("{}: dead pictures, the product is inserted as a draft: {}", p.offer_id, dead.join(", "));
+            p.status = STATUS_DRAFT.to_string();
+            stat.dead.push(p.offer_id.clone());
+        }
+    }
+    stat
+}
+
+fn check_urls(urls: BTreeSet<String>, concurrency: usize) -> HashMap<String, PictureCheck> {
+    let queue = Mutex::new(urls.into_iter());
+    let checks = Mutex::new(HashMap::new());
+    thread::scope(|s| {
+        for _ in 0..concurrency {
+            s.spawn(|| loop {
+                let url = match queue.lock().unwrap().next() {
+                    Some(url) => url,
+                    None => break,
+                };
+                let check = check_url(&url);
+                if let PictureCheck::Unverified(ref e) = check {
+                    warn!("Cannot check picture {}: {}", url, e);
+                }
+                checks.lock().unwrap().insert(url, check);
+            });
+        }
+    });
+    checks.into_inner().unwrap()
+}
+
+/// Servers that do not support HEAD requests are asked for the first byte
+fn check_url(url: &str) -> PictureCheck {
+    let parsed = match Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => parsed,
+        _ => return PictureCheck::Dead("invalid URL".to_string()),
+    };
+    let proxy = match proxy_for(&parsed) {
+        Ok(proxy) => proxy,
+        Err(e) => return PictureCheck::Unverified(e.to_string()),
+    };
+    let mut use_get = false;
+    let mut last_error = String::new();
+    for attempt in 1..=PICTURE_ATTEMPTS {
+        if attempt > 1 {
+            thread::sleep(PICTURE_RETRY_DELAY * (attempt - 1));
+        }
+        let mut req = if use_get {
+            let mut req = ureq::get(url);
+            req.set("Range", "bytes=0-0");
+            req
+        } else {
+            ureq::head(url)
+        };
+        req.timeout_connect(PICTURE_TIMEOUT_MS)
+            .timeout_read(PICTURE_TIMEOUT_MS);
+        if let Some(ref proxy) = proxy {
+            req.set_proxy(proxy.clone());
+        }
+        let resp = req.call();
+        if let Some(e) = resp.synthetic_error() {
+            last_error = e.to_string();
+            continue;
+        }
+        match resp.status() {
+            200..=299 => {
+                // soft 404 pages are served with 200 status
+                return match resp.header("Content-Type") {
+                    Some(content_type) if !content_type.starts_with("image/") => {
+                        PictureCheck::Dead(format!("not an image: {}", content_type))
+                    }
+                    _ => PictureCheck::Alive,
+                };
+            }
+            405 | 501 if !use_get => {
+                use_get = true;
+                last_error = format!("HTTP {}", resp.status());
+            }
+            status @ (429 | 500..=599) => {
+                last_error = format!("HTTP {}", status);
+            }
+            status => return PictureCheck::Dead(format!("HTTP {}", status)),
+        }
+    }
+    PictureCheck::Unverified(last_error)
+}
@@ -0,0 +1,446 @@
+use chrono::NaiveDateTime;
+
+use log::{info, warn};
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use std::collections::HashSet;
+use std::env;
+use std::thread;
+use std::time::Duration;
+
+use url::Url;
+
+use uuid::Uuid;
+
+use crate::Opts;
+use crate::deletes::DeleteMode;
+use crate::error::Error;
+use crate::models;
+use crate::store::{ProductStore, ProductUpdate};
+
+/// Offer ids looked up with a single request
+const PIM_BATCH_SIZE: usize = 100;
+const PIM_ATTEMPTS: u32 = 3;
+/// Pause before the next attempt, it grows with every attempt
+const PIM_RETRY_DELAY: Duration = Duration::from_secs(2);
+const PIM_TIMEOUT: Duration = Duration::from_secs(60);
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Options backed by MySQL tables that the PIM does not have
+pub(crate) fn check_opts(opts: &Opts) -> Result<(), Error> {
+    let incompatible = [
+        ("--staging", opts.staging),
+        ("--sql-out", opts.sql_out.is_some()),
+        ("--available-only", opts.available_only),
+        ("--idempotency-key", opts.idempotency_key.is_some()),
+        ("--pricing-rules", opts.pricing_rules),
+        ("--generate-slugs", opts.generate_slugs),
+        ("--sku-template", opts.sku_template.is_some()),
+        ("--find-duplicates", opts.find_duplicates),
+        ("--create-categories", opts.create_categories),
+        ("--aggregate-stocks", opts.aggregate_stocks),
+        ("--group-variants", opts.group_variants),
+        ("--update-keywords", opts.update_keywords),
+        ("--update-relations", opts.update_relations),
+        ("--channel", opts.channel.is_some()),
+        ("--convert-prices", opts.convert_prices),
+        ("--respect-reservations", opts.respect_reservations),
+        ("--discontinued-categories", opts.discontinued_categories),
+        ("--max-memory", opts.max_memory.is_some()),
+        ("--track-runs", opts.track_runs),
+        ("--track-suppliers", opts.track_suppliers),
+        ("--track-feed-fields", opts.track_feed_fields),
+        ("--check-price-scale", opts.check_price_scale),
+        ("--category-stats", opts.category_stats),
+        ("--skip-unchanged", opts.skip_unchanged),
+        ("--price-smoothing", opts.price_smoothing.is_some()),
+        ("--resolve", !opts.resolve.is_empty()),
+        ("--mark-missing-workers", opts.mark_missing_workers > 1),
+        ("--apply-deletes hard", opts.apply_deletes == Some(DeleteMode::Hard)),
+    ];
+    for (option, is_set) in incompatible.iter() {
+        if *is_set {
+            return Err(Error::config(format!("{} cannot be used with --target pim", option)));
+        }
+    }
+    Ok(())
+}
+
+/// Product as the PIM returns it, offers are matched by `external_id`
+#[derive(Deserialize)]
+struct PimProduct {
+    id: i32,
+    offer_id: String,
+    external_id: Option<String>,
+    category_id: i32,
+    name: String,
+    price: f32,
+    oldprice: Option<f32>,
+    currency_id: Option<String>,
+    available: Option<i8>,
+    description: Option<String>,
+    renew_date: Option<String>,
+    file_id: Option<i8>,
+    on_sale: Option<i8>,
+    discount_percent: Option<i32>,
+    #[serde(default)]
+    unavailable_runs: i32,
+    tags: Option<String>,
+    slug: Option<String>,
+    gtin: Option<i64>,
+    #[serde(default)]
+    version: i32,
+    #[serde(default)]
+    locked_fields: Vec<String>,
+    to_renew: Option<i8>,
+    #[serde(default)]
+    price_from: i8,
+    vat: Option<String>,
+    status: String,
+    source_supplier: Option<String>,
+    quantity_in_stock: Option<i32>,
+    category_path: Option<String>,
+    #[serde(default)]
+    adult: i8,
+    age: Option<i32>,
+    last_import_id: Option<String>,
+    sku: Option<String>,
+    #[serde(default)]
+    low_stock: i8,
+}
+
+impl From<PimProduct> for models::Product {
+    fn from(p: PimProduct) -> models::Product {
+        models::Product {
+            id: p.id,
+            offer_id: p.offer_id,
+            hub_stock_id: p.external_id,
+            categoryId: p.category_id,
+            name: p.name,
+            price: p.price,
+            oldprice: p.oldprice,
+            currencyId: p.currency_id,
+            available: p.available,
+            description: p.description,
+            renew_data: p.renew_date
+                .and_then(|d| NaiveDateTime::parse_from_str(d.trim_end_matches('Z'), DATE_FORMAT).ok()),
+            file_id: p.file_id,
+            on_sale: p.on_sale,
+            discount_percent: p.discount_percent,
+            unavailable_runs: p.unavailable_runs,
+            tags: p.tags,
+            slug: p.slug,
+            GTIN: p.gtin,
+            version: p.version,
+            locked_fields: if p.locked_fields.is_empty() {
+                None
+            } else {
+                Some(Value::from(p.locked_fields).to_string())
+            },
+            to_renew: p.to_renew,
+            price_from: p.price_from,
+            vat: p.vat,
+            status: p.status,
+            source_supplier: p.source_supplier,
+            quantity_in_stock: p.quantity_in_stock,
+            category_path: p.category_path,
+            adult: p.adult,
+            age: p.age,
+            last_import_id: p.last_import_id,
+            sku: p.sku,
+            low_stock: p.low_stock,
+            // the PIM does not support --convert-prices
+            price_original: None,
+            oldprice_original: None,
+            currency_original: None,
+            currency_rate: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PimProductId {
+    id: i32,
+    external_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    items: Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct Count {
+    count: u64,
+}
+
+#[derive(Deserialize)]
+struct Marked {
+    marked: u32,
+}
+
+/// Syncs products through the REST API of the PIM (`--target pim`) for stores without database access.
+/// Requests are authorized with `PIM_TOKEN` environment variable and retried on network errors
+/// and `429`, `5xx` responses. `POST` and `PATCH` requests carry `Idempotency-Key` header that stays
+/// the same for all attempts, so the PIM applies a retried request only once. Updates are sent
+/// one by one with `If-Match` header holding the expected version, `412` response means
+/// the product was modified concurrently.
+pub(crate) struct PimStore {
+    url: Url,
+    token: String,
+}
+
+impl PimStore {
+    pub fn new(url: &Url) -> Result<PimStore, Error> {
+        let token = env::var("PIM_TOKEN")
+            .map_err(|_| Error::config("PIM_TOKEN environment variable is required for --target pim"))?;
+        info!("Syncing products into PIM at {}", url);
+        Ok(PimStore { url: url.clone(), token })
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        query: &[(&str, String)],
+        if_match: Option<i32>,
+        body: Option<Value>,
+    ) -> Result<ureq::Response, Error> {
+        let url = self.url.join(path)
+            .map_err(|e| Error::config_caused_by(format!("Invalid PIM url: {}", self.url), e))?;
+        // a request that failed after the PIM received it must not be applied twice
+        let idempotency_key = match method {
+            "GET" | "HEAD" | "PUT" | "DELETE" => None,
+            _ => Some(Uuid::new_v4().to_string()),
+        };
+        let mut attempt = 1;
+        loop {
+            let mut req = ureq::request(method, url.as_str());
+            req.set("Authorization", &format!("Bearer {}", self.token))
+                .timeout(PIM_TIMEOUT);
+            for (param, value) in query {
+                req.query(param, value);
+            }
+            if let Some(version) = if_match {
+                req.set("If-Match", &format!("\"{}\"", version));
+            }
+            if let Some(ref key) = idempotency_key {
+                req.set("Idempotency-Key", key);
+            }
+            let resp = match body {
+                Some(ref body) => req.send_json(body.clone()),
+                None => req.call(),
+            };
+            let retryable = resp.synthetic() || resp.server_error() || resp.status() == 429;
+            if !retryable || attempt == PIM_ATTEMPTS {
+                return Ok(resp);
+            }
+            match resp.synthetic_error() {
+                Some(e) => warn!("PIM request {} {} failed: {}, retrying", method, path, e),
+                None => warn!("PIM request {} {} failed with status {}, retrying", method, path, resp.status()),
+            }
+            thread::sleep(PIM_RETRY_DELAY * attempt);
+            attempt += 1;
+        }
+    }
+}
+
+/// Fails unless the PIM responded with a success status
+fn ok(resp: ureq::Response) -> Result<ureq::Response, Error> {
+    if let Some(e) = resp.synthetic_error() {
+        return Err(Error::external("pim", format!("PIM request failed: {}", e)));
+    }
+    if resp.error() {
+        let status = resp.status();
+        return Err(Error::external("pim", format!("PIM responded with status {}: {}", status, resp.into_string()?)));
+    }
+    Ok(resp)
+}
+
+fn format_date(date: &NaiveDateTime) -> String {
+    date.format(DATE_FORMAT).to_string()
+}
+
+fn changes_json(changes: &models::ModProduct) -> Value {
+    let mut fields = Map::new();
+    if let Some(available) = changes.available {
+        fields.insert("available".to_string(), json!(available));
+    }
+    if let Some(price) = changes.price {
+        fields.insert("price".to_string(), json!(price));
+    }
+    if let Some(oldprice) = changes.oldprice {
+        fields.insert("oldprice".to_string(), json!(oldprice));
+    }
+    if let Some(currency_id) = changes.currencyId {
+        fields.insert("currency_id".to_string(), json!(currency_id));
+    }
+    if let Some(renew_date) = changes.renew_date {
+        fields.insert("renew_date".to_string(), json!(format_date(renew_date)));
+    }
+    if let Some(on_sale) = changes.on_sale {
+        fields.insert("on_sale".to_string(), json!(on_sale));
+    }
+    if let Some(discount_percent) = changes.discount_percent {
+        fields.insert("discount_percent".to_string(), json!(discount_percent));
+    }
+    if let Some(unavailable_runs) = changes.unavailable_runs {
+        fields.insert("unavailable_runs".to_string(), json!(unavailable_runs));
+    }
+    if let Some(to_renew) = changes.to_renew {
+        fields.insert("to_renew".to_string(), json!(to_renew));
+    }
+    if let Some(price_from) = changes.price_from {
+        fields.insert("price_from".to_string(), json!(price_from));
+    }
+    if let Some(vat) = changes.vat {
+        fields.insert("vat".to_string(), json!(vat));
+    }
+    if let Some(category_path) = changes.category_path {
+        fields.insert("category_path".to_string(), json!(category_path));
+    }
+    if let Some(adult) = changes.adult {
+        fields.insert("adult".to_string(), json!(adult));
+    }
+    if let Some(age) = changes.age {
+        fields.insert("age".to_string(), json!(age));
+    }
+    if let Some(low_stock) = changes.low_stock {
+        fields.insert("low_stock".to_string(), json!(low_stock));
+    }
+    if let Some(source_supplier) = changes.source_supplier {
+        fields.insert("source_supplier".to_string(), json!(source_supplier));
+    }
+    if let Some(last_import_id) = changes.last_import_id {
+        fields.insert("last_import_id".to_string(), json!(last_import_id));
+    }
+    Value::Object(fields)
+}
+
+fn new_product_json(p: &models::NewProduct) -> Value {
+    json!({
+        "offer_id": p.offer_id,
+        "external_id": p.hub_stock_id,
+        "category_id": p.categoryId,
+        "name": p.name,
+        "price": p.price,
+        "oldprice": p.oldprice,
+        "currency_id": p.currencyId,
+        "available": p.available,
+        "description": p.description,
+        "file_id": p.file_id,
+        "on_sale": p.on_sale,
+        "discount_percent": p.discount_percent,
+        "tags": p.tags,
+        "slug": p.slug,
+        "gtin": p.GTIN,
+        "price_from": p.price_from,
+        "vat": p.vat,
+        "status": p.status,
+        "source_supplier": p.source_supplier,
+        "category_path": p.category_path,
+        "adult": p.adult,
+        "age": p.age,
+        "last_import_id": p.last_import_id,
+        "sku": p.sku,
+        "low_stock": p.low_stock,
+    })
+}
+
+impl ProductStore for PimStore {
+    fn name(&self) -> &'static str {
+        "PIM product store"
+    }
+
+    fn find_products(&mut self, offer_ids: &[&str]) -> Result<Vec<models::Product>, Error> {
+        let mut products = vec!();
+        for batch in offer_ids.chunks(PIM_BATCH_SIZE) {
+            let query = batch.iter()
+                .map(|offer_id| ("external_id", offer_id.to_string()))
+                .collect::<Vec<_>>();
+            let page = ok(self.send("GET", "products", &query, None, None)?)?
+                .into_json_deserialize::<Page<PimProduct>>()?;
+            products.extend(page.items.into_iter().map(models::Product::from));
+        }
+        Ok(products)
+    }
+
+    fn count_available(&mut self) -> Result<u64, Error> {
+        let query = [("available", "1".to_string())];
+        Ok(ok(self.send("GET", "products/count", &query, None, None)?)?.into_json_deserialize::<Count>()?.count)
+    }
+
+    fn available_products(&mut self, after_id: i32, limit: usize) -> Result<Vec<(i32, Option<String>)>, Error> {
+        let query = [
+            ("available", "1".to_string()),
+            ("after_id", after_id.to_string()),
+            ("limit", limit.to_string()),
+            ("fields", "id,external_id".to_string()),
+        ];
+        let page = ok(self.send("GET", "products", &query, None, None)?)?
+            .into_json_deserialize::<Page<PimProductId>>()?;
+        Ok(page.items.into_iter().map(|p| (p.id, p.external_id)).collect())
+    }
+
+    /// The PIM has no transactions, every update is applied separately
+    fn apply_updates(&mut self, updates: &[ProductUpdate], _commit_size: usize) -> Result<HashSet<i32>, Error> {
+        let mut conflicted_ids = HashSet::new();
+        for u in updates {
+            // counters are changed without touching the version and the modification date
+            let (query, if_match) = if u.counter_only {
+                (vec!(("touch", "false".to_string())), None)
+            } else {
+                (vec!(), u.expected_version)
+            };
+            let path = format!("products/{}", u.product_id);
+            let resp = self.send("PATCH", &path, &query, if_match, Some(changes_json(&u.changes)))?;
+            if resp.status() == 412 {
+                conflicted_ids.insert(u.product_id);
+                continue;
+            }
+            ok(resp)?;
+        }
+        Ok(conflicted_ids)
+    }
+
+    fn insert_products(&mut self, products: &[models::NewProduct]) -> Result<(), Error> {
+        let items = products.iter().map(new_product_json).collect::<Vec<_>>();
+        ok(self.send("POST", "products", &[], None, Some(json!({"items": items})))?)?;
+        Ok(())
+    }
+
+    fn insert_price_history(&mut self, rows: &[models::NewPriceHistory]) -> Result<(), Error> {
+        let items = rows.iter()
+            .map(|r| json!({
+                "product_id": r.product_id,
+                "old_price": r.old_price,
+                "new_price": r.new_price,
+                "feed_price": r.feed_price,
+                "currency_id": r.currencyId,
+                "run_id": r.run_id,
+                "created_at": format_date(&r.created_at),
+            }))
+            .collect::<Vec<_>>();
+        ok(self.send("POST", "price-history", &[], None, Some(json!({"items": items})))?)?;
+        Ok(())
+    }
+
+    fn mark_missing(
+        &mut self,
+        offer_ids: &[String],
+        file_ids: &[i8],
+        hysteresis: Option<u32>,
+        import_id: &str,
+        _forget_hashes: bool,
+    ) -> Result<u32, Error> {
+        let body = json!({
+            "external_ids": offer_ids,
+            "file_ids": file_ids,
+            "hysteresis": hysteresis,
+            "import_id": import_id,
+        });
+        Ok(ok(self.send("POST", "products/mark-missing", &[], None, Some(body))?)?.into_json_deserialize::<Marked>()?.marked)
+    }
+}
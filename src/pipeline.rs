@@ -0,0 +1,47 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+
+use crate::ProcessedStat;
+use crate::context::ImportContext;
+use crate::error::Error;
+use crate::parser::{parse_products, SyncStage};
+use crate::store::ProductStore;
+
+/// Number of parsed buckets that can wait for syncing
+const PENDING_BUCKETS: usize = 2;
+
+/// Parses the file in a separate thread while the already parsed buckets of products
+/// are synced into the database by the current thread, so parsing and database writes overlap.
+/// The parser waits when `PENDING_BUCKETS` buckets are not synced yet. The feed is downloaded
+/// and pictures are checked the same way as without the pipeline.
+pub(crate) fn parse_offers(
+    ctx: &ImportContext, store: &mut dyn ProductStore,
+) -> Result<ProcessedStat, Error> {
+    let start_processing_at = Instant::now();
+    let mut sync = SyncStage::start(ctx, store)?;
+
+    let (tx, rx) = mpsc::sync_channel(PENDING_BUCKETS);
+    let (parse_result, sync_result) = thread::scope(|s| {
+        let parser = s.spawn(move || {
+            let start_parsing_at = Instant::now();
+            let mut stat = ProcessedStat::default();
+            parse_products(
+                ctx, &mut stat,
+                |products_bucket, stat| {
+                    tx.send((products_bucket, stat.feed_date))
+                        .map_err(|_| Error::external("pipeline", "sync stage has stopped"))
+                }
+            )?;
+            stat.parse_duration = start_parsing_at.elapsed();
+            Ok::<_, Error>(stat)
+        });
+        // the receiver is dropped when syncing fails, so the parser stops too
+        let sync_result = rx.into_iter()
+            .try_for_each(|(products_bucket, feed_date)| sync.sync_bucket(products_bucket, feed_date));
+        (parser.join().expect("parser thread panicked"), sync_result)
+    });
+    // the parser fails when the sync stage has stopped so report the original error
+    sync_result?;
+    sync.finish(parse_result?, start_processing_at)
+}
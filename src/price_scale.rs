@@ -0,0 +1,198 @@
+use chrono::Utc;
+
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use log::{info, warn};
+
+use quick_xml::events::Event;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::Opts;
+use crate::dialect::{self, Dialect};
+use crate::error::{Error, ErrorContext};
+use crate::input;
+use crate::merchant::parse_price;
+use crate::models::NewSupplierPriceMedian;
+use crate::schema::supplier_price_medians;
+use crate::suppliers::file_supplier;
+
+/// Recent runs of the supplier whose medians make the historical median
+const HISTORY_RUNS: i64 = 10;
+/// Suppliers with fewer priced offers are not checked, their median is too noisy
+const MIN_OFFERS: usize = 20;
+/// Medians that differ by this factor or more mean a scale error (cents instead of units or vice versa)
+const SCALE_FACTOR: f64 = 10.0;
+
+/// Supplier whose median price changed by an order of magnitude
+#[derive(Debug)]
+pub(crate) struct PriceScaleAnomaly {
+    pub supplier: String,
+    pub median_price: f64,
+    pub historical_median_price: f64,
+    pub offers: u32,
+}
+
+impl PriceScaleAnomaly {
+    /// How many times the median price grew, below 1 when it dropped
+    pub fn ratio(&self) -> f64 {
+        self.median_price / self.historical_median_price
+    }
+}
+
+/// Median prices of the feed, they are saved only when the import succeeds
+#[derive(Default)]
+pub(crate) struct PriceScaleCheck {
+    medians: Vec<NewSupplierPriceMedian>,
+    anomalies: Vec<PriceScaleAnomaly>,
+}
+
+/// Reads prices of the whole feed, parts of a split feed included, before the import and compares
+/// the median price of every supplier with the median of its recent runs. Anomalies abort the import
+/// unless `--force-price-scale` is passed.
+pub(crate) fn check(conn: &mut MysqlConnection, opts: &Opts) -> Result<PriceScaleCheck, Error> {
+    let run_at = Utc::now().naive_utc();
+    let mut check = PriceScaleCheck::default();
+    let dialect = match opts.dialect {
+        Some(dialect) => dialect,
+        None => dialect::detect(opts.file_path())?,
+    };
+    for (shop, mut prices) in scan_prices(opts.file_path(), dialect)? {
+        if prices.len() < MIN_OFFERS {
+            continue;
+        }
+        let supplier = if shop.is_empty() {
+            file_supplier(opts.file_path())
+        } else {
+            shop
+        };
+        let median_price = median(&mut prices);
+        let mut history = supplier_price_medians::table
+            .select(supplier_price_medians::median_price)
+            .filter(supplier_price_medians::supplier.eq(&supplier))
+            .order(supplier_price_medians::id.desc())
+            .limit(HISTORY_RUNS)
+            .load::<f64>(conn)?;
+        if !history.is_empty() {
+            let anomaly = PriceScaleAnomaly {
+                supplier: supplier.clone(),
+                median_price,
+                historical_median_price: median(&mut history),
+                offers: prices.len() as u32,
+            };
+            let ratio = anomaly.ratio();
+            if ratio >= SCALE_FACTOR || ratio <= 1.0 / SCALE_FACTOR {
+                warn!(
+                    "{}: median price is {:.2} while it was {:.2} in the recent runs",
+                    supplier, anomaly.median_price, anomaly.historical_median_price
+                );
+                check.anomalies.push(anomaly);
+            }
+        }
+        check.medians.push(NewSupplierPriceMedian {
+            supplier,
+            median_price,
+            offers: prices.len() as u32,
+            run_at,
+        });
+    }
+
+    if !check.anomalies.is_empty() {
+        if !opts.force_price_scale {
+            let suppliers = check.anomalies.iter()
+                .map(|a| format!("{} ({:.2} instead of {:.2})", a.supplier, a.median_price, a.historical_median_price))
+                .collect::<Vec<_>>();
+            return Err(Error::validation(
+                format!(
+                    "Median price changed by an order of magnitude: {}, pass --force-price-scale if the prices are right",
+                    suppliers.join(", ")
+                ),
+                ErrorContext::default(),
+            ));
+        }
+        info!("Price scale anomalies are forced with --force-price-scale");
+    }
+    Ok(check)
+}
+
+/// Saves the median prices as the latest run of the suppliers, returns the forced anomalies
+pub(crate) fn save(conn: &mut MysqlConnection, check: PriceScaleCheck) -> Result<Vec<PriceScaleAnomaly>, Error> {
+    if !check.medians.is_empty() {
+        diesel::insert_into(supplier_price_medians::table)
+            .values(&check.medians)
+            .execute(conn)?;
+    }
+    Ok(check.anomalies)
+}
+
+/// Positive offer prices per shop, `""` for offers outside of shops
+fn scan_prices(file_path: &Path, dialect: Dialect) -> Result<BTreeMap<String, Vec<f64>>, Error> {
+    let mut prices = BTreeMap::<String, Vec<f64>>::new();
+    let mut shop = String::new();
+    let mut in_shop_name = false;
+    let mut in_offer = false;
+    let mut in_price = false;
+    input::scan(file_path, |xml_reader, event| {
+        match event {
+            Event::Start(ref e) => match e.name() {
+                b"shop" => shop.clear(),
+                b"name" if !in_offer => in_shop_name = true,
+                b"price" | b"g:price" if in_offer => in_price = true,
+                name if dialect.is_offer_element(name) => in_offer = true,
+                _ => {}
+            },
+            Event::Text(ref v) if in_shop_name => {
+                shop = v.unescape_and_decode(xml_reader)?.trim().to_string();
+            }
+            Event::Text(ref v) if in_price => {
+                let value = v.unescape_and_decode(xml_reader)?;
+                if let Some((price, _)) = parse_price(&value) {
+                    if price > 0.0 {
+                        prices.entry(shop.clone()).or_default().push(price as f64);
+                    }
+                }
+            }
+            Event::End(ref e) => match e.name() {
+                b"name" => in_shop_name = false,
+                b"price" | b"g:price" => in_price = false,
+                name if dialect.is_offer_element(name) => in_offer = false,
+                _ => {}
+            },
+            _ => {}
+        }
+        Ok(true)
+    })?;
+    Ok(prices)
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let middle = values.len() / 2;
+    if values.len() & 1 == 1 {
+        values[middle]
+    } else {
+        (values[middle - 1] + values[middle]) / 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_scan_prices_of_split_feed() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("index.xml"), r#"<?xml version="1.0" encoding="UTF-8"?>
+<yml_catalog xmlns:xi="http://www.w3.org/2001/XInclude"><shop><name>Shop</name>
+<xi:include href="part-1.xml"/><xi:include href="part-2.xml"/></shop></yml_catalog>"#).unwrap();
+        fs::write(dir.path().join("part-1.xml"), r#"<?xml version="1.0" encoding="UTF-8"?>
+<yml_catalog><shop><name>Shop</name><offers><offer id="1"><price>10</price></offer></offers></shop></yml_catalog>"#).unwrap();
+        fs::write(dir.path().join("part-2.xml"), r#"<?xml version="1.0" encoding="UTF-8"?>
+<yml_catalog><shop><name>Shop</name><offers><offer id="2"><price>20</price></offer></offers></shop></yml_catalog>"#).unwrap();
+        let prices = scan_prices(&dir.path().join("index.xml"), Dialect::Yml).unwrap();
+        assert_eq!(prices.get("Shop"), Some(&vec!(10.0, 20.0)));
+    }
+}
@@ -0,0 +1,56 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+
+use log::info;
+
+use std::collections::HashMap;
+
+use crate::error::Error;
+use crate::models::PricingRule;
+
+/// Markup and rounding rules per category managed in the admin panel.
+pub(crate) struct PricingRules {
+    by_category: HashMap<i32, PricingRule>,
+    default: Option<PricingRule>,
+}
+
+impl PricingRules {
+    pub fn load(conn: &mut MysqlConnection) -> Result<PricingRules, Error> {
+        use crate::schema::pricing_rules::dsl;
+
+        let mut by_category = HashMap::new();
+        let mut default = None;
+        let rules = dsl::pricing_rules
+            .select((dsl::category_id, dsl::percent, dsl::fixed_add, dsl::rounding))
+            .load::<PricingRule>(conn)?;
+        for rule in rules {
+            match rule.category_id {
+                Some(category_id) => {
+                    by_category.insert(category_id, rule);
+                }
+                None => {
+                    default = Some(rule);
+                }
+            }
+        }
+        info!("Loaded {} pricing rules", by_category.len() + default.iter().count());
+        Ok(PricingRules { by_category, default })
+    }
+
+    fn rule(&self, category_id: Option<i32>) -> Option<&PricingRule> {
+        category_id
+            .and_then(|category_id| self.by_category.get(&category_id))
+            .or(self.default.as_ref())
+    }
+
+    /// Returns price with applied markup or `None` if there is no rule for the category.
+    pub fn apply(&self, category_id: Option<i32>, price: f32) -> Option<f32> {
+        let rule = self.rule(category_id)?;
+        let price = price * (1.0 + rule.percent / 100.0) + rule.fixed_add;
+        Some(match rule.rounding {
+            Some(step) if step > 0.0 => (price / step).round() * step,
+            _ => price,
+        })
+    }
+}
@@ -1,42 +1,63 @@
 use chrono::NaiveDateTime;
 
-use diesel::connection::SimpleConnection;
 use diesel::mysql::MysqlConnection;
 use diesel::prelude::*;
 
-use failure::Error;
 
-use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::json;
 
-use std::collections::{HashMap, HashSet};
+use log::{info, warn};
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::mpsc;
+use std::thread;
 use std::time::{Duration, Instant};
 
-use crate::{CHUNK_SIZE, Opts};
-use crate::models::{self, AVAILABLE, NOT_AVAILABLE, HUBBER_FILE_ID};
+use crate::{CHUNK_SIZE, establish_mysql_connection, Opts};
+use crate::categories::load_discontinued;
+use crate::conflicts::{self, ConflictPolicy};
+use crate::context::ImportContext;
+use crate::currency::same_converted;
+use crate::database;
+use crate::error::Error;
+use crate::duplicates::find_possible_duplicates;
+use crate::memory::SeenOfferIds;
+use crate::models::{self, AVAILABLE, NOT_AVAILABLE, HUBBER_FILE_ID, STATUS_ACTIVE, STATUS_DRAFT};
+use crate::offer_hashes::{self, load_unchanged, offer_hash};
 use crate::parser::Offer;
-use crate::schema::{self, products};
+use crate::pictures;
+use crate::progress::{ProgressSink, Stage};
+use crate::reactivation::ReactivatedProduct;
+use crate::slug;
+use crate::smoothing::{load_feed_prices, smoothed_price};
+use crate::sku::assign_unique_skus;
+use crate::stocks::{is_fully_reserved, load_reservations};
+use crate::store::{mysql_connection, mysql_read_connection, ProductStore, ProductUpdate};
+use crate::trace::trace;
+use crate::schema::{import_quarantine, products};
 
 
-pub(crate) fn convert_offer_to_product(offer: Offer) -> Option<models::NewProduct> {
-    let name = if let Some(name) = offer.name {
-        name
-    } else {
-        return None;
+pub(crate) fn convert_offer_to_product(offer: Offer, opts: &Opts) -> Option<models::NewProduct> {
+    let sku = opts.sku_template.as_ref().and_then(|t| t.render(&offer));
+    let name = offer.name?;
+    let category_id = offer.category_id?;
+    let price = offer.price?;
+    let (on_sale, discount_percent) = match opts.min_discount {
+        Some(min_discount) => calc_discount(price, offer.old_price, min_discount),
+        None => (None, None),
     };
-    let category_id = if let Some(cat_id) = offer.category_id {
-        cat_id
-    } else {
-        return None;
+    let low_stock = match (opts.low_stock_threshold, offer.quantity) {
+        (Some(threshold), Some(quantity)) => offer.available == AVAILABLE && quantity <= threshold,
+        _ => false,
     };
-    let price = if let Some(price) = offer.price {
-        price
-    } else {
-        return None;
+    let available = match opts.low_stock_available {
+        Some(low_stock_available) if low_stock => low_stock_available,
+        _ => offer.available,
     };
     Some(models::NewProduct {
         offer_id: offer.offer_id.clone(),
         hub_stock_id: offer.offer_id.clone(),
-        available: offer.available,
+        available,
         categoryId: category_id,
         name,
         price,
@@ -44,184 +65,922 @@ pub(crate) fn convert_offer_to_product(offer: Offer) -> Option<models::NewProduc
         currencyId: offer.currency_id,
         description: offer.description,
         file_id: Some(HUBBER_FILE_ID),
+        on_sale,
+        discount_percent,
+        tags: if offer.tags.is_empty() {
+            None
+        } else {
+            Some(offer.tags.join(","))
+        },
+        slug: None,
+        GTIN: offer.barcode.and_then(|b| b.trim().parse().ok()),
+        price_from: offer.price_from as i8,
+        vat: offer.vat,
+        status: if opts.new_as_draft { STATUS_DRAFT } else { STATUS_ACTIVE }.to_string(),
+        source_supplier: None,
+        category_path: None,
+        adult: offer.adult as i8,
+        age: offer.age,
+        last_import_id: None,
+        sku,
+        low_stock: low_stock as i8,
+        stocks: match offer.quantity {
+            Some(quantity) => vec!((offer.shop.unwrap_or_default(), quantity)),
+            None => vec!(),
+        },
+        stock_only: false,
+        keywords: offer.keywords,
+        channel_visible: true,
+        group_id: offer.group_id,
+        params: offer.params,
+        pictures: offer.pictures,
+        original_price: offer.original_price,
+        variant_of: None,
+        relations: offer.relations,
     })
 }
 
+/// Returns `on_sale` flag and discount percent. Discounts less than `min_discount` percent
+/// are not considered as promotions.
+fn calc_discount(price: f32, old_price: Option<f32>, min_discount: f32) -> (Option<i8>, Option<i32>) {
+    match old_price {
+        Some(old_price) if old_price > price && old_price > 0.0 => {
+            let discount = (old_price - price) / old_price * 100.0;
+            if discount >= min_discount {
+                (Some(1), Some(discount.round() as i32))
+            } else {
+                (Some(0), None)
+            }
+        }
+        _ => (Some(0), None),
+    }
+}
+
+#[derive(PartialEq)]
+pub(crate) enum ChangeKind {
+    Updated,
+    Inserted,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Updated => "updated",
+            ChangeKind::Inserted => "inserted",
+        }
+    }
+}
+
+/// Change applied to a product. Values are textual representations of the new field values,
+/// `NULL` values are represented by empty strings.
+pub(crate) struct ProductChange {
+    pub product_id: Option<i32>,
+    pub offer_id: String,
+    pub kind: ChangeKind,
+    pub values: Vec<(&'static str, String)>,
+    /// Values the updated fields had before the change in the same order as `values`, empty for inserts
+    pub old_values: Vec<String>,
+}
+
 #[derive(Default)]
 pub(crate) struct ProcessedProducts {
     pub updated_price: u32,
+    /// Products whose promotion flags changed, see `--min-discount`
+    pub updated_discount: u32,
+    /// Products whose VAT code changed
+    pub updated_vat: u32,
+    /// Products with the same price in another currency
+    pub currency_only_changes: u32,
+    /// Products with a different currency whose converted price is the same, see `--currency-rate`
+    pub converted_same_prices: u32,
+    /// Products whose oldprice was cleared because the offer has none, see `--clear-missing-oldprice`
+    pub cleared_oldprices: u32,
+    /// Products that got an averaged price instead of the feed one, see `--price-smoothing`
+    pub smoothed_prices: u32,
     pub updated_available: u32,
+    /// Products whose availability flipped from 0 to 1
+    pub reactivated: u32,
+    /// Products whose availability flipped from 1 to 0
+    pub deactivated: u32,
+    /// Re-activated products listed into `--reactivated-out` file
+    pub reactivated_products: Vec<ReactivatedProduct>,
+    pub postponed_unavailable: u32,
     pub inserted: u32,
+    pub price_history_records: u32,
+    pub possible_duplicates: u32,
+    pub conflicts: u32,
+    pub suppressed_by_locks: u32,
+    /// Products reported available by the supplier but fully reserved by pending orders
+    pub reserved: u32,
+    /// Products changed after the feed was generated, see `--skip-newer-than-feed`
+    pub skipped_newer: u32,
+    /// Products whose offers did not change since the last sync, see `--skip-unchanged`
+    pub unchanged: u32,
+    /// Products whose differences were kept because of their manual changes, see `--resolve`
+    pub kept_manual: u32,
+    /// Products whose differences were written into product_conflicts table
+    pub queued_conflicts: u32,
+    /// Offers of discontinued categories that were not inserted or made available
+    pub discontinued: u32,
+    pub quarantined: u32,
+    /// Generated SKUs already used by other products, see `--sku-template`
+    pub sku_collisions: u32,
+    /// Offer ids of the new products inserted as drafts because of dead pictures
+    pub dead_pictures: Vec<String>,
+    pub unverified_pictures: u32,
     pub duration: Duration,
+    pub select_duration: Duration,
+    pub update_duration: Duration,
+    pub insert_duration: Duration,
+    pub changes: Vec<ProductChange>,
+}
+
+/// Whether the price and the old price are the same as the product's ones in another currency
+fn is_same_converted(
+    rates: &[(String, f32)],
+    price: f32,
+    oldprice: Option<f32>,
+    currency: Option<&str>,
+    product: &models::Product,
+) -> bool {
+    let product_currency = product.currencyId.as_deref();
+    let same_oldprice = match (oldprice, product.oldprice) {
+        (Some(oldprice), Some(product_oldprice)) => {
+            same_converted(rates, oldprice, currency, product_oldprice, product_currency)
+        }
+        (None, None) => true,
+        _ => false,
+    };
+    same_oldprice && same_converted(rates, price, currency, product.price, product_currency)
+}
+
+/// Whether the product was changed after the feed was generated
+fn is_newer(product: &models::Product, feed_date: Option<&NaiveDateTime>) -> bool {
+    match (product.renew_data.as_ref(), feed_date) {
+        (Some(renew_date), Some(feed_date)) => renew_date > feed_date,
+        _ => false,
+    }
 }
 
 pub(crate) fn sync_products_chunk(
-    conn: &MysqlConnection,
+    store: &mut dyn ProductStore,
     parsed_products: &Vec<models::NewProduct>,
-    opts: &Opts,
-    date_modified: &NaiveDateTime,
+    ctx: &ImportContext,
+    feed_date: Option<&NaiveDateTime>,
 ) -> Result<ProcessedProducts, Error> {
-    use crate::schema::products::dsl::{products as products_table};
-
+    let opts = ctx.opts;
+    let date_modified = &ctx.date_processed;
+    let import_id = ctx.import_id.as_str();
     let start_syncing_at = Instant::now();
     let mut processed_products_stat = ProcessedProducts::default();
 
+    // products whose offers did not change since they were synced are neither loaded nor compared,
+    // with --resolve the hashes tell whether the feed changed
+    let hashes_feature = if opts.skip_unchanged { "--skip-unchanged" } else { "--resolve" };
+    let track_hashes = offer_hashes::is_tracked(opts);
+    let (offer_hashes, stored_hashes) = if track_hashes {
+        let offer_hashes = parsed_products.iter()
+            .map(|p| (p.hub_stock_id.as_str(), offer_hash(opts, p)))
+            .collect::<HashMap<_, _>>();
+        let offer_ids = offer_hashes.keys().copied().collect::<Vec<_>>();
+        let stored_hashes = offer_hashes::load(mysql_connection(store, hashes_feature)?, &offer_ids)?;
+        (offer_hashes, stored_hashes)
+    } else {
+        (HashMap::new(), HashMap::new())
+    };
+    let unchanged_ids = if opts.skip_unchanged {
+        load_unchanged(mysql_connection(store, "--skip-unchanged")?, &offer_hashes, &stored_hashes)?
+    } else {
+        HashSet::new()
+    };
+
     let offer_ids = parsed_products.iter()
+        .filter(|p| !unchanged_ids.contains(&p.hub_stock_id))
         .map(|p| p.offer_id.as_str())
         .collect::<Vec<_>>();
-    let found_products = products_table
-        .filter(schema::products::hub_stock_id.eq_any(offer_ids))
-        .load::<models::Product>(conn)?;
+    let found_products = store.find_products(&offer_ids)?;
+    processed_products_stat.select_duration += start_syncing_at.elapsed();
     let offer_id_to_found_product = found_products.iter()
-        .filter_map(|p| {
-            if let Some(ref hub_stock_id) = p.hub_stock_id {
-                Some((hub_stock_id.as_str(), p))
-            } else {
-                None
-            }
+        .filter_map(|p| p.hub_stock_id.as_ref().map(|hub_stock_id| (hub_stock_id.as_str(), p)))
+        .collect::<HashMap<_, _>>();
+    let reserved_quantities = if opts.respect_reservations {
+        let product_ids = found_products.iter()
+            .map(|p| p.id)
+            .collect::<Vec<_>>();
+        load_reservations(mysql_read_connection(store, "--respect-reservations")?, &product_ids)?
+    } else {
+        HashMap::new()
+    };
+    let discontinued_categories = load_discontinued(store, opts, parsed_products)?;
+    let (feed_prices, previous_feed_prices) = match opts.price_smoothing {
+        Some(window) if window > 1 => {
+            let feed_prices = parsed_products.iter()
+                .filter_map(|p| offer_id_to_found_product.get(p.hub_stock_id.as_str()).map(|fp| (fp.id, p.price)))
+                .collect::<HashMap<_, _>>();
+            let product_ids = feed_prices.keys().copied().collect::<Vec<_>>();
+            let previous = load_feed_prices(mysql_read_connection(store, "--price-smoothing")?, &product_ids, window)?;
+            (feed_prices, previous)
+        }
+        _ => (HashMap::new(), HashMap::new()),
+    };
+    let smoothed_prices = previous_feed_prices.iter()
+        .filter_map(|(product_id, previous)| {
+            let feed_price = feed_prices.get(product_id)?;
+            Some((*product_id, smoothed_price(*feed_price, previous)))
         })
         .collect::<HashMap<_, _>>();
 
-    let mut raw_update_queries = String::new();
+    let mut product_updates = vec!();
+    let mut price_history_rows = vec!();
+    // products matching the feed after the sync, their hashes are saved with --skip-unchanged or --resolve
+    let mut synced_ids = vec!();
+    let mut conflict_rows = vec!();
+    // products unavailable in the feed counted towards --available-hysteresis
+    let mut unavailable_offers = vec!();
+    let mut unavailable_file_ids = BTreeSet::new();
     for p in parsed_products {
-        match offer_id_to_found_product.get(p.hub_stock_id.as_str()) {
+        if unchanged_ids.contains(&p.hub_stock_id) {
+            processed_products_stat.unchanged += 1;
+            trace(opts, &p.offer_id, "action", || json!({
+                "sql": "none",
+                "reason": "the offer did not change since the last sync",
+            }));
+            continue;
+        }
+        let found_product = offer_id_to_found_product.get(p.hub_stock_id.as_str());
+        trace(opts, &p.offer_id, "db_row", || match found_product {
+            Some(fp) => json!({
+                "id": fp.id,
+                "available": fp.available,
+                "price": fp.price,
+                "price_from": fp.price_from,
+                "oldprice": fp.oldprice,
+                "currencyId": fp.currencyId,
+                "vat": fp.vat,
+                "on_sale": fp.on_sale,
+                "discount_percent": fp.discount_percent,
+                "unavailable_runs": fp.unavailable_runs,
+                "version": fp.version,
+                "locked_fields": fp.locked_fields,
+            }),
+            None => json!(null),
+        });
+        match found_product {
+            Some(found_product) if opts.skip_newer_than_feed && is_newer(found_product, feed_date) => {
+                processed_products_stat.skipped_newer += 1;
+                trace(opts, &p.offer_id, "action", || json!({
+                    "sql": "none",
+                    "reason": "the product was changed after the feed was generated",
+                }));
+            }
             Some(found_product) => {
                 let mut should_update = false;
+                let mut in_sync = true;
                 let mut update_product = models::ModProduct::default();
-                if Some(p.available) != found_product.available {
-                    processed_products_stat.updated_available += 1;
-                    if opts.update_available {
-                        update_product.available = Some(&p.available);
-                        should_update = true;
-                    }
+                let mut unavailable_runs = None;
+                let locked_fields = found_product.locked_fields.as_deref()
+                    .map(parse_locked_fields)
+                    .unwrap_or_default();
+                let reserved = reserved_quantities.get(&found_product.id).copied().unwrap_or(0);
+                // with --price-smoothing the moving average of the feed prices is stored
+                let price = smoothed_prices.get(&found_product.id).unwrap_or(&p.price);
+                if *price != p.price {
+                    processed_products_stat.smoothed_prices += 1;
+                    trace(opts, &p.offer_id, "smoothed_price", || json!({"feed_price": p.price, "price": price}));
                 }
-                if p.price != found_product.price ||
-                    p.oldprice != found_product.oldprice ||
-                    p.currencyId != found_product.currencyId
+                let available = if p.available == AVAILABLE && is_fully_reserved(p, found_product, reserved) {
+                    processed_products_stat.reserved += 1;
+                    trace(opts, &p.offer_id, "reserved", || json!({"quantity": reserved}));
+                    &NOT_AVAILABLE
+                } else if p.available == AVAILABLE &&
+                    found_product.available != Some(AVAILABLE) &&
+                    discontinued_categories.contains(&p.categoryId)
                 {
-                    processed_products_stat.updated_price += 1;
+                    // the assortment decision outweighs the supplier's stock
+                    processed_products_stat.discontinued += 1;
+                    trace(opts, &p.offer_id, "discontinued", || json!({"categoryId": p.categoryId}));
+                    &NOT_AVAILABLE
+                } else {
+                    &p.available
+                };
+                if Some(*available) != found_product.available {
+                    // the counter is incremented by mark_missing like the ones of the missing products,
+                    // the loaded value tells whether this run reaches the hysteresis
+                    let postpone = match opts.available_hysteresis {
+                        Some(hysteresis) if *available == NOT_AVAILABLE => {
+                            if opts.update_available && !locked_fields.contains("available") {
+                                unavailable_offers.push((found_product.id, p.hub_stock_id.clone()));
+                                unavailable_file_ids.extend(found_product.file_id);
+                            }
+                            found_product.unavailable_runs + 1 < hysteresis as i32
+                        }
+                        _ => false,
+                    };
+                    if postpone {
+                        processed_products_stat.postponed_unavailable += 1;
+                        in_sync = false;
+                    } else {
+                        in_sync &= opts.update_available && !locked_fields.contains("available");
+                        processed_products_stat.updated_available += 1;
+                        if *available == AVAILABLE {
+                            processed_products_stat.reactivated += 1;
+                        } else {
+                            processed_products_stat.deactivated += 1;
+                        }
+                        if opts.update_available {
+                            if locked_fields.contains("available") {
+                                processed_products_stat.suppressed_by_locks += 1;
+                            } else {
+                                update_product.available = Some(available);
+                                should_update = true;
+                                if opts.reactivated_out.is_some() && *available == AVAILABLE {
+                                    processed_products_stat.reactivated_products.push(ReactivatedProduct {
+                                        product_id: found_product.id,
+                                        offer_id: p.offer_id.clone(),
+                                        name: found_product.name.clone(),
+                                        renew_date: found_product.renew_data,
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+                if *available == AVAILABLE && found_product.unavailable_runs > 0 {
+                    unavailable_runs = Some(0);
+                }
+                let discount_changed = opts.min_discount.is_some() && (
+                    p.on_sale != found_product.on_sale ||
+                    p.discount_percent != found_product.discount_percent
+                );
+                // the price and its currency are compared as a unit, with --currency-rate
+                // the same price in another currency is not a change
+                let currency_changed = p.currencyId != found_product.currencyId;
+                let converted_same = currency_changed && !opts.currency_rates.is_empty() &&
+                    is_same_converted(&opts.currency_rates, *price, p.oldprice, p.currencyId.as_deref(), found_product);
+                let money_changed = !converted_same && (
+                    *price != found_product.price || p.oldprice != found_product.oldprice || currency_changed
+                );
+                if converted_same {
+                    processed_products_stat.converted_same_prices += 1;
+                } else if currency_changed && *price == found_product.price {
+                    processed_products_stat.currency_only_changes += 1;
+                }
+                let price_changed = money_changed || p.price_from != found_product.price_from;
+                let vat_changed = p.vat != found_product.vat;
+                if price_changed || vat_changed || discount_changed {
+                    // every kind of the difference has its own counter, a new price often changes the discount too
+                    if price_changed {
+                        processed_products_stat.updated_price += 1;
+                    }
+                    if vat_changed {
+                        processed_products_stat.updated_vat += 1;
+                    }
+                    if discount_changed {
+                        processed_products_stat.updated_discount += 1;
+                    }
+                    in_sync &= opts.update_price;
                     if opts.update_price {
-                        update_product.price = Some(&p.price);
-                        update_product.oldprice = Some(p.oldprice.as_ref());
-                        update_product.currencyId = Some(p.currencyId.as_deref());
+                        let mut suppressed = false;
+                        let mut changed = false;
+                        if locked_fields.contains("price") {
+                            suppressed = *price != found_product.price ||
+                                p.price_from != found_product.price_from;
+                        } else {
+                            update_product.price = Some(price);
+                            update_product.price_from = Some(&p.price_from);
+                            changed = *price != found_product.price ||
+                                p.price_from != found_product.price_from;
+                        }
+                        if locked_fields.contains("oldprice") {
+                            suppressed |= p.oldprice != found_product.oldprice;
+                        } else {
+                            update_product.oldprice = Some(p.oldprice.as_ref());
+                            changed |= p.oldprice != found_product.oldprice;
+                        }
+                        if locked_fields.contains("currencyId") {
+                            suppressed |= p.currencyId != found_product.currencyId;
+                        } else {
+                            update_product.currencyId = Some(p.currencyId.as_deref());
+                            changed |= p.currencyId != found_product.currencyId;
+                        }
+                        if locked_fields.contains("vat") {
+                            suppressed |= p.vat != found_product.vat;
+                        } else {
+                            update_product.vat = Some(p.vat.as_deref());
+                            changed |= p.vat != found_product.vat;
+                        }
+                        if opts.min_discount.is_some() {
+                            if locked_fields.contains("discount") {
+                                suppressed |= discount_changed;
+                            } else {
+                                update_product.on_sale = Some(p.on_sale.as_ref());
+                                update_product.discount_percent = Some(p.discount_percent.as_ref());
+                                changed |= discount_changed;
+                            }
+                        }
+                        if suppressed {
+                            processed_products_stat.suppressed_by_locks += 1;
+                            in_sync = false;
+                        }
+                        should_update |= changed;
+                    }
+                }
+                let price_applied = update_product.price.is_some() && *price != found_product.price;
+                // the smoothing window needs every new feed price even when the stored price stays the same
+                let feed_price_changed = feed_prices.contains_key(&found_product.id) &&
+                    !locked_fields.contains("price") &&
+                    previous_feed_prices.get(&found_product.id).and_then(|prices| prices.first()) != Some(&p.price);
+                if opts.price_history && (price_applied || feed_price_changed) {
+                    price_history_rows.push(models::NewPriceHistory {
+                        product_id: found_product.id,
+                        old_price: found_product.price,
+                        new_price: if price_applied { *price } else { found_product.price },
+                        feed_price: feed_prices.get(&found_product.id).copied(),
+                        currencyId: p.currencyId.clone(),
+                        run_id: import_id.to_string(),
+                        created_at: *date_modified,
+                    });
+                }
+                // the promotion is over when the feed stops sending the old price
+                if opts.clear_missing_oldprice && p.oldprice.is_none() && found_product.oldprice.is_some() {
+                    if locked_fields.contains("oldprice") {
+                        processed_products_stat.suppressed_by_locks += 1;
+                        in_sync = false;
+                    } else {
+                        update_product.oldprice = Some(None);
+                        if !locked_fields.contains("discount") {
+                            update_product.on_sale = Some(Some(&0));
+                            update_product.discount_percent = Some(None);
+                        }
+                        processed_products_stat.cleared_oldprices += 1;
+                        should_update = true;
+                    }
+                }
+                if opts.low_stock_threshold.is_some() && p.low_stock != found_product.low_stock {
+                    update_product.low_stock = Some(&p.low_stock);
+                    should_update = true;
+                }
+                // the merged product follows the supplier whose offer won the merge
+                if p.source_supplier.is_some() && p.source_supplier != found_product.source_supplier {
+                    update_product.source_supplier = Some(p.source_supplier.as_deref());
+                    should_update = true;
+                }
+                if opts.category_paths && p.category_path != found_product.category_path {
+                    update_product.category_path = Some(p.category_path.as_deref());
+                    should_update = true;
+                }
+                if p.adult != found_product.adult || p.age != found_product.age {
+                    if locked_fields.contains("adult") {
+                        processed_products_stat.suppressed_by_locks += 1;
+                        in_sync = false;
+                    } else {
+                        update_product.adult = Some(&p.adult);
+                        update_product.age = Some(p.age.as_ref());
                         should_update = true;
                     }
                 }
                 if should_update {
                     // println!("Updating product with offer_id={}: {:?}", p.offer_id, update_product);
-                    raw_update_queries.push_str("UPDATE `products` SET ");
+
+                    let mut values = vec!();
+                    let mut old_values = vec!();
                     if let Some(available) = update_product.available {
-                        raw_update_queries.push_str(
-                            &format!("`available` = {}, ", available.to_string())
-                        );
+                        values.push(("available", available.to_string()));
+                        old_values.push(optional_to_string(found_product.available.as_ref()));
                     }
                     if let Some(price) = update_product.price {
-                        raw_update_queries.push_str(
-                            &format!("`price` = {}, ", price.to_string())
-                        );
+                        values.push(("price", price.to_string()));
+                        old_values.push(found_product.price.to_string());
+                    }
+                    if let Some(price_from) = update_product.price_from {
+                        values.push(("price_from", price_from.to_string()));
+                        old_values.push(found_product.price_from.to_string());
                     }
                     if let Some(oldprice) = update_product.oldprice {
-                        raw_update_queries.push_str(
-                            &format!("`oldprice` = {}, ", optional_to_sql(oldprice))
-                        );
+                        values.push(("oldprice", optional_to_string(oldprice)));
+                        old_values.push(optional_to_string(found_product.oldprice.as_ref()));
                     }
                     if let Some(currency_id) = update_product.currencyId {
-                        raw_update_queries.push_str(
-                            &format!("`currencyId` = {}, ", optional_string_to_sql(currency_id))
-                        );
+                        values.push(("currencyId", optional_to_string(currency_id)));
+                        old_values.push(optional_to_string(found_product.currencyId.as_deref()));
+                    }
+                    if let Some(vat) = update_product.vat {
+                        values.push(("vat", optional_to_string(vat)));
+                        old_values.push(optional_to_string(found_product.vat.as_deref()));
                     }
-                    raw_update_queries.push_str(&format!(
-                        "`renew_date` = '{}', `to_renew` = 1 WHERE `id` = {};\n",
-                        &date_modified, found_product.id
-                    ));
-
-//                    update_product.renew_date = Some(&date_modified);
-//                    diesel::update(schema::products::table.find(found_product.id))
-//                        .set(&update_product)
-//                        .execute(conn)?;
+                    if let Some(on_sale) = update_product.on_sale {
+                        values.push(("on_sale", optional_to_string(on_sale)));
+                        old_values.push(optional_to_string(found_product.on_sale.as_ref()));
+                    }
+                    if let Some(discount_percent) = update_product.discount_percent {
+                        values.push(("discount_percent", optional_to_string(discount_percent)));
+                        old_values.push(optional_to_string(found_product.discount_percent.as_ref()));
+                    }
+                    if let Some(category_path) = update_product.category_path {
+                        values.push(("category_path", optional_to_string(category_path)));
+                        old_values.push(optional_to_string(found_product.category_path.as_deref()));
+                    }
+                    if let Some(adult) = update_product.adult {
+                        values.push(("adult", adult.to_string()));
+                        old_values.push(found_product.adult.to_string());
+                    }
+                    if let Some(age) = update_product.age {
+                        values.push(("age", optional_to_string(age)));
+                        old_values.push(optional_to_string(found_product.age.as_ref()));
+                    }
+                    if let Some(low_stock) = update_product.low_stock {
+                        values.push(("low_stock", low_stock.to_string()));
+                        old_values.push(found_product.low_stock.to_string());
+                    }
+                    if let Some(source_supplier) = update_product.source_supplier {
+                        values.push(("source_supplier", optional_to_string(source_supplier)));
+                        old_values.push(optional_to_string(found_product.source_supplier.as_ref()));
+                    }
+                    let change_class = offer_hashes.get(p.hub_stock_id.as_str()).and_then(|offer_hash| {
+                        conflicts::classify(
+                            stored_hashes.get(&p.hub_stock_id), offer_hash, found_product.renew_data.as_ref()
+                        )
+                    });
+                    let policy = change_class.map_or(ConflictPolicy::Feed, |class| conflicts::policy(opts, class));
+                    if let (Some(class), ConflictPolicy::Manual | ConflictPolicy::Review) = (change_class, policy) {
+                        // the differences stay so the product is compared again by the next run
+                        in_sync = false;
+                        price_history_rows.retain(|r| r.product_id != found_product.id);
+                        processed_products_stat.reactivated_products.retain(|r| r.product_id != found_product.id);
+                        if policy == ConflictPolicy::Review {
+                            processed_products_stat.queued_conflicts += 1;
+                            conflict_rows.extend(values.iter().zip(old_values).map(|((field, feed_value), db_value)| {
+                                models::NewProductConflict {
+                                    product_id: found_product.id,
+                                    field: field.to_string(),
+                                    offer_id: p.offer_id.clone(),
+                                    change_class: class.as_str().to_string(),
+                                    db_value,
+                                    feed_value: feed_value.clone(),
+                                    import_id: import_id.to_string(),
+                                }
+                            }));
+                        } else {
+                            processed_products_stat.kept_manual += 1;
+                        }
+                        trace(opts, &p.offer_id, "action", || json!({
+                            "sql": "none",
+                            "product_id": found_product.id,
+                            "reason": format!("{} changed, {} policy", class.as_str(), policy.as_str()),
+                            "values": values.iter().cloned().collect::<HashMap<_, _>>(),
+                        }));
+                    } else {
+                        trace(opts, &p.offer_id, "action", || json!({
+                            "sql": "update",
+                            "product_id": found_product.id,
+                            "values": values.iter().cloned().collect::<HashMap<_, _>>(),
+                        }));
+                        processed_products_stat.changes.push(ProductChange {
+                            product_id: Some(found_product.id),
+                            offer_id: p.offer_id.clone(),
+                            kind: ChangeKind::Updated,
+                            values,
+                            old_values,
+                        });
+
+                        update_product.renew_date = Some(date_modified);
+                        update_product.to_renew = Some(1);
+                        update_product.last_import_id = Some(import_id);
+                        update_product.unavailable_runs = unavailable_runs.filter(|_| opts.update_available);
+                        product_updates.push(ProductUpdate {
+                            product_id: found_product.id,
+                            expected_version: Some(found_product.version).filter(|_| opts.optimistic_locking),
+                            counter_only: false,
+                            changes: update_product,
+                        });
+                    }
+                } else if let Some(unavailable_runs) = unavailable_runs.filter(|_| opts.update_available) {
+                    trace(opts, &p.offer_id, "action", || json!({
+                        "sql": "update",
+                        "product_id": found_product.id,
+                        "values": {"unavailable_runs": unavailable_runs},
+                    }));
+                    product_updates.push(ProductUpdate {
+                        product_id: found_product.id,
+                        expected_version: None,
+                        counter_only: true,
+                        changes: models::ModProduct {
+                            unavailable_runs: Some(unavailable_runs),
+                            last_import_id: Some(import_id),
+                            ..Default::default()
+                        },
+                    });
+                } else {
+                    trace(opts, &p.offer_id, "action", || json!({
+                        "sql": "none",
+                        "reason": "no changes or updates are disabled or locked",
+                    }));
+                }
+                // the hysteresis counter is kept only with --update-available
+                if in_sync && (unavailable_runs.is_none() || opts.update_available) {
+                    synced_ids.push((Some(found_product.id), p.hub_stock_id.as_str()));
                 }
             }
             None => {}
         }
     }
-    if !raw_update_queries.is_empty() {
-        conn.batch_execute(&raw_update_queries)?;
+    let start_updating_at = Instant::now();
+    if !product_updates.is_empty() {
+        let conflicted_ids = store.apply_updates(&product_updates, commit_size(opts, product_updates.len()))?;
+        for change in &processed_products_stat.changes {
+            if let Some(id) = change.product_id.filter(|id| conflicted_ids.contains(id)) {
+                warn!("{}: product {} was modified concurrently, update skipped", change.offer_id, id);
+                trace(opts, &change.offer_id, "action", || json!({
+                    "sql": "none", "product_id": id, "reason": "modified concurrently",
+                }));
+            }
+        }
+        processed_products_stat.changes.retain(|c| match c.product_id {
+            Some(id) => !conflicted_ids.contains(&id),
+            None => true,
+        });
+        // the version is compared by the update itself so nothing else may be saved for the skipped products
+        price_history_rows.retain(|r| !conflicted_ids.contains(&r.product_id));
+        processed_products_stat.reactivated_products.retain(|r| !conflicted_ids.contains(&r.product_id));
+        unavailable_offers.retain(|(id, _)| !conflicted_ids.contains(id));
+        synced_ids.retain(|(id, _)| !matches!(id, Some(id) if conflicted_ids.contains(id)));
+        processed_products_stat.conflicts += conflicted_ids.len() as u32;
+    }
+    if !unavailable_offers.is_empty() {
+        let unavailable_offer_ids = unavailable_offers.into_iter()
+            .map(|(_, offer_id)| offer_id)
+            .collect::<Vec<_>>();
+        let file_ids = unavailable_file_ids.into_iter().collect::<Vec<_>>();
+        mark_unavailable(store, &unavailable_offer_ids, &file_ids, opts, import_id)?;
     }
+    if !price_history_rows.is_empty() {
+        store.insert_price_history(&price_history_rows)?;
+        processed_products_stat.price_history_records += price_history_rows.len() as u32;
+    }
+    processed_products_stat.update_duration += start_updating_at.elapsed();
 
-    let insert_products = parsed_products.iter()
+    let mut insert_products = parsed_products.iter()
+        .filter(|&p| {
+            !offer_id_to_found_product.contains_key(p.hub_stock_id.as_str()) &&
+                !unchanged_ids.contains(&p.hub_stock_id)
+        })
         .filter(|&p| {
-            !offer_id_to_found_product.contains_key(p.hub_stock_id.as_str())
+            if discontinued_categories.contains(&p.categoryId) {
+                processed_products_stat.discontinued += 1;
+                trace(opts, &p.offer_id, "action", || json!({
+                    "sql": "none",
+                    "reason": "the category is discontinued",
+                }));
+                false
+            } else {
+                true
+            }
+        })
+        .map(|p| models::NewProduct {
+            last_import_id: Some(import_id.to_string()),
+            ..p.clone()
         })
         .collect::<Vec<_>>();
     processed_products_stat.inserted += insert_products.len() as u32;
+    let start_inserting_at = Instant::now();
     if !insert_products.is_empty() {
         if opts.insert_new {
-            diesel::insert_into(products::table)
-                .values(insert_products)
-                .execute(conn)?;
+            if opts.generate_slugs {
+                assign_unique_slugs(mysql_connection(store, "--generate-slugs")?, &mut insert_products)?;
+            }
+            if opts.sku_template.is_some() {
+                processed_products_stat.sku_collisions += assign_unique_skus(
+                    mysql_connection(store, "--sku-template")?, &mut insert_products
+                )?;
+            }
+            if opts.validate_picture_urls {
+                let pictures_stat = pictures::check_new_products(&mut insert_products, opts);
+                processed_products_stat.dead_pictures = pictures_stat.dead;
+                processed_products_stat.unverified_pictures = pictures_stat.unverified;
+            }
+            let mut inserted_products = Vec::with_capacity(insert_products.len());
+            for insert_rows in insert_products.chunks(commit_size(opts, insert_products.len())) {
+                if let Err(e) = store.insert_products(insert_rows) {
+                    if !e.is_data_error() {
+                        return Err(e);
+                    }
+                    warn!("Inserting a chunk failed, falling back to row by row insertion: {}", e);
+                    let (inserted, quarantined) = insert_row_by_row(
+                        store, insert_rows.to_vec(), opts, import_id, date_modified
+                    )?;
+                    inserted_products.extend(inserted);
+                    processed_products_stat.inserted -= quarantined;
+                    processed_products_stat.quarantined += quarantined;
+                } else {
+                    inserted_products.extend_from_slice(insert_rows);
+                }
+            }
+            insert_products = inserted_products;
+            if opts.find_duplicates {
+                processed_products_stat.possible_duplicates += find_possible_duplicates(
+                    mysql_connection(store, "--find-duplicates")?, &insert_products, opts.duplicate_similarity
+                )?;
+            }
+            synced_ids.extend(
+                insert_products.iter()
+                    .filter_map(|p| offer_hashes.get_key_value(p.hub_stock_id.as_str()))
+                    .map(|(&id, _)| (None, id))
+            );
+            for p in insert_products {
+                trace(opts, &p.offer_id, "action", || json!({"sql": "insert", "status": p.status}));
+                processed_products_stat.changes.push(ProductChange {
+                    product_id: None,
+                    offer_id: p.offer_id.clone(),
+                    kind: ChangeKind::Inserted,
+                    values: vec!(
+                        ("available", p.available.to_string()),
+                        ("price", p.price.to_string()),
+                        ("price_from", p.price_from.to_string()),
+                        ("oldprice", optional_to_string(p.oldprice.as_ref())),
+                        ("currencyId", optional_to_string(p.currencyId.as_ref())),
+                        ("categoryId", p.categoryId.to_string()),
+                        ("name", p.name),
+                        ("status", p.status),
+                    ),
+                    old_values: vec!(),
+                });
+            }
+        } else {
+            for p in &insert_products {
+                trace(opts, &p.offer_id, "action", || json!({
+                    "sql": "none", "reason": "new product, --insert-new is not set",
+                }));
+            }
         }
     }
+    processed_products_stat.insert_duration += start_inserting_at.elapsed();
+
+    if track_hashes {
+        let hashes = synced_ids.iter()
+            .filter_map(|&(_, id)| offer_hashes.get(id).map(|hash| (id, hash.as_str())))
+            .collect::<Vec<_>>();
+        offer_hashes::save(mysql_connection(store, hashes_feature)?, &hashes, date_modified)?;
+    }
+    if !conflict_rows.is_empty() {
+        conflicts::queue(mysql_connection(store, "--resolve")?, &conflict_rows)?;
+    }
 
     processed_products_stat.duration += start_syncing_at.elapsed();
 
     Ok(processed_products_stat)
 }
 
-fn optional_to_sql<T: ToString>(v: Option<&T>) -> String {
-    return if let Some(v) = v {
-        v.to_string()
-    } else {
-        "NULL".to_string()
+/// Inserts products one by one, offers rejected by the database are written
+/// into the `import_quarantine` table. Returns inserted products and a number of quarantined offers.
+fn insert_row_by_row(
+    store: &mut dyn ProductStore,
+    new_products: Vec<models::NewProduct>,
+    opts: &Opts,
+    import_id: &str,
+    date_modified: &NaiveDateTime,
+) -> Result<(Vec<models::NewProduct>, u32), Error> {
+    let mut inserted = vec!();
+    let mut quarantine_rows = vec!();
+    for p in new_products {
+        match store.insert_products(std::slice::from_ref(&p)) {
+            Ok(()) => inserted.push(p),
+            Err(e) => {
+                if !e.is_data_error() {
+                    return Err(e);
+                }
+                warn!("Offer {} is quarantined: {}", p.offer_id, e);
+                trace(opts, &p.offer_id, "action", || json!({
+                    "sql": "insert", "quarantined": e.to_string(),
+                }));
+                quarantine_rows.push(models::NewQuarantinedOffer {
+                    offer_id: p.offer_id,
+                    hub_stock_id: p.hub_stock_id,
+                    name: p.name,
+                    error: e.to_string(),
+                    run_id: import_id.to_string(),
+                    created_at: *date_modified,
+                });
+            }
+        }
+    }
+    if !quarantine_rows.is_empty() {
+        diesel::insert_into(import_quarantine::table)
+            .values(&quarantine_rows)
+            .execute(mysql_connection(store, "Quarantine")?)?;
     }
+    Ok((inserted, quarantine_rows.len() as u32))
 }
 
-fn optional_string_to_sql(s: Option<&str>) -> String {
-    return if let Some(s) = s {
-        format!(
-            "'{}'",
-            s
-            .replace(r"\", r"\\")
-            .replace("'", r"\'")
-            .replace(r#"""#, r#"\""#))
-    } else {
-        "NULL".to_string()
+/// Number of rows written per transaction or statement, the whole chunk by default
+fn commit_size(opts: &Opts, rows: usize) -> usize {
+    opts.commit_every.unwrap_or(rows).max(1)
+}
+
+/// Assigns unique slugs to new products adding numeric suffixes when a slug is already used.
+fn assign_unique_slugs(conn: &mut MysqlConnection, new_products: &mut [models::NewProduct]) -> Result<(), Error> {
+    use crate::schema::products::dsl;
+
+    let base_slugs = new_products.iter()
+        .map(|p| slug::slugify(&p.name))
+        .collect::<Vec<_>>();
+    let unique_base_slugs = base_slugs.iter()
+        .filter(|s| !s.is_empty())
+        .collect::<HashSet<_>>();
+    let taken_base_slugs = dsl::products.select(dsl::slug)
+        .filter(dsl::slug.eq_any(unique_base_slugs))
+        .load::<Option<String>>(conn)?;
+    let mut used_slugs = HashSet::new();
+    for base_slug in taken_base_slugs.into_iter().flatten() {
+        let suffixed_slugs = dsl::products.select(dsl::slug)
+            .filter(dsl::slug.like(format!("{}-%", base_slug)))
+            .load::<Option<String>>(conn)?;
+        used_slugs.extend(suffixed_slugs.into_iter().flatten());
+        used_slugs.insert(base_slug);
     }
+
+    for (p, base_slug) in new_products.iter_mut().zip(base_slugs) {
+        if base_slug.is_empty() {
+            continue;
+        }
+        let mut slug = base_slug.clone();
+        let mut n = 1;
+        while used_slugs.contains(&slug) {
+            n += 1;
+            slug = format!("{}-{}", base_slug, n);
+        }
+        used_slugs.insert(slug.clone());
+        p.slug = Some(slug);
+    }
+
+    Ok(())
+}
+
+/// Parses list of locked fields stored either as CSV or as JSON array of strings
+pub(crate) fn parse_locked_fields(locked_fields: &str) -> HashSet<&str> {
+    locked_fields
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|f| f.trim().trim_matches('"'))
+        .filter(|f| !f.is_empty())
+        .collect()
+}
+
+fn optional_to_string<T: ToString + ?Sized>(v: Option<&T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
 }
 
 pub(crate) fn mark_missing_as_unavailable(
-    conn: &MysqlConnection,
-    all_offer_ids: &HashSet<String>,
-    opts: &Opts,
+    store: &mut dyn ProductStore,
+    seen_offer_ids: &SeenOfferIds,
+    ctx: &ImportContext,
 ) -> Result<u32, Error> {
-    use crate::schema::products::dsl;
-
-    let mut last_product_id = 0;
-    let mut missing_offer_ids = Vec::with_capacity(CHUNK_SIZE);
-    let mut marked_count: u32 = 0;
+    let opts = ctx.opts;
+    let import_id = ctx.import_id.as_str();
+    let file_ids = ctx.supplier_file_ids();
+    let total_products = if opts.no_progress {
+        0
+    } else {
+        store.count_available()?
+    };
+    let mut progress = ctx.progress.lock().unwrap();
+    progress.start(Stage::MarkMissing, Some(total_products));
+    let mut report_progress = |total_processed: u64| progress.update(total_processed, None);
 
-    let progress = if !opts.no_progress {
-        let total_products = dsl::products.select(dsl::id)
-            .filter(dsl::available.eq(AVAILABLE))
-            .count()
-            .get_result::<i64>(conn)? as u64;
-        let pb = ProgressBar::new(total_products);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>7}/{len:6} ({eta}) searching missing products")
-        );
-        Some((pb, total_products / 100))
+    let workers = opts.mark_missing_workers;
+    if workers > 1 && seen_offer_ids.is_spilled() {
+        info!("Offer ids are in a temporary table, missing products are searched by a single connection");
+    }
+    let (marked_count, total_processed) = if workers > 1 && !seen_offer_ids.is_spilled() {
+        mark_missing_in_parallel(
+            mysql_connection(store, "--mark-missing-workers")?,
+            workers, seen_offer_ids, &file_ids, opts, import_id, &mut report_progress
+        )?
     } else {
-        None
+        mark_missing_sequentially(store, seen_offer_ids, &file_ids, opts, import_id, &mut report_progress)?
     };
 
+    progress.finish(total_processed, None);
+
+    Ok(marked_count)
+}
+
+/// Scans available products by id chunks, returns numbers of marked and scanned products
+fn mark_missing_sequentially(
+    store: &mut dyn ProductStore,
+    seen_offer_ids: &SeenOfferIds,
+    file_ids: &[i8],
+    opts: &Opts,
+    import_id: &str,
+    report_progress: &mut dyn FnMut(u64),
+) -> Result<(u32, u64), Error> {
+    let mut last_product_id = 0;
+    let mut missing_offer_ids = Vec::with_capacity(CHUNK_SIZE);
+    let mut marked_count: u32 = 0;
     let mut total_processed: u64 = 0;
     loop {
-        let db_offers = dsl::products.select((dsl::id, dsl::hub_stock_id))
-            .filter(dsl::id.gt(last_product_id))
-            .filter(dsl::available.eq(AVAILABLE))
-            .order(dsl::id)
-            .limit(CHUNK_SIZE as i64)
-            .load::<(i32, Option<String>)>(conn)?;
+        let db_offers = store.available_products(last_product_id, CHUNK_SIZE)?;
 
         if db_offers.is_empty() {
             break;
@@ -230,42 +989,135 @@ pub(crate) fn mark_missing_as_unavailable(
         total_processed += db_offers.len() as u64;
         last_product_id = db_offers.last().unwrap().0;
 
-        for (_, db_offer_id) in db_offers {
-            if let Some(db_offer_id) = db_offer_id {
-                if !all_offer_ids.contains(&db_offer_id) {
-                    missing_offer_ids.push(db_offer_id);
-                }
-            }
-        }
+        let db_offer_ids = db_offers.into_iter()
+            .filter_map(|(_, db_offer_id)| db_offer_id)
+            .collect();
+        missing_offer_ids.extend(seen_offer_ids.missing(store, db_offer_ids)?);
         if !missing_offer_ids.is_empty() {
-            diesel::update(dsl::products.filter(
-                dsl::hub_stock_id.eq_any(&missing_offer_ids)
-                    .and(dsl::file_id.eq(HUBBER_FILE_ID))
-            ))
-                .set(dsl::available.eq(NOT_AVAILABLE))
-                .execute(conn)?;
-            marked_count += missing_offer_ids.len() as u32;
+            marked_count += mark_unavailable(store, &missing_offer_ids, file_ids, opts, import_id)?;
             missing_offer_ids.clear();
         }
 
-        if let Some((ref pb, update_after_count)) = progress {
-            if pb.position() + update_after_count < total_processed {
-                pb.set_position(total_processed);
+        report_progress(total_processed);
+    }
+    Ok((marked_count, total_processed))
+}
+
+/// Splits ids of the available products into ranges scanned by `workers` threads,
+/// every thread has its own connection. Offer ids of the file must be kept in memory
+/// because a temporary table is visible to its connection only.
+fn mark_missing_in_parallel(
+    conn: &mut MysqlConnection,
+    workers: usize,
+    seen_offer_ids: &SeenOfferIds,
+    file_ids: &[i8],
+    opts: &Opts,
+    import_id: &str,
+    report_progress: &mut dyn FnMut(u64),
+) -> Result<(u32, u64), Error> {
+    use diesel::dsl::{max, min};
+
+    let (min_id, max_id) = match products::table
+        .select((min(products::id), max(products::id)))
+        .filter(products::available.eq(AVAILABLE))
+        .first::<(Option<i32>, Option<i32>)>(conn)?
+    {
+        (Some(min_id), Some(max_id)) => (min_id, max_id),
+        _ => return Ok((0, 0)),
+    };
+    let range_size = (max_id - min_id) / workers as i32 + 1;
+    let database_url = database::database_url(opts)?;
+
+    let (tx, rx) = mpsc::channel();
+    thread::scope(|s| {
+        let handles = (0..workers as i32)
+            .map(|i| {
+                let from_id = min_id + i * range_size;
+                let to_id = (from_id + range_size - 1).min(max_id);
+                let tx = tx.clone();
+                let database_url = &database_url;
+                s.spawn(move || {
+                    let mut conn = establish_mysql_connection(database_url)?;
+                    mark_missing_in_range(&mut conn, from_id, to_id, seen_offer_ids, file_ids, opts, import_id, &tx)
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(tx);
+
+        let mut total_processed = 0;
+        for processed in rx {
+            total_processed += processed;
+            report_progress(total_processed);
+        }
+        let mut marked_count = 0;
+        for handle in handles {
+            marked_count += handle.join().expect("mark missing worker panicked")?;
+        }
+        Ok((marked_count, total_processed))
+    })
+}
+
+/// Marks missing products with ids from `from_id` to `to_id` inclusive,
+/// numbers of scanned products are sent into `progress`
+#[allow(clippy::too_many_arguments)]
+fn mark_missing_in_range(
+    conn: &mut MysqlConnection,
+    from_id: i32,
+    to_id: i32,
+    seen_offer_ids: &SeenOfferIds,
+    file_ids: &[i8],
+    opts: &Opts,
+    import_id: &str,
+    progress: &mpsc::Sender<u64>,
+) -> Result<u32, Error> {
+    let mut last_product_id = from_id - 1;
+    let mut marked_count = 0;
+    loop {
+        let db_offers = products::table.select((products::id, products::hub_stock_id))
+            .filter(products::id.gt(last_product_id))
+            .filter(products::id.le(to_id))
+            .filter(products::available.eq(AVAILABLE))
+            .order(products::id)
+            .limit(CHUNK_SIZE as i64)
+            .load::<(i32, Option<String>)>(conn)?;
+        last_product_id = match db_offers.last() {
+            Some(&(id, _)) => id,
+            None => break,
+        };
+        progress.send(db_offers.len() as u64).ok();
+
+        let db_offer_ids = db_offers.into_iter()
+            .filter_map(|(_, db_offer_id)| db_offer_id)
+            .collect();
+        let missing_offer_ids = seen_offer_ids.missing(conn, db_offer_ids)?;
+        if !missing_offer_ids.is_empty() {
+            for offer_ids in missing_offer_ids.chunks(commit_size(opts, missing_offer_ids.len())) {
+                marked_count += conn.mark_missing(
+                    offer_ids, file_ids, opts.available_hysteresis, import_id, offer_hashes::is_tracked(opts)
+                )?;
             }
         }
     }
+    Ok(marked_count)
+}
 
-    if let Some((pb, _)) = progress {
-        pb.finish();
+/// Marks products of the offers missing from the feed or unavailable in it as unavailable, with
+/// `--available-hysteresis` only those missing or unavailable in enough runs in a row. Every path
+/// changing availability goes through `ProductStore::mark_missing` so the counters follow the same rules.
+/// Returns a number of marked products.
+pub(crate) fn mark_unavailable(
+    store: &mut dyn ProductStore,
+    offer_ids: &[String],
+    file_ids: &[i8],
+    opts: &Opts,
+    import_id: &str,
+) -> Result<u32, Error> {
+    let mut marked_count = 0;
+    for offer_ids in offer_ids.chunks(commit_size(opts, offer_ids.len())) {
+        marked_count += store.mark_missing(
+            offer_ids, file_ids, opts.available_hysteresis, import_id, offer_hashes::is_tracked(opts)
+        )?;
     }
-
     Ok(marked_count)
 }
 
-pub(crate) fn finilize_processing(conn: &MysqlConnection, date_processing: &NaiveDateTime) -> Result<(), Error> {
-    // TODO: Create row if not exists
-    conn.batch_execute(&format!(
-        "UPDATE timestamps SET event_date='{}' WHERE event = 'hub_xml_update';", date_processing
-    ))?;
-    Ok(())
-}
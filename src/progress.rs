@@ -0,0 +1,344 @@
+use indicatif::{ProgressBar, ProgressStyle};
+
+use serde_json::json;
+
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::{Opts, ProcessedStat};
+use crate::error::Error;
+
+/// How often progress events are emitted
+const EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ProgressFormat {
+    /// Progress bar for a terminal
+    Bar,
+    /// JSON progress event per line on stdout for a process running the importer
+    JsonLines,
+}
+
+impl FromStr for ProgressFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ProgressFormat, Error> {
+        match s {
+            "bar" => Ok(ProgressFormat::Bar),
+            "json-lines" => Ok(ProgressFormat::JsonLines),
+            _ => Err(Error::config(format!("Unknown progress format: {}", s))),
+        }
+    }
+}
+
+/// Stage of the run whose progress is reported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Stage {
+    /// Position is in bytes of the feed
+    Parsing,
+    /// Position is in products
+    MarkMissing,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Parsing => "parsing",
+            Stage::MarkMissing => "mark_missing",
+        }
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Stage::Parsing => "bytes",
+            Stage::MarkMissing => "products",
+        }
+    }
+}
+
+/// Receives progress of the run stages. A sink is started for every stage, updated on every
+/// step of the stage and decides itself how often to report it.
+pub(crate) trait ProgressSink: Send {
+    /// `total` is `None` when the size of the stage is unknown
+    fn start(&mut self, stage: Stage, total: Option<u64>);
+    fn update(&mut self, position: u64, stat: Option<&ProcessedStat>);
+    fn finish(&mut self, position: u64, stat: Option<&ProcessedStat>);
+}
+
+/// Receives counters of the finished run
+pub(crate) trait StatSink: Send + Sync {
+    fn record(&self, stat: &ProcessedStat) -> Result<(), Error>;
+}
+
+/// Progress sinks of the run, every one of them gets all the events
+#[derive(Default)]
+pub(crate) struct Progress {
+    sinks: Vec<Box<dyn ProgressSink>>,
+}
+
+impl Progress {
+    /// Sinks of the `--progress-format`s, none with `--no-progress`
+    pub fn new(opts: &Opts) -> Progress {
+        let mut progress = Progress::default();
+        if opts.no_progress {
+            return progress;
+        }
+        for format in &opts.progress_format {
+            match format {
+                ProgressFormat::Bar => progress.add(Box::new(BarSink::new(opts))),
+                ProgressFormat::JsonLines => progress.add(Box::<JsonProgress>::default()),
+            }
+        }
+        progress
+    }
+
+    pub fn add(&mut self, sink: Box<dyn ProgressSink>) {
+        self.sinks.push(sink);
+    }
+}
+
+impl ProgressSink for Progress {
+    fn start(&mut self, stage: Stage, total: Option<u64>) {
+        for sink in &mut self.sinks {
+            sink.start(stage, total);
+        }
+    }
+
+    fn update(&mut self, position: u64, stat: Option<&ProcessedStat>) {
+        for sink in &mut self.sinks {
+            sink.update(position, stat);
+        }
+    }
+
+    fn finish(&mut self, position: u64, stat: Option<&ProcessedStat>) {
+        for sink in &mut self.sinks {
+            sink.finish(position, stat);
+        }
+    }
+}
+
+pub(crate) const PARSE_TEMPLATE: &str =
+    "[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta}) parsing file and updating products";
+/// Used when the size of the feed is unknown
+pub(crate) const PARSE_SPINNER_TEMPLATE: &str =
+    "[{elapsed_precise}] {spinner} {bytes} parsing file and updating products";
+pub(crate) const MARK_MISSING_TEMPLATE: &str =
+    "[{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>7}/{len:6} ({eta}) searching missing products";
+
+/// Counters of the run that templates may contain besides indicatif's placeholders
+const COUNTER_PLACEHOLDERS: [&str; 3] = ["{offers}", "{offers_per_sec}", "{chunks}"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ProgressCharset {
+    Unicode,
+    /// Plain ASCII bars and spinners for terminals and log collectors that garble other characters
+    Ascii,
+}
+
+impl FromStr for ProgressCharset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ProgressCharset, Error> {
+        match s {
+            "unicode" => Ok(ProgressCharset::Unicode),
+            "ascii" => Ok(ProgressCharset::Ascii),
+            _ => Err(Error::config(format!("Unknown progress charset: {}", s))),
+        }
+    }
+}
+
+/// Progress bar, or a spinner when the length is unknown, rendered with an indicatif template.
+/// The style is rebuilt on updates when the template contains counters of the run.
+pub(crate) struct TemplatedBar {
+    pb: ProgressBar,
+    template: String,
+    spinner: bool,
+    charset: ProgressCharset,
+    progress_chars: Option<&'static str>,
+    has_counters: bool,
+    started_at: Instant,
+}
+
+impl TemplatedBar {
+    pub fn new(charset: ProgressCharset, len: Option<u64>, template: &str) -> TemplatedBar {
+        let bar = TemplatedBar {
+            pb: len.map_or_else(ProgressBar::new_spinner, ProgressBar::new),
+            template: template.to_string(),
+            spinner: len.is_none(),
+            charset,
+            progress_chars: None,
+            has_counters: COUNTER_PLACEHOLDERS.iter().any(|p| template.contains(p)),
+            started_at: Instant::now(),
+        };
+        bar.pb.set_style(bar.style(&bar.counters(None)));
+        bar
+    }
+
+    /// Characters of the bar in the unicode charset, indicatif's ones are used by default
+    pub fn progress_chars(mut self, chars: &'static str) -> TemplatedBar {
+        self.progress_chars = Some(chars);
+        self.pb.set_style(self.style(&self.counters(None)));
+        self
+    }
+
+    pub fn position(&self) -> u64 {
+        self.pb.position()
+    }
+
+    pub fn update(&self, position: u64, stat: Option<&ProcessedStat>) {
+        if self.has_counters {
+            self.pb.set_style(self.style(&self.counters(stat)));
+        }
+        self.pb.set_position(position);
+    }
+
+    pub fn finish(&self, stat: Option<&ProcessedStat>) {
+        if self.has_counters {
+            self.pb.set_style(self.style(&self.counters(stat)));
+        }
+        self.pb.finish();
+    }
+
+    /// Template with the counter placeholders replaced by the values
+    fn counters(&self, stat: Option<&ProcessedStat>) -> String {
+        if !self.has_counters {
+            return self.template.clone();
+        }
+        let (offers, chunks) = stat.map_or((0, 0), |s| (s.total_offers, s.chunk_durations.len()));
+        let elapsed = self.started_at.elapsed().as_secs_f64();
+        let offers_per_sec = if elapsed > 0.0 { offers as f64 / elapsed } else { 0.0 };
+        self.template
+            .replace("{offers}", &offers.to_string())
+            .replace("{offers_per_sec}", &format!("{:.0}", offers_per_sec))
+            .replace("{chunks}", &chunks.to_string())
+    }
+
+    fn style(&self, template: &str) -> ProgressStyle {
+        let style = if self.spinner {
+            ProgressStyle::default_spinner()
+        } else {
+            ProgressStyle::default_bar()
+        };
+        let style = style.template(template);
+        match (self.charset, self.progress_chars) {
+            (ProgressCharset::Ascii, _) => style.progress_chars("#>-").tick_chars("-\\|/ "),
+            (ProgressCharset::Unicode, Some(chars)) => style.progress_chars(chars),
+            (ProgressCharset::Unicode, None) => style,
+        }
+    }
+}
+
+/// Terminal progress bar of the current stage, redrawn every percent of the stage
+pub(crate) struct BarSink {
+    charset: ProgressCharset,
+    parse_template: Option<String>,
+    mark_missing_template: Option<String>,
+    /// Bar of the started stage with the number of steps between redraws
+    bar: Option<(TemplatedBar, u64)>,
+}
+
+impl BarSink {
+    pub fn new(opts: &Opts) -> BarSink {
+        BarSink {
+            charset: opts.progress_charset,
+            parse_template: opts.progress_template.clone(),
+            mark_missing_template: opts.mark_missing_progress_template.clone(),
+            bar: None,
+        }
+    }
+}
+
+impl ProgressSink for BarSink {
+    fn start(&mut self, stage: Stage, total: Option<u64>) {
+        let bar = match stage {
+            Stage::Parsing => {
+                let default_template = match total {
+                    Some(_) => PARSE_TEMPLATE,
+                    None => PARSE_SPINNER_TEMPLATE,
+                };
+                let template = self.parse_template.as_deref().unwrap_or(default_template);
+                TemplatedBar::new(self.charset, total, template).progress_chars("#>-")
+            }
+            Stage::MarkMissing => {
+                let template = self.mark_missing_template.as_deref().unwrap_or(MARK_MISSING_TEMPLATE);
+                TemplatedBar::new(self.charset, total, template)
+            }
+        };
+        self.bar = Some((bar, total.unwrap_or(0) / 100));
+    }
+
+    fn update(&mut self, position: u64, stat: Option<&ProcessedStat>) {
+        if let Some((ref bar, step)) = self.bar {
+            if position > bar.position() + step {
+                bar.update(position, stat);
+            }
+        }
+    }
+
+    fn finish(&mut self, _position: u64, stat: Option<&ProcessedStat>) {
+        if let Some((bar, _)) = self.bar.take() {
+            bar.finish(stat);
+        }
+    }
+}
+
+/// Emits `{"event": "progress", "stage": "parsing", "position": 1024, "total": 4096, ...}` lines
+/// at most once per `EVENT_INTERVAL` and a `finished` event at the end of the stage
+#[derive(Default)]
+pub(crate) struct JsonProgress {
+    /// Started stage with its total
+    stage: Option<(Stage, Option<u64>)>,
+    started_at: Option<Instant>,
+    last_event_at: Option<Instant>,
+}
+
+impl ProgressSink for JsonProgress {
+    fn start(&mut self, stage: Stage, total: Option<u64>) {
+        self.stage = Some((stage, total));
+        self.started_at = Some(Instant::now());
+        self.last_event_at = None;
+    }
+
+    fn update(&mut self, position: u64, stat: Option<&ProcessedStat>) {
+        if self.last_event_at.is_some_and(|at| at.elapsed() < EVENT_INTERVAL) {
+            return;
+        }
+        self.last_event_at = Some(Instant::now());
+        self.emit("progress", position, stat);
+    }
+
+    fn finish(&mut self, position: u64, stat: Option<&ProcessedStat>) {
+        self.emit("finished", position, stat);
+        self.stage = None;
+    }
+}
+
+impl JsonProgress {
+    fn emit(&self, event: &str, position: u64, stat: Option<&ProcessedStat>) {
+        let (stage, total) = match self.stage {
+            Some(stage) => stage,
+            None => return,
+        };
+        let mut line = json!({
+            "event": event,
+            "stage": stage.as_str(),
+            "unit": stage.unit(),
+            "position": position,
+            "total": total,
+            "elapsed_ms": self.started_at.map_or(0, |at| at.elapsed().as_millis() as u64),
+        });
+        if let Some(stat) = stat {
+            line["counters"] = json!({
+                "offers": stat.total_offers,
+                "ignored_offers": stat.ignored_offers,
+                "chunks": stat.chunk_durations.len(),
+                "synced_products": stat.synced_products,
+                "updated_price": stat.updated_price,
+                "updated_available": stat.updated_available,
+                "inserted_products": stat.inserted_products,
+                "failed_offers": stat.failed_offers,
+            });
+        }
+        println!("{}", line);
+    }
+}
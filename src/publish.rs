@@ -0,0 +1,159 @@
+use log::info;
+
+#[cfg(any(feature = "amqp", feature = "kafka"))]
+use serde_json::{Map, Value};
+
+use url::Url;
+
+use crate::Opts;
+#[cfg(feature = "amqp")]
+use crate::amqp::AmqpPublisher;
+use crate::error::Error;
+#[cfg(feature = "kafka")]
+use crate::kafka::{KafkaMessage, KafkaProducer};
+use crate::process::ProductChange;
+
+enum Broker {
+    Redis(redis::Connection),
+    #[cfg(feature = "amqp")]
+    Amqp(Box<AmqpPublisher>),
+    #[cfg(feature = "kafka")]
+    Kafka(KafkaProducer),
+}
+
+/// Parses a librdkafka property of `--kafka-property`, e.g. `security.protocol=SASL_SSL`
+pub(crate) fn parse_property(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.to_string())),
+        _ => Err(format!("Expected KEY=VALUE: {}", s)),
+    }
+}
+
+#[cfg(feature = "kafka")]
+fn connect_kafka(brokers: &str, properties: &[(String, String)]) -> Result<Broker, Error> {
+    Ok(Broker::Kafka(KafkaProducer::connect(brokers, properties)?))
+}
+
+#[cfg(not(feature = "kafka"))]
+fn connect_kafka(_brokers: &str, _properties: &[(String, String)]) -> Result<Broker, Error> {
+    Err(Error::config("--publish-kafka requires hubber_xml built with the kafka feature"))
+}
+
+#[cfg(feature = "amqp")]
+fn connect_amqp(url: &Url) -> Result<Broker, Error> {
+    Ok(Broker::Amqp(Box::new(AmqpPublisher::connect(url)?)))
+}
+
+#[cfg(not(feature = "amqp"))]
+fn connect_amqp(_url: &Url) -> Result<Broker, Error> {
+    Err(Error::config("Publishing into RabbitMQ requires hubber_xml built with the amqp feature"))
+}
+
+/// Publishes applied product changes into a Redis stream, a RabbitMQ queue or a Kafka topic
+/// so downstream services can react to them incrementally.
+pub(crate) struct Publisher {
+    broker: Broker,
+    stream: String,
+}
+
+impl Publisher {
+    /// Connects to the broker of `--publish` or `--publish-kafka`
+    pub fn from_opts(opts: &Opts) -> Result<Option<Publisher>, Error> {
+        let broker = match (&opts.publish, &opts.publish_kafka) {
+            (Some(url), _) => Publisher::connect_url(url)?,
+            (None, Some(brokers)) => connect_kafka(brokers, &opts.kafka_property)?,
+            (None, None) => return Ok(None),
+        };
+        info!("Publishing changes into {} stream", opts.publish_stream);
+        Ok(Some(Publisher {
+            broker,
+            stream: opts.publish_stream.clone(),
+        }))
+    }
+
+    fn connect_url(url: &Url) -> Result<Broker, Error> {
+        Ok(match url.scheme() {
+            "redis" | "rediss" => {
+                let client = redis::Client::open(url.as_str())?;
+                Broker::Redis(client.get_connection()?)
+            }
+            "amqp" | "amqps" => connect_amqp(url)?,
+            "kafka" => {
+                return Err(Error::config("Use --publish-kafka with a list of brokers to publish into Kafka"));
+            }
+            scheme => {
+                return Err(Error::config(format!("Unsupported publish url scheme: {}", scheme)));
+            }
+        })
+    }
+
+    pub fn publish(&mut self, changes: &[ProductChange]) -> Result<u32, Error> {
+        if changes.is_empty() {
+            return Ok(0);
+        }
+        match self.broker {
+            Broker::Redis(ref mut conn) => {
+                let mut pipe = redis::pipe();
+                for change in changes {
+                    let cmd = pipe.cmd("XADD").arg(&self.stream).arg("*")
+                        .arg("change").arg(change.kind.as_str())
+                        .arg("offer_id").arg(&change.offer_id);
+                    if let Some(product_id) = change.product_id {
+                        cmd.arg("product_id").arg(product_id);
+                    }
+                    for (field, value) in &change.values {
+                        cmd.arg(*field).arg(value);
+                    }
+                    cmd.ignore();
+                }
+                pipe.query::<()>(conn)?;
+            }
+            #[cfg(feature = "amqp")]
+            Broker::Amqp(ref mut publisher) => {
+                // the stream is the routing key of the default exchange, i.e. the name of the queue
+                let messages = changes.iter().map(change_json).collect::<Vec<_>>();
+                publisher.publish("", &self.stream, messages.iter().map(Vec::as_slice))?;
+            }
+            #[cfg(feature = "kafka")]
+            Broker::Kafka(ref mut producer) => {
+                // the offer id is the key so changes of a product stay ordered within a partition
+                let values = changes.iter().map(change_json).collect::<Vec<_>>();
+                let messages = changes.iter().zip(&values)
+                    .map(|(change, value)| KafkaMessage { key: change.offer_id.as_bytes(), value })
+                    .collect::<Vec<_>>();
+                producer.send(&self.stream, &messages)?;
+            }
+        }
+        Ok(changes.len() as u32)
+    }
+}
+
+/// JSON message with the same fields as the entries of the Redis stream
+#[cfg(any(feature = "amqp", feature = "kafka"))]
+fn change_json(change: &ProductChange) -> Vec<u8> {
+    let mut message = Map::new();
+    message.insert("change".to_string(), Value::from(change.kind.as_str()));
+    message.insert("offer_id".to_string(), Value::from(change.offer_id.as_str()));
+    if let Some(product_id) = change.product_id {
+        message.insert("product_id".to_string(), Value::from(product_id));
+    }
+    for (field, value) in &change.values {
+        message.insert(field.to_string(), Value::from(value.as_str()));
+    }
+    Value::Object(message).to_string().into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_property() {
+        assert_eq!(
+            parse_property("sasl.jaas.config=a=b").unwrap(),
+            ("sasl.jaas.config".to_string(), "a=b".to_string())
+        );
+        assert!(parse_property("security.protocol").is_err());
+        assert!(parse_property("=SSL").is_err());
+    }
+}
@@ -0,0 +1,49 @@
+use chrono::{NaiveDateTime, Utc};
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::error::Error;
+use crate::locale::ReportLocale;
+
+/// Product made available again by the feed, see `--reactivated-out`
+#[derive(Clone, Debug)]
+pub(crate) struct ReactivatedProduct {
+    pub product_id: i32,
+    pub offer_id: String,
+    pub name: String,
+    /// The last time the product was changed, approximately when it went out of stock
+    pub renew_date: Option<NaiveDateTime>,
+}
+
+/// Writes the re-activated products as CSV, the longest absent products go first
+pub(crate) fn write(path: &Path, products: &[ReactivatedProduct], locale: ReportLocale) -> Result<(), Error> {
+    let file = File::create(path)
+        .map_err(|e| Error::config_caused_by(format!("Cannot create re-activated file: {}", path.display()), e))?;
+    let mut out = BufWriter::new(file);
+    let now = Utc::now().naive_utc();
+    let mut products = products.iter().collect::<Vec<_>>();
+    products.sort_by_key(|p| p.renew_date);
+    writeln!(out, "product_id,offer_id,name,renew_date,days_absent")?;
+    for p in products {
+        writeln!(
+            out, "{},{},{},{},{}",
+            p.product_id,
+            csv_field(&p.offer_id),
+            csv_field(&p.name),
+            p.renew_date.map(|d| locale.date(&d)).unwrap_or_default(),
+            p.renew_date.map(|d| (now - d).num_days().to_string()).unwrap_or_default(),
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
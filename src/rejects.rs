@@ -0,0 +1,137 @@
+use chrono::Utc;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::error::Error;
+use crate::parser::Offer;
+
+/// Corrective feed of the rejected and ignored offers (`--rejects-as-xml`) to send back to the suppliers.
+/// Every offer is preceded by a comment with the reason. Text values are kept escaped as they are in the feed.
+pub(crate) struct RejectsXml {
+    out: BufWriter<File>,
+    /// Name of the open `<shop>`, `None` when no shop is open yet
+    shop: Option<Option<String>>,
+    offers: u32,
+}
+
+impl RejectsXml {
+    pub fn create(path: &Path) -> Result<RejectsXml, Error> {
+        let file = File::create(path)
+            .map_err(|e| Error::config_caused_by(format!("Cannot create rejects file: {}", path.display()), e))?;
+        let mut out = BufWriter::new(file);
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, r#"<yml_catalog date="{}">"#, Utc::now().format("%Y-%m-%d %H:%M"))?;
+        Ok(RejectsXml { out, shop: None, offers: 0 })
+    }
+
+    /// Offers of the same shop follow each other in the feed, so a new `<shop>` is opened when the shop changes
+    pub fn add(&mut self, offer: &Offer, reason: &str) -> Result<(), Error> {
+        if self.shop.as_ref() != Some(&offer.shop) {
+            self.close_shop()?;
+            self.open_shop(offer.shop.clone())?;
+        }
+        writeln!(self.out, "<!-- {} -->", reason.replace("--", "- -"))?;
+        write_offer(&mut self.out, offer)?;
+        self.offers += 1;
+        Ok(())
+    }
+
+    /// Returns a number of the written offers
+    pub fn finish(mut self) -> Result<u32, Error> {
+        if self.shop.is_none() {
+            self.open_shop(None)?;
+        }
+        self.close_shop()?;
+        writeln!(self.out, "</yml_catalog>")?;
+        self.out.flush()?;
+        Ok(self.offers)
+    }
+
+    fn open_shop(&mut self, shop: Option<String>) -> Result<(), Error> {
+        writeln!(self.out, "<shop>")?;
+        if let Some(ref name) = shop {
+            writeln!(self.out, "<name>{}</name>", name)?;
+        }
+        writeln!(self.out, "<offers>")?;
+        self.shop = Some(shop);
+        Ok(())
+    }
+
+    fn close_shop(&mut self) -> Result<(), Error> {
+        if self.shop.take().is_some() {
+            writeln!(self.out, "</offers>")?;
+            writeln!(self.out, "</shop>")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reason of an offer ignored because of missing required fields
+pub(crate) fn missing_fields(offer: &Offer) -> String {
+    let missing = [
+        ("name", offer.name.is_none()),
+        ("categoryId", offer.category_id.is_none()),
+        ("price", offer.price.is_none()),
+    ];
+    let fields = missing.iter()
+        .filter(|(_, is_missing)| *is_missing)
+        .map(|(field, _)| *field)
+        .collect::<Vec<_>>();
+    format!("missing {}", fields.join(", "))
+}
+
+fn write_offer(out: &mut dyn Write, offer: &Offer) -> Result<(), Error> {
+    let available = if offer.available == 1 { "true" } else { "false" };
+    write!(out, r#"<offer id="{}" available="{}""#, offer.offer_id, available)?;
+    if let Some(ref group_id) = offer.group_id {
+        write!(out, r#" group_id="{}""#, group_id)?;
+    }
+    writeln!(out, ">")?;
+    if let Some(price) = offer.price {
+        if offer.price_from {
+            writeln!(out, r#"<price from="true">{}</price>"#, price)?;
+        } else {
+            writeln!(out, "<price>{}</price>", price)?;
+        }
+    }
+    if let Some(old_price) = offer.old_price {
+        writeln!(out, "<oldprice>{}</oldprice>", old_price)?;
+    }
+    let elements = [
+        ("currencyId", offer.currency_id.clone()),
+        ("categoryId", offer.category_id.map(|id| id.to_string())),
+        ("name", offer.name.clone()),
+        ("description", offer.description.clone()),
+        ("vendor", offer.vendor.clone()),
+        ("vendorCode", offer.vendor_code.clone()),
+        ("typePrefix", offer.type_prefix.clone()),
+        ("model", offer.model.clone()),
+        ("barcode", offer.barcode.clone()),
+        ("vat", offer.vat.clone()),
+        ("quantity", offer.quantity.map(|q| q.to_string())),
+        ("adult", offer.adult.then(|| "true".to_string())),
+        ("age", offer.age.map(|age| age.to_string())),
+        ("delivery_days", offer.delivery_days.map(|days| days.to_string())),
+        ("keywords", Some(offer.keywords.join(", ")).filter(|k| !k.is_empty())),
+    ];
+    for (element, value) in elements.iter() {
+        if let Some(value) = value {
+            writeln!(out, "<{element}>{}</{element}>", value, element = element)?;
+        }
+    }
+    // pictures are the only unescaped values of the offer
+    for picture in &offer.pictures {
+        writeln!(out, "<picture>{}</picture>", escape_xml(picture))?;
+    }
+    for (name, value) in &offer.params {
+        writeln!(out, r#"<param name="{}">{}</param>"#, name, value)?;
+    }
+    writeln!(out, "</offer>")?;
+    Ok(())
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,121 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::CHUNK_SIZE;
+use crate::error::Error;
+use crate::models::{NewProduct, NewProductRelation};
+use crate::schema::{product_relations, products};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelationType {
+    /// Goods bought together with the product, `<rec>` of the feed
+    Accessory,
+    /// Replacement of the product
+    Similar,
+}
+
+impl RelationType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RelationType::Accessory => "accessory",
+            RelationType::Similar => "similar",
+        }
+    }
+}
+
+impl FromStr for RelationType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "accessory" | "accessories" => Ok(RelationType::Accessory),
+            "similar" => Ok(RelationType::Similar),
+            _ => Err(Error::config(format!("Unknown relation type: {}", s))),
+        }
+    }
+}
+
+/// Relations of the run after they are saved into product_relations
+#[derive(Debug, Default)]
+pub(crate) struct RelationsStat {
+    pub relations: u32,
+    /// References to offers that have no product, e.g. the offer is missing in the feed or was not inserted
+    pub unresolved: u32,
+}
+
+/// Offer relations of the synced products, they are resolved to product ids when every offer
+/// of the feed is synced, so references to the offers of the later chunks are found too
+#[derive(Default)]
+pub(crate) struct RelationCollector {
+    /// Offer ids of the synced products, their previous relations are replaced
+    offer_ids: Vec<String>,
+    relations: Vec<(String, String, RelationType)>,
+}
+
+impl RelationCollector {
+    pub fn add(&mut self, products: &[NewProduct]) {
+        for p in products {
+            self.offer_ids.push(p.hub_stock_id.clone());
+            for (related_id, relation_type) in &p.relations {
+                self.relations.push((p.hub_stock_id.clone(), related_id.clone(), *relation_type));
+            }
+        }
+    }
+
+    /// Replaces relations of the synced products with the ones from the feed
+    pub fn save(self, conn: &mut MysqlConnection) -> Result<RelationsStat, Error> {
+        let mut offer_ids = self.offer_ids.iter()
+            .chain(self.relations.iter().map(|(_, related_id, _)| related_id))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        offer_ids.sort_unstable();
+        offer_ids.dedup();
+        let mut product_ids = HashMap::new();
+        for offer_ids_chunk in offer_ids.chunks(CHUNK_SIZE) {
+            let found = products::table
+                .select((products::hub_stock_id, products::id))
+                .filter(products::hub_stock_id.eq_any(offer_ids_chunk))
+                .load::<(Option<String>, i32)>(conn)?;
+            product_ids.extend(found.into_iter().filter_map(|(offer_id, id)| Some((offer_id?, id))));
+        }
+
+        let mut stat = RelationsStat::default();
+        let mut rows = vec!();
+        for (offer_id, related_id, relation_type) in &self.relations {
+            match (product_ids.get(offer_id), product_ids.get(related_id)) {
+                (Some(product_id), Some(related_product_id)) if product_id != related_product_id => {
+                    rows.push(NewProductRelation {
+                        product_id: *product_id,
+                        related_product_id: *related_product_id,
+                        relation_type: relation_type.as_str(),
+                    });
+                }
+                (Some(_), Some(_)) => {}
+                _ => stat.unresolved += 1,
+            }
+        }
+        let source_ids = self.offer_ids.iter()
+            .filter_map(|offer_id| product_ids.get(offer_id).copied())
+            .collect::<Vec<_>>();
+        conn.transaction::<_, Error, _>(|conn| {
+            for source_ids_chunk in source_ids.chunks(CHUNK_SIZE) {
+                diesel::delete(product_relations::table)
+                    .filter(product_relations::product_id.eq_any(source_ids_chunk))
+                    .execute(conn)?;
+            }
+            for rows_chunk in rows.chunks(CHUNK_SIZE) {
+                stat.relations += diesel::insert_or_ignore_into(product_relations::table)
+                    .values(rows_chunk)
+                    .execute(conn)? as u32;
+            }
+            Ok(())
+        })?;
+        Ok(stat)
+    }
+}
@@ -0,0 +1,104 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use flate2::Crc;
+
+use log::{error, info};
+
+use std::error::Error as _;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::{fetch, run, Opts};
+use crate::error::Error;
+use crate::store::{mysql_read_connection, ProductStore};
+
+/// Runs the import every `interval` until the process is stopped. Rules are loaded
+/// by every run, so edited rule files and pricing rules are picked up without restarting.
+/// A failed run is logged and the next one is started as usual.
+pub(crate) fn run_daemon(opts: &mut Opts, interval: Duration) {
+    let file_path = opts.file_path.clone();
+    let checks = fetch::FeedChecks::from_opts(opts);
+    let mut watcher = RuleSetWatcher::default();
+    loop {
+        opts.file_path = file_path.clone();
+        let result = fetch::fetch_remote_file(&mut opts.file_path, &checks)
+            .and_then(|_downloaded_file| run(opts, &mut watcher));
+        if let Err(e) = result {
+            error!("Run failed: {}", e);
+            let mut source = e.source();
+            while let Some(cause) = source {
+                error!("Caused by: {}", cause);
+                source = cause.source();
+            }
+        }
+        info!("Next run in {:?}", interval);
+        thread::sleep(interval);
+    }
+}
+
+/// Tracks the version of the rule set between runs
+#[derive(Default)]
+pub(crate) struct RuleSetWatcher {
+    version: Option<String>,
+}
+
+impl RuleSetWatcher {
+    /// Returns the version of the rules the run is going to use, e.g. `rules 1a2b3c4d, pricing 5e6f7a8b`.
+    /// Changes since the previous run are logged.
+    pub fn check(&mut self, opts: &Opts, store: &mut dyn ProductStore) -> Result<Option<String>, Error> {
+        let version = rule_set_version(opts, store)?;
+        match (&self.version, &version) {
+            (Some(previous), Some(version)) if previous != version => {
+                info!("Rule set has changed: {} -> {}, reloading", previous, version);
+            }
+            (None, Some(version)) => info!("Using rule set: {}", version),
+            _ => {}
+        }
+        self.version = version.clone();
+        Ok(version)
+    }
+}
+
+fn rule_set_version(opts: &Opts, store: &mut dyn ProductStore) -> Result<Option<String>, Error> {
+    let mut versions = vec!();
+    if let Some(ref path) = opts.rules {
+        versions.push(format!("rules {}", file_version(path)?));
+    }
+    if let Some(ref path) = opts.channel_filter {
+        versions.push(format!("channel filter {}", file_version(path)?));
+    }
+    if opts.pricing_rules {
+        versions.push(format!("pricing {}", pricing_rules_version(mysql_read_connection(store, "--pricing-rules")?)?));
+    }
+    if versions.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(versions.join(", ")))
+}
+
+/// Checksum of the file contents
+fn file_version(path: &Path) -> Result<String, Error> {
+    let content = fs::read(path)
+        .map_err(|e| Error::config_caused_by(format!("Cannot read rules: {}", path.display()), e))?;
+    let mut crc = Crc::new();
+    crc.update(&content);
+    Ok(format!("{:08x}", crc.sum()))
+}
+
+/// Checksum of the rows of `pricing_rules` table
+fn pricing_rules_version(conn: &mut MysqlConnection) -> Result<String, Error> {
+    use crate::schema::pricing_rules::dsl;
+
+    let rules = dsl::pricing_rules
+        .select((dsl::id, dsl::category_id, dsl::percent, dsl::fixed_add, dsl::rounding))
+        .order(dsl::id)
+        .load::<(i32, Option<i32>, f32, f32, Option<f32>)>(conn)?;
+    let mut crc = Crc::new();
+    for rule in rules {
+        crc.update(format!("{:?}\n", rule).as_bytes());
+    }
+    Ok(format!("{:08x}", crc.sum()))
+}
@@ -0,0 +1,63 @@
+use rhai::{Dynamic, Engine, Scope, AST};
+
+use std::path::Path;
+
+use crate::error::{Error, ErrorContext};
+use crate::parser::Offer;
+
+/// Business rules written in Rhai script. The script sees the parsed offer as `offer`
+/// object map and can modify its fields, push tags into `offer.tags`
+/// or reject the offer returning `false`.
+pub(crate) struct Rules {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Rules {
+    pub fn load(path: &Path) -> Result<Rules, Error> {
+        let engine = Engine::new();
+        let ast = engine.compile_file(path.to_path_buf())
+            .map_err(|e| Error::config(format!("Cannot compile rules {}: {}", path.display(), e)))?;
+        Ok(Rules { engine, ast })
+    }
+
+    /// Returns modified offer or `None` if the offer was rejected by the rules.
+    pub fn apply(&self, offer: Offer) -> Result<Option<Offer>, Error> {
+        let offer_id = offer.offer_id.clone();
+        let mut scope = Scope::new();
+        let offer_dynamic = rhai::serde::to_dynamic(&offer)
+            .map_err(|e| Error::validation(e.to_string(), ErrorContext::offer(&offer_id)))?;
+        scope.push("offer", offer_dynamic);
+        let result = self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| Error::validation(
+                format!("error when applying rules: {}", e), ErrorContext::offer(&offer_id)
+            ))?;
+        if result.as_bool() == Ok(false) {
+            return Ok(None);
+        }
+        let offer = scope.get("offer")
+            .ok_or_else(|| Error::validation(
+                "rules removed the offer variable", ErrorContext::offer(&offer_id)
+            ))?;
+        // Go through JSON value so integers assigned to float fields are accepted
+        let offer = serde_json::to_value(offer)
+            .and_then(serde_json::from_value::<Offer>)
+            .map_err(|e| Error::validation(
+                format!("invalid offer after applying rules: {}", e), ErrorContext::offer(&offer_id)
+            ))?;
+        Ok(Some(offer))
+    }
+
+    /// Whether the script does not return `false` for the offer, changes of the offer are ignored
+    pub fn matches(&self, offer: &Offer) -> Result<bool, Error> {
+        let mut scope = Scope::new();
+        let offer_dynamic = rhai::serde::to_dynamic(offer)
+            .map_err(|e| Error::validation(e.to_string(), ErrorContext::offer(&offer.offer_id)))?;
+        scope.push("offer", offer_dynamic);
+        let result = self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| Error::validation(
+                format!("error when applying rules: {}", e), ErrorContext::offer(&offer.offer_id)
+            ))?;
+        Ok(result.as_bool() != Ok(false))
+    }
+}
@@ -0,0 +1,207 @@
+use log::error;
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::{import, Opts, ProcessedStat};
+use crate::error::Error;
+use crate::fetch::{self, FeedChecks};
+use crate::locale::ReportLocale;
+use crate::reload::RuleSetWatcher;
+use crate::suppliers::file_supplier;
+
+const HEADERS: [&str; 7] = [
+    "Supplier", "Offers", "Inserted", "Price updates", "Availability updates", "Errors", "Duration",
+];
+
+/// Format of the table written with `--table-out`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TableFormat {
+    Markdown,
+    Html,
+}
+
+impl FromStr for TableFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<TableFormat, Error> {
+        match s {
+            "markdown" => Ok(TableFormat::Markdown),
+            "html" => Ok(TableFormat::Html),
+            _ => Err(Error::config(format!("Unknown table format: {}", s))),
+        }
+    }
+}
+
+/// Results of a feed, `stat` is missing when the import failed
+struct FeedRow {
+    supplier: String,
+    stat: Option<ProcessedStat>,
+    duration: Duration,
+}
+
+/// Imports the feeds one by one with the same options and prints a table of the suppliers
+/// with totals, failed feeds do not stop the others
+pub(crate) fn run_all(
+    opts: &mut Opts,
+    watcher: &mut RuleSetWatcher,
+    feeds: &[PathBuf],
+    table_out: Option<&Path>,
+    table_format: TableFormat,
+) -> Result<(), Error> {
+    let mut rows = vec!();
+    for feed in feeds {
+        let supplier = file_supplier(feed);
+        let started_at = Instant::now();
+        opts.file_path = Some(feed.clone());
+        let checks = FeedChecks::from_opts(opts);
+        let result = fetch::fetch_remote_file(&mut opts.file_path, &checks)
+            .and_then(|_downloaded_file| import(opts, watcher));
+        let stat = match result {
+            Ok(stat) => Some(stat),
+            Err(e) => {
+                error!("{}: {}", supplier, e);
+                None
+            }
+        };
+        rows.push(FeedRow { supplier, stat, duration: started_at.elapsed() });
+    }
+
+    let l = opts.report_locale;
+    let cells = table_cells(&rows, l);
+    println!();
+    print!("{}", text_table(&cells));
+    if let Some(path) = table_out {
+        let table = match table_format {
+            TableFormat::Markdown => markdown_table(&cells),
+            TableFormat::Html => html_table(&cells),
+        };
+        fs::write(path, table)
+            .map_err(|e| Error::config_caused_by(format!("Cannot write table into {}", path.display()), e))?;
+    }
+
+    let failed = rows.iter().filter(|row| row.stat.is_none()).count();
+    if failed > 0 {
+        return Err(Error::external("run-all", format!("{} of {} feeds failed", failed, rows.len())));
+    }
+    Ok(())
+}
+
+/// Rows of the formatted values, the last one contains totals
+fn table_cells(rows: &[FeedRow], l: ReportLocale) -> Vec<[String; 7]> {
+    let mut totals = [0u64; 5];
+    let mut total_duration = Duration::default();
+    let mut cells = vec!();
+    for row in rows {
+        total_duration += row.duration;
+        let duration = l.duration(Duration::from_millis(row.duration.as_millis() as u64));
+        let stat = match row.stat {
+            Some(ref stat) => stat,
+            None => {
+                cells.push([
+                    row.supplier.clone(), "-".to_string(), "-".to_string(), "-".to_string(), "-".to_string(),
+                    "failed".to_string(), duration,
+                ]);
+                continue;
+            }
+        };
+        let values = [
+            stat.total_offers,
+            stat.inserted_products,
+            stat.updated_price,
+            stat.updated_available,
+            stat.ignored_offers + stat.rejected_offers,
+        ];
+        for (total, value) in totals.iter_mut().zip(values.iter()) {
+            *total += *value as u64;
+        }
+        cells.push([
+            row.supplier.clone(),
+            l.int(values[0]), l.int(values[1]), l.int(values[2]), l.int(values[3]), l.int(values[4]),
+            duration,
+        ]);
+    }
+    cells.push([
+        "Total".to_string(),
+        l.int(totals[0]), l.int(totals[1]), l.int(totals[2]), l.int(totals[3]), l.int(totals[4]),
+        l.duration(Duration::from_millis(total_duration.as_millis() as u64)),
+    ]);
+    cells
+}
+
+/// Columns are padded to the widest value, numbers are aligned to the right
+fn text_table(cells: &[[String; 7]]) -> String {
+    let mut widths = HEADERS.map(|header| header.chars().count());
+    for row in cells {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let format_row = |row: &[&str]| {
+        let mut line = String::new();
+        for (i, (cell, width)) in row.iter().zip(widths.iter()).enumerate() {
+            if i == 0 {
+                write!(line, "{:<width$}", cell, width = width).unwrap();
+            } else {
+                write!(line, "  {:>width$}", cell, width = width).unwrap();
+            }
+        }
+        line.truncate(line.trim_end().len());
+        line.push('\n');
+        line
+    };
+    let mut table = format_row(&HEADERS);
+    let separator_width = widths.iter().sum::<usize>() + 2 * (widths.len() - 1);
+    let separator = format!("{}\n", "-".repeat(separator_width));
+    table.push_str(&separator);
+    for (i, row) in cells.iter().enumerate() {
+        if i == cells.len() - 1 {
+            table.push_str(&separator);
+        }
+        table.push_str(&format_row(&row.each_ref().map(String::as_str)));
+    }
+    table
+}
+
+fn markdown_table(cells: &[[String; 7]]) -> String {
+    let mut table = format!("| {} |\n", HEADERS.join(" | "));
+    table.push_str("|---|---:|---:|---:|---:|---:|---:|\n");
+    for (i, row) in cells.iter().enumerate() {
+        let is_total = i == cells.len() - 1;
+        let row = row.iter()
+            .map(|cell| {
+                let cell = cell.replace('|', "\\|");
+                if is_total { format!("**{}**", cell) } else { cell }
+            })
+            .collect::<Vec<_>>();
+        writeln!(table, "| {} |", row.join(" | ")).unwrap();
+    }
+    table
+}
+
+fn html_table(cells: &[[String; 7]]) -> String {
+    let row_html = |tag: &str, row: &[&str]| {
+        let cells = row.iter()
+            .map(|cell| format!("<{tag}>{}</{tag}>", escape_html(cell), tag = tag))
+            .collect::<String>();
+        format!("<tr>{}</tr>\n", cells)
+    };
+    let mut table = String::from("<table>\n<thead>\n");
+    table.push_str(&row_html("th", &HEADERS));
+    table.push_str("</thead>\n<tbody>\n");
+    for (i, row) in cells.iter().enumerate() {
+        if i == cells.len() - 1 {
+            table.push_str("</tbody>\n<tfoot>\n");
+        }
+        table.push_str(&row_html("td", &row.each_ref().map(String::as_str)));
+    }
+    table.push_str("</tfoot>\n</table>\n");
+    table
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
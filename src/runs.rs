@@ -0,0 +1,71 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use std::path::Path;
+
+use crate::ProcessedStat;
+use crate::error::Error;
+use crate::locale::ReportLocale;
+use crate::models::{ImportRun, NewImportRun};
+use crate::schema::import_runs;
+
+/// Changes at least of this percent of the previous value are flagged as unusual
+const UNUSUAL_PERCENT: u64 = 50;
+/// Smaller changes are never flagged
+const UNUSUAL_MIN_DELTA: u64 = 10;
+
+/// Loads statistics of the last run that imported the same file
+pub(crate) fn load_previous(conn: &mut MysqlConnection, file_path: &str) -> Result<Option<ImportRun>, Error> {
+    Ok(
+        import_runs::table
+            .select((
+                import_runs::total_offers,
+                import_runs::ignored_offers,
+                import_runs::parsed_offers,
+                import_runs::updated_price,
+                import_runs::updated_available,
+                import_runs::inserted_products,
+                import_runs::marked_as_unavailable,
+            ))
+            .filter(import_runs::file_path.eq(file_path))
+            .order(import_runs::id.desc())
+            .first(conn)
+            .optional()?
+    )
+}
+
+pub(crate) fn save(
+    conn: &mut MysqlConnection, file_path: &str, archive_path: Option<&Path>, stat: &ProcessedStat,
+) -> Result<(), Error> {
+    diesel::insert_into(import_runs::table)
+        .values(&NewImportRun {
+            import_id: stat.import_id.clone(),
+            file_path: file_path.to_string(),
+            total_offers: stat.total_offers,
+            ignored_offers: stat.ignored_offers,
+            parsed_offers: stat.parsed_offers,
+            updated_price: stat.updated_price,
+            updated_available: stat.updated_available,
+            inserted_products: stat.inserted_products,
+            marked_as_unavailable: stat.marked_as_unavailable,
+            rules_version: stat.rules_version.clone(),
+            archive_path: archive_path.map(|path| path.to_string_lossy().to_string()),
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Formats the value with its change since the previous run: `120 (+95 vs last run)`
+pub(crate) fn with_delta(locale: ReportLocale, value: u32, previous: Option<u32>) -> String {
+    let previous = match previous {
+        Some(previous) => previous,
+        None => return locale.int(value),
+    };
+    let delta = value as i64 - previous as i64;
+    let unusual = delta.unsigned_abs() >= UNUSUAL_MIN_DELTA &&
+        delta.unsigned_abs() * 100 >= previous as u64 * UNUSUAL_PERCENT;
+    format!(
+        "{} ({} vs last run){}",
+        locale.int(value), locale.int(format!("{:+}", delta)), if unusual { " [unusual]" } else { "" }
+    )
+}
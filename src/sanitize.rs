@@ -0,0 +1,37 @@
+use crate::parser::Offer;
+
+/// Removes characters that break storage or display: control characters
+/// (line breaks and tabs are kept), byte order marks and replacement characters
+/// left after decoding invalid UTF-8. Returns `true` when anything was removed.
+pub(crate) fn sanitize_offer(offer: &mut Offer) -> bool {
+    let mut cleaned = false;
+    for value in [
+        &mut offer.name,
+        &mut offer.description,
+        &mut offer.vendor,
+        &mut offer.vendor_code,
+        &mut offer.type_prefix,
+        &mut offer.model,
+        &mut offer.barcode,
+        &mut offer.currency_id,
+    ].iter_mut().flat_map(|v| v.as_mut()) {
+        cleaned |= sanitize(value);
+    }
+    cleaned
+}
+
+fn sanitize(value: &mut String) -> bool {
+    if !value.chars().any(is_garbage) {
+        return false;
+    }
+    value.retain(|c| !is_garbage(c));
+    true
+}
+
+fn is_garbage(c: char) -> bool {
+    match c {
+        '\n' | '\r' | '\t' => false,
+        '\u{FEFF}' | '\u{FFFD}' => true,
+        c => c.is_control(),
+    }
+}
@@ -13,6 +13,30 @@ table! {
 //        vendorCode -> Nullable<Integer>,
         renew_date -> Nullable<Timestamp>,
         file_id -> Nullable<Tinyint>,
+        on_sale -> Nullable<Tinyint>,
+        discount_percent -> Nullable<Integer>,
+        unavailable_runs -> Integer,
+        tags -> Nullable<Varchar>,
+        slug -> Nullable<Varchar>,
+        GTIN -> Nullable<Bigint>,
+        version -> Integer,
+        locked_fields -> Nullable<Varchar>,
+        to_renew -> Nullable<Tinyint>,
+        price_from -> Tinyint,
+        vat -> Nullable<Varchar>,
+        status -> Varchar,
+        source_supplier -> Nullable<Varchar>,
+        quantity_in_stock -> Nullable<Integer>,
+        category_path -> Nullable<Varchar>,
+        adult -> Tinyint,
+        age -> Nullable<Integer>,
+        last_import_id -> Nullable<Varchar>,
+        sku -> Nullable<Varchar>,
+        low_stock -> Tinyint,
+        price_original -> Nullable<Float>,
+        oldprice_original -> Nullable<Float>,
+        currency_original -> Nullable<Varchar>,
+        currency_rate -> Nullable<Float>,
 //        insert_date -> Nullable<Timestamp>,
 //        group_id -> Nullable<Varchar>,
 //        prom_cat_id -> Nullable<Integer>,
@@ -22,7 +46,6 @@ table! {
 //        cpa_saldo -> Nullable<Float>,
 //        // force_rk_type -> Nullable<Enum>,
 //        minimum_order_quantity -> Nullable<Integer>,
-//        quantity_in_stock -> Nullable<Integer>,
 //        vendor -> Nullable<Varchar>,
 //        country -> Nullable<Varchar>,
 //        prod_blacklist -> Nullable<Tinyint>,
@@ -30,6 +53,264 @@ table! {
 //        vendor_id -> Nullable<Integer>,
 //        presence_sure -> Nullable<Tinyint>,
 //        source_id -> Nullable<Integer>,
-//        GTIN -> Nullable<Bigint>,
+    }
+}
+
+table! {
+    price_history (id) {
+        id -> Integer,
+        product_id -> Integer,
+        old_price -> Float,
+        new_price -> Float,
+        feed_price -> Nullable<Float>,
+        currencyId -> Nullable<Varchar>,
+        run_id -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    possible_duplicates (id) {
+        id -> Integer,
+        product_id -> Integer,
+        duplicate_product_id -> Integer,
+        similarity -> Float,
+        reason -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    import_quarantine (id) {
+        id -> Integer,
+        offer_id -> Varchar,
+        hub_stock_id -> Varchar,
+        name -> Text,
+        error -> Text,
+        run_id -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    product_stocks (hub_stock_id, supplier) {
+        hub_stock_id -> Varchar,
+        supplier -> Varchar,
+        quantity -> Integer,
+        run_id -> Varchar,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    product_conflicts (product_id, field) {
+        product_id -> Integer,
+        field -> Varchar,
+        offer_id -> Varchar,
+        change_class -> Varchar,
+        db_value -> Text,
+        feed_value -> Text,
+        import_id -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    product_hashes (hub_stock_id) {
+        hub_stock_id -> Varchar,
+        offer_hash -> Char,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    product_channels (hub_stock_id, channel) {
+        hub_stock_id -> Varchar,
+        channel -> Varchar,
+        visible -> Tinyint,
+        run_id -> Varchar,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    product_keywords (hub_stock_id, keyword) {
+        hub_stock_id -> Varchar,
+        keyword -> Varchar,
+    }
+}
+
+table! {
+    product_relations (product_id, related_product_id, relation_type) {
+        product_id -> Integer,
+        related_product_id -> Integer,
+        #[sql_name = "type"]
+        relation_type -> Varchar,
+    }
+}
+
+table! {
+    product_variants (hub_stock_id) {
+        hub_stock_id -> Varchar,
+        group_id -> Varchar,
+        product_hub_stock_id -> Varchar,
+        price -> Float,
+        available -> Tinyint,
+        size -> Nullable<Varchar>,
+        color -> Nullable<Varchar>,
+        params -> Nullable<Text>,
+        run_id -> Varchar,
+        updated_at -> Timestamp,
+    }
+}
+
+// Copy of the products table structure where the whole feed is loaded in `--staging` mode,
+// only the columns filled from offers are listed
+table! {
+    products_staging (id) {
+        id -> Integer,
+        offer_id -> Varchar,
+        hub_stock_id -> Nullable<Varchar>,
+        categoryId -> Integer,
+        name -> Varchar,
+        price -> Float,
+        oldprice -> Nullable<Float>,
+        currencyId -> Nullable<Varchar>,
+        available -> Nullable<Tinyint>,
+        description -> Nullable<Mediumtext>,
+        file_id -> Nullable<Tinyint>,
+        on_sale -> Nullable<Tinyint>,
+        discount_percent -> Nullable<Integer>,
+        tags -> Nullable<Varchar>,
+        slug -> Nullable<Varchar>,
+        GTIN -> Nullable<Bigint>,
+        price_from -> Tinyint,
+        vat -> Nullable<Varchar>,
+        status -> Varchar,
+        source_supplier -> Nullable<Varchar>,
+        category_path -> Nullable<Varchar>,
+        adult -> Tinyint,
+        age -> Nullable<Integer>,
+    }
+}
+
+// Temporary table created when offer ids do not fit into memory
+table! {
+    seen_offer_ids (hub_stock_id) {
+        hub_stock_id -> Varchar,
+    }
+}
+
+table! {
+    available_offers (hub_stock_id) {
+        hub_stock_id -> Varchar,
+        available -> Tinyint,
+    }
+}
+
+table! {
+    import_chunks (chunk_hash) {
+        chunk_hash -> Varchar,
+        idempotency_key -> Varchar,
+        chunk_index -> Unsigned<Integer>,
+        batch_index -> Unsigned<Integer>,
+        products -> Unsigned<Integer>,
+        import_id -> Varchar,
+        applied_at -> Timestamp,
+    }
+}
+
+table! {
+    import_runs (id) {
+        id -> Integer,
+        import_id -> Nullable<Varchar>,
+        file_path -> Varchar,
+        total_offers -> Unsigned<Integer>,
+        ignored_offers -> Unsigned<Integer>,
+        parsed_offers -> Unsigned<Integer>,
+        updated_price -> Unsigned<Integer>,
+        updated_available -> Unsigned<Integer>,
+        inserted_products -> Unsigned<Integer>,
+        marked_as_unavailable -> Unsigned<Integer>,
+        rules_version -> Nullable<Varchar>,
+        archive_path -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    supplier_feed_stats (id) {
+        id -> Integer,
+        supplier -> Varchar,
+        feed_date -> Nullable<Datetime>,
+        run_at -> Timestamp,
+        total_offers -> Unsigned<Integer>,
+        error_offers -> Unsigned<Integer>,
+    }
+}
+
+table! {
+    supplier_feed_fields (supplier, field) {
+        supplier -> Varchar,
+        field -> Varchar,
+        offers -> Unsigned<Integer>,
+        total_offers -> Unsigned<Integer>,
+        run_at -> Timestamp,
+    }
+}
+
+table! {
+    supplier_price_medians (id) {
+        id -> Integer,
+        supplier -> Varchar,
+        median_price -> Double,
+        offers -> Unsigned<Integer>,
+        run_at -> Timestamp,
+    }
+}
+
+table! {
+    category_stats (category_id) {
+        category_id -> Integer,
+        available_products -> Unsigned<Integer>,
+        total_products -> Unsigned<Integer>,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    categories (id) {
+        id -> Integer,
+        parent_id -> Nullable<Integer>,
+        name -> Varchar,
+        auto_created -> Tinyint,
+    }
+}
+
+table! {
+    discontinued_categories (category_id) {
+        category_id -> Integer,
+        reason -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    reservations (id) {
+        id -> Integer,
+        product_id -> Integer,
+        order_id -> Integer,
+        quantity -> Integer,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    pricing_rules (id) {
+        id -> Integer,
+        category_id -> Nullable<Integer>,
+        percent -> Float,
+        fixed_add -> Float,
+        rounding -> Nullable<Float>,
     }
 }
@@ -0,0 +1,91 @@
+use chrono::Utc;
+
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use log::warn;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::ProcessedStat;
+use crate::error::Error;
+use crate::models::{NewSupplierFeedField, SupplierFeedField};
+use crate::schema::supplier_feed_fields;
+use crate::suppliers::file_supplier;
+
+/// Fields that were present at least in this share of the offers of the previous run are common
+const COMMON_SHARE: f64 = 0.5;
+
+/// Common field of the supplier's offers that none of the offers contain anymore
+#[derive(Debug)]
+pub(crate) struct DisappearedField {
+    pub supplier: String,
+    pub field: String,
+    /// Share of the offers that contained the field in the previous run
+    pub previous_share: f64,
+}
+
+/// Compares elements and attributes of the offers with the previous run of every supplier
+/// and saves the current ones, returns common fields that disappeared from the feed
+pub(crate) fn detect(
+    conn: &mut MysqlConnection, file_path: &Path, stat: &ProcessedStat,
+) -> Result<Vec<DisappearedField>, Error> {
+    let run_at = Utc::now().naive_utc();
+    let shops_offers = stat.shop_offers.values().sum::<u32>();
+    let mut disappeared = vec!();
+    for (shop, fields) in &stat.feed_fields {
+        let (supplier, total_offers) = if shop.is_empty() {
+            (file_supplier(file_path), stat.total_offers - shops_offers)
+        } else {
+            (shop.clone(), stat.shop_offers.get(shop).copied().unwrap_or(0))
+        };
+        if total_offers == 0 {
+            continue;
+        }
+        let previous = supplier_feed_fields::table
+            .select((
+                supplier_feed_fields::field,
+                supplier_feed_fields::offers,
+                supplier_feed_fields::total_offers,
+            ))
+            .filter(supplier_feed_fields::supplier.eq(&supplier))
+            .load::<SupplierFeedField>(conn)?;
+
+        let mut rows = fields.iter()
+            .map(|(field, &offers)| (field.clone(), offers))
+            .collect::<BTreeMap<_, _>>();
+        for prev in previous {
+            if fields.contains_key(&prev.field) || prev.total_offers == 0 {
+                continue;
+            }
+            let previous_share = prev.offers as f64 / prev.total_offers as f64;
+            if previous_share >= COMMON_SHARE {
+                warn!(
+                    "{}: {} disappeared from the offers, it was in {:.0}% of them in the previous run",
+                    supplier, prev.field, previous_share * 100.0
+                );
+                disappeared.push(DisappearedField {
+                    supplier: supplier.clone(),
+                    field: prev.field.clone(),
+                    previous_share,
+                });
+            }
+            rows.insert(prev.field, 0);
+        }
+
+        let rows = rows.into_iter()
+            .map(|(field, offers)| NewSupplierFeedField {
+                supplier: supplier.clone(),
+                field,
+                offers,
+                total_offers,
+                run_at,
+            })
+            .collect::<Vec<_>>();
+        diesel::replace_into(supplier_feed_fields::table)
+            .values(&rows)
+            .execute(conn)?;
+    }
+    Ok(disappeared)
+}
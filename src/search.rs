@@ -0,0 +1,230 @@
+use log::info;
+
+use serde_json::{json, Map, Value};
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use url::Url;
+
+use crate::error::Error;
+use crate::models::NewProduct;
+use crate::process::{ChangeKind, ProductChange};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SearchBackend {
+    Elasticsearch,
+    Meilisearch,
+}
+
+impl FromStr for SearchBackend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SearchBackend, Error> {
+        match s {
+            "elasticsearch" => Ok(SearchBackend::Elasticsearch),
+            "meilisearch" => Ok(SearchBackend::Meilisearch),
+            _ => Err(Error::config(format!("Unknown search backend: {}", s))),
+        }
+    }
+}
+
+/// Document of a changed product: inserted products are indexed as a whole,
+/// only the applied values of updated products are merged into their documents.
+enum Document {
+    Full(Value),
+    Partial(Value),
+}
+
+/// Pushes documents of inserted and updated products into a search backend.
+pub(crate) struct SearchIndexer {
+    backend: SearchBackend,
+    url: Url,
+    index: String,
+    batch_size: usize,
+    documents: Vec<(String, Document)>,
+}
+
+impl SearchIndexer {
+    pub fn new(backend: SearchBackend, url: &Url, index: &str, batch_size: usize) -> SearchIndexer {
+        info!("Syncing changed products into {} index at {}", index, url);
+        SearchIndexer {
+            backend,
+            url: url.clone(),
+            index: index.to_string(),
+            batch_size,
+            documents: vec!(),
+        }
+    }
+
+    /// Adds documents for changed products and pushes them when a batch is full.
+    /// Returns number of pushed documents.
+    pub fn add(&mut self, changes: &[ProductChange], products: &[NewProduct]) -> Result<u32, Error> {
+        let products = products.iter()
+            .map(|p| (p.offer_id.as_str(), p))
+            .collect::<HashMap<_, _>>();
+        let mut pushed = 0;
+        for change in changes {
+            let document = match change.kind {
+                ChangeKind::Inserted => products.get(change.offer_id.as_str())
+                    .map(|p| Document::Full(product_document(p, change.product_id))),
+                ChangeKind::Updated => change_document(change).map(Document::Partial),
+            };
+            if let Some(document) = document {
+                self.documents.push((change.offer_id.clone(), document));
+            }
+            if self.documents.len() >= self.batch_size {
+                pushed += self.flush()?;
+            }
+        }
+        Ok(pushed)
+    }
+
+    pub fn flush(&mut self) -> Result<u32, Error> {
+        if self.documents.is_empty() {
+            return Ok(0);
+        }
+        let resp = match self.backend {
+            SearchBackend::Elasticsearch => {
+                let mut body = String::new();
+                for (id, doc) in &self.documents {
+                    let (action, source) = match doc {
+                        Document::Full(doc) => ("index", doc.clone()),
+                        Document::Partial(doc) => ("update", json!({"doc": doc, "doc_as_upsert": true})),
+                    };
+                    body.push_str(&json!({action: {"_index": self.index, "_id": id}}).to_string());
+                    body.push('\n');
+                    body.push_str(&source.to_string());
+                    body.push('\n');
+                }
+                ureq::post(self.endpoint("_bulk")?.as_str())
+                    .set("Content-Type", "application/x-ndjson")
+                    .send_string(&body)
+            }
+            SearchBackend::Meilisearch => {
+                // PUT merges the fields into existing documents
+                let docs = self.documents.iter()
+                    .map(|(_, doc)| match doc {
+                        Document::Full(doc) | Document::Partial(doc) => doc.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                ureq::put(self.endpoint(&format!("indexes/{}/documents", self.index))?.as_str())
+                    .send_json(Value::Array(docs))
+            }
+        };
+        if let Some(e) = resp.synthetic_error() {
+            return Err(Error::external("search", format!("Error pushing documents into index: {}", e)));
+        }
+        if resp.error() {
+            let status = resp.status();
+            return Err(Error::external(
+                "search",
+                format!("Backend responded with status {}: {}", status, resp.into_string()?)
+            ));
+        }
+        if let SearchBackend::Elasticsearch = self.backend {
+            check_bulk_response(&resp.into_json()?)?;
+        }
+        let pushed = self.documents.len() as u32;
+        self.documents.clear();
+        Ok(pushed)
+    }
+
+    fn endpoint(&self, path: &str) -> Result<Url, Error> {
+        self.url.join(path)
+            .map_err(|e| Error::config_caused_by(format!("Invalid search url: {}", self.url), e))
+    }
+}
+
+/// Elasticsearch responds with 200 even when some of the documents are rejected
+fn check_bulk_response(resp: &Value) -> Result<(), Error> {
+    if resp["errors"] != Value::Bool(true) {
+        return Ok(());
+    }
+    let items = resp["items"].as_array().map_or(&[][..], Vec::as_slice);
+    let failed = items.iter()
+        .filter_map(|item| item.as_object()?.values().next())
+        .filter(|result| !result["error"].is_null())
+        .collect::<Vec<_>>();
+    let reason = failed.first()
+        .map(|result| format!("{}: {}", result["_id"], result["error"]["reason"]))
+        .unwrap_or_default();
+    Err(Error::external(
+        "search",
+        format!("{} of {} documents were rejected, first of them {}", failed.len(), items.len(), reason)
+    ))
+}
+
+/// Applied values of an updated product converted to the types of the document fields,
+/// `None` when none of the indexed fields changed
+fn change_document(change: &ProductChange) -> Option<Value> {
+    let mut doc = Map::new();
+    for (field, value) in &change.values {
+        let value = match *field {
+            _ if value.is_empty() => Value::Null,
+            "name" | "currencyId" | "description" => Value::from(value.as_str()),
+            "price" | "oldprice" => value.parse::<f64>().map_or(Value::Null, Value::from),
+            "categoryId" | "available" => value.parse::<i64>().map_or(Value::Null, Value::from),
+            _ => continue,
+        };
+        doc.insert(field.to_string(), value);
+    }
+    if doc.is_empty() {
+        return None;
+    }
+    doc.insert("id".to_string(), Value::from(change.offer_id.as_str()));
+    doc.insert("product_id".to_string(), change.product_id.map_or(Value::Null, Value::from));
+    Some(Value::Object(doc))
+}
+
+fn product_document(p: &NewProduct, product_id: Option<i32>) -> Value {
+    json!({
+        "id": p.offer_id,
+        "product_id": product_id,
+        "name": p.name,
+        "categoryId": p.categoryId,
+        "price": p.price,
+        "oldprice": p.oldprice,
+        "currencyId": p.currencyId,
+        "available": p.available,
+        "description": p.description,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_bulk_response() {
+        assert!(check_bulk_response(&json!({"errors": false, "items": []})).is_ok());
+        let resp = json!({
+            "errors": true,
+            "items": [
+                {"index": {"_id": "1", "status": 201}},
+                {"update": {"_id": "2", "status": 400, "error": {"type": "mapper_parsing_exception", "reason": "bad price"}}},
+            ]
+        });
+        assert_eq!(
+            check_bulk_response(&resp).unwrap_err().to_string(),
+            "search error: 1 of 2 documents were rejected, first of them \"2\": \"bad price\""
+        );
+    }
+
+    #[test]
+    fn test_change_document() {
+        let change = ProductChange {
+            product_id: Some(7),
+            offer_id: "abc".to_string(),
+            kind: ChangeKind::Updated,
+            values: vec!(("price", "10.5".to_string()), ("oldprice", "".to_string()), ("vat", "20".to_string())),
+            old_values: vec!(),
+        };
+        assert_eq!(
+            change_document(&change),
+            Some(json!({"id": "abc", "product_id": 7, "price": 10.5, "oldprice": null}))
+        );
+        let change = ProductChange { values: vec!(("vat", "20".to_string())), ..change };
+        assert_eq!(change_document(&change), None);
+    }
+}
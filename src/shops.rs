@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::models::HUBBER_FILE_ID;
+
+/// Supplier (`file_id`) per shop for aggregated feeds that contain several `<shop>` sections.
+pub(crate) struct ShopSuppliers {
+    by_shop: HashMap<String, i8>,
+}
+
+impl ShopSuppliers {
+    /// Loads `shop name = file id` lines, lines starting with `#` are ignored.
+    pub fn load(path: &Path) -> Result<ShopSuppliers, Error> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| Error::config_caused_by(
+                format!("Cannot read shop suppliers: {}", path.display()), e
+            ))?;
+        let mut by_shop = HashMap::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (shop, file_id) = line.rsplit_once('=')
+                .ok_or_else(|| Error::config(format!("Invalid shop supplier line: {}", line)))?;
+            let file_id = file_id.trim().parse()
+                .map_err(|_| Error::config(format!("Invalid file id of the shop supplier: {}", line)))?;
+            by_shop.insert(shop.trim().to_string(), file_id);
+        }
+        Ok(ShopSuppliers { by_shop })
+    }
+
+    pub fn file_id(&self, shop: &str) -> Option<i8> {
+        self.by_shop.get(shop).copied()
+    }
+
+    /// All file ids products can be imported with
+    pub fn file_ids(&self) -> Vec<i8> {
+        let mut file_ids = self.by_shop.values().copied().collect::<Vec<_>>();
+        file_ids.push(HUBBER_FILE_ID);
+        file_ids.sort_unstable();
+        file_ids.dedup();
+        file_ids
+    }
+}
@@ -0,0 +1,188 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use log::warn;
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::models::NewProduct;
+use crate::name_template::{field_value, parse_parts, Part};
+use crate::parser::Offer;
+
+const FIELDS: &[&str] = &["supplier", "offer_id", "vendor", "vendorCode", "model", "barcode"];
+const MAX_SKU_LENGTH: usize = 64;
+/// Suffixed SKUs of every taken SKU looked up with a single query
+const SUFFIX_LOOKUP_SIZE: usize = 10;
+
+/// Template of internal SKUs, e.g. `{supplier}-{vendorCode|offer_id}`
+#[derive(Debug)]
+pub(crate) struct SkuTemplate {
+    parts: Vec<Part>,
+}
+
+impl FromStr for SkuTemplate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SkuTemplate, Error> {
+        Ok(SkuTemplate { parts: parse_parts(s, FIELDS, "SKU template")? })
+    }
+}
+
+impl SkuTemplate {
+    /// Builds the SKU from the offer fields. Whitespaces are replaced with dashes and separators
+    /// left by missing fields are trimmed. Returns `None` when all the fields are missing.
+    pub fn render(&self, offer: &Offer) -> Option<String> {
+        let mut sku = String::new();
+        let mut has_values = false;
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => sku.push_str(literal),
+                Part::Field(fields) => {
+                    if let Some(value) = fields.iter().find_map(|f| field_value(offer, f)) {
+                        sku.push_str(value.trim());
+                        has_values = true;
+                    }
+                }
+            }
+        }
+        if !has_values {
+            return None;
+        }
+        let sku = sku.split_whitespace()
+            .collect::<Vec<_>>()
+            .join("-");
+        let sku = sku.trim_matches(|c: char| c == '-' || c == '_' || c == '/' || c == '.');
+        Some(truncate(sku, MAX_SKU_LENGTH).to_string())
+    }
+}
+
+/// Longest prefix of at most `max_len` bytes that ends on a char boundary
+fn truncate(s: &str, max_len: usize) -> &str {
+    if s.len() <= max_len {
+        return s;
+    }
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// The `n`th candidate of the SKU: the SKU itself for `1`, otherwise the SKU shortened
+/// to fit the `-n` suffix into the column
+fn suffixed(base_sku: &str, n: usize) -> String {
+    if n == 1 {
+        return base_sku.to_string();
+    }
+    let suffix = format!("-{}", n);
+    format!("{}{}", truncate(base_sku, MAX_SKU_LENGTH - suffix.len()), suffix)
+}
+
+/// Makes SKUs of new products unique adding numeric suffixes when a SKU is already used
+/// by another product. Returns a number of collisions.
+pub(crate) fn assign_unique_skus(
+    conn: &mut MysqlConnection, new_products: &mut [NewProduct],
+) -> Result<u32, Error> {
+    use crate::schema::products::dsl;
+
+    let mut needed = HashMap::<String, usize>::new();
+    for sku in new_products.iter().filter_map(|p| p.sku.as_deref()) {
+        *needed.entry(sku.to_string()).or_default() += 1;
+    }
+    // candidates of the SKUs are looked up until every SKU has enough free ones for the products,
+    // most SKUs are free so the SKUs themselves are looked up first
+    let mut pending = needed.into_iter().collect::<Vec<_>>();
+    let mut used_skus = HashSet::new();
+    let mut candidates = 1..2;
+    while !pending.is_empty() {
+        let lookup = pending.iter()
+            .flat_map(|(base_sku, _)| candidates.clone().map(move |n| suffixed(base_sku, n)))
+            .collect::<Vec<_>>();
+        let taken_skus = dsl::products.select(dsl::sku)
+            .filter(dsl::sku.eq_any(&lookup))
+            .load::<Option<String>>(conn)?;
+        used_skus.extend(taken_skus.into_iter().flatten());
+        pending = pending.into_iter()
+            .filter_map(|(base_sku, needed)| {
+                let free = candidates.clone()
+                    .filter(|n| !used_skus.contains(&suffixed(&base_sku, *n)))
+                    .count();
+                (free < needed).then(|| (base_sku, needed - free))
+            })
+            .collect();
+        candidates = candidates.end..candidates.end + SUFFIX_LOOKUP_SIZE;
+    }
+
+    let mut collisions = 0;
+    for p in new_products.iter_mut() {
+        let base_sku = match p.sku.take() {
+            Some(sku) => sku,
+            None => continue,
+        };
+        let mut sku = base_sku.clone();
+        let mut n = 1;
+        while used_skus.contains(&sku) {
+            n += 1;
+            sku = suffixed(&base_sku, n);
+        }
+        if n > 1 {
+            warn!("{}: SKU {} is already used, assigned {}", p.offer_id, base_sku, sku);
+            collisions += 1;
+        }
+        used_skus.insert(sku.clone());
+        p.sku = Some(sku);
+    }
+    Ok(collisions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer() -> Offer {
+        let mut offer = Offer::new("1001".to_string(), 1);
+        offer.shop = Some("Acme".to_string());
+        offer
+    }
+
+    #[test]
+    fn test_render_fallback_fields() {
+        let template = "{supplier}-{vendorCode|offer_id}".parse::<SkuTemplate>().unwrap();
+        let mut offer = offer();
+        assert_eq!(template.render(&offer).as_deref(), Some("Acme-1001"));
+        offer.vendor_code = Some("AB 12".to_string());
+        assert_eq!(template.render(&offer).as_deref(), Some("Acme-AB-12"));
+    }
+
+    #[test]
+    fn test_render_trims_separators() {
+        let template = "{vendor}/{model}-{barcode}".parse::<SkuTemplate>().unwrap();
+        let mut offer = offer();
+        assert_eq!(template.render(&offer), None);
+        offer.model = Some(" X 100 ".to_string());
+        assert_eq!(template.render(&offer).as_deref(), Some("X-100"));
+    }
+
+    #[test]
+    fn test_render_truncates() {
+        let template = "{vendorCode}".parse::<SkuTemplate>().unwrap();
+        let mut offer = offer();
+        offer.vendor_code = Some("я".repeat(40));
+        let sku = template.render(&offer).unwrap();
+        assert_eq!(sku, "я".repeat(32));
+        assert!(sku.len() <= MAX_SKU_LENGTH);
+    }
+
+    #[test]
+    fn test_suffixed() {
+        assert_eq!(suffixed("ACME-1", 1), "ACME-1");
+        assert_eq!(suffixed("ACME-1", 2), "ACME-1-2");
+        let long = "x".repeat(MAX_SKU_LENGTH);
+        assert_eq!(suffixed(&long, 2), format!("{}-2", "x".repeat(MAX_SKU_LENGTH - 2)));
+        assert_eq!(suffixed(&long, 12).len(), MAX_SKU_LENGTH);
+        let cyrillic = "я".repeat(32);
+        assert_eq!(suffixed(&cyrillic, 2), format!("{}-2", "я".repeat(31)));
+    }
+}
@@ -0,0 +1,53 @@
+const MAX_SLUG_LENGTH: usize = 100;
+
+fn transliterate_char(c: char) -> Option<&'static str> {
+    let t = match c {
+        'а' => "a", 'б' => "b", 'в' => "v", 'г' => "h", 'ґ' => "g", 'д' => "d",
+        'е' => "e", 'є' => "ye", 'ё' => "yo", 'ж' => "zh", 'з' => "z", 'и' => "y",
+        'і' => "i", 'ї' => "yi", 'й' => "y", 'к' => "k", 'л' => "l", 'м' => "m",
+        'н' => "n", 'о' => "o", 'п' => "p", 'р' => "r", 'с' => "s", 'т' => "t",
+        'у' => "u", 'ф' => "f", 'х' => "kh", 'ц' => "ts", 'ч' => "ch", 'ш' => "sh",
+        'щ' => "shch", 'ъ' => "", 'ы' => "y", 'ь' => "", 'э' => "e", 'ю' => "yu",
+        'я' => "ya", '\'' | '’' | 'ʼ' => "",
+        _ => return None,
+    };
+    Some(t)
+}
+
+/// Makes URL slug from a product name transliterating cyrillic characters.
+pub(crate) fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    let mut pending_dash = false;
+    for c in name.to_lowercase().chars() {
+        let part = match transliterate_char(c) {
+            Some(t) => t,
+            None if c.is_ascii_alphanumeric() => {
+                if pending_dash && !slug.is_empty() {
+                    slug.push('-');
+                }
+                pending_dash = false;
+                slug.push(c);
+                continue;
+            }
+            None => {
+                pending_dash = true;
+                continue;
+            }
+        };
+        if part.is_empty() {
+            continue;
+        }
+        if pending_dash && !slug.is_empty() {
+            slug.push('-');
+        }
+        pending_dash = false;
+        slug.push_str(part);
+    }
+    if slug.len() > MAX_SLUG_LENGTH {
+        slug.truncate(MAX_SLUG_LENGTH);
+        if let Some(pos) = slug.rfind('-') {
+            slug.truncate(pos);
+        }
+    }
+    slug
+}
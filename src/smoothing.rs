@@ -0,0 +1,80 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{Bigint, Float, Integer};
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::error::Error;
+
+/// Products whose latest prices are fetched with a single query
+const LOOKUP_BATCH_SIZE: usize = 100;
+
+#[derive(QueryableByName)]
+struct FeedPrice {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+    #[diesel(sql_type = Integer)]
+    product_id: i32,
+    #[diesel(sql_type = Float)]
+    feed_price: f32,
+}
+
+/// Feed prices of the products recorded by the previous runs, the latest first.
+/// Only `window - 1` prices are fetched, the current feed price completes the window.
+pub(crate) fn load_feed_prices(
+    conn: &mut MysqlConnection, product_ids: &[i32], window: usize,
+) -> Result<HashMap<i32, Vec<f32>>, Error> {
+    let mut feed_prices = HashMap::<i32, Vec<f32>>::new();
+    if window < 2 {
+        return Ok(feed_prices);
+    }
+    for batch in product_ids.chunks(LOOKUP_BATCH_SIZE) {
+        let mut rows = latest_feed_prices(conn, batch, (window - 1) as i64)?;
+        rows.sort_by_key(|row| Reverse(row.id));
+        for row in rows {
+            feed_prices.entry(row.product_id).or_default().push(row.feed_price);
+        }
+    }
+    Ok(feed_prices)
+}
+
+/// The latest `limit` feed prices of every product, lookups of the batch are sent
+/// as a single `UNION ALL` query so every product reads its rows of the product_id index only
+fn latest_feed_prices(conn: &mut MysqlConnection, product_ids: &[i32], limit: i64) -> Result<Vec<FeedPrice>, Error> {
+    if product_ids.is_empty() {
+        return Ok(vec!());
+    }
+    let lookup = "(SELECT id, product_id, feed_price FROM price_history \
+        WHERE product_id = ? AND feed_price IS NOT NULL \
+        ORDER BY id DESC LIMIT ?)";
+    let mut query = diesel::sql_query(vec![lookup; product_ids.len()].join(" UNION ALL "))
+        .into_boxed();
+    for product_id in product_ids {
+        query = query
+            .bind::<Integer, _>(*product_id)
+            .bind::<Bigint, _>(limit);
+    }
+    Ok(query.load::<FeedPrice>(conn)?)
+}
+
+/// Moving average of the current feed price and the previous ones rounded to cents
+pub(crate) fn smoothed_price(feed_price: f32, previous: &[f32]) -> f32 {
+    let sum = previous.iter().fold(feed_price as f64, |sum, price| sum + *price as f64);
+    let average = sum / (previous.len() + 1) as f64;
+    ((average * 100.0).round() / 100.0) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smoothed_price() {
+        assert_eq!(smoothed_price(100.0, &[]), 100.0);
+        assert_eq!(smoothed_price(102.0, &[98.0, 100.0]), 100.0);
+        // rounded to cents
+        assert_eq!(smoothed_price(10.0, &[10.01, 10.01]), 10.01);
+        assert_eq!(smoothed_price(10.0, &[10.0, 10.01]), 10.0);
+    }
+}
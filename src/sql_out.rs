@@ -0,0 +1,413 @@
+use chrono::NaiveDateTime;
+
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::Opts;
+use crate::error::Error;
+use crate::models::{self, NOT_AVAILABLE};
+use crate::offer_hashes;
+use crate::store::{ProductStore, ProductUpdate};
+
+/// Writes statements of the run into a file instead of executing them (`--sql-out`).
+/// Values are inlined and all the statements are wrapped into a single transaction.
+pub(crate) struct SqlWriter {
+    out: BufWriter<File>,
+    statements: u32,
+}
+
+impl SqlWriter {
+    pub fn create(path: &Path) -> Result<SqlWriter, Error> {
+        let file = File::create(path)
+            .map_err(|e| Error::config_caused_by(format!("Cannot create SQL file: {}", path.display()), e))?;
+        let mut out = BufWriter::new(file);
+        writeln!(out, "START TRANSACTION;")?;
+        Ok(SqlWriter { out, statements: 0 })
+    }
+
+    pub fn statement(&mut self, sql: &str) -> Result<(), Error> {
+        writeln!(self.out, "{};", sql)?;
+        self.statements += 1;
+        Ok(())
+    }
+
+    /// Commits the transaction, returns a number of written statements
+    pub fn finish(&mut self) -> Result<u32, Error> {
+        writeln!(self.out, "COMMIT;")?;
+        self.out.flush()?;
+        Ok(self.statements)
+    }
+}
+
+/// Product store of `--sql-out`: products are read from the database while all the writes
+/// go into the SQL file. Features writing other tables are not supported by it,
+/// those only reading them use the connection as usual.
+pub(crate) struct SqlOutStore {
+    conn: MysqlConnection,
+    writer: SqlWriter,
+}
+
+impl SqlOutStore {
+    pub fn new(conn: MysqlConnection, path: &Path) -> Result<SqlOutStore, Error> {
+        Ok(SqlOutStore { conn, writer: SqlWriter::create(path)? })
+    }
+}
+
+impl ProductStore for SqlOutStore {
+    fn name(&self) -> &'static str {
+        "--sql-out"
+    }
+
+    fn find_products(&mut self, offer_ids: &[&str]) -> Result<Vec<models::Product>, Error> {
+        self.conn.find_products(offer_ids)
+    }
+
+    fn count_available(&mut self) -> Result<u64, Error> {
+        self.conn.count_available()
+    }
+
+    fn available_products(&mut self, after_id: i32, limit: usize) -> Result<Vec<(i32, Option<String>)>, Error> {
+        self.conn.available_products(after_id, limit)
+    }
+
+    /// Versions are checked by the statements themselves, so no update is reported as conflicted
+    fn apply_updates(&mut self, updates: &[ProductUpdate], _commit_size: usize) -> Result<HashSet<i32>, Error> {
+        for u in updates {
+            self.writer.statement(&update_product_sql(
+                u.product_id, &u.changes, !u.counter_only, u.expected_version.filter(|_| !u.counter_only)
+            ))?;
+        }
+        Ok(HashSet::new())
+    }
+
+    fn insert_products(&mut self, products: &[models::NewProduct]) -> Result<(), Error> {
+        self.writer.statement(&insert_products_sql(products))
+    }
+
+    fn insert_price_history(&mut self, rows: &[models::NewPriceHistory]) -> Result<(), Error> {
+        self.writer.statement(&insert_price_history_sql(rows))
+    }
+
+    /// Returns a number of the products that would be marked
+    fn mark_missing(
+        &mut self,
+        offer_ids: &[String],
+        file_ids: &[i8],
+        hysteresis: Option<u32>,
+        import_id: &str,
+        forget_hashes: bool,
+    ) -> Result<u32, Error> {
+        use crate::schema::products::dsl;
+
+        for sql in mark_missing_statements(offer_ids, file_ids, hysteresis, import_id, forget_hashes) {
+            self.writer.statement(&sql)?;
+        }
+        match hysteresis {
+            Some(hysteresis) => {
+                let marked = dsl::products.select(dsl::id)
+                    .filter(dsl::hub_stock_id.eq_any(offer_ids))
+                    .filter(dsl::file_id.eq_any(file_ids))
+                    .filter((dsl::unavailable_runs + 1).ge(hysteresis as i32))
+                    .count()
+                    .get_result::<i64>(&mut self.conn)?;
+                Ok(marked as u32)
+            }
+            None => Ok(offer_ids.len() as u32),
+        }
+    }
+
+    fn mark_written(&mut self, import_id: &str) -> Result<(), Error> {
+        self.writer.statement(&offer_hashes::mark_written_sql(&import_id.to_sql()))
+    }
+
+    fn set_processed_date(&mut self, date_processed: &NaiveDateTime) -> Result<(), Error> {
+        self.writer.statement(&format!(
+            "UPDATE timestamps SET event_date = {} WHERE event = 'hub_xml_update'", date_processed.to_sql()
+        ))
+    }
+
+    fn finish(&mut self) -> Result<u32, Error> {
+        self.writer.finish()
+    }
+
+    fn read_connection(&mut self) -> Option<&mut MysqlConnection> {
+        Some(&mut self.conn)
+    }
+}
+
+/// Consumers of applied changes that would see the changes only written into the SQL file.
+/// Features writing other tables are rejected by [`SqlOutStore`] itself when they are used.
+pub(crate) fn check_opts(opts: &Opts) -> Result<(), Error> {
+    let incompatible = [
+        ("--publish", opts.publishes()),
+        ("--search-url", opts.search_url.is_some()),
+    ];
+    for (option, is_set) in incompatible.iter() {
+        if *is_set {
+            return Err(Error::config(format!("{} cannot be used with --sql-out", option)));
+        }
+    }
+    Ok(())
+}
+
+/// Value inlined into SQL statements
+pub(crate) trait SqlValue {
+    fn to_sql(&self) -> String;
+}
+
+impl SqlValue for i8 {
+    fn to_sql(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl SqlValue for i32 {
+    fn to_sql(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl SqlValue for i64 {
+    fn to_sql(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl SqlValue for f32 {
+    fn to_sql(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl SqlValue for str {
+    fn to_sql(&self) -> String {
+        let mut quoted = String::with_capacity(self.len() + 2);
+        quoted.push('\'');
+        for c in self.chars() {
+            match c {
+                '\'' => quoted.push_str("\\'"),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\0' => quoted.push_str("\\0"),
+                '\x1a' => quoted.push_str("\\Z"),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('\'');
+        quoted
+    }
+}
+
+impl SqlValue for String {
+    fn to_sql(&self) -> String {
+        self.as_str().to_sql()
+    }
+}
+
+impl SqlValue for NaiveDateTime {
+    fn to_sql(&self) -> String {
+        self.format("%Y-%m-%d %H:%M:%S").to_string().to_sql()
+    }
+}
+
+impl<T: SqlValue + ?Sized> SqlValue for &T {
+    fn to_sql(&self) -> String {
+        (**self).to_sql()
+    }
+}
+
+impl<T: SqlValue> SqlValue for Option<T> {
+    fn to_sql(&self) -> String {
+        match self {
+            Some(v) => v.to_sql(),
+            None => "NULL".to_string(),
+        }
+    }
+}
+
+/// Comma separated list of values, e.g. for `IN (...)`
+pub(crate) fn sql_list<T: SqlValue>(values: &[T]) -> String {
+    values.iter()
+        .map(SqlValue::to_sql)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Statements marking the missing products. With `forget_hashes` hashes of their offers are forgotten
+/// so the offers are synced again when they come back.
+fn mark_missing_statements(
+    offer_ids: &[String], file_ids: &[i8], hysteresis: Option<u32>, import_id: &str, forget_hashes: bool,
+) -> Vec<String> {
+    let condition = format!("hub_stock_id IN ({}) AND file_id IN ({})", sql_list(offer_ids), sql_list(file_ids));
+    let mut statements = vec!();
+    if forget_hashes {
+        statements.push(format!("DELETE FROM product_hashes WHERE hub_stock_id IN ({})", sql_list(offer_ids)));
+    }
+    match hysteresis {
+        Some(hysteresis) => {
+            statements.push(format!(
+                "UPDATE products SET unavailable_runs = unavailable_runs + 1, last_import_id = {} WHERE {}",
+                import_id.to_sql(), condition
+            ));
+            statements.push(format!(
+                "UPDATE products SET available = {}, last_import_id = {} WHERE {} AND unavailable_runs >= {}",
+                NOT_AVAILABLE, import_id.to_sql(), condition, hysteresis
+            ));
+        }
+        None => {
+            statements.push(format!(
+                "UPDATE products SET available = {}, last_import_id = {} WHERE {}",
+                NOT_AVAILABLE, import_id.to_sql(), condition
+            ));
+        }
+    }
+    statements
+}
+
+pub(crate) fn insert_products_sql(products: &[models::NewProduct]) -> String {
+    let rows = products.iter()
+        .map(|p| format!(
+            "({})",
+            [
+                p.offer_id.to_sql(), p.hub_stock_id.to_sql(), p.categoryId.to_sql(), p.name.to_sql(),
+                p.price.to_sql(), p.oldprice.to_sql(), p.currencyId.to_sql(), p.available.to_sql(),
+                p.description.to_sql(), p.file_id.to_sql(), p.on_sale.to_sql(), p.discount_percent.to_sql(),
+                p.tags.to_sql(), p.slug.to_sql(), p.GTIN.to_sql(), p.price_from.to_sql(), p.vat.to_sql(),
+                p.status.to_sql(), p.source_supplier.to_sql(), p.category_path.to_sql(),
+                p.adult.to_sql(), p.age.to_sql(), p.last_import_id.to_sql(), p.sku.to_sql(),
+                p.low_stock.to_sql(),
+            ].join(", ")
+        ))
+        .collect::<Vec<_>>();
+    format!(
+        "INSERT INTO products (offer_id, hub_stock_id, categoryId, name, price, oldprice, currencyId, available, \
+         description, file_id, on_sale, discount_percent, tags, slug, GTIN, price_from, vat, status, source_supplier, \
+         category_path, adult, age, last_import_id, sku, low_stock) \
+         VALUES {}",
+        rows.join(",\n  ")
+    )
+}
+
+pub(crate) fn insert_price_history_sql(rows: &[models::NewPriceHistory]) -> String {
+    let rows = rows.iter()
+        .map(|r| format!(
+            "({}, {}, {}, {}, {}, {}, {})",
+            r.product_id.to_sql(), r.old_price.to_sql(), r.new_price.to_sql(), r.feed_price.to_sql(),
+            r.currencyId.to_sql(), r.run_id.to_sql(), r.created_at.to_sql()
+        ))
+        .collect::<Vec<_>>();
+    format!(
+        "INSERT INTO price_history (product_id, old_price, new_price, feed_price, currencyId, run_id, created_at) \
+         VALUES {}",
+        rows.join(",\n  ")
+    )
+}
+
+/// `UPDATE` of a single product, `expected_version` is checked with optimistic locking
+pub(crate) fn update_product_sql(
+    product_id: i32, changes: &models::ModProduct, bump_version: bool, expected_version: Option<i32>,
+) -> String {
+    let mut assignments = vec!();
+    if let Some(available) = changes.available {
+        assignments.push(format!("available = {}", available.to_sql()));
+    }
+    if let Some(price) = changes.price {
+        assignments.push(format!("price = {}", price.to_sql()));
+    }
+    if let Some(oldprice) = changes.oldprice {
+        assignments.push(format!("oldprice = {}", oldprice.to_sql()));
+    }
+    if let Some(currency_id) = changes.currencyId {
+        assignments.push(format!("currencyId = {}", currency_id.to_sql()));
+    }
+    if let Some(renew_date) = changes.renew_date {
+        assignments.push(format!("renew_date = {}", renew_date.to_sql()));
+    }
+    if let Some(on_sale) = changes.on_sale {
+        assignments.push(format!("on_sale = {}", on_sale.to_sql()));
+    }
+    if let Some(discount_percent) = changes.discount_percent {
+        assignments.push(format!("discount_percent = {}", discount_percent.to_sql()));
+    }
+    if let Some(unavailable_runs) = changes.unavailable_runs {
+        assignments.push(format!("unavailable_runs = {}", unavailable_runs.to_sql()));
+    }
+    if let Some(to_renew) = changes.to_renew {
+        assignments.push(format!("to_renew = {}", to_renew.to_sql()));
+    }
+    if let Some(price_from) = changes.price_from {
+        assignments.push(format!("price_from = {}", price_from.to_sql()));
+    }
+    if let Some(vat) = changes.vat {
+        assignments.push(format!("vat = {}", vat.to_sql()));
+    }
+    if let Some(category_path) = changes.category_path {
+        assignments.push(format!("category_path = {}", category_path.to_sql()));
+    }
+    if let Some(adult) = changes.adult {
+        assignments.push(format!("adult = {}", adult.to_sql()));
+    }
+    if let Some(age) = changes.age {
+        assignments.push(format!("age = {}", age.to_sql()));
+    }
+    if let Some(low_stock) = changes.low_stock {
+        assignments.push(format!("low_stock = {}", low_stock.to_sql()));
+    }
+    if let Some(source_supplier) = changes.source_supplier {
+        assignments.push(format!("source_supplier = {}", source_supplier.to_sql()));
+    }
+    if let Some(last_import_id) = changes.last_import_id {
+        assignments.push(format!("last_import_id = {}", last_import_id.to_sql()));
+    }
+    if bump_version {
+        assignments.push("version = version + 1".to_string());
+    }
+    let mut sql = format!("UPDATE products SET {} WHERE id = {}", assignments.join(", "), product_id);
+    if let Some(version) = expected_version {
+        sql.push_str(&format!(" AND version = {}", version));
+    }
+    sql
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_values() {
+        assert_eq!("O'Reilly".to_sql(), r"'O\'Reilly'");
+        assert_eq!(r"C:\feeds".to_sql(), r"'C:\\feeds'");
+        assert_eq!("line\nbreak\r\0\x1a".to_sql(), r"'line\nbreak\r\0\Z'");
+        assert_eq!("Тест".to_sql(), "'Тест'");
+        assert_eq!(None::<i32>.to_sql(), "NULL");
+        assert_eq!(Some(12.5f32).to_sql(), "12.5");
+        let date = NaiveDateTime::parse_from_str("2026-10-16 12:30:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(date.to_sql(), "'2026-10-16 12:30:00'");
+        assert_eq!(sql_list(&["a", "b'c"]), r"'a', 'b\'c'");
+    }
+
+    #[test]
+    fn test_mark_missing_statements() {
+        let offer_ids = vec!("1".to_string(), "2".to_string());
+        assert_eq!(
+            mark_missing_statements(&offer_ids, &[3], None, "import-1", true),
+            vec!(
+                "DELETE FROM product_hashes WHERE hub_stock_id IN ('1', '2')".to_string(),
+                "UPDATE products SET available = 0, last_import_id = 'import-1' \
+                 WHERE hub_stock_id IN ('1', '2') AND file_id IN (3)".to_string(),
+            )
+        );
+        let statements = mark_missing_statements(&offer_ids, &[3], Some(2), "import-1", true);
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements[0], "DELETE FROM product_hashes WHERE hub_stock_id IN ('1', '2')");
+        // without tracked hashes the table is not touched
+        let statements = mark_missing_statements(&offer_ids, &[3], Some(2), "import-1", false);
+        assert!(statements.iter().all(|sql| !sql.contains("product_hashes")));
+    }
+}
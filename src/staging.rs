@@ -0,0 +1,499 @@
+use chrono::NaiveDateTime;
+
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{Bigint, Nullable, Timestamp, Tinyint, Varchar};
+
+use log::info;
+
+use std::collections::HashSet;
+use std::time::Instant;
+
+use crate::{Opts, ProcessedStat};
+use crate::error::Error;
+use crate::models::{self, AVAILABLE, NOT_AVAILABLE};
+use crate::process::mark_unavailable;
+use crate::schema::products_staging;
+
+/// Columns of the products filled from offers
+const OFFER_COLUMNS: &str = "offer_id, hub_stock_id, categoryId, name, price, oldprice, currencyId, available, \
+    description, file_id, on_sale, discount_percent, tags, slug, GTIN, price_from, vat, status, source_supplier, \
+    category_path, adult, age";
+
+/// Columns referencing products by id and by hub_stock_id, rows of the products
+/// deleted by `--full-reload` are deleted with them. Tables of the features whose migrations
+/// were not applied are skipped.
+const PRODUCT_ID_REFERENCES: &[(&str, &str)] = &[
+    ("price_history", "product_id"),
+    ("product_relations", "product_id"),
+    ("product_relations", "related_product_id"),
+    ("product_conflicts", "product_id"),
+    ("possible_duplicates", "product_id"),
+    ("possible_duplicates", "duplicate_product_id"),
+];
+const HUB_STOCK_ID_REFERENCES: &[(&str, &str)] = &[
+    ("product_stocks", "hub_stock_id"),
+    ("product_hashes", "hub_stock_id"),
+    ("product_channels", "hub_stock_id"),
+    ("product_keywords", "hub_stock_id"),
+    ("product_variants", "product_hub_stock_id"),
+];
+
+#[derive(QueryableByName)]
+struct Count {
+    #[diesel(sql_type = Bigint)]
+    count: i64,
+}
+
+#[derive(QueryableByName)]
+struct TableName {
+    #[diesel(sql_type = Varchar)]
+    table_name: String,
+}
+
+#[derive(QueryableByName)]
+struct OfferFile {
+    #[diesel(sql_type = Varchar)]
+    hub_stock_id: String,
+    #[diesel(sql_type = Nullable<Tinyint>)]
+    file_id: Option<i8>,
+}
+
+/// Offer ids with file ids of products to be counted by `mark_missing`
+#[derive(Default)]
+pub(crate) struct UnavailableOffers {
+    pub offer_ids: Vec<String>,
+    pub file_ids: Vec<i8>,
+}
+
+impl UnavailableOffers {
+    fn from_rows(rows: Vec<OfferFile>) -> Self {
+        let mut file_ids = rows.iter()
+            .filter_map(|row| row.file_id)
+            .collect::<Vec<_>>();
+        file_ids.sort_unstable();
+        file_ids.dedup();
+        UnavailableOffers {
+            offer_ids: rows.into_iter().map(|row| row.hub_stock_id).collect(),
+            file_ids,
+        }
+    }
+
+    /// Increments `unavailable_runs` of the products and marks the ones reaching the hysteresis
+    /// as unavailable, returns a number of marked products
+    pub fn mark(&self, conn: &mut MysqlConnection, opts: &Opts, import_id: &str) -> Result<u32, Error> {
+        if self.offer_ids.is_empty() {
+            return Ok(0);
+        }
+        mark_unavailable(conn, &self.offer_ids, &self.file_ids, opts, import_id)
+    }
+}
+
+/// Options that need products to be processed one by one and cannot be used with `--staging`.
+/// The staged products are applied with joined statements that neither look at single products
+/// nor collect their changes.
+pub(crate) fn check_opts(opts: &Opts) -> Result<(), Error> {
+    const CHANGES_NOT_COLLECTED: &str = "changes of single products are not collected";
+    const ONE_BY_ONE: &str = "products are not checked one by one";
+    const NOT_STAGED: &str = "the column is not staged";
+    let incompatible = [
+        ("--price-history", opts.price_history, CHANGES_NOT_COLLECTED),
+        ("--optimistic-locking", opts.optimistic_locking, "versions are not compared by the joined update"),
+        ("--skip-newer-than-feed", opts.skip_newer_than_feed, ONE_BY_ONE),
+        ("--skip-unchanged", opts.skip_unchanged, "unchanged products are never updated by the joined update"),
+        ("--resolve", !opts.resolve.is_empty(), ONE_BY_ONE),
+        ("--respect-reservations", opts.respect_reservations, ONE_BY_ONE),
+        ("--reactivated-out", opts.reactivated_out.is_some(), CHANGES_NOT_COLLECTED),
+        ("--changes-csv", opts.changes_csv.is_some(), CHANGES_NOT_COLLECTED),
+        ("--low-stock-threshold", opts.low_stock_threshold.is_some(), NOT_STAGED),
+        ("--discontinued-categories", opts.discontinued_categories, ONE_BY_ONE),
+        ("--discontinued-category", !opts.discontinued_category.is_empty(), ONE_BY_ONE),
+        ("--clear-missing-oldprice", opts.clear_missing_oldprice, ONE_BY_ONE),
+        ("--currency-rate", !opts.currency_rates.is_empty(), "prices are compared without conversion"),
+        ("--generate-slugs", opts.generate_slugs, ONE_BY_ONE),
+        ("--sku-template", opts.sku_template.is_some(), NOT_STAGED),
+        ("--find-duplicates", opts.find_duplicates, ONE_BY_ONE),
+        ("--publish", opts.publishes(), CHANGES_NOT_COLLECTED),
+        ("--search-url", opts.search_url.is_some(), CHANGES_NOT_COLLECTED),
+        ("--commit-every", opts.commit_every.is_some(), "staged products are applied in a single transaction"),
+    ];
+    for (option, is_set, reason) in incompatible.iter() {
+        if *is_set {
+            return Err(Error::config(format!("{} cannot be used with --staging: {}", option, reason)));
+        }
+    }
+    Ok(())
+}
+
+/// Creates an empty staging table. The table is temporary: it is seen only by the connection
+/// of the run, so concurrent runs do not share it and it disappears when the run fails.
+pub(crate) fn create_staging_table(conn: &mut MysqlConnection) -> Result<(), Error> {
+    drop_staging_table(conn)?;
+    diesel::sql_query("CREATE TEMPORARY TABLE products_staging LIKE products")
+        .execute(conn)?;
+    Ok(())
+}
+
+pub(crate) fn drop_staging_table(conn: &mut MysqlConnection) -> Result<(), Error> {
+    diesel::sql_query("DROP TEMPORARY TABLE IF EXISTS products_staging")
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Loads parsed products into the staging table, the last offer wins for duplicated ids
+pub(crate) fn load_bucket(conn: &mut MysqlConnection, products: &[models::NewProduct]) -> Result<(), Error> {
+    let rows = products.iter()
+        .map(models::NewStagedProduct::from)
+        .collect::<Vec<_>>();
+    diesel::replace_into(products_staging::table)
+        .values(&rows)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Applies the difference between the staging table and products in a single transaction
+/// so the catalog is never seen half-updated. Nothing is applied when some offers
+/// could not be staged. With `--full-reload` products of the suppliers are replaced
+/// with the staged ones: unlocked columns are overwritten and products missing from the feed are deleted.
+pub(crate) fn apply_staging(
+    conn: &mut MysqlConnection,
+    opts: &Opts,
+    file_ids: &[i8],
+    date_processed: &NaiveDateTime,
+    stat: &mut ProcessedStat,
+) -> Result<(), Error> {
+    if stat.failed_offers > 0 {
+        return Err(Error::external(
+            "staging",
+            format!("{} offers were not staged, products are left untouched", stat.failed_offers)
+        ));
+    }
+
+    let money_columns = [
+        ("price", "price"),
+        ("price_from", "price"),
+        ("oldprice", "oldprice"),
+        ("currencyId", "currencyId"),
+    ];
+    let vat_columns = [("vat", "vat")];
+    let discount_columns = [("on_sale", "discount"), ("discount_percent", "discount")];
+    let mut price_columns = [&money_columns[..], &vat_columns[..]].concat();
+    if opts.min_discount.is_some() {
+        price_columns.extend_from_slice(&discount_columns);
+    }
+    let available_columns = vec![("available", "available")];
+
+    let unavailable = match opts.available_hysteresis {
+        Some(hysteresis) if opts.update_available || opts.full_reload => {
+            hold_unavailable(conn, "products_staging", hysteresis, &mut stat.postponed_unavailable)?
+        }
+        _ => UnavailableOffers::default(),
+    };
+
+    stat.updated_price = count_joined(conn, &differs(&money_columns, false))?;
+    stat.updated_vat = count_joined(conn, &differs(&vat_columns, false))?;
+    if opts.min_discount.is_some() {
+        stat.updated_discount = count_joined(conn, &differs(&discount_columns, false))?;
+    }
+    stat.updated_available = count_joined(conn, &differs(&available_columns, false))?;
+    stat.inserted_products = count(
+        conn,
+        "SELECT COUNT(*) AS count FROM products_staging s \
+         LEFT JOIN products p ON p.hub_stock_id = s.hub_stock_id WHERE p.id IS NULL"
+    )?;
+    if opts.new_as_draft && (opts.insert_new || opts.full_reload) {
+        stat.drafted_products = stat.inserted_products;
+    }
+
+    let file_ids = file_ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut update_columns = vec!();
+    if opts.full_reload {
+        update_columns.extend(
+            OFFER_COLUMNS.split(", ")
+                .filter(|&column| column != "offer_id" && column != "hub_stock_id")
+                .map(|column| (column, lock_name(column)))
+        );
+    }
+    if opts.update_price && !opts.full_reload {
+        update_columns.extend_from_slice(&price_columns);
+    }
+    if opts.update_available && !opts.full_reload {
+        update_columns.extend_from_slice(&available_columns);
+    }
+    if opts.category_paths && !opts.full_reload {
+        update_columns.push(("category_path", ""));
+    }
+    if !opts.full_reload {
+        update_columns.extend_from_slice(&[("adult", "adult"), ("age", "adult")]);
+    }
+
+    let start_applying_at = Instant::now();
+    conn.transaction(|conn| {
+        if opts.full_reload && !file_ids.is_empty() {
+            stat.deleted_products = delete_missing(conn, opts, &file_ids)?;
+        }
+        if !update_columns.is_empty() {
+            let assignments = update_columns.iter()
+                .map(|(column, lock)| if lock.is_empty() {
+                    format!("p.{column} = s.{column}", column = column)
+                } else {
+                    format!("p.{column} = IF({locked}, p.{column}, s.{column})", column = column, locked = locked(lock))
+                })
+                .collect::<Vec<_>>();
+            let updated = diesel::sql_query(format!(
+                "UPDATE products p JOIN products_staging s ON s.hub_stock_id = p.hub_stock_id \
+                 SET {}, p.renew_date = ?, p.to_renew = 1, p.version = p.version + 1, p.last_import_id = ? \
+                 WHERE {}",
+                assignments.join(", "), differs(&update_columns, true)
+            ))
+                .bind::<Timestamp, _>(date_processed)
+                .bind::<Varchar, _>(&stat.import_id)
+                .execute(conn)?;
+            if opts.full_reload {
+                stat.reloaded_products = updated as u32;
+            }
+        }
+        if opts.insert_new || opts.full_reload {
+            let staged_columns = OFFER_COLUMNS.split(", ")
+                .map(|column| format!("s.{}", column))
+                .collect::<Vec<_>>();
+            diesel::sql_query(format!(
+                "INSERT INTO products ({}, last_import_id) SELECT {}, ? FROM products_staging s \
+                 LEFT JOIN products p ON p.hub_stock_id = s.hub_stock_id WHERE p.id IS NULL",
+                OFFER_COLUMNS, staged_columns.join(", ")
+            ))
+                .bind::<Varchar, _>(&stat.import_id)
+                .execute(conn)?;
+        }
+        if opts.update_available || opts.full_reload {
+            unavailable.mark(conn, opts, &stat.import_id)?;
+            reset_unavailable_runs(conn, "products_staging")?;
+        }
+        if opts.mark_missing_unavailable && !file_ids.is_empty() {
+            stat.marked_as_unavailable = missing_offers(conn, "products_staging", &file_ids)?
+                .mark(conn, opts, &stat.import_id)?;
+        }
+        Ok::<_, Error>(())
+    })?;
+    stat.staging_apply_duration = start_applying_at.elapsed();
+    info!("Applied staged products in {:?}", stat.staging_apply_duration);
+
+    drop_staging_table(conn)
+}
+
+/// Deletes products of the files missing from the staging table together with the rows referencing them.
+/// Fails when more products than `--max-deleted` or `--max-deleted-ratio` of the files' products
+/// would be deleted, e.g. when a truncated feed was staged.
+fn delete_missing(conn: &mut MysqlConnection, opts: &Opts, file_ids: &str) -> Result<u32, Error> {
+    let missing = format!(
+        "LEFT JOIN products_staging s ON s.hub_stock_id = p.hub_stock_id WHERE s.id IS NULL AND p.file_id IN ({})",
+        file_ids
+    );
+    let deleted = count(conn, &format!("SELECT COUNT(*) AS count FROM products p {}", missing))?;
+    if deleted == 0 {
+        return Ok(0);
+    }
+    let total = count(conn, &format!("SELECT COUNT(*) AS count FROM products WHERE file_id IN ({})", file_ids))?;
+    check_deleted_limit(deleted, total, opts.max_deleted, opts.max_deleted_ratio)?;
+
+    let references = PRODUCT_ID_REFERENCES.iter()
+        .map(|reference| ("id", reference))
+        .chain(HUB_STOCK_ID_REFERENCES.iter().map(|reference| ("hub_stock_id", reference)))
+        .collect::<Vec<_>>();
+    let tables = existing_tables(conn, references.iter().map(|(_, (table, _))| *table))?;
+    for (key, (table, column)) in references {
+        if !tables.contains(*table) {
+            continue;
+        }
+        diesel::sql_query(format!(
+            "DELETE r FROM {} r JOIN products p ON p.{} = r.{} {}",
+            table, key, column, missing
+        ))
+            .execute(conn)?;
+    }
+    diesel::sql_query(format!("DELETE p FROM products p {}", missing))
+        .execute(conn)?;
+    Ok(deleted)
+}
+
+/// Names of the given tables that exist in the current database
+fn existing_tables<'a>(
+    conn: &mut MysqlConnection, tables: impl Iterator<Item = &'a str>,
+) -> Result<HashSet<String>, Error> {
+    let names = tables
+        .map(|table| format!("'{}'", table))
+        .collect::<Vec<_>>();
+    Ok(
+        diesel::sql_query(format!(
+            "SELECT TABLE_NAME AS table_name FROM information_schema.TABLES \
+             WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME IN ({})",
+            names.join(", ")
+        ))
+            .load::<TableName>(conn)?
+            .into_iter()
+            .map(|t| t.table_name)
+            .collect()
+    )
+}
+
+fn check_deleted_limit(deleted: u32, total: u32, max_deleted: Option<u32>, max_ratio: f32) -> Result<(), Error> {
+    let exceeded = match max_deleted {
+        Some(max_deleted) if deleted > max_deleted => Some(format!("--max-deleted {}", max_deleted)),
+        _ if total > 0 && deleted as f32 / total as f32 > max_ratio => {
+            Some(format!("--max-deleted-ratio {}", max_ratio))
+        }
+        _ => None,
+    };
+    match exceeded {
+        Some(limit) => Err(Error::external(
+            "staging",
+            format!("Full reload would delete {} of {} products exceeding {}, nothing is applied", deleted, total, limit)
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Lock name of the column in `locked_fields`, columns of the same field share the lock
+fn lock_name(column: &str) -> &str {
+    match column {
+        "price_from" => "price",
+        "on_sale" | "discount_percent" => "discount",
+        "age" => "adult",
+        column => column,
+    }
+}
+
+/// Keeps the current availability in the joined `table` for products becoming unavailable
+/// until they are unavailable in `hysteresis` runs in a row. Returns all the products becoming
+/// unavailable, their counters must be incremented after the table is applied.
+pub(crate) fn hold_unavailable(
+    conn: &mut MysqlConnection, table: &str, hysteresis: u32, postponed: &mut u32,
+) -> Result<UnavailableOffers, Error> {
+    let condition = format!(
+        "s.available = {} AND NOT (p.available <=> s.available) AND NOT {}",
+        NOT_AVAILABLE, locked("available")
+    );
+    let rows = diesel::sql_query(format!(
+        "SELECT p.hub_stock_id, p.file_id FROM products p JOIN {} s ON s.hub_stock_id = p.hub_stock_id WHERE {}",
+        table, condition
+    ))
+        .load::<OfferFile>(conn)?;
+    *postponed += diesel::sql_query(format!(
+        "UPDATE {} s JOIN products p ON p.hub_stock_id = s.hub_stock_id SET s.available = p.available \
+         WHERE {} AND p.unavailable_runs + 1 < {}",
+        table, condition, hysteresis
+    ))
+        .execute(conn)? as u32;
+    Ok(UnavailableOffers::from_rows(rows))
+}
+
+/// Products of the files available in the database but missing from the joined `table`
+pub(crate) fn missing_offers(
+    conn: &mut MysqlConnection, table: &str, file_ids: &str,
+) -> Result<UnavailableOffers, Error> {
+    let rows = diesel::sql_query(format!(
+        "SELECT p.hub_stock_id, p.file_id FROM products p LEFT JOIN {} s ON s.hub_stock_id = p.hub_stock_id \
+         WHERE s.hub_stock_id IS NULL AND p.hub_stock_id IS NOT NULL AND p.available = {} AND p.file_id IN ({})",
+        table, AVAILABLE, file_ids
+    ))
+        .load::<OfferFile>(conn)?;
+    Ok(UnavailableOffers::from_rows(rows))
+}
+
+/// Resets `unavailable_runs` of products available in the joined `table`
+pub(crate) fn reset_unavailable_runs(conn: &mut MysqlConnection, table: &str) -> Result<(), Error> {
+    diesel::sql_query(format!(
+        "UPDATE products p JOIN {} s ON s.hub_stock_id = p.hub_stock_id SET p.unavailable_runs = 0 \
+         WHERE s.available = {} AND p.unavailable_runs > 0",
+        table, AVAILABLE
+    ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Condition matching products where any of the columns differs from the staged one,
+/// locked columns are skipped if `skip_locked` is set. Columns without a lock name cannot be locked.
+fn differs(columns: &[(&str, &str)], skip_locked: bool) -> String {
+    let conditions = columns.iter()
+        .map(|(column, lock)| {
+            let changed = format!("NOT (p.{column} <=> s.{column})", column = column);
+            if skip_locked && !lock.is_empty() {
+                format!("(NOT {} AND {})", locked(lock), changed)
+            } else {
+                changed
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("({})", conditions.join(" OR "))
+}
+
+/// Whether the field is listed in `locked_fields`, e.g. `["price", "vat"]`
+pub(crate) fn locked(field: &str) -> String {
+    format!(
+        "FIND_IN_SET('{}', REPLACE(REPLACE(REPLACE(REPLACE(COALESCE(p.locked_fields, ''), \
+         '[', ''), ']', ''), '\"', ''), ' ', '')) > 0",
+        field
+    )
+}
+
+fn count_joined(conn: &mut MysqlConnection, condition: &str) -> Result<u32, Error> {
+    count(conn, &format!(
+        "SELECT COUNT(*) AS count FROM products p \
+         JOIN products_staging s ON s.hub_stock_id = p.hub_stock_id WHERE {}",
+        condition
+    ))
+}
+
+fn count(conn: &mut MysqlConnection, query: &str) -> Result<u32, Error> {
+    let row = diesel::sql_query(query)
+        .get_result::<Count>(conn)?;
+    Ok(row.count as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_deleted_limit() {
+        assert!(check_deleted_limit(20, 100, None, 0.2).is_ok());
+        assert_eq!(
+            check_deleted_limit(21, 100, None, 0.2).unwrap_err().to_string(),
+            "staging error: Full reload would delete 21 of 100 products exceeding --max-deleted-ratio 0.2, \
+             nothing is applied"
+        );
+        assert_eq!(
+            check_deleted_limit(11, 100, Some(10), 0.2).unwrap_err().to_string(),
+            "staging error: Full reload would delete 11 of 100 products exceeding --max-deleted 10, \
+             nothing is applied"
+        );
+        assert!(check_deleted_limit(10, 100, Some(10), 1.0).is_ok());
+    }
+
+    #[test]
+    fn test_differs() {
+        let columns = [("price", "price"), ("price_from", "price"), ("category_path", "")];
+        assert_eq!(
+            differs(&columns, false),
+            "(NOT (p.price <=> s.price) OR NOT (p.price_from <=> s.price_from) \
+             OR NOT (p.category_path <=> s.category_path))"
+        );
+        assert_eq!(
+            differs(&columns[1..], true),
+            format!(
+                "((NOT {} AND NOT (p.price_from <=> s.price_from)) OR NOT (p.category_path <=> s.category_path))",
+                locked("price")
+            )
+        );
+    }
+
+    #[test]
+    fn test_lock_name() {
+        assert_eq!(lock_name("price_from"), "price");
+        assert_eq!(lock_name("discount_percent"), "discount");
+        assert_eq!(lock_name("name"), "name");
+    }
+}
@@ -0,0 +1,115 @@
+use diesel::mysql::{Mysql, MysqlConnection};
+use diesel::prelude::*;
+use diesel::sql_types::Varchar;
+
+use log::warn;
+
+use std::collections::HashMap;
+
+use crate::error::Error;
+use crate::models::{NewProduct, NewProductStock, Product};
+use crate::schema::{product_stocks, reservations};
+
+/// Saves stocks of every supplier of the products. Stocks are keyed by `hub_stock_id`
+/// that is shared by the offers of all the suppliers when they are merged.
+/// Offers outside of a named `<shop>` have no supplier, so their stocks are skipped.
+pub(crate) fn save_stocks(
+    conn: &mut MysqlConnection, products: &[NewProduct], import_id: &str,
+) -> Result<(), Error> {
+    let without_supplier = products.iter()
+        .filter(|p| p.stocks.iter().any(|(supplier, _)| supplier.is_empty()))
+        .count();
+    if without_supplier > 0 {
+        warn!("Stocks of {} offers without a <shop> name are not saved", without_supplier);
+    }
+    let rows = products.iter()
+        .flat_map(|p| {
+            p.stocks.iter()
+                .filter(|(supplier, _)| !supplier.is_empty())
+                .map(move |(supplier, quantity)| NewProductStock {
+                    hub_stock_id: p.hub_stock_id.clone(),
+                    supplier: supplier.clone(),
+                    quantity: *quantity,
+                    run_id: import_id.to_string(),
+                })
+        })
+        .collect::<Vec<_>>();
+    if !rows.is_empty() {
+        diesel::replace_into(product_stocks::table)
+            .values(&rows)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Quantities reserved by pending orders per product id
+pub(crate) fn load_reservations(
+    conn: &mut MysqlConnection, product_ids: &[i32],
+) -> Result<HashMap<i32, i32>, Error> {
+    let rows = reservations::table
+        .select((reservations::product_id, reservations::quantity))
+        .filter(reservations::product_id.eq_any(product_ids))
+        .load::<(i32, i32)>(conn)?;
+    let mut reserved = HashMap::new();
+    for (product_id, quantity) in rows {
+        *reserved.entry(product_id).or_insert(0) += quantity;
+    }
+    Ok(reserved)
+}
+
+/// Whether pending orders reserved all the stock of the product: the quantity
+/// of the offer or the aggregated quantity of the product when the offer has none
+pub(crate) fn is_fully_reserved(product: &NewProduct, found_product: &Product, reserved: i32) -> bool {
+    let quantity = if product.stocks.is_empty() {
+        match found_product.quantity_in_stock {
+            Some(quantity) => quantity,
+            None => return false,
+        }
+    } else {
+        product.stocks.iter().map(|(_, quantity)| quantity).sum()
+    };
+    reserved > 0 && reserved >= quantity
+}
+
+/// Sums stocks of all suppliers into `products.quantity_in_stock` for the products whose stocks
+/// were changed by the run. Stocks of the given suppliers that were not updated by the run
+/// are outdated and removed. Returns a number of updated products.
+pub(crate) fn aggregate_stocks(
+    conn: &mut MysqlConnection, suppliers: &[String], import_id: &str,
+) -> Result<u32, Error> {
+    // outdated stocks take part in the aggregation as zero ones
+    if !suppliers.is_empty() {
+        let mut reset_outdated = diesel::sql_query(format!(
+            "UPDATE product_stocks SET quantity = 0, run_id = ? WHERE run_id <> ? AND supplier IN ({})",
+            vec!["?"; suppliers.len()].join(", ")
+        ))
+            .into_boxed::<Mysql>()
+            .bind::<Varchar, _>(import_id)
+            .bind::<Varchar, _>(import_id);
+        for supplier in suppliers {
+            reset_outdated = reset_outdated.bind::<Varchar, _>(supplier);
+        }
+        reset_outdated.execute(conn)?;
+    }
+
+    let updated_products = diesel::sql_query(
+        "UPDATE products p \
+         JOIN (\
+           SELECT hub_stock_id, SUM(quantity) AS quantity FROM product_stocks \
+           WHERE hub_stock_id IN (SELECT hub_stock_id FROM product_stocks WHERE run_id = ?) \
+           GROUP BY hub_stock_id\
+         ) s ON s.hub_stock_id = p.hub_stock_id \
+         SET p.quantity_in_stock = s.quantity"
+    )
+        .bind::<Varchar, _>(import_id)
+        .execute(conn)?;
+
+    diesel::delete(
+        product_stocks::table
+            .filter(product_stocks::run_id.eq(import_id))
+            .filter(product_stocks::quantity.eq(0))
+    )
+        .execute(conn)?;
+
+    Ok(updated_products as u32)
+}
@@ -0,0 +1,270 @@
+use chrono::NaiveDateTime;
+
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{Timestamp, Varchar};
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::models::{self, AVAILABLE, NOT_AVAILABLE};
+use crate::offer_hashes;
+use crate::schema::{price_history, products};
+
+/// Storage selected with `--target`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Target {
+    Mysql,
+    /// REST API of the PIM, see `pim::PimStore`
+    Pim,
+}
+
+impl FromStr for Target {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Target, Error> {
+        match s {
+            "mysql" => Ok(Target::Mysql),
+            "pim" => Ok(Target::Pim),
+            _ => Err(Error::config(format!("Unknown target: {}", s))),
+        }
+    }
+}
+
+/// Update of an existing product planned while comparing it with the parsed offer
+pub(crate) struct ProductUpdate<'a> {
+    pub product_id: i32,
+    /// Version the product had when it was loaded, checked with optimistic locking
+    pub expected_version: Option<i32>,
+    /// Only `unavailable_runs` counter is changed so the product must not be renewed
+    pub counter_only: bool,
+    pub changes: models::ModProduct<'a>,
+}
+
+/// Storage the feed is synchronized into. Implemented for MySQL connection,
+/// other backends only need to support the core operations.
+pub(crate) trait ProductStore {
+    /// Name of the store in errors about unsupported features
+    fn name(&self) -> &'static str;
+
+    /// Products with the given `hub_stock_id`s
+    fn find_products(&mut self, offer_ids: &[&str]) -> Result<Vec<models::Product>, Error>;
+
+    /// Number of available products
+    fn count_available(&mut self) -> Result<u64, Error>;
+
+    /// Ids and offer ids of available products ordered by id starting after `after_id`
+    fn available_products(&mut self, after_id: i32, limit: usize) -> Result<Vec<(i32, Option<String>)>, Error>;
+
+    /// Applies planned updates in transactions of `commit_size` updates.
+    /// Returns ids of the products that were not updated because their version had been changed
+    /// by someone else after the products were loaded.
+    fn apply_updates(&mut self, updates: &[ProductUpdate], commit_size: usize) -> Result<HashSet<i32>, Error>;
+
+    fn insert_products(&mut self, products: &[models::NewProduct]) -> Result<(), Error>;
+
+    fn insert_price_history(&mut self, rows: &[models::NewPriceHistory]) -> Result<(), Error>;
+
+    /// Marks products of the offers as unavailable, returns a number of marked products.
+    /// With `hysteresis` the products are marked after that many runs in a row they are missing.
+    /// With `forget_hashes` the stored offer hashes of the products are deleted.
+    fn mark_missing(
+        &mut self,
+        offer_ids: &[String],
+        file_ids: &[i8],
+        hysteresis: Option<u32>,
+        import_id: &str,
+        forget_hashes: bool,
+    ) -> Result<u32, Error>;
+
+    /// Records the time the products were processed at, stores keeping no timestamps ignore it
+    fn set_processed_date(&mut self, _date_processed: &NaiveDateTime) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Makes the writes of the import the last sync of the written products, so `--resolve` classifies
+    /// only later changes as manual ones
+    fn mark_written(&mut self, _import_id: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Completes the writes of the run, returns a number of statements written instead of being executed
+    fn finish(&mut self) -> Result<u32, Error> {
+        Ok(0)
+    }
+
+    /// Connection used by the features backed by additional MySQL tables
+    fn connection(&mut self) -> Option<&mut MysqlConnection> {
+        None
+    }
+
+    /// Connection used to read additional MySQL tables and to keep scratch tables of the run,
+    /// a store that does not write into MySQL itself may still provide it
+    fn read_connection(&mut self) -> Option<&mut MysqlConnection> {
+        self.connection()
+    }
+}
+
+/// MySQL connection of the store, fails when the `feature` is used with another backend
+pub(crate) fn mysql_connection<'a>(
+    store: &'a mut dyn ProductStore, feature: &str,
+) -> Result<&'a mut MysqlConnection, Error> {
+    let name = store.name();
+    store.connection()
+        .ok_or_else(|| Error::config(format!("{} is not supported by {}", feature, name)))
+}
+
+/// MySQL connection of the store for the `feature` that only reads additional tables
+pub(crate) fn mysql_read_connection<'a>(
+    store: &'a mut dyn ProductStore, feature: &str,
+) -> Result<&'a mut MysqlConnection, Error> {
+    let name = store.name();
+    store.read_connection()
+        .ok_or_else(|| Error::config(format!("{} is not supported by {}", feature, name)))
+}
+
+impl ProductStore for MysqlConnection {
+    fn name(&self) -> &'static str {
+        "MySQL product store"
+    }
+
+    fn find_products(&mut self, offer_ids: &[&str]) -> Result<Vec<models::Product>, Error> {
+        Ok(
+            products::table
+                .filter(products::hub_stock_id.eq_any(offer_ids))
+                .load::<models::Product>(self)?
+        )
+    }
+
+    fn count_available(&mut self) -> Result<u64, Error> {
+        Ok(
+            products::table.select(products::id)
+                .filter(products::available.eq(AVAILABLE))
+                .count()
+                .get_result::<i64>(self)? as u64
+        )
+    }
+
+    fn available_products(&mut self, after_id: i32, limit: usize) -> Result<Vec<(i32, Option<String>)>, Error> {
+        Ok(
+            products::table.select((products::id, products::hub_stock_id))
+                .filter(products::id.gt(after_id))
+                .filter(products::available.eq(AVAILABLE))
+                .order(products::id)
+                .limit(limit as i64)
+                .load::<(i32, Option<String>)>(self)?
+        )
+    }
+
+    fn apply_updates(&mut self, updates: &[ProductUpdate], commit_size: usize) -> Result<HashSet<i32>, Error> {
+        use crate::schema::products::dsl;
+
+        let mut conflicted_ids = HashSet::new();
+        for transaction_updates in updates.chunks(commit_size) {
+            self.transaction(|conn| {
+                for u in transaction_updates {
+                    let target = dsl::products.filter(dsl::id.eq(u.product_id));
+                    if u.counter_only {
+                        diesel::update(target)
+                            .set(&u.changes)
+                            .execute(conn)?;
+                        continue;
+                    }
+                    let changes = (&u.changes, dsl::version.eq(dsl::version + 1));
+                    match u.expected_version {
+                        Some(version) => {
+                            let updated = diesel::update(target.filter(dsl::version.eq(version)))
+                                .set(changes)
+                                .execute(conn)?;
+                            if updated == 0 {
+                                conflicted_ids.insert(u.product_id);
+                            }
+                        }
+                        None => {
+                            diesel::update(target)
+                                .set(changes)
+                                .execute(conn)?;
+                        }
+                    }
+                }
+                Ok::<_, Error>(())
+            })?;
+        }
+        Ok(conflicted_ids)
+    }
+
+    fn insert_products(&mut self, products: &[models::NewProduct]) -> Result<(), Error> {
+        diesel::insert_into(products::table)
+            .values(products)
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn insert_price_history(&mut self, rows: &[models::NewPriceHistory]) -> Result<(), Error> {
+        diesel::insert_into(price_history::table)
+            .values(rows)
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn mark_missing(
+        &mut self,
+        offer_ids: &[String],
+        file_ids: &[i8],
+        hysteresis: Option<u32>,
+        import_id: &str,
+        forget_hashes: bool,
+    ) -> Result<u32, Error> {
+        use crate::schema::products::dsl;
+
+        let missing = dsl::products.filter(
+            dsl::hub_stock_id.eq_any(offer_ids)
+                .and(dsl::file_id.eq_any(file_ids))
+        );
+        // the products no longer match their synced offers, even those only counting missing runs
+        if forget_hashes {
+            offer_hashes::invalidate(self, offer_ids)?;
+        }
+        match hysteresis {
+            Some(hysteresis) => {
+                diesel::update(missing.clone())
+                    .set((
+                        dsl::unavailable_runs.eq(dsl::unavailable_runs + 1),
+                        dsl::last_import_id.eq(import_id),
+                    ))
+                    .execute(self)?;
+                Ok(
+                    diesel::update(missing.filter(dsl::unavailable_runs.ge(hysteresis as i32)))
+                        .set((dsl::available.eq(NOT_AVAILABLE), dsl::last_import_id.eq(import_id)))
+                        .execute(self)? as u32
+                )
+            }
+            None => {
+                diesel::update(missing)
+                    .set((dsl::available.eq(NOT_AVAILABLE), dsl::last_import_id.eq(import_id)))
+                    .execute(self)?;
+                Ok(offer_ids.len() as u32)
+            }
+        }
+    }
+
+    fn set_processed_date(&mut self, date_processed: &NaiveDateTime) -> Result<(), Error> {
+        // TODO: Create row if not exists
+        diesel::sql_query("UPDATE timestamps SET event_date = ? WHERE event = 'hub_xml_update'")
+            .bind::<Timestamp, _>(date_processed)
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn mark_written(&mut self, import_id: &str) -> Result<(), Error> {
+        diesel::sql_query(offer_hashes::mark_written_sql("?"))
+            .bind::<Varchar, _>(import_id)
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn connection(&mut self) -> Option<&mut MysqlConnection> {
+        Some(self)
+    }
+}
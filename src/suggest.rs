@@ -0,0 +1,377 @@
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::input;
+
+/// Distinct values remembered per element, enough to tell ids from repeated values like brands
+const DISTINCT_VALUES: usize = 1000;
+/// Text of an element longer than this is not accumulated further
+const MAX_VALUE_CHARS: usize = 10_000;
+/// Elements whose score is lower are not suggested
+const MIN_SCORE: u32 = 3;
+
+/// Offer fields with element names that suppliers use for them, in English, Ukrainian, Russian
+/// and Polish transliterations. Names are compared lowercased without `_`, `-` and namespace prefixes.
+const TARGETS: &[(&str, &[&str])] = &[
+    ("id", &["id", "offerid", "productid", "itemid", "tovarid", "uid", "guid"]),
+    ("price", &["price", "cena", "cina", "tsina", "tsena", "cost", "retailprice", "priceuah", "prise", "preis"]),
+    ("oldprice", &["oldprice", "priceold", "stracena", "staracena", "staraiatsena", "regularprice", "pricebefore"]),
+    ("currencyId", &["currencyid", "currency", "valuta", "waluta", "curr"]),
+    ("categoryId", &[
+        "categoryid", "category", "categoryref", "kategoria", "kategoriia", "kategoriya", "rubric", "razdel",
+    ]),
+    ("name", &["name", "title", "nazva", "nazvanie", "naimenovanie", "nazwa", "productname", "tovar"]),
+    ("description", &["description", "desc", "opis", "opys", "opisanie", "details", "fulldescription"]),
+    ("vendor", &["vendor", "brand", "manufacturer", "proizvoditel", "vyrobnyk", "producer", "marka", "producent"]),
+    ("vendorCode", &["vendorcode", "kod", "code", "sku", "article", "artikul", "articul", "art", "mpn", "kodtovaru"]),
+    ("barcode", &["barcode", "ean", "ean13", "gtin", "upc", "shtrihkod", "shtrikhkod", "kodkreskowy"]),
+    ("picture", &["picture", "image", "img", "photo", "foto", "zdjecie", "imageurl", "pictureurl"]),
+    ("available", &["available", "availability", "instock", "nalichie", "naiavnist", "nayavnist", "dostepnosc"]),
+    ("quantity", &["quantity", "qty", "stock", "ostatok", "zalyshok", "kolichestvo", "kilkist", "ilosc", "stan"]),
+    ("url", &["url", "link", "href", "producturl", "posylannia"]),
+    ("param", &["param", "attribute", "property", "feature", "kharakterystyka", "harakteristika", "parametr"]),
+];
+
+/// Values of an element of the records
+#[derive(Default)]
+struct ElementStats {
+    count: u64,
+    numeric: u64,
+    integer: u64,
+    boolean: u64,
+    currency_code: u64,
+    barcode: u64,
+    picture_url: u64,
+    url: u64,
+    total_chars: u64,
+    distinct: HashSet<String>,
+    /// Number of the records containing the element more than once
+    repeated: u64,
+    /// Number of the values with a `name` attribute, like `<param name="Color">`
+    named: u64,
+    example: Option<String>,
+}
+
+impl ElementStats {
+    fn add(&mut self, value: &str, named: bool) {
+        let value = value.trim();
+        self.count += 1;
+        if named {
+            self.named += 1;
+        }
+        if value.is_empty() {
+            return;
+        }
+        let number = value.replace(' ', "").replace(',', ".");
+        if number.parse::<f64>().is_ok() {
+            self.numeric += 1;
+        }
+        if value.chars().all(|c| c.is_ascii_digit()) {
+            self.integer += 1;
+            if [8, 12, 13, 14].contains(&value.len()) {
+                self.barcode += 1;
+            }
+        }
+        let lowercase = value.to_lowercase();
+        let booleans = [
+            "true", "false", "yes", "no", "1", "0", "+", "-", "так", "да", "ні", "нет", "є", "в наявності", "в наличии",
+        ];
+        if booleans.contains(&lowercase.as_str()) {
+            self.boolean += 1;
+        }
+        if value.len() == 3 && value.chars().all(|c| c.is_ascii_uppercase()) {
+            self.currency_code += 1;
+        }
+        if lowercase.starts_with("http://") || lowercase.starts_with("https://") {
+            let path = lowercase.split(['?', '#']).next().unwrap_or_default();
+            if [".jpg", ".jpeg", ".png", ".webp", ".gif"].iter().any(|ext| path.ends_with(ext)) {
+                self.picture_url += 1;
+            } else {
+                self.url += 1;
+            }
+        }
+        self.total_chars += value.chars().count() as u64;
+        if self.distinct.len() < DISTINCT_VALUES {
+            self.distinct.insert(value.to_string());
+        }
+        if self.example.is_none() {
+            self.example = Some(value.chars().take(40).collect::<String>().replace(['\n', '\r'], " "));
+        }
+    }
+
+    /// Share of the values matching a pattern
+    fn share(&self, matching: u64) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            matching as f64 / self.count as f64
+        }
+    }
+
+    fn average_chars(&self) -> f64 {
+        self.share(self.total_chars)
+    }
+
+    /// Few distinct values like brands or categories, not ids
+    fn is_repetitive(&self) -> bool {
+        self.count >= 10 && (self.distinct.len() as f64) < self.count as f64 * 0.5
+    }
+
+    fn is_unique(&self) -> bool {
+        self.distinct.len() as u64 == self.count.min(DISTINCT_VALUES as u64)
+    }
+}
+
+/// Element with the stats of its direct children and attributes
+#[derive(Default)]
+struct Container {
+    count: u64,
+    elements: BTreeMap<String, ElementStats>,
+}
+
+struct OpenElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    text: String,
+    /// Child element names with their number
+    children: BTreeMap<String, u32>,
+}
+
+/// Scans `sample` records of the feed and prints a field map TOML that maps offer fields
+/// to the feed's elements judging by the element names and their values.
+/// Records are the most frequent elements with child elements, so feeds without `<offer>` are handled too.
+pub(crate) fn suggest_mapping(feed_path: &Path, sample: u64, out_path: Option<&Path>) -> Result<(), Error> {
+    let (record, container) = scan(feed_path, sample)?
+        .ok_or_else(|| Error::config("No repeated elements with child elements are found in the feed"))?;
+    let suggestions = suggest(&container);
+
+    let mut toml = vec!();
+    writeln!(
+        toml, "# Suggested from {} <{}> elements of {}, review before use",
+        container.count, record, feed_path.display()
+    )?;
+    writeln!(toml, "offer = {}", toml_string(&record))?;
+    writeln!(toml)?;
+    writeln!(toml, "[fields]")?;
+    for (target, element, reason) in &suggestions {
+        writeln!(toml, "{} = {}  # {}", target, toml_string(element), reason)?;
+    }
+    let mapped = suggestions.iter().map(|(_, element, _)| element.as_str()).collect::<HashSet<_>>();
+    let unmapped = container.elements.iter()
+        .filter(|(name, _)| !mapped.contains(name.as_str()))
+        .collect::<Vec<_>>();
+    if !unmapped.is_empty() {
+        writeln!(toml)?;
+        writeln!(toml, "# Elements without a suggestion:")?;
+        for (name, stats) in unmapped {
+            writeln!(
+                toml, "# {} in {:.0}% of the records, e.g. {}",
+                name, percent(stats.count, container.count), stats.example.as_deref().unwrap_or("(empty)")
+            )?;
+        }
+    }
+
+    match out_path {
+        Some(path) => fs::write(path, &toml)
+            .map_err(|e| Error::config_caused_by(format!("Cannot write field map: {}", path.display()), e))?,
+        None => print!("{}", String::from_utf8_lossy(&toml)),
+    }
+    Ok(())
+}
+
+/// Returns the record element name with the stats of its children, reading stops when
+/// a record element is found `sample` times
+fn scan(feed_path: &Path, sample: u64) -> Result<Option<(String, Container)>, Error> {
+    let (reader, _) = input::open(feed_path)?;
+    let mut xml_reader = Reader::from_reader(reader);
+    let mut buf = vec!();
+    let mut containers = BTreeMap::<String, Container>::new();
+    let mut stack = Vec::<OpenElement>::new();
+
+    loop {
+        let event = xml_reader.read_event(&mut buf)
+            .map_err(|e| Error::from(e).at_position(xml_reader.buffer_position()))?;
+        match event {
+            Event::Start(ref e) => {
+                stack.push(open_element(e)?);
+            }
+            Event::Empty(ref e) => {
+                let element = open_element(e)?;
+                if close_element(&mut containers, &mut stack, element) >= sample {
+                    break;
+                }
+            }
+            Event::Text(ref e) => {
+                if let Some(element) = stack.last_mut() {
+                    if element.text.len() < MAX_VALUE_CHARS {
+                        element.text.push_str(&e.unescape_and_decode(&xml_reader)?);
+                    }
+                }
+            }
+            Event::CData(ref e) => {
+                if let Some(element) = stack.last_mut() {
+                    if element.text.len() < MAX_VALUE_CHARS {
+                        element.text.push_str(&String::from_utf8_lossy(e));
+                    }
+                }
+            }
+            Event::End(_) => {
+                if let Some(element) = stack.pop() {
+                    if close_element(&mut containers, &mut stack, element) >= sample {
+                        break;
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(
+        containers.into_iter()
+            .filter(|(_, c)| c.count > 1)
+            .max_by_key(|(_, c)| (c.count, c.elements.len()))
+    )
+}
+
+fn open_element(e: &BytesStart) -> Result<OpenElement, Error> {
+    let mut attributes = vec!();
+    for attr_res in e.attributes() {
+        let attr = attr_res?;
+        attributes.push((
+            String::from_utf8_lossy(attr.key).into_owned(),
+            String::from_utf8_lossy(&attr.value).into_owned(),
+        ));
+    }
+    Ok(OpenElement {
+        name: String::from_utf8_lossy(e.name()).into_owned(),
+        attributes,
+        text: String::new(),
+        children: BTreeMap::new(),
+    })
+}
+
+/// Adds the closed element to the stats, returns the number of the elements with the same name
+/// when it is a container
+fn close_element(
+    containers: &mut BTreeMap<String, Container>, stack: &mut [OpenElement], element: OpenElement,
+) -> u64 {
+    if let Some(parent) = stack.last_mut() {
+        *parent.children.entry(element.name.clone()).or_default() += 1;
+    }
+    if element.children.is_empty() {
+        if let Some(parent) = stack.last() {
+            let named = element.attributes.iter().any(|(key, _)| key == "name");
+            containers.entry(parent.name.clone()).or_default()
+                .elements.entry(element.name).or_default()
+                .add(&element.text, named);
+        }
+        return 0;
+    }
+    let container = containers.entry(element.name).or_default();
+    container.count += 1;
+    for (key, value) in &element.attributes {
+        container.elements.entry(format!("@{}", key)).or_default().add(value, false);
+    }
+    for (child, count) in &element.children {
+        if *count > 1 {
+            container.elements.entry(child.clone()).or_default().repeated += 1;
+        }
+    }
+    container.count
+}
+
+/// Best element per offer field, every element is suggested once
+fn suggest(container: &Container) -> Vec<(&'static str, String, String)> {
+    let mut candidates = vec!();
+    for (element, stats) in &container.elements {
+        for (target, names) in TARGETS {
+            let (score, reason) = score(target, names, element, stats, container.count);
+            if score >= MIN_SCORE {
+                candidates.push((score, *target, element.clone(), reason));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.2.cmp(&b.2)));
+
+    let mut used_targets = HashSet::new();
+    let mut used_elements = HashSet::new();
+    let mut suggestions = vec!();
+    for (_, target, element, reason) in candidates {
+        if used_targets.contains(target) || used_elements.contains(&element) {
+            continue;
+        }
+        used_targets.insert(target);
+        used_elements.insert(element.clone());
+        suggestions.push((target, element, reason));
+    }
+    let order = |target: &str| TARGETS.iter().position(|(t, _)| *t == target);
+    suggestions.sort_by_key(|(target, _, _)| order(target));
+    suggestions
+}
+
+fn score(
+    target: &str, names: &[&str], element: &str, stats: &ElementStats, records: u64,
+) -> (u32, String) {
+    let local_name = element.trim_start_matches('@').rsplit(':').next().unwrap_or_default();
+    let normalized = local_name.to_lowercase().replace(['_', '-'], "");
+    let mut score = 0;
+    let mut reasons = vec!();
+    if names.contains(&normalized.as_str()) {
+        score += 3;
+        reasons.push(format!("name looks like {}", target));
+    } else if names.iter().any(|name| name.len() > 3 && normalized.contains(name)) {
+        score += 1;
+        reasons.push(format!("name contains {}", target));
+    }
+
+    let (pattern_score, pattern) = match target {
+        "id" if element.starts_with('@') && stats.is_unique() => (2, "unique attribute"),
+        "price" | "oldprice" if stats.share(stats.numeric) > 0.9 && stats.share(stats.integer) < 1.0 => {
+            (2, "decimal numbers")
+        }
+        "price" | "oldprice" if stats.share(stats.numeric) > 0.9 => (1, "numbers"),
+        "currencyId" if stats.share(stats.currency_code) > 0.9 => (3, "currency codes"),
+        "categoryId" if stats.share(stats.integer) > 0.9 && stats.is_repetitive() => (2, "repeated numeric ids"),
+        "name" if (10.0..=200.0).contains(&stats.average_chars()) && stats.share(stats.numeric) < 0.1 => {
+            (1, "short texts")
+        }
+        "description" if stats.average_chars() > 100.0 => (2, "long texts"),
+        "vendor" if stats.is_repetitive() && stats.share(stats.numeric) < 0.1 => (1, "repeated texts"),
+        "vendorCode" if stats.is_unique() && stats.average_chars() <= 32.0 => (1, "unique codes"),
+        "barcode" if stats.share(stats.barcode) > 0.9 => (3, "EAN/UPC digits"),
+        "picture" if stats.share(stats.picture_url) > 0.8 => (3, "image URLs"),
+        "url" if stats.share(stats.url) > 0.8 => (2, "URLs"),
+        "available" if stats.share(stats.boolean) > 0.9 => (2, "yes/no values"),
+        "quantity" if stats.share(stats.integer) > 0.9 && !stats.is_unique() => (1, "integers"),
+        "param" if stats.repeated * 2 > records && stats.share(stats.named) > 0.9 => (3, "repeated named values"),
+        _ => (0, ""),
+    };
+    if pattern_score > 0 {
+        score += pattern_score;
+        reasons.push(pattern.to_string());
+    }
+    if let Some(ref example) = stats.example {
+        reasons.push(format!("e.g. {}", example));
+    }
+    (score, reasons.join(", "))
+}
+
+fn toml_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
@@ -0,0 +1,641 @@
+use serde_json::json;
+
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::{parser, runs, vat, Opts, ProcessedStat};
+use crate::deletes::DeleteMode;
+use crate::error::Error;
+use crate::models::ImportRun;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SummaryFormat {
+    /// Human-readable lines
+    Text,
+    /// Single JSON object with the counters and durations
+    Json,
+}
+
+impl FromStr for SummaryFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SummaryFormat, Error> {
+        match s {
+            "text" => Ok(SummaryFormat::Text),
+            "json" => Ok(SummaryFormat::Json),
+            _ => Err(Error::config(format!("Unknown summary format: {}", s))),
+        }
+    }
+}
+
+/// Writes the summary of the run in `--summary-format`, `prev` is the previous run of the file
+/// for `--track-runs`
+pub(crate) fn write(
+    out: &mut dyn Write, opts: &Opts, stat: &ProcessedStat, prev: Option<&ImportRun>,
+) -> io::Result<()> {
+    match opts.summary_format {
+        SummaryFormat::Text => write_text(out, opts, stat, prev),
+        SummaryFormat::Json => write_json(out, stat, prev),
+    }
+}
+
+fn write_text(out: &mut dyn Write, opts: &Opts, stat: &ProcessedStat, prev: Option<&ImportRun>) -> io::Result<()> {
+    let l = opts.report_locale;
+    writeln!(out, "Import id: {}", stat.import_id)?;
+    if let Some(ref rules_version) = stat.rules_version {
+        writeln!(out, "Rule set: {}", rules_version)?;
+    }
+    if stat.leading_junk_bytes > 0 {
+        writeln!(out, "Skipped before XML: {} bytes", l.int(stat.leading_junk_bytes))?;
+    }
+    writeln!(out, "Total offers: {}", runs::with_delta(l, stat.total_offers, prev.map(|r| r.total_offers)))?;
+    writeln!(
+        out,
+        "Ignored offers: {} (with errors or missing required fields)",
+        runs::with_delta(l, stat.ignored_offers, prev.map(|r| r.ignored_offers))
+    )?;
+    if opts.transform_cmd.is_some() || opts.rules.is_some() {
+        writeln!(out, "Rejected offers: {}", l.int(stat.rejected_offers))?;
+    }
+    if let Some(threshold) = opts.low_stock_threshold {
+        writeln!(
+            out, "Low stock offers: {} (quantity at or below {})", l.int(stat.low_stock_offers), l.int(threshold)
+        )?;
+    }
+    if let Some(max_delivery_days) = opts.max_delivery_days {
+        writeln!(
+            out,
+            "Unavailable due to delivery longer than {} days: {}",
+            max_delivery_days, l.int(stat.long_delivery_offers)
+        )?;
+    }
+    if opts.skip_adult {
+        writeln!(out, "Skipped adult offers: {}", l.int(stat.skipped_adult_offers))?;
+    }
+    if opts.apply_deletes.is_some() || !stat.deleted_offer_ids.is_empty() {
+        writeln!(out, "Deleted offers: {} (marked in the feed)", l.int(stat.deleted_offer_ids.len()))?;
+    }
+    if stat.normalized_values > 0 {
+        writeln!(out, "Normalized values: {} (surrounding whitespace removed)", l.int(stat.normalized_values))?;
+    }
+    if stat.sanitized_offers > 0 {
+        writeln!(out, "Sanitized offers: {} (control characters removed)", l.int(stat.sanitized_offers))?;
+    }
+    if stat.oversized_offers > 0 {
+        writeln!(out, "Oversized offers: {} (fields exceed column limits)", l.int(stat.oversized_offers))?;
+    }
+    if stat.shop_offers.len() > 1 {
+        for (shop, count) in &stat.shop_offers {
+            writeln!(out, "Shop offers [{}]: {}", shop, l.int(count))?;
+        }
+    }
+    if opts.track_feed_fields {
+        writeln!(out, "Disappeared fields: {} (common in the previous run)", l.int(stat.disappeared_fields.len()))?;
+        for f in &stat.disappeared_fields {
+            writeln!(
+                out, "  {} [{}]: was in {}% of the offers", f.field, f.supplier, l.decimal(f.previous_share * 100.0, 0)
+            )?;
+        }
+    }
+    if opts.check_price_scale {
+        writeln!(
+            out, "Price scale anomalies: {} (forced with --force-price-scale)", l.int(stat.price_scale_anomalies.len())
+        )?;
+        for a in &stat.price_scale_anomalies {
+            writeln!(
+                out, "  {}: median price {} instead of {} ({}x)",
+                a.supplier, l.decimal(a.median_price, 2), l.decimal(a.historical_median_price, 2), l.decimal(a.ratio(), 2)
+            )?;
+        }
+    }
+    for (tag, count) in &stat.tagged_offers {
+        writeln!(out, "Tagged offers [{}]: {}", tag, l.int(count))?;
+    }
+    writeln!(out, "Parsed offers: {}", runs::with_delta(l, stat.parsed_offers, prev.map(|r| r.parsed_offers)))?;
+    if opts.merge_strategy.is_some() {
+        writeln!(out, "Merged offers: {} (same SKU offered by several suppliers)", l.int(stat.merged_offers))?;
+    }
+    if opts.group_variants {
+        writeln!(
+            out,
+            "Variant offers: {} (saved as variants of the first offer of the group)", l.int(stat.variant_offers)
+        )?;
+    }
+    if opts.prices_are == vat::PricesAre::Net {
+        writeln!(out, "Grossed up offers: {} (VAT added)", l.int(stat.grossed_up_offers))?;
+    }
+    if opts.pricing_rules {
+        writeln!(out, "Repriced offers: {}", l.int(stat.repriced_offers))?;
+    }
+    if opts.name_template.is_some() {
+        writeln!(out, "Templated names: {} (built from offer fields)", l.int(stat.templated_names))?;
+    }
+    if opts.normalize_names {
+        let s = &stat.name_normalization;
+        writeln!(
+            out,
+            "Normalized names: {} (whitespace: {}, skus: {}, emoji: {}, all caps: {}, banned: {}, truncated: {})",
+            l.int(s.changed), l.int(s.whitespace), l.int(s.skus), l.int(s.emoji), l.int(s.all_caps), l.int(s.banned),
+            l.int(s.truncated)
+        )?;
+    }
+    if opts.update_price {
+        writeln!(
+            out, "Updated price: {}", runs::with_delta(l, stat.updated_price, prev.map(|r| r.updated_price))
+        )?;
+    } else {
+        writeln!(out, "Different price: {} (not_updated)", l.int(stat.updated_price))?;
+    }
+    if opts.min_discount.is_some() {
+        if opts.update_price {
+            writeln!(out, "Updated discounts: {}", l.int(stat.updated_discount))?;
+        } else {
+            writeln!(out, "Different discounts: {} (not_updated)", l.int(stat.updated_discount))?;
+        }
+    }
+    if stat.updated_vat > 0 {
+        if opts.update_price {
+            writeln!(out, "Updated VAT: {}", l.int(stat.updated_vat))?;
+        } else {
+            writeln!(out, "Different VAT: {} (not_updated)", l.int(stat.updated_vat))?;
+        }
+    }
+    if stat.currency_only_changes > 0 {
+        writeln!(out, "Currency-only price changes: {}", l.int(stat.currency_only_changes))?;
+    }
+    if opts.convert_prices {
+        writeln!(
+            out,
+            "Converted prices: {} (unknown rate: {})", l.int(stat.converted_prices), l.int(stat.unconverted_prices)
+        )?;
+    }
+    if !opts.currency_rates.is_empty() {
+        writeln!(out, "Same prices in another currency: {} (not updated)", l.int(stat.converted_same_prices))?;
+    }
+    if let Some(window) = opts.price_smoothing {
+        writeln!(out, "Smoothed prices: {} (average of {} feed prices)", l.int(stat.smoothed_prices), window)?;
+    }
+    if opts.clear_missing_oldprice {
+        writeln!(out, "Cleared promotions: {} (oldprice is missing)", l.int(stat.cleared_oldprices))?;
+    }
+    if opts.update_available {
+        writeln!(
+            out,
+            "Updated available: {}", runs::with_delta(l, stat.updated_available, prev.map(|r| r.updated_available))
+        )?;
+    } else {
+        writeln!(out, "Different available: {} (not_updated)", l.int(stat.updated_available))?;
+    }
+    if stat.reactivated_products > 0 || stat.deactivated_products > 0 {
+        writeln!(
+            out,
+            "  re-activated (0 -> 1): {}, deactivated (1 -> 0): {}",
+            l.int(stat.reactivated_products), l.int(stat.deactivated_products)
+        )?;
+    }
+    if let Some(ref path) = opts.reactivated_out {
+        writeln!(
+            out, "Re-activated products: {} (written to {})", l.int(stat.reactivated_list.len()), path.display()
+        )?;
+    }
+    if opts.available_hysteresis.is_some() {
+        writeln!(out, "Postponed unavailable: {} (available hysteresis)", l.int(stat.postponed_unavailable))?;
+    }
+    if opts.price_history {
+        writeln!(out, "Price history records: {}", l.int(stat.price_history_records))?;
+    }
+    if opts.insert_new || opts.full_reload {
+        writeln!(
+            out,
+            "Inserted products: {}", runs::with_delta(l, stat.inserted_products, prev.map(|r| r.inserted_products))
+        )?;
+    } else {
+        writeln!(out, "New products: {} (not inserted)", l.int(stat.inserted_products))?;
+    }
+    if opts.staging {
+        writeln!(
+            out,
+            "Staged products: {} (applied in {})",
+            l.int(stat.staged_products), l.duration(stat.staging_apply_duration)
+        )?;
+    }
+    if let Some(ref path) = opts.sql_out {
+        writeln!(
+            out, "SQL statements: {} (written to {}, not executed)", l.int(stat.sql_statements), path.display()
+        )?;
+    }
+    if opts.full_reload {
+        writeln!(out, "Reloaded products: {} (overwritten from the feed)", l.int(stat.reloaded_products))?;
+        writeln!(out, "Deleted products: {} (missing from the feed)", l.int(stat.deleted_products))?;
+    }
+    match opts.apply_deletes {
+        Some(DeleteMode::Soft) => writeln!(out, "Deactivated as deleted: {}", l.int(stat.applied_deletes))?,
+        Some(DeleteMode::Hard) => writeln!(out, "Deleted as marked in the feed: {}", l.int(stat.applied_deletes))?,
+        None => {}
+    }
+    if let Some(ref channel) = opts.channel {
+        writeln!(out, "Hidden in {} channel: {}", channel, l.int(stat.channel_hidden_products))?;
+    }
+    if opts.update_keywords {
+        writeln!(out, "Products with keywords: {}", l.int(stat.keyword_products))?;
+    }
+    if opts.update_relations {
+        writeln!(
+            out,
+            "Product relations: {} (unresolved offer ids: {})",
+            l.int(stat.product_relations), l.int(stat.unresolved_relations)
+        )?;
+    }
+    if opts.aggregate_stocks {
+        writeln!(out, "Aggregated stocks: {} (products)", l.int(stat.aggregated_stocks))?;
+    }
+    if opts.respect_reservations {
+        writeln!(out, "Unavailable due to reservations: {}", l.int(stat.reserved_products))?;
+    }
+    if opts.discontinued_categories || !opts.discontinued_category.is_empty() {
+        writeln!(out, "Suppressed offers of discontinued categories: {}", l.int(stat.discontinued_offers))?;
+    }
+    if opts.create_categories {
+        writeln!(out, "Created categories: {}", l.int(stat.created_categories))?;
+    }
+    if opts.new_as_draft && (opts.insert_new || opts.full_reload) {
+        writeln!(out, "Drafted products: {} (waiting for moderation)", l.int(stat.drafted_products))?;
+        for offer_id in &stat.drafted_offer_ids {
+            writeln!(out, "  {}", offer_id)?;
+        }
+        if stat.drafted_products as usize > stat.drafted_offer_ids.len() {
+            writeln!(out, "  ... and {} more", l.int(stat.drafted_products as usize - stat.drafted_offer_ids.len()))?;
+        }
+    }
+    if opts.validate_picture_urls {
+        writeln!(out, "Dead pictures: {} (products inserted as drafts)", l.int(stat.dead_picture_products))?;
+        for offer_id in &stat.dead_picture_offer_ids {
+            writeln!(out, "  {}", offer_id)?;
+        }
+        let listed = stat.dead_picture_offer_ids.len();
+        if stat.dead_picture_products as usize > listed {
+            writeln!(out, "  ... and {} more", l.int(stat.dead_picture_products as usize - listed))?;
+        }
+        if stat.unverified_pictures > 0 {
+            writeln!(out, "Unverified pictures: {} (servers did not respond)", l.int(stat.unverified_pictures))?;
+        }
+    }
+    if opts.skip_newer_than_feed {
+        match stat.feed_date {
+            Some(feed_date) => writeln!(
+                out,
+                "Skipped products changed after the feed date {}: {}",
+                l.date(&feed_date), l.int(stat.skipped_newer_products)
+            )?,
+            None => writeln!(out, "Feed date is missing, products were updated regardless of their renew date")?,
+        }
+    }
+    if opts.skip_unchanged {
+        writeln!(out, "Unchanged products: {} (not loaded, offers did not change)", l.int(stat.unchanged_products))?;
+    }
+    if !opts.resolve.is_empty() {
+        writeln!(out, "Kept manual changes: {}", l.int(stat.kept_manual_products))?;
+        writeln!(out, "Queued conflicts: {} (products written into product_conflicts)", l.int(stat.queued_conflicts))?;
+    }
+    if stat.suppressed_by_locks > 0 {
+        writeln!(out, "Suppressed by field locks: {}", l.int(stat.suppressed_by_locks))?;
+    }
+    if opts.optimistic_locking {
+        writeln!(out, "Conflicts: {} (modified concurrently, not updated)", l.int(stat.conflicts))?;
+    }
+    if stat.quarantined_offers > 0 {
+        writeln!(out, "Quarantined offers: {} (rejected by the database)", l.int(stat.quarantined_offers))?;
+    }
+    if opts.idempotency_key.is_some() {
+        writeln!(out, "Skipped chunks: {} (already applied)", l.int(stat.skipped_chunks))?;
+    }
+    if let Some(chunk_deadline) = opts.chunk_deadline {
+        writeln!(out, "Slow chunks: {} (longer than {}s)", l.int(stat.slow_chunks), chunk_deadline)?;
+        if let Some(size) = stat.reduced_chunk_size {
+            writeln!(out, "Reduced chunk size: {} products", l.int(size))?;
+        }
+    }
+    if stat.description_flushes > 0 {
+        writeln!(
+            out, "Early chunks: {} (descriptions over --max-chunk-description-bytes)", l.int(stat.description_flushes)
+        )?;
+    }
+    if stat.large_descriptions > 0 {
+        writeln!(
+            out, "Large descriptions: {} (synced by {} batches)",
+            l.int(stat.large_descriptions), l.int(stat.large_description_batches)
+        )?;
+        let largest = stat.largest_descriptions.iter()
+            .map(|(offer_id, bytes)| format!("{} ({} KiB)", offer_id, l.int(bytes >> 10)))
+            .collect::<Vec<_>>();
+        writeln!(out, "  largest: {}", largest.join(", "))?;
+    }
+    if stat.retried_chunks > 0 || stat.failed_offers > 0 {
+        writeln!(out, "Retried chunks: {}", l.int(stat.retried_chunks))?;
+        writeln!(out, "Failed offers: {} (transient database errors)", l.int(stat.failed_offers))?;
+    }
+    if opts.find_duplicates {
+        writeln!(out, "Possible duplicates: {}", l.int(stat.possible_duplicates))?;
+    }
+    if opts.sku_template.is_some() && stat.sku_collisions > 0 {
+        writeln!(out, "SKU collisions: {} (suffixed to stay unique)", l.int(stat.sku_collisions))?;
+    }
+    if opts.mark_missing_unavailable {
+        writeln!(
+            out,
+            "Marked as unavailable: {}",
+            runs::with_delta(l, stat.marked_as_unavailable, prev.map(|r| r.marked_as_unavailable))
+        )?;
+    }
+    if opts.publishes() {
+        writeln!(out, "Published changes: {}", l.int(stat.published_changes))?;
+    }
+    if opts.search_url.is_some() {
+        writeln!(out, "Indexed documents: {}", l.int(stat.indexed_documents))?;
+    }
+    if let Some(ref path) = opts.changes_csv {
+        writeln!(out, "Changes CSV: {} rows (written to {})", l.int(stat.changes_csv_rows), path.display())?;
+    }
+    if let Some(ref path) = opts.rejects_as_xml {
+        writeln!(out, "Rejects XML: {} offers (written to {})", l.int(stat.rejects_xml_offers), path.display())?;
+    }
+    if opts.category_stats {
+        writeln!(
+            out, "Category stats: {} categories, {} without available products",
+            l.int(stat.category_stats.categories), l.int(stat.category_stats.empty)
+        )?;
+    }
+    if let Some(peak_memory) = stat.peak_memory {
+        writeln!(out, "Peak memory: {} MiB", l.int(peak_memory >> 20))?;
+    }
+    if opts.mark_missing_unavailable {
+        if stat.seen_offer_ids_spilled {
+            writeln!(out, "Offer ids: {} (in temporary table)", l.int(stat.seen_offer_ids))?;
+        } else {
+            writeln!(
+                out, "Offer ids: {} (~{} KiB)", l.int(stat.seen_offer_ids), l.int(stat.seen_offer_ids_memory >> 10)
+            )?;
+        }
+    }
+    writeln!(out, "Total time: {}", l.duration(stat.total_duration))?;
+    writeln!(out, "Parse time: {}", l.duration(stat.parse_duration))?;
+    if opts.mark_missing_unavailable {
+        writeln!(out, "Mark missing time: {}", l.duration(stat.mark_missing_duration))?;
+    }
+    if !stat.chunk_durations.is_empty() {
+        let sync_duration = stat.chunk_durations.iter().sum::<Duration>();
+        writeln!(
+            out,
+            "Sync time: {} (select: {}, update: {}, insert: {}, {} rows/s)",
+            l.duration(sync_duration), l.duration(stat.select_duration), l.duration(stat.update_duration),
+            l.duration(stat.insert_duration), l.decimal(parser::rows_per_second(stat.synced_products, sync_duration), 0)
+        )?;
+        let mut chunk_durations = stat.chunk_durations.clone();
+        chunk_durations.sort();
+        writeln!(
+            out,
+            "Chunk latency: p50 {}, p95 {}, max {} ({} chunks)",
+            l.duration(percentile(&chunk_durations, 50)),
+            l.duration(percentile(&chunk_durations, 95)),
+            l.duration(chunk_durations[chunk_durations.len() - 1]),
+            l.int(chunk_durations.len())
+        )?;
+    }
+    Ok(())
+}
+
+/// Counters are written regardless of the options, zero counters of disabled options included
+fn write_json(out: &mut dyn Write, stat: &ProcessedStat, prev: Option<&ImportRun>) -> io::Result<()> {
+    let mut summary = json!({
+        "import_id": stat.import_id,
+        "rules_version": stat.rules_version,
+        "counters": {
+            "total_offers": stat.total_offers,
+            "ignored_offers": stat.ignored_offers,
+            "rejected_offers": stat.rejected_offers,
+            "parsed_offers": stat.parsed_offers,
+            "merged_offers": stat.merged_offers,
+            "variant_offers": stat.variant_offers,
+            "repriced_offers": stat.repriced_offers,
+            "low_stock_offers": stat.low_stock_offers,
+            "long_delivery_offers": stat.long_delivery_offers,
+            "skipped_adult_offers": stat.skipped_adult_offers,
+            "deleted_offers": stat.deleted_offer_ids.len(),
+            "updated_price": stat.updated_price,
+            "updated_discount": stat.updated_discount,
+            "updated_vat": stat.updated_vat,
+            "converted_prices": stat.converted_prices,
+            "updated_available": stat.updated_available,
+            "reactivated_products": stat.reactivated_products,
+            "deactivated_products": stat.deactivated_products,
+            "postponed_unavailable": stat.postponed_unavailable,
+            "inserted_products": stat.inserted_products,
+            "drafted_products": stat.drafted_products,
+            "marked_as_unavailable": stat.marked_as_unavailable,
+            "applied_deletes": stat.applied_deletes,
+            "reserved_products": stat.reserved_products,
+            "discontinued_offers": stat.discontinued_offers,
+            "suppressed_by_locks": stat.suppressed_by_locks,
+            "unchanged_products": stat.unchanged_products,
+            "kept_manual_products": stat.kept_manual_products,
+            "queued_conflicts": stat.queued_conflicts,
+            "conflicts": stat.conflicts,
+            "quarantined_offers": stat.quarantined_offers,
+            "skipped_chunks": stat.skipped_chunks,
+            "slow_chunks": stat.slow_chunks,
+            "description_flushes": stat.description_flushes,
+            "large_descriptions": stat.large_descriptions,
+            "retried_chunks": stat.retried_chunks,
+            "failed_offers": stat.failed_offers,
+            "synced_products": stat.synced_products,
+        },
+        "durations_ms": {
+            "total": stat.total_duration.as_millis() as u64,
+            "parse": stat.parse_duration.as_millis() as u64,
+            "mark_missing": stat.mark_missing_duration.as_millis() as u64,
+            "select": stat.select_duration.as_millis() as u64,
+            "update": stat.update_duration.as_millis() as u64,
+            "insert": stat.insert_duration.as_millis() as u64,
+        },
+    });
+    if stat.dead_picture_products > 0 || stat.unverified_pictures > 0 {
+        summary["dead_pictures"] = json!({
+            "products": stat.dead_picture_products,
+            "offer_ids": stat.dead_picture_offer_ids,
+            "unverified_pictures": stat.unverified_pictures,
+        });
+    }
+    if !stat.disappeared_fields.is_empty() {
+        summary["disappeared_fields"] = stat.disappeared_fields.iter()
+            .map(|f| json!({"supplier": f.supplier, "field": f.field, "previous_share": f.previous_share}))
+            .collect();
+    }
+    if !stat.price_scale_anomalies.is_empty() {
+        summary["price_scale_anomalies"] = stat.price_scale_anomalies.iter()
+            .map(|a| json!({
+                "supplier": a.supplier,
+                "median_price": a.median_price,
+                "historical_median_price": a.historical_median_price,
+                "offers": a.offers,
+            }))
+            .collect();
+    }
+    if !stat.largest_descriptions.is_empty() {
+        summary["largest_descriptions"] = stat.largest_descriptions.iter()
+            .map(|(offer_id, bytes)| json!({"offer_id": offer_id, "bytes": bytes}))
+            .collect();
+    }
+    if let Some(prev) = prev {
+        summary["previous_run"] = json!({
+            "total_offers": prev.total_offers,
+            "ignored_offers": prev.ignored_offers,
+            "parsed_offers": prev.parsed_offers,
+            "updated_price": prev.updated_price,
+            "updated_available": prev.updated_available,
+            "inserted_products": prev.inserted_products,
+            "marked_as_unavailable": prev.marked_as_unavailable,
+        });
+    }
+    writeln!(out, "{}", serde_json::to_string_pretty(&summary)?)
+}
+
+/// Nearest-rank percentile of sorted durations
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (sorted.len() * p).div_ceil(100);
+    sorted[rank.max(1) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use structopt::StructOpt;
+
+    use super::*;
+    use crate::context::ImportContext;
+    use crate::parser::parse_products;
+
+    fn fixture(name: &str) -> String {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn opts(fixture_name: &str, args: &[&str]) -> Opts {
+        let file_path = fixture(fixture_name);
+        let mut all_args = vec!("hubber_xml", "--no-progress");
+        all_args.extend_from_slice(args);
+        all_args.push(&file_path);
+        Opts::from_iter(&all_args)
+    }
+
+    /// Parses the fixture without a database, every parsed product counts as synced
+    fn parse_fixture(opts: &Opts) -> ProcessedStat {
+        let mut stat = ProcessedStat {
+            import_id: "00000000-0000-0000-0000-000000000000".to_string(),
+            ..Default::default()
+        };
+        parse_products(&ImportContext::new(opts).unwrap(), &mut stat, |products_bucket, stat| {
+            stat.synced_products += products_bucket.len() as u32;
+            Ok(())
+        }).unwrap();
+        stat
+    }
+
+    fn summary(opts: &Opts, stat: &ProcessedStat, prev: Option<&ImportRun>) -> String {
+        let mut out = vec!();
+        write(&mut out, opts, stat, prev).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_text_summary() {
+        let opts = opts("yml_catalog.xml", &[]);
+        let stat = parse_fixture(&opts);
+        insta::assert_snapshot!(summary(&opts, &stat, None));
+    }
+
+    #[test]
+    fn test_text_summary_with_options() {
+        let opts = opts("yml_catalog.xml", &[
+            "--update-price", "--update-available", "--insert-new", "--max-delivery-days", "7",
+            "--low-stock-threshold", "2", "--skip-adult", "--mark-missing-unavailable",
+        ]);
+        let stat = parse_fixture(&opts);
+        insta::assert_snapshot!(summary(&opts, &stat, None));
+    }
+
+    #[test]
+    fn test_text_summary_of_multiple_shops() {
+        let opts = opts("multishop.xml", &[]);
+        let stat = parse_fixture(&opts);
+        insta::assert_snapshot!(summary(&opts, &stat, None));
+    }
+
+    #[test]
+    fn test_text_summary_of_merchant_feed_with_junk() {
+        let opts = opts("merchant_with_junk.xml", &[]);
+        let stat = parse_fixture(&opts);
+        insta::assert_snapshot!(summary(&opts, &stat, None));
+    }
+
+    #[test]
+    fn test_text_summary_compared_with_previous_run() {
+        let opts = opts("yml_catalog.xml", &["--update-price", "--update-available", "--track-runs"]);
+        let mut stat = parse_fixture(&opts);
+        stat.updated_price = 3;
+        stat.updated_available = 2;
+        stat.reactivated_products = 1;
+        stat.deactivated_products = 1;
+        stat.inserted_products = 1;
+        stat.select_duration = Duration::from_millis(4);
+        stat.update_duration = Duration::from_millis(10);
+        stat.insert_duration = Duration::from_millis(6);
+        stat.chunk_durations = vec!(Duration::from_millis(20));
+        let prev = ImportRun {
+            total_offers: 40,
+            ignored_offers: 1,
+            parsed_offers: 39,
+            updated_price: 2,
+            updated_available: 0,
+            inserted_products: 1,
+            marked_as_unavailable: 0,
+        };
+        insta::assert_snapshot!(summary(&opts, &stat, Some(&prev)));
+    }
+
+    #[test]
+    fn test_text_summary_in_ukrainian_locale() {
+        let opts = opts("yml_catalog.xml", &["--update-price", "--track-runs", "--report-locale", "uk"]);
+        let mut stat = parse_fixture(&opts);
+        stat.total_offers = 12_345;
+        stat.parsed_offers = 12_340;
+        stat.updated_price = 1_234;
+        stat.synced_products = 12_340;
+        stat.select_duration = Duration::from_millis(1_500);
+        stat.update_duration = Duration::from_secs(125);
+        stat.insert_duration = Duration::from_micros(2_500);
+        stat.chunk_durations = vec!(Duration::from_secs(127));
+        let prev = ImportRun {
+            total_offers: 10_000,
+            ignored_offers: 1,
+            parsed_offers: 9_999,
+            updated_price: 2_500,
+            updated_available: 0,
+            inserted_products: 0,
+            marked_as_unavailable: 0,
+        };
+        insta::assert_snapshot!(summary(&opts, &stat, Some(&prev)));
+    }
+
+    #[test]
+    fn test_json_summary() {
+        let opts = opts("yml_catalog.xml", &["--summary-format", "json"]);
+        let stat = parse_fixture(&opts);
+        insta::assert_snapshot!(summary(&opts, &stat, None));
+    }
+}
@@ -0,0 +1,135 @@
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::ProcessedStat;
+use crate::error::Error;
+use crate::locale::ReportLocale;
+use crate::models::{NewSupplierFeedStat, SupplierFeedStat};
+use crate::schema::supplier_feed_stats;
+
+/// Parses `date` attribute of `<yml_catalog>`: `2024-01-15 10:30` or RFC 3339.
+/// Dates without a time zone are considered UTC.
+pub(crate) fn parse_feed_date(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.naive_utc());
+    }
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M"))
+        .ok()
+}
+
+/// Supplier of a feed without shop names
+pub(crate) fn file_supplier(file_path: &Path) -> String {
+    file_path.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// Saves statistics of every supplier (`<shop>`) of the file, feeds without shop names
+/// are identified by the file name
+pub(crate) fn save_feed_stats(
+    conn: &mut MysqlConnection, file_path: &Path, stat: &ProcessedStat,
+) -> Result<(), Error> {
+    let run_at = Utc::now().naive_utc();
+    let rows = if stat.shop_offers.is_empty() {
+        vec!(NewSupplierFeedStat {
+            supplier: file_supplier(file_path),
+            feed_date: stat.feed_date,
+            run_at,
+            total_offers: stat.total_offers,
+            error_offers: stat.ignored_offers + stat.rejected_offers,
+        })
+    } else {
+        stat.shop_offers.iter()
+            .map(|(shop, &total_offers)| NewSupplierFeedStat {
+                supplier: shop.clone(),
+                feed_date: stat.feed_date,
+                run_at,
+                total_offers,
+                error_offers: stat.shop_error_offers.get(shop).copied().unwrap_or(0),
+            })
+            .collect()
+    };
+    diesel::insert_into(supplier_feed_stats::table)
+        .values(&rows)
+        .execute(conn)?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct SupplierSummary {
+    runs: u32,
+    last_run_at: Option<NaiveDateTime>,
+    total_offers: u64,
+    error_offers: u64,
+    dated_runs: u32,
+    total_delay: Duration,
+    max_delay: Duration,
+    late_runs: u32,
+}
+
+/// Prints feed freshness and error rates of the suppliers for the last days
+pub(crate) fn print_report(
+    conn: &mut MysqlConnection, days: u32, late_after_hours: u32, locale: ReportLocale,
+) -> Result<(), Error> {
+    let since = Utc::now().naive_utc() - Duration::days(days as i64);
+    let late_after = Duration::hours(late_after_hours as i64);
+    let feed_stats = supplier_feed_stats::table
+        .filter(supplier_feed_stats::run_at.ge(since))
+        .select((
+            supplier_feed_stats::supplier,
+            supplier_feed_stats::feed_date,
+            supplier_feed_stats::run_at,
+            supplier_feed_stats::total_offers,
+            supplier_feed_stats::error_offers,
+        ))
+        .load::<SupplierFeedStat>(conn)?;
+
+    let mut summaries = BTreeMap::<_, SupplierSummary>::new();
+    for s in &feed_stats {
+        let summary = summaries.entry(s.supplier.as_str()).or_default();
+        summary.runs += 1;
+        summary.last_run_at = summary.last_run_at.max(Some(s.run_at));
+        summary.total_offers += s.total_offers as u64;
+        summary.error_offers += s.error_offers as u64;
+        if let Some(feed_date) = s.feed_date {
+            let delay = s.run_at - feed_date;
+            summary.dated_runs += 1;
+            summary.total_delay += delay;
+            summary.max_delay = summary.max_delay.max(delay);
+            if delay > late_after {
+                summary.late_runs += 1;
+            }
+        }
+    }
+
+    println!("Suppliers for the last {} days:", days);
+    for (supplier, s) in &summaries {
+        let error_rate = if s.total_offers > 0 {
+            s.error_offers as f64 * 100.0 / s.total_offers as f64
+        } else {
+            0.0
+        };
+        let delay = if s.dated_runs > 0 {
+            format!(
+                "avg delay {}h, max delay {}h, late runs {}",
+                locale.decimal((s.total_delay / s.dated_runs as i32).num_minutes() as f64 / 60.0, 1),
+                locale.decimal(s.max_delay.num_minutes() as f64 / 60.0, 1),
+                locale.int(s.late_runs),
+            )
+        } else {
+            "feed date unknown".to_string()
+        };
+        println!(
+            "{}: runs {}, last run {}, avg offers {}, errors {}%, {}",
+            supplier, locale.int(s.runs),
+            s.last_run_at.map(|d| locale.date(&d)).unwrap_or_default(),
+            locale.int(s.total_offers / s.runs as u64), locale.decimal(error_rate, 1), delay
+        );
+    }
+    Ok(())
+}
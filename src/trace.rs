@@ -0,0 +1,74 @@
+use log::info;
+
+use serde_json::json;
+
+use std::collections::BTreeMap;
+
+use crate::Opts;
+
+pub(crate) fn is_traced(opts: &Opts, offer_id: &str) -> bool {
+    opts.trace_offer.iter().any(|id| id == offer_id)
+}
+
+/// Logs a decision made for the offer when it is traced with `--trace-offer`.
+/// Every record is a single JSON line: `{"offer_id": ..., "stage": ..., "details": ...}`
+pub(crate) fn trace<F>(opts: &Opts, offer_id: &str, stage: &str, details: F)
+where
+    F: FnOnce() -> serde_json::Value,
+{
+    if is_traced(opts, offer_id) {
+        info!(
+            target: "offer_trace", "{}",
+            json!({"offer_id": offer_id, "stage": stage, "details": details()})
+        );
+    }
+}
+
+/// Stages that set every field of a traced offer for `--trace-provenance`.
+/// Fields are compared between the stages, so a stage that sets the same value is not listed.
+pub(crate) struct Provenance {
+    last: serde_json::Value,
+    fields: BTreeMap<String, Vec<&'static str>>,
+}
+
+impl Provenance {
+    /// Non-empty fields of the parsed offer come from the feed
+    pub fn new<T: serde::Serialize>(offer: &T) -> Provenance {
+        let last = serde_json::to_value(offer).unwrap_or_default();
+        let fields = last.as_object()
+            .map(|fields| {
+                fields.iter()
+                    .filter(|(_, value)| !value.is_null() && value.as_array().is_none_or(|a| !a.is_empty()))
+                    .map(|(field, _)| (field.clone(), vec!("feed")))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Provenance { last, fields }
+    }
+
+    /// Attributes the fields changed since the previous stage to the `stage`
+    pub fn record<T: serde::Serialize>(&mut self, stage: &'static str, offer: &T) {
+        let current = serde_json::to_value(offer).unwrap_or_default();
+        if let Some(fields) = current.as_object() {
+            for (field, value) in fields {
+                if self.last.get(field) != Some(value) {
+                    self.fields.entry(field.clone()).or_default().push(stage);
+                }
+            }
+        }
+        self.last = current;
+    }
+
+    /// `{"price": ["feed", "currency", "pricing_rules"], ...}`
+    pub fn to_json(&self) -> serde_json::Value {
+        json!(self.fields)
+    }
+}
+
+/// Traces the offer at the stage, `None` means the offer was rejected
+pub(crate) fn trace_offer<T: serde::Serialize>(opts: &Opts, offer_id: &str, stage: &str, offer: Option<&T>) {
+    trace(opts, offer_id, stage, || match offer {
+        Some(offer) => serde_json::to_value(offer).unwrap_or_default(),
+        None => json!("rejected"),
+    });
+}
@@ -0,0 +1,41 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::error::{Error, ErrorContext};
+use crate::parser::Offer;
+
+/// Runs external command passing the offer as JSON into its stdin and reads modified offer
+/// from its stdout. Empty output or `null` means that the offer must be rejected.
+pub(crate) fn transform_offer(cmd: &Path, offer: Offer) -> Result<Option<Offer>, Error> {
+    let mut child = Command::new(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::config_caused_by(
+            format!("Cannot run transform command: {}", cmd.display()), e
+        ))?;
+    {
+        let stdin = child.stdin.as_mut()
+            .ok_or_else(|| Error::external("transform", "Cannot open stdin of the command"))?;
+        serde_json::to_writer(&mut *stdin, &offer)
+            .map_err(|e| Error::external("transform", e.to_string()))?;
+        stdin.write_all(b"\n")?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(Error::external(
+            "transform", format!("{}: command exited with {}", offer.offer_id, output.status)
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = stdout.trim();
+    if stdout.is_empty() {
+        return Ok(None);
+    }
+    let transformed = serde_json::from_str::<Option<Offer>>(stdout)
+        .map_err(|e| Error::validation(
+            format!("invalid output of the transform command: {}", e), ErrorContext::offer(&offer.offer_id)
+        ))?;
+    Ok(transformed)
+}
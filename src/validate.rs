@@ -0,0 +1,239 @@
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use crate::{Opts, ProcessedStat};
+use crate::context::ImportContext;
+use crate::dialect::{self, Dialect};
+use crate::error::Error;
+use crate::input;
+use crate::parser::parse_products;
+
+/// Elements of YML offers mapped by the parser, keep in sync with `parse_products`
+const YML_ELEMENTS: &[&str] = &[
+    "price", "oldprice", "currencyId", "categoryId", "name", "description", "vendor", "vendorCode",
+    "typePrefix", "model", "barcode", "vat", "available", "quantity", "stock_quantity", "quantity_in_stock",
+    "instock", "adult", "age", "delivery-options", "delivery_days", "keywords", "param",
+];
+/// Elements of Google Merchant items mapped by `merchant::parse_merchant_item`
+const MERCHANT_ELEMENTS: &[&str] = &[
+    "g:id", "title", "g:title", "description", "g:description", "summary", "g:price", "g:sale_price",
+    "g:availability", "g:brand", "g:mpn", "g:gtin", "g:item_group_id", "g:google_product_category",
+    "g:size", "g:color",
+];
+/// How many undeclared category ids are listed
+const LISTED_CATEGORIES: usize = 10;
+
+/// Structure of the offers collected without interpreting them
+#[derive(Default)]
+struct FeedStats {
+    offers: u64,
+    /// Child elements of the offers and a number of the offers containing them
+    elements: BTreeMap<String, u64>,
+    description_lengths: Vec<usize>,
+    currencies: BTreeMap<String, u64>,
+    declared_categories: HashSet<String>,
+    /// Category ids of the offers and a number of the offers in the category
+    offer_categories: BTreeMap<String, u64>,
+}
+
+impl FeedStats {
+    fn add_field(&mut self, dialect: Dialect, name: &str, value: &str) {
+        let value = value.trim();
+        match (dialect, name) {
+            (Dialect::GoogleMerchant, "description") | (Dialect::GoogleMerchant, "g:description") |
+            (Dialect::Yml, "description") | (Dialect::Custom, "description") => {
+                self.description_lengths.push(value.chars().count());
+            }
+            (Dialect::GoogleMerchant, "g:price") => {
+                if let Some(currency) = value.split_whitespace().nth(1) {
+                    *self.currencies.entry(currency.to_string()).or_insert(0) += 1;
+                }
+            }
+            (Dialect::GoogleMerchant, "g:google_product_category") |
+            (Dialect::Yml, "categoryId") | (Dialect::Custom, "categoryId") => {
+                *self.offer_categories.entry(value.to_string()).or_insert(0) += 1;
+            }
+            (Dialect::Yml, "currencyId") | (Dialect::Custom, "currencyId") => {
+                *self.currencies.entry(value.to_string()).or_insert(0) += 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parses the feed without touching the database and prints its structure:
+/// frequency of the offer elements, elements unknown to the parser, description lengths,
+/// currencies and how many offers reference categories declared in `<categories>`
+pub(crate) fn validate_feed(opts: &Opts) -> Result<(), Error> {
+    let file_path = opts.file_path();
+    let dialect = match opts.dialect {
+        Some(dialect) => dialect,
+        None => dialect::detect(file_path)?,
+    };
+    let stats = collect_stats(file_path, dialect)?;
+
+    let mut stat = ProcessedStat::default();
+    let mut parsed_offers = 0;
+    parse_products(&ImportContext::new(opts)?, &mut stat, |products_bucket, _| {
+        parsed_offers += products_bucket.len();
+        Ok(())
+    })?;
+
+    println!("Dialect: {}", dialect);
+    println!(
+        "Offers: {} (parsed: {}, ignored: {})", stats.offers, parsed_offers, stat.ignored_offers
+    );
+
+    println!("Offer elements:");
+    for (name, count) in &stats.elements {
+        println!("  {:<28} {:>8} ({:.1}%)", name, count, percent(*count, stats.offers));
+    }
+    let known = match dialect {
+        Dialect::GoogleMerchant => MERCHANT_ELEMENTS,
+        Dialect::Yml | Dialect::Custom => YML_ELEMENTS,
+    };
+    let unknown = stats.elements.keys()
+        .filter(|name| !known.contains(&name.as_str()))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if unknown.is_empty() {
+        println!("Unknown elements: none");
+    } else {
+        println!("Unknown elements: {}", unknown.join(", "));
+    }
+
+    let lengths = &stats.description_lengths;
+    match (lengths.iter().min(), lengths.iter().max()) {
+        (Some(min), Some(max)) => println!(
+            "Description length: min {}, max {}, avg {:.0} ({} descriptions)",
+            min, max, lengths.iter().sum::<usize>() as f64 / lengths.len() as f64, lengths.len()
+        ),
+        _ => println!("Description length: no descriptions"),
+    }
+
+    let currencies = stats.currencies.iter()
+        .map(|(currency, count)| format!("{} ({})", currency, count))
+        .collect::<Vec<_>>();
+    println!("Currencies: {}", if currencies.is_empty() { "none".to_string() } else { currencies.join(", ") });
+
+    let with_category = stats.offer_categories.values().sum::<u64>();
+    let undeclared = stats.offer_categories.keys()
+        .filter(|id| !stats.declared_categories.contains(*id))
+        .collect::<Vec<_>>();
+    let declared_offers = stats.offer_categories.iter()
+        .filter(|(id, _)| stats.declared_categories.contains(*id))
+        .map(|(_, count)| count)
+        .sum::<u64>();
+    println!(
+        "Categories: {} declared, {} used by offers, {} of {} offers ({:.1}%) are in declared categories",
+        stats.declared_categories.len(), stats.offer_categories.len(),
+        declared_offers, stats.offers, percent(declared_offers, stats.offers)
+    );
+    println!("Offers without category: {}", stats.offers - with_category.min(stats.offers));
+    if !undeclared.is_empty() {
+        let listed = undeclared.iter()
+            .take(LISTED_CATEGORIES)
+            .map(|id| id.as_str())
+            .collect::<Vec<_>>();
+        println!(
+            "Undeclared categories: {}{}",
+            listed.join(", "), if undeclared.len() > LISTED_CATEGORIES { ", ..." } else { "" }
+        );
+    }
+    Ok(())
+}
+
+fn collect_stats(file_path: &Path, dialect: Dialect) -> Result<FeedStats, Error> {
+    let (reader, _) = input::open(file_path)?;
+    let mut xml_reader = Reader::from_reader(reader);
+    let mut buf = vec!();
+    let mut stats = FeedStats::default();
+    // depth inside the current offer
+    let mut offer_depth = None;
+    let mut offer_elements = HashSet::new();
+    let mut field = None;
+    let mut value = String::new();
+    loop {
+        match xml_reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => {
+                match offer_depth {
+                    None if dialect.is_offer_element(e.name()) => {
+                        stats.offers += 1;
+                        offer_elements.clear();
+                        offer_depth = Some(0);
+                    }
+                    None if e.name() == b"category" => add_category(&mut stats, e)?,
+                    None => {}
+                    Some(0) => {
+                        let name = String::from_utf8_lossy(e.name()).into_owned();
+                        offer_elements.insert(name.clone());
+                        field = Some(name);
+                        value.clear();
+                        offer_depth = Some(1);
+                    }
+                    Some(depth) => offer_depth = Some(depth + 1),
+                }
+            }
+            Ok(Event::Empty(ref e)) => {
+                match offer_depth {
+                    None if dialect.is_offer_element(e.name()) => stats.offers += 1,
+                    None if e.name() == b"category" => add_category(&mut stats, e)?,
+                    Some(0) => {
+                        offer_elements.insert(String::from_utf8_lossy(e.name()).into_owned());
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::Text(ref e)) if offer_depth == Some(1) => {
+                value.push_str(&e.unescape_and_decode(&xml_reader)?);
+            }
+            Ok(Event::CData(ref e)) if offer_depth == Some(1) => {
+                value.push_str(&String::from_utf8_lossy(e));
+            }
+            Ok(Event::End(_)) => {
+                match offer_depth {
+                    Some(0) => {
+                        for name in offer_elements.drain() {
+                            *stats.elements.entry(name).or_insert(0) += 1;
+                        }
+                        offer_depth = None;
+                    }
+                    Some(1) => {
+                        if let Some(name) = field.take() {
+                            stats.add_field(dialect, &name, &value);
+                        }
+                        offer_depth = Some(0);
+                    }
+                    Some(depth) => offer_depth = Some(depth - 1),
+                    None => {}
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(Error::from(e).at_position(xml_reader.buffer_position())),
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(stats)
+}
+
+fn add_category(stats: &mut FeedStats, e: &BytesStart) -> Result<(), Error> {
+    for attr_res in e.attributes() {
+        let attr = attr_res?;
+        if attr.key == b"id" {
+            stats.declared_categories.insert(String::from_utf8_lossy(&attr.value).trim().to_string());
+        }
+    }
+    Ok(())
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
@@ -0,0 +1,79 @@
+use diesel::mysql::MysqlConnection;
+use diesel::prelude::*;
+
+use serde_json::{Map, Value};
+
+use std::collections::HashMap;
+
+use crate::error::Error;
+use crate::models::{NewProduct, NewProductVariant};
+use crate::schema::product_variants;
+
+const SIZE_PARAMS: &[&str] = &["size", "размер"];
+const COLOR_PARAMS: &[&str] = &["color", "colour", "цвет"];
+
+/// Keeps the first offer of every `group_id` as the canonical product,
+/// the following offers of the group become its variants.
+#[derive(Default)]
+pub(crate) struct VariantGrouper {
+    canonical_ids: HashMap<String, String>,
+}
+
+impl VariantGrouper {
+    /// Returns `true` when the product is a variant of an already seen product
+    pub fn assign(&mut self, product: &mut NewProduct) -> bool {
+        let group_id = match product.group_id {
+            Some(ref group_id) => group_id,
+            None => return false,
+        };
+        match self.canonical_ids.get(group_id) {
+            Some(canonical_id) => {
+                product.variant_of = Some(canonical_id.clone());
+                true
+            }
+            None => {
+                self.canonical_ids.insert(group_id.clone(), product.hub_stock_id.clone());
+                false
+            }
+        }
+    }
+}
+
+pub(crate) fn save_variants(
+    conn: &mut MysqlConnection, variants: &[NewProduct], import_id: &str,
+) -> Result<(), Error> {
+    let rows = variants.iter()
+        .filter_map(|p| {
+            Some(NewProductVariant {
+                hub_stock_id: p.hub_stock_id.clone(),
+                group_id: p.group_id.clone()?,
+                product_hub_stock_id: p.variant_of.clone()?,
+                price: p.price,
+                available: p.available,
+                size: param_value(&p.params, SIZE_PARAMS),
+                color: param_value(&p.params, COLOR_PARAMS),
+                params: if p.params.is_empty() {
+                    None
+                } else {
+                    let params = p.params.iter()
+                        .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+                        .collect::<Map<_, _>>();
+                    Some(Value::Object(params).to_string())
+                },
+                run_id: import_id.to_string(),
+            })
+        })
+        .collect::<Vec<_>>();
+    if !rows.is_empty() {
+        diesel::replace_into(product_variants::table)
+            .values(&rows)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+fn param_value(params: &[(String, String)], names: &[&str]) -> Option<String> {
+    params.iter()
+        .find(|(name, _)| names.contains(&name.to_lowercase().as_str()))
+        .map(|(_, value)| value.clone())
+}
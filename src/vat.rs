@@ -0,0 +1,64 @@
+use std::str::FromStr;
+
+use crate::error::Error;
+
+/// Whether feed prices include VAT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PricesAre {
+    Net,
+    Gross,
+}
+
+impl FromStr for PricesAre {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<PricesAre, Error> {
+        match s {
+            "net" => Ok(PricesAre::Net),
+            "gross" => Ok(PricesAre::Gross),
+            _ => Err(Error::config(format!("Unknown prices type: {}", s))),
+        }
+    }
+}
+
+/// VAT rate in percents from codes like `VAT_20`, `VAT_10_110`, `20%`, `20` or `NO_VAT`
+pub(crate) fn vat_rate(code: &str) -> Option<f32> {
+    let code = code.trim().to_uppercase();
+    if code == "NO_VAT" {
+        return Some(0.0);
+    }
+    code.trim_start_matches("VAT_")
+        .split('_')
+        .next()?
+        .trim_end_matches('%')
+        .parse()
+        .ok()
+}
+
+/// Adds VAT to the net price rounding it to cents
+pub(crate) fn to_gross(net_price: f32, rate: f32) -> f32 {
+    (net_price * (100.0 + rate) / 100.0 * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vat_rate() {
+        assert_eq!(vat_rate("VAT_20"), Some(20.0));
+        assert_eq!(vat_rate("vat_10_110"), Some(10.0));
+        assert_eq!(vat_rate(" 7% "), Some(7.0));
+        assert_eq!(vat_rate("20"), Some(20.0));
+        assert_eq!(vat_rate("NO_VAT"), Some(0.0));
+        assert_eq!(vat_rate("VAT_FREE"), None);
+    }
+
+    #[test]
+    fn test_to_gross() {
+        assert_eq!(to_gross(100.0, 20.0), 120.0);
+        assert_eq!(to_gross(9.99, 20.0), 11.99);
+        assert_eq!(to_gross(10.0, 7.0), 10.7);
+        assert_eq!(to_gross(15.5, 0.0), 15.5);
+    }
+}
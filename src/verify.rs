@@ -0,0 +1,149 @@
+use diesel::prelude::*;
+
+use log::{info, warn};
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::{database, establish_mysql_connection, CHUNK_SIZE, Opts, ProcessedStat};
+use crate::context::ImportContext;
+use crate::error::{Error, ErrorContext};
+use crate::models::{self, AVAILABLE, NOT_AVAILABLE};
+use crate::parser::parse_products;
+use crate::process::parse_locked_fields;
+use crate::schema::import_runs;
+use crate::store::ProductStore;
+
+#[derive(Default)]
+struct VerifyStat {
+    sampled: u32,
+    matching: u32,
+    changed_later: u32,
+    missing: u32,
+    differing: u32,
+}
+
+/// Re-reads the archived feed of the run and compares sampled products with the database.
+/// Products stamped by later runs are skipped, other differences mean the products were changed
+/// outside of the importer. Conversion options must be the same as of the verified run.
+pub(crate) fn verify_run(opts: &mut Opts, run_id: i32, sample: usize) -> Result<(), Error> {
+    if !opts.update_price && !opts.update_available {
+        return Err(Error::config(
+            "Nothing to verify: pass --update-price or --update-available of the verified run"
+        ));
+    }
+    let mut conn = establish_mysql_connection(&database::database_url(opts)?)?;
+    let (import_id, archive_path, parsed_offers) = import_runs::table
+        .find(run_id)
+        .select((import_runs::import_id, import_runs::archive_path, import_runs::parsed_offers))
+        .first::<(Option<String>, Option<String>, u32)>(&mut conn)
+        .optional()?
+        .ok_or_else(|| Error::config(format!("Run {} is not found", run_id)))?;
+    let archive_path = archive_path
+        .ok_or_else(|| Error::config(format!("Feed of run {} is not archived, see --archive-feeds", run_id)))?;
+    let later_import_ids = import_runs::table
+        .filter(import_runs::id.gt(run_id))
+        .select(import_runs::import_id)
+        .load::<Option<String>>(&mut conn)?
+        .into_iter()
+        .flatten()
+        .collect::<HashSet<_>>();
+    info!(
+        "Verifying run {} ({}) against {}",
+        run_id, import_id.as_deref().unwrap_or("unknown import id"), archive_path
+    );
+
+    opts.file_path = Some(PathBuf::from(archive_path));
+    let ctx = ImportContext::load(opts, &mut conn)?;
+
+    // products are sampled evenly across the feed
+    let every = (parsed_offers as usize / sample.max(1)).max(1);
+    let mut sampled = Vec::with_capacity(sample);
+    let mut index = 0;
+    parse_products(
+        &ctx, &mut ProcessedStat::default(),
+        |products_bucket, _| {
+            for product in products_bucket {
+                if index % every == 0 && sampled.len() < sample {
+                    sampled.push(product);
+                }
+                index += 1;
+            }
+            Ok(())
+        }
+    )?;
+
+    let mut stat = VerifyStat::default();
+    for products in sampled.chunks(CHUNK_SIZE) {
+        let offer_ids = products.iter().map(|p| p.hub_stock_id.as_str()).collect::<Vec<_>>();
+        let found_products = conn.find_products(&offer_ids)?.into_iter()
+            .filter_map(|p| p.hub_stock_id.clone().map(|id| (id, p)))
+            .collect::<HashMap<_, _>>();
+        for product in products {
+            stat.sampled += 1;
+            let found_product = match found_products.get(&product.hub_stock_id) {
+                Some(found_product) => found_product,
+                None => {
+                    stat.missing += 1;
+                    continue;
+                }
+            };
+            if found_product.last_import_id.as_ref().is_some_and(|id| later_import_ids.contains(id)) {
+                stat.changed_later += 1;
+                continue;
+            }
+            let differences = differences(opts, product, found_product);
+            if differences.is_empty() {
+                stat.matching += 1;
+            } else {
+                warn!("{}: differs from the archived feed: {}", product.offer_id, differences.join(", "));
+                stat.differing += 1;
+            }
+        }
+    }
+
+    let l = opts.report_locale;
+    println!("Sampled products: {}", l.int(stat.sampled));
+    println!("Matching: {}", l.int(stat.matching));
+    println!("Changed by later runs: {}", l.int(stat.changed_later));
+    println!("Not in the database: {}", l.int(stat.missing));
+    println!("Differing: {}", l.int(stat.differing));
+    if stat.differing > 0 {
+        return Err(Error::validation(
+            format!("{} products differ from the archived feed of run {}", stat.differing, run_id),
+            ErrorContext::default(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fields updated by the run that differ from the feed, locked fields are skipped
+fn differences(opts: &Opts, expected: &models::NewProduct, found: &models::Product) -> Vec<String> {
+    let locked_fields = found.locked_fields.as_deref()
+        .map(parse_locked_fields)
+        .unwrap_or_default();
+    let mut differences = vec!();
+    if opts.update_price {
+        if !locked_fields.contains("price") && expected.price != found.price {
+            differences.push(format!("price {} instead of {}", found.price, expected.price));
+        }
+        if !locked_fields.contains("oldprice") && expected.oldprice != found.oldprice {
+            differences.push(format!("oldprice {:?} instead of {:?}", found.oldprice, expected.oldprice));
+        }
+        if !locked_fields.contains("currencyId") && expected.currencyId != found.currencyId {
+            differences.push(format!("currencyId {:?} instead of {:?}", found.currencyId, expected.currencyId));
+        }
+    }
+    // reservations, discontinued categories and the hysteresis legitimately keep the other value
+    let may_be_suppressed = match expected.available {
+        AVAILABLE => opts.respect_reservations || opts.discontinued_categories || !opts.discontinued_category.is_empty(),
+        NOT_AVAILABLE => opts.available_hysteresis.is_some(),
+        _ => false,
+    };
+    if opts.update_available && !locked_fields.contains("available") && !may_be_suppressed &&
+        Some(expected.available) != found.available
+    {
+        differences.push(format!("available {:?} instead of {}", found.available, expected.available));
+    }
+    differences
+}